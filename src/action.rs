@@ -2,26 +2,111 @@ use crossterm::event::KeyEvent;
 use serde::{Deserialize, Serialize};
 use strum::Display;
 
+use crate::{
+  components::branch_list::scope::BranchScope,
+  git::git_repo::{GitBranch, GitRemoteBranch, ResetMode},
+};
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Display, Deserialize)]
 pub enum Action {
+  AbortOperation,
+  ArchiveSelectedBranch,
+  BranchScopeChanged(BranchScope),
+  BranchesLoaded(Vec<GitBranch>),
+  /// Some of the branches, sent while the rest are still being read.
+  BranchesLoading(Vec<GitBranch>),
+  /// Scrolls the list so the selected row is in the middle of it.
+  CenterSelection,
+  /// Fetches from the default remote with prune in the background, then offers to delete the branches whose upstreams
+  /// went with it.
+  CleanUpGoneBranches,
+  CheckoutBranch(String),
+  CheckoutCommit(String),
+  CheckoutDefaultBranch,
+  CheckoutPreviousBranch,
+  CheckoutRemoteBranch(GitRemoteBranch),
   CheckoutSelectedBranch,
+  CheckoutUpstream,
+  CherryPickCommit(String),
+  CopySelectedBranchName,
+  CopySelectedBranchRef,
   CreateBranch(String),
+  CreateBranchAt(String, String),
+  /// Creates the named branch from the base branch and checks it out.
+  CreateBranchFrom(String, String),
+  CreateWipBranch,
+  CycleBranchScope,
   DeleteBranch,
+  /// Deletes the named branches if their upstreams are still gone from every remote.
+  DeleteGoneBranches(Vec<String>),
   DeleteStagedBranches,
   EndInputMod,
   Error(String),
+  FastForwardBranches,
+  /// A background fetch failed, with git's error.
+  FetchFailed(String),
+  FetchStarted,
+  FindCommitBranches(String),
+  /// Checks out the named branch, throwing away local changes in the way.
+  ForceCheckoutBranch(String),
+  ForceDeleteBranch,
+  InitDescribeBranch,
   InitNewBranch,
+  InitRenameBranch,
+  /// Marks the commit being bisected, or the selected branch before bisecting has started, as bad.
+  MarkBisectBad,
+  MarkBisectGood,
+  /// Checks out the selected branch, stashing local changes and restoring the ones stashed when it was last left.
+  QuickCheckoutSelectedBranch,
   Quit,
   Refresh,
+  /// A background fetch finished, so the remote branches may have moved.
+  RemoteFetched,
+  /// A background fetch with prune finished, with the remote branches it pruned.
+  RemotePruned(Vec<String>),
+  /// Renames each branch named by the first of a pair to the second, carrying on past failures.
+  RenameBranches(Vec<(String, String)>),
+  RenameSelectedBranch(String),
+  /// Renames the upstream of the named branch on its remote to match the branch.
+  RenameUpstream(String),
   Render,
+  ResetCurrentBranch(String, ResetMode),
   Resize(u16, u16),
   Resume,
+  SearchNext,
+  SearchPrevious,
+  SelectFirstBranch,
+  SelectLastBranch,
   SelectNextBranch,
+  SelectNextPage,
   SelectPreviousBranch,
+  SelectPreviousPage,
+  /// Sets the description of the named branch, or removes it with `None`.
+  SetBranchDescription(String, Option<String>),
+  /// Makes the remote branch the upstream of the named local branch.
+  SetBranchUpstream(String, GitRemoteBranch),
+  /// Hands the terminal to the configured pager or editor to show the text.
+  ShowInViewer(String),
   StageBranchForDeletion,
+  StageMergedBranches,
+  StartFilter,
   StartInputMode,
+  StartSearch,
+  StopBisect,
   Suspend,
   Tick,
+  ToggleBehindSort,
+  ToggleBranchGrouping,
+  ToggleBranchSelection,
+  ToggleCleanupFilter,
+  ToggleCommitGraph,
+  TogglePinnedBranch,
+  ToggleSelectedGroup,
+  ToggleTicketFilter,
+  ToggleUnpushedFilter,
+  UndoDelete,
   UnstageBranchForDeletion,
+  UpdateFilter(KeyEvent),
   UpdateNewBranchName(KeyEvent),
+  UpdateSearch(KeyEvent),
 }
@@ -1,16 +1,34 @@
+use std::path::PathBuf;
+
 use color_eyre::eyre::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use ratatui::prelude::Rect;
+use ratatui::{
+  layout::{Constraint, Layout},
+  prelude::Rect,
+};
 use tokio::sync::mpsc;
 
 use crate::{
   action::Action,
-  components::{branch_list::BranchList, stash_list::StashList, Component},
-  config::Config,
-  git::{git2_repo::Git2Repo, git_cli_repo::GitCliRepo},
+  components::{
+    branch_list::{ui_state::UiStateStore, BranchList},
+    help_overlay::HelpOverlay,
+    stash_list::StashList,
+    status_bar::StatusBar,
+    Component,
+  },
+  config::{keybindings::KeyCommand, load_config, Config},
+  git::{
+    git2_repo::Git2Repo,
+    git_cli_repo::GitCliRepo,
+    git_repo::{fetch_in_background, load_local_branches_in_background, prune_in_background, DEFAULT_ABBREV_LENGTH},
+    progress::TransferProgress,
+    protected::ProtectedBranches,
+  },
   mode::Mode,
   tui,
   tui::Tui,
+  utils::{get_cache_dir, set_log_level},
+  viewer::open_in_viewer,
 };
 
 pub enum View {
@@ -21,10 +39,24 @@ pub enum View {
 const TICK_RATE: f64 = 10.0;
 const FRAME_RATE: f64 = 30.0;
 
+/// Whether to fetch when the app starts. Never in a dry run or read-only, which are meant to leave the repository
+/// untouched.
+fn should_fetch_on_startup(config: &Config, dry_run: bool, read_only: bool) -> bool {
+  config.fetch_on_startup && !dry_run && !read_only
+}
+
 pub struct App {
   pub config: Config,
+  pub help: HelpOverlay,
+  pub repo_path: PathBuf,
   pub branch_list: Box<dyn Component>,
   pub stash_list: Box<dyn Component>,
+  pub status_bar: StatusBar,
+  pub fetch_on_startup: bool,
+  /// Shared with the status bar, which shows how far the background fetches have got.
+  pub fetch_progress: TransferProgress,
+  pub dry_run: bool,
+  pub read_only: bool,
   pub should_quit: bool,
   pub should_suspend: bool,
   pub mode: Mode,
@@ -32,55 +64,163 @@ pub struct App {
 }
 
 impl App {
-  pub fn new() -> Result<Self> {
-    let config = Config::new()?;
+  /// Creates the app for the repository containing `path`, or the current directory when no path is given.
+  ///
+  /// In a dry run all branch changes are logged instead of made, and when read-only they are refused.
+  pub fn new(repo_path: PathBuf, dry_run: bool, read_only: bool) -> Result<Self> {
+    let config = load_config(&GitCliRepo::from_path(&repo_path)?)?;
+    if let Some(level) = &config.log_level {
+      set_log_level(level);
+    }
     // TODO only have a single repo that is shared
-    let branch_list = Box::new(BranchList::new(Box::new(GitCliRepo::from_cwd().unwrap())));
-    let stash_list = Box::new(StashList::new(Box::new(Git2Repo::from_cwd().unwrap())));
+    let protected = ProtectedBranches::new(config.protected_branches.clone());
+    let untracked_is_dirty = config.untracked_files_are_dirty;
+    let abbrev_length = config.abbrev_length.unwrap_or(DEFAULT_ABBREV_LENGTH);
+    let branch_repo = GitCliRepo::from_path(&repo_path)?
+      .protected(protected)
+      .dry_run(dry_run)
+      .read_only(read_only)
+      .untracked_is_dirty(untracked_is_dirty)
+      .allow_detached_reset(config.allow_detached_reset)
+      .abbrev_length(abbrev_length)
+      .remote(config.default_remote.clone());
+    let state_store = UiStateStore::new(get_cache_dir().join("ui_state.json"), &repo_path);
+    let branch_list = Box::new(BranchList::new(Box::new(branch_repo), &config).remember_state(state_store));
+    let stash_repo = Git2Repo::from_path(&repo_path)?
+      .dry_run(dry_run)
+      .read_only(read_only)
+      .untracked_is_dirty(untracked_is_dirty)
+      .remote(config.default_remote.clone());
+    let stash_list = Box::new(StashList::new(Box::new(stash_repo), &config));
+    let status_repo = GitCliRepo::from_path(&repo_path)?
+      .dry_run(dry_run)
+      .read_only(read_only)
+      .untracked_is_dirty(untracked_is_dirty)
+      .abbrev_length(abbrev_length)
+      .remote(config.default_remote.clone());
+    let fetch_progress = TransferProgress::default();
+    let status_bar = StatusBar::new(Box::new(status_repo), &config).fetch_progress(fetch_progress.clone());
+    let help = HelpOverlay::new(config.keybindings.clone(), config.theme.clone());
     let mode = Mode::Default;
-    Ok(Self { config, branch_list, stash_list, should_quit: false, should_suspend: false, mode, view: View::Branches })
+    let fetch_on_startup = should_fetch_on_startup(&config, dry_run, read_only);
+    Ok(Self {
+      config,
+      help,
+      repo_path,
+      branch_list,
+      stash_list,
+      status_bar,
+      fetch_on_startup,
+      fetch_progress,
+      dry_run,
+      read_only,
+      should_quit: false,
+      should_suspend: false,
+      mode,
+      view: View::Branches,
+    })
+  }
+
+  /// Fetches from the default remote with prune on another thread, the branch list offers to clean up once it's done.
+  fn prune_in_background(&self, action_tx: &mpsc::UnboundedSender<Action>) -> Result<()> {
+    let prune_tx = action_tx.clone();
+    let prune_path = self.repo_path.clone();
+    let prune_remote = self.config.default_remote.clone();
+    let prune_progress = self.fetch_progress.clone();
+    let (dry_run, read_only) = (self.dry_run, self.read_only);
+    action_tx.send(Action::FetchStarted)?;
+    tokio::spawn(async move {
+      let open_repo = move || {
+        Git2Repo::from_path(&prune_path)
+          .map(|repo| repo.dry_run(dry_run).read_only(read_only).remote(prune_remote).progress(prune_progress))
+      };
+      let action = match prune_in_background(open_repo, String::new()).await {
+        Ok(Ok(pruned)) => Action::RemotePruned(pruned),
+        Ok(Err(err)) => Action::FetchFailed(err.to_string()),
+        Err(err) => Action::FetchFailed(err.to_string()),
+      };
+      let _ = prune_tx.send(action);
+    });
+    Ok(())
   }
 
   pub async fn run(&mut self) -> Result<()> {
     let (action_tx, mut action_rx) = mpsc::unbounded_channel();
 
-    let mut tui = tui::Tui::new()?.tick_rate(TICK_RATE).frame_rate(FRAME_RATE);
-    // tui.mouse(true);
+    // Terminals without mouse support never send mouse events, so capture can always be enabled
+    let mut tui = tui::Tui::new()?.tick_rate(TICK_RATE).frame_rate(FRAME_RATE).mouse(true);
     tui.enter()?;
 
     self.branch_list.register_action_handler(action_tx.clone())?;
     self.stash_list.register_action_handler(action_tx.clone())?;
 
+    let loader_tx = action_tx.clone();
+    let batch_tx = action_tx.clone();
+    let loader_path = self.repo_path.clone();
+    tokio::spawn(async move {
+      let open_repo = move || GitCliRepo::from_path(&loader_path);
+      let on_batch = move |batch| {
+        let _ = batch_tx.send(Action::BranchesLoading(batch));
+      };
+      let action = match load_local_branches_in_background(open_repo, on_batch).await {
+        Ok(Ok(branches)) => Action::BranchesLoaded(branches),
+        Ok(Err(err)) => Action::Error(format!("Failed to load branches: {}", err)),
+        Err(err) => Action::Error(format!("Failed to load branches: {}", err)),
+      };
+      // The receiver is only gone when the app is quitting
+      let _ = loader_tx.send(action);
+    });
+
+    if self.fetch_on_startup {
+      let fetch_tx = action_tx.clone();
+      let fetch_path = self.repo_path.clone();
+      let fetch_remote = self.config.default_remote.clone();
+      let fetch_progress = self.fetch_progress.clone();
+      action_tx.send(Action::FetchStarted)?;
+      tokio::spawn(async move {
+        // git2 rather than the cli so the status bar can show how much has been received
+        let open_repo =
+          move || Git2Repo::from_path(&fetch_path).map(|repo| repo.remote(fetch_remote).progress(fetch_progress));
+        // No remote given fetches from the current branch's remote, or the configured default
+        let action = match fetch_in_background(open_repo, String::new()).await {
+          Ok(Ok(())) => Action::RemoteFetched,
+          Ok(Err(err)) => Action::FetchFailed(err.to_string()),
+          Err(err) => Action::FetchFailed(err.to_string()),
+        };
+        let _ = fetch_tx.send(action);
+      });
+    }
+
     loop {
       if let Some(e) = tui.next().await {
+        // The help overlay takes all key and mouse input while it is open
+        let help_was_open = self.help.is_open();
         match e {
           tui::Event::Quit => action_tx.send(Action::Quit)?,
           tui::Event::Tick => action_tx.send(Action::Tick)?,
           tui::Event::Render => action_tx.send(Action::Render)?,
           tui::Event::Resize(x, y) => action_tx.send(Action::Resize(x, y))?,
-          tui::Event::Key(key) => {
-            if self.mode == Mode::Default {
-              let action = match key {
-                KeyEvent { code: KeyCode::Esc, modifiers: _, state: _, kind: _ } => Some(Action::Quit),
-                KeyEvent { code: KeyCode::Char('c' | 'C'), modifiers: KeyModifiers::CONTROL, state: _, kind: _ } => {
-                  Some(Action::Quit)
-                },
-                _ => None,
-              };
-              if action.is_some() {
-                action_tx.send(action.unwrap())?;
-              }
-            };
+          tui::Event::Key(key) if help_was_open => self.help.handle_key_event(key),
+          tui::Event::Key(key) if self.mode == Mode::Default => {
+            match self.config.keybindings.command_for(&key) {
+              Some(KeyCommand::Quit) => action_tx.send(Action::Quit)?,
+              Some(KeyCommand::Help) => self.help.open(),
+              _ => {},
+            }
           },
           _ => {},
         }
 
+        let help_has_input =
+          (help_was_open || self.help.is_open()) && matches!(e, tui::Event::Key(_) | tui::Event::Mouse(_));
         let component: &mut Box<dyn Component> = match self.view {
           View::Branches => &mut self.branch_list,
           View::Stashes => &mut self.stash_list,
         };
-        if let Some(action) = component.handle_events(Some(e.clone()))? {
-          action_tx.send(action)?;
+        if !help_has_input {
+          if let Some(action) = component.handle_events(Some(e.clone()))? {
+            action_tx.send(action)?;
+          }
         }
       }
 
@@ -88,6 +228,10 @@ impl App {
         if action != Action::Tick && action != Action::Render {
           log::debug!("{action:?}");
         }
+        // Before the component is borrowed, as the fetch needs the app's settings
+        if action == Action::CleanUpGoneBranches {
+          self.prune_in_background(&action_tx)?;
+        }
         let component: &mut Box<dyn Component> = match self.view {
           View::Branches => &mut self.branch_list,
           View::Stashes => &mut self.stash_list,
@@ -99,21 +243,37 @@ impl App {
           Action::Quit => self.should_quit = true,
           Action::Suspend => self.should_suspend = true,
           Action::Resume => self.should_suspend = false,
+          Action::ShowInViewer(ref text) => {
+            tui.exit()?;
+            let result = open_in_viewer(self.config.commit_viewer, text);
+            tui.enter()?;
+            // The viewer drew over the screen, so everything has to be redrawn rather than just what changed
+            tui.clear()?;
+            if let Err(err) = result {
+              action_tx.send(Action::Error(err.to_string()))?;
+            }
+          },
           Action::Resize(w, h) => {
             tui.resize(Rect::new(0, 0, w, h))?;
             tui.draw(|f| {
-              let r = component.draw(f, f.area());
+              let [main_area, status_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(f.area());
+              let r = component.draw(f, main_area).and_then(|_| self.status_bar.draw(f, status_area));
               if let Err(e) = r {
                 action_tx.send(Action::Error(format!("Failed to draw: {:?}", e))).unwrap();
               }
+              self.help.render(f, f.area());
             })?;
           },
           Action::Render => {
             tui.draw(|f| {
-              let r = component.draw(f, f.area());
+              let [main_area, status_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(f.area());
+              let r = component.draw(f, main_area).and_then(|_| self.status_bar.draw(f, status_area));
               if let Err(e) = r {
                 action_tx.send(Action::Error(format!("Failed to draw: {:?}", e))).unwrap();
               }
+              self.help.render(f, f.area());
             })?;
           },
           _ => {},
@@ -121,11 +281,13 @@ impl App {
         if let Some(action) = component.update(action.clone())? {
           action_tx.send(action)?
         };
+        // After the component so the status reflects whatever the action just changed
+        self.status_bar.update(action)?;
       }
       if self.should_suspend {
         tui.suspend()?;
         action_tx.send(Action::Resume)?;
-        tui = Tui::new()?.tick_rate(TICK_RATE).frame_rate(FRAME_RATE);
+        tui = Tui::new()?.tick_rate(TICK_RATE).frame_rate(FRAME_RATE).mouse(true);
         tui.enter()?;
       } else if self.should_quit {
         tui.stop()?;
@@ -136,3 +298,17 @@ impl App {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn startup_fetches_only_when_configured_and_allowed_to_change_the_repository() {
+    let enabled = Config { fetch_on_startup: true, ..Config::default() };
+    assert!(!should_fetch_on_startup(&Config::default(), false, false));
+    assert!(should_fetch_on_startup(&enabled, false, false));
+    assert!(!should_fetch_on_startup(&enabled, true, false));
+    assert!(!should_fetch_on_startup(&enabled, false, true));
+  }
+}
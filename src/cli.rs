@@ -1,7 +1,32 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 
 use crate::utils::version;
 
 #[derive(Parser, Debug)]
 #[command(author, version = version(), about)]
-pub struct Cli {}
+pub struct Cli {
+  /// The repository to manage, defaults to the current directory
+  pub path: Option<PathBuf>,
+
+  /// Print the local branches as JSON and exit instead of starting the UI
+  #[arg(long)]
+  pub json: bool,
+
+  /// Answer json commands read from stdin, one per line, instead of starting the UI, for driving it from an editor
+  #[arg(long, conflicts_with = "json")]
+  pub stdio: bool,
+
+  /// Log checkouts and branch creates, renames and deletes instead of making them
+  #[arg(long)]
+  pub dry_run: bool,
+
+  /// Refuse every change to the repository and its remotes, for browsing without risk
+  #[arg(long)]
+  pub read_only: bool,
+
+  /// Manage the repository containing the submodule at the path instead of the submodule
+  #[arg(long)]
+  pub superproject: bool,
+}
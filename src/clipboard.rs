@@ -0,0 +1,75 @@
+use std::{
+  io::{ErrorKind, Write},
+  process::{Command, Stdio},
+};
+
+use tracing::{info, warn};
+
+use crate::error::Error;
+
+/// The platform's clipboard commands that read the text to copy from stdin, in the order they are tried.
+fn clipboard_commands() -> Vec<(&'static str, Vec<&'static str>)> {
+  if cfg!(target_os = "macos") {
+    return vec![("pbcopy", vec![])];
+  }
+  if cfg!(target_os = "windows") {
+    return vec![("clip", vec![])];
+  }
+  let mut commands = Vec::new();
+  if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+    commands.push(("wl-copy", vec![]));
+  }
+  commands.push(("xclip", vec!["-selection", "clipboard"]));
+  commands.push(("xsel", vec!["--clipboard", "--input"]));
+  commands
+}
+
+/// Copies `text` to the system clipboard with the first clipboard command that is installed.
+pub fn copy_to_clipboard(text: &str) -> Result<(), Error> {
+  copy_with(&clipboard_commands(), text)
+}
+
+/// Pipes `text` to the first of `commands` that runs and succeeds.
+fn copy_with(commands: &[(&str, Vec<&str>)], text: &str) -> Result<(), Error> {
+  for (program, args) in commands {
+    let child =
+      Command::new(program).args(args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn();
+    let mut child = match child {
+      Ok(child) => child,
+      Err(err) if err.kind() == ErrorKind::NotFound => continue,
+      Err(err) => return Err(Error::Clipboard(format!("Failed to run {}: {}", program, err))),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin
+        .write_all(text.as_bytes())
+        .map_err(|err| Error::Clipboard(format!("Failed to write to {}: {}", program, err)))?;
+    }
+    let output = child.wait_with_output().map_err(|err| Error::Clipboard(format!("{} failed: {}", program, err)))?;
+    if !output.status.success() {
+      // e.g. xclip without a display, another tool may still work
+      warn!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+      continue;
+    }
+    info!("Copied to the clipboard with {}", program);
+    return Ok(());
+  }
+  Err(Error::Clipboard("no clipboard command found, install wl-copy, xclip or xsel".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn the_first_command_that_works_copies_the_text() {
+    let commands = [("no-such-clipboard-tool", vec![]), ("cat", vec![])];
+    assert!(copy_with(&commands, "feature/login").is_ok());
+  }
+
+  #[test]
+  fn without_a_working_clipboard_command_it_is_an_error() {
+    let commands = [("no-such-clipboard-tool", vec![]), ("false", vec![])];
+    let result = copy_with(&commands, "feature/login");
+    assert!(matches!(result, Err(Error::Clipboard(_))), "{:?}", result);
+  }
+}
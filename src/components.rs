@@ -9,7 +9,10 @@ use crate::{
 };
 
 pub mod branch_list;
+pub mod confirm_dialog;
+pub mod help_overlay;
 pub mod stash_list;
+pub mod status_bar;
 
 /// `Component` is a trait that represents a visual and interactive element of the user interface.
 ///
@@ -87,6 +90,9 @@ pub trait Component {
   }
   /// Render the component on the screen. (REQUIRED)
   ///
+  /// Components only draw into the frame they are given, never the terminal directly, so they render the same into
+  /// a buffer from ratatui's `TestBackend`.
+  ///
   /// # Arguments
   ///
   /// * `f` - A frame used for rendering.
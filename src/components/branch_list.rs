@@ -1,360 +1,3692 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{
+  cmp::{Ordering, Reverse},
+  collections::{HashMap, HashSet},
+  time::{Duration, Instant},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::{
-  layout::{Constraint, Direction, Layout, Rect},
-  style::{Color, Modifier, Style},
-  text::Text,
+  layout::{Constraint, Direction, Layout, Position, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span, Text},
   widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
 };
-use tracing::error;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{error, warn};
 
 use crate::{
   action::Action,
+  clipboard::copy_to_clipboard,
   components::{
-    branch_list::{branch_input::BranchInput, branch_item::BranchItem, instruction_footer::InstructionFooter},
+    branch_list::{
+      ahead_behind::{AheadBehindCache, DivergenceCache},
+      bisect::Bisect,
+      branch_input::BranchInput,
+      branch_item::BranchItem,
+      bulk_rename::{BulkRename, BulkRenameOutcome},
+      commit_log::CommitLog,
+      commit_picker::{CommitPicker, PickerOutcome, PickerPurpose},
+      commit_search::{CommitSearch, SearchOutcome},
+      copy_target::{copy_text, CopyTarget},
+      fuzzy::fuzzy_match,
+      grouping::{branch_prefix, group_by_prefix},
+      history::History,
+      instruction_footer::InstructionFooter,
+      palette::{palette_candidates, Palette, PaletteAction, PaletteOutcome},
+      patch_view::PatchView,
+      pinned::pinned_first,
+      reflog_view::{ReflogOutcome, ReflogView},
+      reset_prompt::{ResetOutcome, ResetPrompt},
+      scope::{link_remotes, BranchScope},
+      ticket::{matches_ticket, TicketPattern},
+      ui_state::{UiState, UiStateStore},
+    },
+    confirm_dialog::{ConfirmDialog, ConfirmStyle, DialogOutcome, DEFAULT_BULK_DELETE_THRESHOLD},
     Component,
   },
+  config::{
+    keybindings::{KeyBindings, KeyCommand},
+    layout::{Column, ListLayout},
+    theme::Theme,
+    Config,
+  },
   error::Error,
-  git::git_repo::{GitBranch, GitRepo},
+  git::{
+    git_repo::{
+      BisectStep, CommitInfo, DeleteRisk, GitBranch, GitRemoteBranch, GitRepo, MergeOutcome, ResetMode, UpstreamStatus,
+      ARCHIVE_TAG_PREFIX,
+    },
+    wip_name::DEFAULT_WIP_PATTERN,
+  },
   tui::Frame,
 };
 
+mod ahead_behind;
+mod bisect;
 mod branch_input;
 mod branch_item;
+mod bulk_rename;
+mod commit_graph;
+mod commit_log;
+mod commit_picker;
+mod commit_search;
+mod copy_target;
+mod fuzzy;
+mod grouping;
+mod history;
 mod instruction_footer;
+mod palette;
+mod patch_view;
+mod pinned;
+mod reflog_view;
+mod reset_prompt;
+pub mod scope;
+mod search;
+mod ticket;
+pub mod ui_state;
+
+/// The log preview is only shown next to the list when there is room for both.
+const MIN_WIDTH_FOR_LOG: u16 = 100;
+
+/// How many of a branch's commits are offered for cherry-picking.
+const PICKER_LIMIT: usize = 50;
+
+/// How many lines of a diff with HEAD are shown, longer ones are cut short.
+const PATCH_LIMIT: usize = 5000;
+
+/// How many of the commits matching a message search are listed.
+const SEARCH_LIMIT: usize = 50;
+
+/// Two clicks on the same row within this time count as a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum Mode {
   Selection,
   Input,
+  Filter,
+  Search,
+}
+
+/// A line in the branch list, either a group header or a branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Row {
+  Group { prefix: String, count: usize, collapsed: bool },
+  Branch { index: usize, matched: Vec<usize> },
 }
 
 pub struct BranchList {
+  action_tx: Option<UnboundedSender<Action>>,
+  keybindings: KeyBindings,
+  theme: Theme,
+  layout: ListLayout,
   mode: Mode,
   repo: Box<dyn GitRepo>,
   error: Option<String>,
+  /// An informational message, like the result of a bulk action, shown until the next key press.
+  notice: Option<String>,
+  loading: bool,
+  /// Fuzzy query narrowing the visible branches, empty shows them all.
+  filter: String,
+  /// Whether the filter and search match names regardless of accents.
+  ignore_accents: bool,
+  /// Text the selection jumps to as it is typed, kept after the search ends so the matches can be cycled.
+  search: String,
+  /// Hides branches whose commits are all on their upstream.
+  unpushed_only: bool,
+  /// Only list the cleanup candidates, branches whose upstream is gone from every remote.
+  cleanup_only: bool,
+  /// Lists the remote branches local branches track in the all scope instead of folding them into the local rows.
+  show_tracked_remotes: bool,
+  /// Finds the ticket ids in branch names.
+  ticket_pattern: TicketPattern,
+  /// Only list the branches for this ticket.
+  ticket_filter: Option<String>,
+  /// The bisect in progress, started by marking a branch good or bad.
+  bisect: Option<Bisect>,
+  /// Whether local branches, remote branches or both are listed, remote rows are only in `branches` outside the
+  /// local scope.
+  scope: BranchScope,
+  wip_branch_pattern: String,
+  /// The configured branch new branches are based on, see [`new_branch_bases`].
+  default_base_branch: Option<String>,
+  bulk_delete_threshold: usize,
+  /// Whether the quick checkout key is enabled, it stashes changes so is opt-in.
+  quick_checkout: bool,
+  /// Whether branches are shown under collapsible headers by their prefix.
+  grouped: bool,
+  collapsed_groups: HashSet<String>,
+  /// Branch names kept at the top of the list, in the order they were pinned.
+  pinned: Vec<String>,
+  /// The group header that is selected, when set no branch is selected.
+  selected_group: Option<String>,
   // List state
   branches: Vec<BranchItem>,
+  /// Filled in for the rows on screen as they are drawn, as counting every branch up front is slow.
+  ahead_behind: AheadBehindCache,
+  /// The diffstats against the upstreams, filled in like the ahead and behind counts when the layout shows them.
+  diffstats: AheadBehindCache,
+  /// Lists the branches most behind the default branch first instead of by name.
+  behind_sort: bool,
+  /// How far each branch and the default branch have moved apart, for the behind sort and unique commit counts.
+  default_divergence: DivergenceCache,
+  /// The last commits, filled in like the ahead and behind counts when the layout shows commit ages or authors.
+  last_commits: AheadBehindCache<Option<CommitInfo>>,
+  /// The first lines of the descriptions by branch name, read as they are drawn when the layout shows them.
+  descriptions: HashMap<String, Option<String>>,
   list_state: ListState,
+  /// Where the list was last drawn, used to map mouse clicks to rows.
+  list_area: Rect,
+  /// The row and time of the last click, for detecting double clicks.
+  last_click: Option<(usize, Instant)>,
   selected_index: usize,
+  /// Resolved when the branches load, used for cleaning up merged branches and the checkout default shortcut.
+  default_branch: Option<GitBranch>,
+  /// The branches removed by the last delete and the commits they pointed at, kept until the next change so the
+  /// delete can be undone.
+  deleted: Vec<(GitBranch, String)>,
+  /// The branch last copied with [`KeyCommand::CopyRef`] and what was copied, so copying it again moves on.
+  last_copy: Option<(String, CopyTarget)>,
+  /// Where the UI state is saved on quit, nothing is remembered without one.
+  state_store: Option<UiStateStore>,
+  /// The remembered state, until the branches load and the remembered branch can be selected.
+  restored_state: Option<UiState>,
   // Components
   branch_input: BranchInput,
   instruction_footer: InstructionFooter,
+  confirm_dialog: ConfirmDialog,
+  reflog_view: ReflogView,
+  patch_view: PatchView,
+  history: History,
+  palette: Palette,
+  reset_prompt: ResetPrompt,
+  bulk_rename: BulkRename,
+  commit_picker: CommitPicker,
+  commit_search: CommitSearch,
+  commit_log: CommitLog,
 }
 
 impl BranchList {
-  pub fn new(repo: Box<dyn GitRepo>) -> Self {
-    // Assume branch names are all valid as they come from git
-    let branches: Vec<BranchItem> =
-      repo.local_branches().unwrap().iter().map(|branch| BranchItem::new(branch.clone(), true)).collect();
+  /// Creates an empty list in a loading state, the branches arrive later via [`Action::BranchesLoaded`].
+  pub fn new(repo: Box<dyn GitRepo>, config: &Config) -> Self {
+    let state = repo.repo_state();
+    let read_only = repo.is_read_only();
+    let notice = if state.is_clean() {
+      repo.superproject_path().map(|path| {
+        format!("This is a submodule of {}, start with --superproject to manage its branches", path.display())
+      })
+    } else {
+      Some(format!("A {} is in progress, {} to abort it", state, config.keybindings.label(KeyCommand::AbortOperation)))
+    };
     BranchList {
+      action_tx: None,
+      keybindings: config.keybindings.clone(),
+      theme: config.theme.clone(),
+      layout: config.list_layout.clone(),
       repo,
       mode: Mode::Selection,
       error: None,
-      branches,
+      notice,
+      loading: true,
+      filter: String::new(),
+      ignore_accents: config.match_ignores_accents,
+      search: String::new(),
+      unpushed_only: false,
+      cleanup_only: false,
+      show_tracked_remotes: config.show_tracked_remotes,
+      ticket_pattern: TicketPattern::from_config(config.ticket_pattern.as_deref()),
+      ticket_filter: None,
+      bisect: None,
+      scope: BranchScope::default(),
+      wip_branch_pattern: config.wip_branch_pattern.clone().unwrap_or_else(|| String::from(DEFAULT_WIP_PATTERN)),
+      default_base_branch: config.default_base_branch.clone(),
+      bulk_delete_threshold: config.bulk_delete_confirm_threshold.unwrap_or(DEFAULT_BULK_DELETE_THRESHOLD),
+      quick_checkout: config.quick_checkout,
+      grouped: false,
+      collapsed_groups: HashSet::new(),
+      pinned: Vec::new(),
+      selected_group: None,
+      branches: Vec::new(),
+      ahead_behind: AheadBehindCache::default(),
+      diffstats: AheadBehindCache::default(),
+      behind_sort: false,
+      default_divergence: DivergenceCache::default(),
+      last_commits: AheadBehindCache::default(),
+      descriptions: HashMap::new(),
       list_state: ListState::default(),
+      list_area: Rect::default(),
+      last_click: None,
       selected_index: 0,
-      branch_input: BranchInput::new(),
-      instruction_footer: InstructionFooter::default(),
+      default_branch: None,
+      deleted: Vec::new(),
+      last_copy: None,
+      state_store: None,
+      restored_state: None,
+      branch_input: BranchInput::new(config.theme.clone(), config.name_snippets.clone()),
+      instruction_footer: InstructionFooter::new(config.keybindings.clone(), read_only, config.quick_checkout),
+      confirm_dialog: ConfirmDialog::new(config.theme.clone()),
+      reflog_view: ReflogView::new(config.theme.clone()),
+      patch_view: PatchView::new(config.theme.clone()),
+      history: History::new(config.theme.clone()),
+      palette: Palette::new(config.theme.clone(), config.match_ignores_accents),
+      reset_prompt: ResetPrompt::new(config.theme.clone()),
+      bulk_rename: BulkRename::new(config.theme.clone()),
+      commit_picker: CommitPicker::new(config.theme.clone()),
+      commit_search: CommitSearch::new(config.theme.clone()),
+      commit_log: CommitLog::new(config.theme.clone()),
     }
   }
 
   pub fn clear_error(&mut self) {
     self.error = None;
+    self.notice = None;
   }
 
-  pub fn select_previous(&mut self) {
-    if self.selected_index == 0 {
-      self.selected_index = self.branches.len() - 1;
+  /// Restores the state remembered by the store and saves it there on quit.
+  pub fn remember_state(mut self, store: UiStateStore) -> Self {
+    let state = store.load();
+    self.grouped = state.grouped;
+    self.collapsed_groups = state.collapsed_groups.iter().cloned().collect();
+    self.pinned = state.pinned.clone();
+    self.state_store = Some(store);
+    self.restored_state = Some(state);
+    self
+  }
+
+  fn save_state(&self) {
+    let Some(store) = &self.state_store else {
       return;
+    };
+    let mut collapsed_groups: Vec<String> = self.collapsed_groups.iter().cloned().collect();
+    collapsed_groups.sort();
+    let state = UiState {
+      selected_branch: self.get_selected_branch().map(|item| item.branch.name.clone()),
+      collapsed_groups,
+      grouped: self.grouped,
+      pinned: self.pinned.clone(),
+    };
+    if let Err(err) = store.save(&state) {
+      warn!("Unable to save the UI state: {}", err);
     }
-    if self.selected_index >= self.branches.len() {
-      self.selected_index = self.branches.len() - 1;
-      return;
+  }
+
+  fn load_branches(&mut self, branches: Vec<GitBranch>) {
+    // Assume branch names are all valid as they come from git
+    self.branches = branches.into_iter().map(|branch| BranchItem::new(branch, true)).collect();
+    self.commit_log.clear();
+    self.selected_index = 0;
+    self.loading = false;
+    self.default_branch = self.repo.default_branch().unwrap_or_else(|err| {
+      warn!("Unable to resolve the default branch: {}", err);
+      None
+    });
+    if let Err(err) = self.repo.mark_refs_seen() {
+      warn!("Unable to read the refs the branches were loaded from: {}", err);
+    }
+    let default_name = self.default_branch.as_ref().map(|branch| branch.name.clone());
+    for item in self.branches.iter_mut() {
+      item.is_default = default_name.as_ref().is_some_and(|name| *name == item.branch.name);
+      item.protected = self.repo.protected_branches().matches(&item.branch.name) || item.is_default;
+      item.ticket = self.ticket_pattern.ticket(&item.branch.name);
+    }
+    if let Err(err) = self.mark_cleanup_candidates() {
+      warn!("Unable to find the branches gone from every remote: {}", err);
+    }
+    // Pins of branches deleted outside the app are forgotten
+    self.pinned.retain(|pin| self.branches.iter().any(|item| item.branch.name == *pin));
+    self.sort_branches();
+    if let Some(index) = self.restored_state.take().and_then(|state| state.selected_index(&self.branches)) {
+      self.selected_index = index;
+    }
+    if let Err(err) = self.load_remote_rows() {
+      self.maybe_handle_git_error(Some(err));
     }
-    self.selected_index -= 1;
   }
 
-  pub fn select_next(&mut self) {
-    if self.selected_index == self.branches.len() - 1 {
-      self.selected_index = 0;
-      return;
+  fn mark_cleanup_candidates(&mut self) -> Result<(), Error> {
+    // Listing the remote branches is only worth it when some upstream is gone
+    if !self.branches.iter().any(|item| item.branch.upstream_status == UpstreamStatus::Gone) {
+      return Ok(());
     }
-    if self.selected_index >= self.branches.len() {
-      self.selected_index = 0;
-      return;
+    let remote = self.repo.remote_branches()?;
+    let remotes = self.repo.remotes()?;
+    for item in self.branches.iter_mut() {
+      item.cleanup_candidate = !item.is_remote && item.branch.is_orphaned_tracking(&remote, &remotes);
     }
-    self.selected_index += 1;
+    Ok(())
   }
 
-  fn get_selected_branch(&self) -> Option<&BranchItem> {
-    self.branches.get(self.selected_index)
+  /// Replaces the remote branch rows with the current remote branches when the scope includes them, keeping the
+  /// selected row where it can.
+  fn load_remote_rows(&mut self) -> Result<(), Error> {
+    let selected = self.get_selected_branch().map(|item| (item.branch.name.clone(), item.is_remote));
+    self.branches.retain(|item| !item.is_remote);
+    if self.scope.includes_remotes() {
+      let local: Vec<GitBranch> = self.branches.iter().map(|item| item.branch.clone()).collect();
+      let remote = self.repo.remote_branches()?;
+      let linked = link_remotes(&local, &remote);
+      let collapse = self.scope.collapses_tracked(self.show_tracked_remotes);
+      let tracking: HashSet<String> = linked.iter().filter_map(|(_, tracked_by)| tracked_by.clone()).collect();
+      for item in self.branches.iter_mut() {
+        item.collapsed_remote = collapse && tracking.contains(&item.branch.name);
+      }
+      self.branches.extend(linked.into_iter().map(|(remote, tracked_by)| {
+        let ticket = self.ticket_pattern.ticket(&remote.name);
+        BranchItem { ticket, ..BranchItem::remote(remote, tracked_by) }
+      }));
+    } else {
+      for item in self.branches.iter_mut() {
+        item.collapsed_remote = false;
+      }
+    }
+    if let Some((name, is_remote)) = selected {
+      let position = self.branches.iter().position(|item| item.branch.name == name && item.is_remote == is_remote);
+      self.selected_index = position.unwrap_or(0);
+    }
+    Ok(())
   }
 
-  fn checkout_selected(&mut self) -> Result<(), Error> {
-    let maybe_selected = self.get_selected_branch();
-    if maybe_selected.is_none() {
-      return Ok(());
+  /// Re-reads the branches for changes made outside the app, keeping the selected and staged branches where they
+  /// still exist.
+  fn reload_branches(&mut self) -> Result<(), Error> {
+    let selected = self.get_selected_branch().map(|item| (item.branch.name.clone(), item.is_remote));
+    let staged: HashSet<String> =
+      self.branches.iter().filter(|item| item.staged_for_deletion).map(|item| item.branch.name.clone()).collect();
+    self.repo.refresh();
+    let branches = self.repo.local_branches()?;
+    self.load_branches(branches);
+    for item in self.branches.iter_mut() {
+      item.staged_for_deletion = !item.is_remote && !item.protected && staged.contains(&item.branch.name);
     }
-    let name_to_checkout = maybe_selected.unwrap().branch.name.clone();
-    self.repo.checkout_branch_from_name(&name_to_checkout)?;
-    for existing_branch in self.branches.iter_mut() {
-      existing_branch.branch.is_head = existing_branch.branch.name == name_to_checkout;
+    if let Some((name, is_remote)) = selected {
+      let position = self.branches.iter().position(|item| item.branch.name == name && item.is_remote == is_remote);
+      if let Some(position) = position {
+        self.selected_index = position;
+      }
+    }
+    if self.selected_group.is_some() && self.selected_row(&self.visible_rows()).is_none() {
+      self.selected_group = None;
     }
     Ok(())
   }
 
-  pub fn stage_selected_for_deletion(&mut self, stage: bool) {
-    let maybe_selected = self.branches.get_mut(self.selected_index);
-    if maybe_selected.is_none() {
-      return;
+  /// Sorts the branches by name, or by how far behind the default branch they are, with the pinned ones first. Ties
+  /// are broken by [`name_order`] so the order is the same on every refresh.
+  fn sort_branches(&mut self) {
+    let branches = std::mem::take(&mut self.branches);
+    let mut keyed: Vec<(Option<usize>, BranchItem)> = branches
+      .into_iter()
+      .map(|item| (if self.behind_sort { self.behind_default(&item) } else { None }, item))
+      .collect();
+    // Branches that can't be counted go last
+    keyed
+      .sort_by(|(a_behind, a), (b_behind, b)| Reverse(a_behind).cmp(&Reverse(b_behind)).then_with(|| name_order(a, b)));
+    let branches = keyed.into_iter().map(|(_, item)| item).collect();
+    self.branches = pinned_first(branches, &self.pinned, |item| &item.branch.name);
+    for item in self.branches.iter_mut() {
+      item.pinned = !item.is_remote && self.pinned.contains(&item.branch.name);
     }
-    let selected = maybe_selected.unwrap();
-    if selected.branch.is_head {
-      return;
+  }
+
+  /// How many commits the branch has that the default branch doesn't and the other way round, `None` for remote rows
+  /// and when there is no default branch or no shared history to count from.
+  fn default_divergence(&mut self, item: &BranchItem) -> Option<(usize, usize)> {
+    let base = self.default_branch.as_ref().filter(|_| !item.is_remote)?;
+    let (tip, base_tip) = (item.branch.commit_id.as_deref()?, base.commit_id.as_deref()?);
+    let repo = &self.repo;
+    self.default_divergence.get_or_compute(tip, base_tip, || {
+      repo.divergence(&item.branch, base).map(|divergence| (divergence.ahead, divergence.behind)).ok()
+    })
+  }
+
+  /// How many commits the default branch has that the branch doesn't, see [`BranchList::default_divergence`].
+  fn behind_default(&mut self, item: &BranchItem) -> Option<usize> {
+    self.default_divergence(item).map(|(_, behind)| behind)
+  }
+
+  fn toggle_behind_sort(&mut self) {
+    let selected = self.get_selected_branch().map(|selected| (selected.branch.name.clone(), selected.is_remote));
+    self.behind_sort = !self.behind_sort;
+    self.sort_branches();
+    if let Some((name, is_remote)) = selected {
+      self.selected_index = self
+        .branches
+        .iter()
+        .position(|item| item.is_remote == is_remote && item.branch.name == name)
+        .unwrap_or(self.selected_index);
     }
-    selected.stage_for_deletion(stage);
   }
 
-  pub fn deleted_selected(&mut self) -> Result<(), Error> {
-    let selected = self.branches.get(self.selected_index);
-    if selected.is_none() {
-      return Ok(());
+  /// Pins the selected local branch to the top of the list, or unpins it, keeping it selected.
+  fn toggle_pin(&mut self) {
+    let Some(selected) = self.get_selected_branch().filter(|selected| !selected.is_remote) else {
+      return;
+    };
+    let name = selected.branch.name.clone();
+    match self.pinned.iter().position(|pin| *pin == name) {
+      Some(position) => {
+        self.pinned.remove(position);
+      },
+      None => self.pinned.push(name.clone()),
     }
-    let delete_result = self.repo.delete_branch(&selected.unwrap().branch);
-    if delete_result.is_err() {
-      return Ok(());
+    self.sort_branches();
+    self.selected_index =
+      self.branches.iter().position(|item| !item.is_remote && item.branch.name == name).unwrap_or(0);
+  }
+
+  fn cycle_scope(&mut self) -> Option<Action> {
+    self.scope = self.scope.next();
+    if let Err(err) = self.load_remote_rows() {
+      self.maybe_handle_git_error(Some(err));
     }
-    self.branches.remove(self.selected_index);
-    if self.selected_index >= self.branches.len() {
-      self.selected_index -= 1;
+    let visible = self.visible_branches();
+    if self.selected_group.is_none() && !visible.iter().any(|(index, _)| *index == self.selected_index) {
+      self.selected_index = visible.first().map_or(self.branches.len(), |(index, _)| *index);
     }
-    Ok(())
+    Some(Action::BranchScopeChanged(self.scope))
   }
 
-  pub fn delete_staged_branches(&mut self) -> Result<(), Error> {
-    let mut indexes_to_delete: Vec<usize> = Vec::new();
+  /// The indexes into `branches` that pass the filter, in display order, with the name chars the filter matched.
+  fn visible_branches(&self) -> Vec<(usize, Vec<usize>)> {
+    let listed: Vec<usize> = (0..self.branches.len())
+      .filter(|index| {
+        let item = &self.branches[*index];
+        self.scope.shows(item, self.show_tracked_remotes)
+          && (!self.unpushed_only || (!item.is_remote && item.branch.has_unpushed_commits()))
+          && (!self.cleanup_only || item.cleanup_candidate)
+          && matches_ticket(item, self.ticket_filter.as_deref())
+      })
+      .collect();
+    fuzzy_match(&self.filter, &listed, |index| &self.branches[*index].branch.name, self.ignore_accents)
+  }
 
-    for branch_index in 0..self.branches.len() {
-      let branch_item = &self.branches[branch_index];
-      if !branch_item.staged_for_deletion {
-        continue;
+  /// The rows to display, grouping the visible branches under their prefix headers when grouping is on.
+  fn visible_rows(&self) -> Vec<Row> {
+    let visible = self.visible_branches();
+    if !self.grouped {
+      return visible.into_iter().map(|(index, matched)| Row::Branch { index, matched }).collect();
+    }
+    let mut rows = Vec::new();
+    for group in group_by_prefix(visible, |(index, _)| self.group_prefix(*index)) {
+      let collapsed = self.collapsed_groups.contains(&group.prefix);
+      rows.push(Row::Group { prefix: group.prefix, count: group.items.len(), collapsed });
+      if !collapsed {
+        rows.extend(group.items.into_iter().map(|(index, matched)| Row::Branch { index, matched }));
       }
-      let del_result = self.repo.delete_branch(&branch_item.branch);
-      if del_result.is_ok() {
-        indexes_to_delete.push(branch_index);
-      } else {
-        // TODO communicate deletion error
+    }
+    rows
+  }
+
+  /// Remote branch rows are grouped under their remote, which may itself contain a `/`, local ones by the prefix of
+  /// their name.
+  fn group_prefix(&self, index: usize) -> String {
+    let item = &self.branches[index];
+    match &item.remote {
+      Some(remote) if item.is_remote => remote.clone(),
+      _ => String::from(branch_prefix(&item.branch.name)),
+    }
+  }
+
+  fn selected_row(&self, rows: &[Row]) -> Option<usize> {
+    rows.iter().position(|row| {
+      match (row, &self.selected_group) {
+        (Row::Group { prefix, .. }, Some(selected)) => prefix == selected,
+        (Row::Branch { index, .. }, None) => *index == self.selected_index,
+        _ => false,
       }
+    })
+  }
+
+  fn select_row(&mut self, row: &Row) {
+    match row {
+      Row::Group { prefix, .. } => self.selected_group = Some(prefix.clone()),
+      Row::Branch { index, .. } => {
+        self.selected_group = None;
+        self.selected_index = *index;
+      },
     }
+  }
 
-    // Sort and reverse, so we remove branches starting from the end,
-    // which means we don't need to worry about changing array positions.
-    indexes_to_delete.reverse();
-    for index in indexes_to_delete {
-      self.branches.remove(index);
+  pub fn select_previous(&mut self) {
+    let rows = self.visible_rows();
+    if rows.is_empty() {
+      return;
     }
-    if self.selected_index >= self.branches.len() {
-      self.selected_index = self.branches.len() - 1
-    } else if self.selected_index != 0 {
-      self.selected_index -= 1
+    let previous = match self.selected_row(&rows) {
+      Some(position) if position > 0 => position - 1,
+      _ => rows.len() - 1,
+    };
+    self.select_row(&rows[previous]);
+  }
+
+  pub fn select_next(&mut self) {
+    let rows = self.visible_rows();
+    if rows.is_empty() {
+      return;
     }
-    Ok(())
+    let next = match self.selected_row(&rows) {
+      Some(position) if position + 1 < rows.len() => position + 1,
+      _ => 0,
+    };
+    self.select_row(&rows[next]);
   }
 
-  fn create_branch(&mut self, name: String) -> Result<(), Error> {
-    let branch = GitBranch { name: name.clone(), is_head: false, upstream: None };
-    self.repo.create_branch(&branch)?;
-    self.branches.push(BranchItem::new(branch, true));
-    self.branches.sort_by(|a, b| a.branch.name.cmp(&b.branch.name));
-    self.repo.checkout_branch_from_name(&name)?;
-    for existing_branch in self.branches.iter_mut() {
-      existing_branch.branch.is_head = existing_branch.branch.name == name;
+  /// How many rows fit inside the list's borders, at least one so a page always moves.
+  fn page_size(&self) -> usize {
+    usize::from(self.list_area.height.saturating_sub(2)).max(1)
+  }
+
+  /// Moves the selection a page, stopping at the ends rather than wrapping, and keeps it in the middle of the list.
+  fn select_page(&mut self, forward: bool) {
+    let rows = self.visible_rows();
+    if rows.is_empty() {
+      return;
     }
-    self.selected_index = self.branches.iter().position(|b| b.branch.name == name).unwrap_or(0);
-    Ok(())
+    let selected = self.selected_row(&rows).unwrap_or(0);
+    self.select_row(&rows[page_target(selected, rows.len(), self.page_size(), forward)]);
+    self.center_selection();
   }
 
-  fn maybe_handle_git_error(&mut self, err: Option<Error>) {
-    if err.is_some() {
-      let error = err.unwrap();
-      error!("{}", error);
-      self.error = Some(error.to_string());
+  fn select_first(&mut self) {
+    if let Some(row) = self.visible_rows().first() {
+      self.select_row(row);
     }
   }
 
-  fn render_list(&mut self, f: &mut Frame<'_>, area: Rect) {
-    // TODO don't clone, figure out the index to place the pseudo branch in the list
-    let mut branches = self.branches.clone();
-    let input_state = self.branch_input.input_state.clone();
-    if input_state.value.is_some() && self.mode == Mode::Input {
-      let content = input_state.value.unwrap();
-      branches.push(BranchItem {
-        branch: GitBranch::new(content.clone()),
-        staged_for_creation: true,
-        staged_for_deletion: false,
-        is_valid_name: self.branch_input.input_state.is_valid.unwrap_or(false),
-      });
-      branches.sort_by(|a, b| a.branch.name.cmp(&b.branch.name));
-      self.list_state.select(branches.iter().position(|bi| bi.staged_for_creation))
-    } else {
-      self.list_state.select(Some(self.selected_index));
+  fn select_last(&mut self) {
+    if let Some(row) = self.visible_rows().last() {
+      self.select_row(row);
     }
+  }
 
-    let render_items: Vec<ListItem> = branches.iter().map(|git_branch| git_branch.render()).collect();
-    let list = List::new(render_items)
-      .block(Block::default().title("Local Branches").borders(Borders::ALL))
-      .style(Style::default().fg(Color::White))
-      .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-      .highlight_symbol("→")
-      .repeat_highlight_symbol(true);
+  fn center_selection(&mut self) {
+    let rows = self.visible_rows();
+    let selected = self.selected_row(&rows).unwrap_or(0);
+    *self.list_state.offset_mut() = centered_offset(selected, rows.len(), self.page_size());
+  }
 
-    f.render_stateful_widget(list, area, &mut self.list_state);
+  fn toggle_grouping(&mut self) {
+    self.grouped = !self.grouped;
+    self.selected_group = None;
   }
 
-  fn render_error(&mut self, f: &mut Frame<'_>, area: Rect) {
-    if self.error.is_none() {
+  fn toggle_selected_group(&mut self) {
+    let Some(prefix) = self.selected_group.clone() else {
       return;
+    };
+    if !self.collapsed_groups.remove(&prefix) {
+      self.collapsed_groups.insert(prefix);
     }
-    let error_message = self.error.as_ref().unwrap().clone();
-    let text = Text::from(error_message);
-    let component = Paragraph::new(text)
-      .block(Block::bordered().title("Error"))
-      .style(Style::from(Color::Red))
-      .wrap(Wrap { trim: true });
-    f.render_widget(component, area);
   }
-}
 
-impl Component for BranchList {
-  fn handle_key_events(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
-    self.clear_error();
+  /// The branch name of each row for searching, group headers have none so they never match.
+  fn row_names<'a>(&'a self, rows: &[Row]) -> Vec<&'a str> {
+    rows
+      .iter()
+      .map(|row| {
+        match row {
+          Row::Group { .. } => "",
+          Row::Branch { index, .. } => self.branches[*index].branch.name.as_str(),
+        }
+      })
+      .collect()
+  }
 
-    if self.mode == Mode::Input {
-      return Ok(Some(Action::UpdateNewBranchName(key)));
-    }
-    match key {
-      KeyEvent { code: KeyCode::Down, modifiers: KeyModifiers::NONE, kind: _, state: _ } => {
-        Ok(Some(Action::SelectNextBranch))
-      },
-      KeyEvent { code: KeyCode::Up, modifiers: KeyModifiers::NONE, kind: _, state: _ } => {
-        Ok(Some(Action::SelectPreviousBranch))
-      },
-      KeyEvent { code: KeyCode::Char('c' | 'C'), modifiers: KeyModifiers::SHIFT, kind: _, state: _ } => {
-        Ok(Some(Action::InitNewBranch))
-      },
-      KeyEvent { code: KeyCode::Char('c' | 'C'), modifiers: KeyModifiers::NONE, kind: _, state: _ } => {
-        Ok(Some(Action::CheckoutSelectedBranch))
-      },
-      KeyEvent { code: KeyCode::Char('d' | 'D'), modifiers: KeyModifiers::SHIFT, kind: _, state: _ } => {
-        Ok(Some(Action::UnstageBranchForDeletion))
+  fn update_search(&mut self, key_event: KeyEvent) -> Option<Action> {
+    match key_event.code {
+      KeyCode::Esc => {
+        self.search.clear();
+        self.mode = Mode::Selection;
+        return Some(Action::EndInputMod);
       },
-      KeyEvent { code: KeyCode::Char('d' | 'D'), modifiers: KeyModifiers::CONTROL, kind: _, state: _ } => {
-        Ok(Some(Action::DeleteStagedBranches))
+      KeyCode::Enter => {
+        self.mode = Mode::Selection;
+        return Some(Action::EndInputMod);
       },
-      KeyEvent { code: KeyCode::Char('d' | 'D'), modifiers: KeyModifiers::NONE, kind: _, state: _ } => {
-        if self.get_selected_branch().is_none() {
-          return Ok(None);
-        }
-        let selected = self.get_selected_branch().unwrap();
-        if selected.staged_for_deletion {
-          return Ok(Some(Action::DeleteBranch));
-        }
-        Ok(Some(Action::StageBranchForDeletion))
+      KeyCode::Backspace => {
+        self.search.pop();
       },
-      _ => Ok(None),
+      KeyCode::Char(c) if !key_event.modifiers.contains(KeyModifiers::CONTROL) => self.search.push(c),
+      _ => return None,
+    }
+    if self.search.is_empty() {
+      return None;
     }
+    let rows = self.visible_rows();
+    if let Some(position) = search::first_match(&self.row_names(&rows), &self.search, self.ignore_accents) {
+      self.select_row(&rows[position]);
+    }
+    None
   }
 
-  fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
-    match action {
-      Action::SelectPreviousBranch => {
-        self.select_previous();
-        Ok(None)
-      },
-      Action::SelectNextBranch => {
-        self.select_next();
-        Ok(None)
-      },
-      Action::InitNewBranch => {
-        self.mode = Mode::Input;
-        self.branch_input.init_style();
-        Ok(Some(Action::StartInputMode))
-      },
-      Action::EndInputMod => {
+  /// Moves to the next or previous row matching the last search.
+  fn cycle_search(&mut self, forward: bool) {
+    if self.search.is_empty() {
+      return;
+    }
+    let rows = self.visible_rows();
+    let names = self.row_names(&rows);
+    let found = match self.selected_row(&rows) {
+      Some(current) => search::cycle_match(&names, &self.search, current, forward, self.ignore_accents),
+      None => search::first_match(&names, &self.search, self.ignore_accents),
+    };
+    if let Some(position) = found {
+      self.select_row(&rows[position]);
+    }
+  }
+
+  fn update_filter(&mut self, key_event: KeyEvent) -> Option<Action> {
+    match key_event.code {
+      KeyCode::Esc => {
+        self.filter.clear();
         self.mode = Mode::Selection;
-        Ok(None)
-      },
-      Action::UpdateNewBranchName(key_event) => {
-        Ok(self.branch_input.handle_key_event(
-          key_event,
-          &*self.repo,
-          self.branches.iter().map(|branch_item| &branch_item.branch).collect(),
-        ))
-      },
-      Action::CheckoutSelectedBranch => {
-        let result = self.checkout_selected();
-        self.maybe_handle_git_error(result.err());
-        Ok(None)
+        return Some(Action::EndInputMod);
       },
-      Action::CreateBranch(name) => {
+      KeyCode::Enter => {
         self.mode = Mode::Selection;
-        let result = self.create_branch(name);
-        self.maybe_handle_git_error(result.err());
-        Ok(Some(Action::EndInputMod))
-      },
-      Action::StageBranchForDeletion => {
-        self.stage_selected_for_deletion(true);
-        Ok(None)
-      },
-      Action::UnstageBranchForDeletion => {
-        self.stage_selected_for_deletion(false);
-        Ok(None)
+        return Some(Action::EndInputMod);
       },
-      Action::DeleteBranch => {
-        let result = self.deleted_selected();
-        self.maybe_handle_git_error(result.err());
-        Ok(None)
+      KeyCode::Up => return Some(Action::SelectPreviousBranch),
+      KeyCode::Down => return Some(Action::SelectNextBranch),
+      KeyCode::Backspace => {
+        self.filter.pop();
       },
-      Action::DeleteStagedBranches => {
-        let result = self.delete_staged_branches();
-        self.maybe_handle_git_error(result.err());
-        Ok(None)
+      KeyCode::Char(c) if !key_event.modifiers.contains(KeyModifiers::CONTROL) => self.filter.push(c),
+      _ => return None,
+    }
+    // Keep the selection if it still matches, otherwise move to the best match or select nothing
+    let visible = self.visible_branches();
+    if self.selected_group.is_none() && !visible.iter().any(|(index, _)| *index == self.selected_index) {
+      self.selected_index = visible.first().map_or(self.branches.len(), |(index, _)| *index);
+    }
+    None
+  }
+
+  fn get_selected_branch(&self) -> Option<&BranchItem> {
+    if self.selected_group.is_some() {
+      return None;
+    }
+    self.branches.get(self.selected_index)
+  }
+
+  fn checkout_selected(&mut self) -> Result<(), Error> {
+    let maybe_selected = self.get_selected_branch();
+    if maybe_selected.is_none() {
+      return Ok(());
+    }
+    let selected = maybe_selected.unwrap();
+    let name_to_checkout = selected.branch.name.clone();
+    if selected.is_remote {
+      return match selected.tracked_by.clone() {
+        Some(local) => self.checkout_named(&local),
+        None => self.checkout_remote(GitRemoteBranch { name: name_to_checkout, remote: selected.remote.clone() }),
+      };
+    }
+    self.checkout_named(&name_to_checkout)
+  }
+
+  /// Lists the local changes checking out the selected branch would carry over and the ones that would stop it, offering
+  /// the checkout when nothing is in the way.
+  fn preview_checkout(&mut self) -> Option<Action> {
+    let selected =
+      self.get_selected_branch().filter(|selected| !selected.is_remote && !selected.branch.name_is_lossy)?;
+    let name = selected.branch.name.clone();
+    let preview = match self.repo.checkout_preview(&selected.branch) {
+      Ok(preview) => preview,
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        return None;
       },
-      _ => Ok(None),
+    };
+    if !preview.blocking.is_empty() {
+      let mut notice = format!("Changes to {} stop checking out {}", preview.blocking.join(", "), name);
+      if !preview.carried.is_empty() {
+        notice.push_str(&format!(", the changes to {} would be carried over", preview.carried.join(", ")));
+      }
+      self.notice = Some(notice);
+      return None;
     }
+    let message = match preview.carried.is_empty() {
+      true => format!("Check out {}? There are no local changes to carry over.", name),
+      false => format!("Check out {} carrying over the changes to {}?", name, preview.carried.join(", ")),
+    };
+    self.confirm(message, Action::CheckoutBranch(name))
   }
 
-  fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
-    if self.mode == Mode::Input {
-      let layout =
-        Layout::new(Direction::Vertical, [Constraint::Fill(1), Constraint::Length(3), Constraint::Length(1)])
-          .margin(1)
-          .split(area);
-      self.render_list(f, layout[0]);
-      self.branch_input.render(f, layout[1]);
-      self.instruction_footer.render(f, layout[2], &self.branches, self.get_selected_branch());
+  fn checkout_named(&mut self, name: &str) -> Result<(), Error> {
+    self.repo.checkout_branch_from_name(name)?;
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = existing_branch.branch.name == name;
+    }
+    Ok(())
+  }
+
+  /// Checks out the selected local branch, stashing the changes on the branch left and restoring the ones left on it.
+  fn quick_checkout_selected(&mut self) -> Result<(), Error> {
+    let Some(selected) = self.get_selected_branch() else {
+      return Ok(());
+    };
+    if selected.is_remote {
+      self.notice = Some(String::from("Quick checkout only switches between local branches"));
       return Ok(());
     }
+    let branch = selected.branch.clone();
+    let result = self.repo.quick_checkout(&branch);
+    // The branch is checked out even when its stashed changes didn't apply
+    if matches!(result, Ok(_) | Err(Error::AutoStashNotApplied(..))) {
+      for existing_branch in self.branches.iter_mut() {
+        existing_branch.branch.is_head = !existing_branch.is_remote && existing_branch.branch.name == branch.name;
+      }
+    }
+    let outcome = result?;
+    self.notice = Some(match (outcome.stashed, outcome.restored) {
+      (false, false) => format!("Checked out {}", branch.name),
+      (true, false) => format!("Stashed the local changes and checked out {}", branch.name),
+      (false, true) => format!("Checked out {} and restored its stashed changes", branch.name),
+      (true, true) => format!("Stashed the local changes, checked out {} and restored its changes", branch.name),
+    });
+    Ok(())
+  }
 
-    if self.error.is_some() {
-      let err_size = self.error.clone().unwrap().lines().count() + 2;
-      let layout = Layout::new(Direction::Vertical, [
-        Constraint::Fill(1),
-        Constraint::Length(u16::try_from(err_size)?),
-        Constraint::Length(1),
-      ])
-      .margin(1)
-      .split(area);
-      self.render_list(f, layout[0]);
-      self.render_error(f, layout[1]);
-      self.instruction_footer.render(f, layout[2], &self.branches, self.get_selected_branch());
+  fn force_checkout(&mut self, name: &str) -> Result<(), Error> {
+    let Some(item) = self.branches.iter().find(|item| !item.is_remote && item.branch.name == name) else {
       return Ok(());
+    };
+    self.repo.force_checkout_branch(&item.branch)?;
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = existing_branch.branch.name == name;
+    }
+    Ok(())
+  }
+
+  /// Checks out a new local branch tracking the remote branch and selects it.
+  fn checkout_remote(&mut self, remote: GitRemoteBranch) -> Result<(), Error> {
+    let branch = self.repo.checkout_remote_branch(&remote)?;
+    let remote_name = remote.name;
+    let name = branch.name.clone();
+    let mut item = BranchItem::new(branch, true);
+    item.protected = self.repo.protected_branches().matches(&name);
+    self.branches.push(item);
+    self.sort_branches();
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = !existing_branch.is_remote && existing_branch.branch.name == name;
+      if existing_branch.is_remote && existing_branch.branch.name == remote_name {
+        existing_branch.tracked_by = Some(name.clone());
+      }
     }
+    self.selected_group = None;
+    // Stay on the remote row when local branches aren't listed
+    let (wanted_name, wanted_remote) =
+      if self.scope == BranchScope::Remote { (&remote_name, true) } else { (&name, false) };
+    self.selected_index =
+      self.branches.iter().position(|b| b.is_remote == wanted_remote && b.branch.name == *wanted_name).unwrap_or(0);
+    Ok(())
+  }
 
-    let layout = Layout::new(Direction::Vertical, [Constraint::Fill(1), Constraint::Length(1)]).margin(1).split(area);
-    self.render_list(f, layout[0]);
-    self.instruction_footer.render(f, layout[1], &self.branches, self.get_selected_branch());
+  fn rename_selected(&mut self, new_name: String) -> Result<(), Error> {
+    let Some(selected) = self.get_selected_branch() else {
+      return Ok(());
+    };
+    self.repo.rename_branch(&selected.branch, &new_name)?;
+    let old_name = selected.branch.name.clone();
+    if let Some(pin) = self.pinned.iter_mut().find(|pin| **pin == old_name) {
+      *pin = new_name.clone();
+    }
+    self.branches[self.selected_index].branch.name = new_name.clone();
+    self.sort_branches();
+    self.selected_index = self.branches.iter().position(|b| b.branch.name == new_name).unwrap_or(0);
+    self.commit_log.clear();
+    Ok(())
+  }
 
+  fn set_description(&mut self, name: &str, description: Option<&str>) -> Result<(), Error> {
+    let Some(item) = self.branches.iter().find(|item| !item.is_remote && item.branch.name == name) else {
+      return Ok(());
+    };
+    self.repo.set_branch_description(&item.branch, description)?;
+    self.commit_log.forget_description(name);
+    self.descriptions.remove(name);
     Ok(())
   }
+
+  /// Asks whether to rename the upstream on the remote too when the branch `old_name` was renamed to `new_name` and
+  /// its upstream is still under the old name.
+  fn rename_upstream_question(&self, old_name: &str, new_name: &str) -> Option<String> {
+    let item = self.branches.iter().find(|item| !item.is_remote && item.branch.name == new_name)?;
+    let upstream = item.branch.upstream.as_ref().filter(|_| item.branch.upstream_status == UpstreamStatus::Tracked)?;
+    let remotes = self.repo.remotes().unwrap_or_default();
+    let upstream = GitRemoteBranch::on_remote(upstream.name.clone(), &remotes);
+    let (remote_name, branch_name) = upstream.split()?;
+    (branch_name == old_name)
+      .then(|| format!("Also rename {} to {}/{} on the remote?", upstream.name, remote_name, new_name))
+  }
+
+  /// After checking out the named branch, offers to track the remote branch called the same when it has no upstream.
+  fn track_question(&mut self, name: &str) -> Option<Action> {
+    let item = self.branches.iter().find(|item| !item.is_remote && item.branch.is_head && item.branch.name == name)?;
+    let remote = match self.repo.suggest_upstream(&item.branch) {
+      Ok(remote) => remote?,
+      Err(err) => {
+        warn!("Unable to look for an upstream for {}: {}", name, err);
+        return None;
+      },
+    };
+    let message = format!("{} has no upstream, track {}?", name, remote.name);
+    self.confirm(message, Action::SetBranchUpstream(String::from(name), remote))
+  }
+
+  fn set_branch_upstream(&mut self, name: &str, remote: &GitRemoteBranch) -> Result<(), Error> {
+    let Some(item) = self.branches.iter().find(|item| !item.is_remote && item.branch.name == name) else {
+      return Ok(());
+    };
+    self.repo.set_upstream(&item.branch, Some(remote))?;
+    self.notice = Some(format!("{} now tracks {}", name, remote.name));
+    // Reloaded for the ahead and behind counts against the new upstream
+    self.reload_branches()
+  }
+
+  fn rename_upstream(&mut self, name: &str) -> Result<(), Error> {
+    let Some(index) = self.branches.iter().position(|item| !item.is_remote && item.branch.name == name) else {
+      return Ok(());
+    };
+    let renamed = self.repo.rename_upstream(&self.branches[index].branch)?;
+    self.notice = Some(format!("Renamed the upstream to {}", renamed.name));
+    self.branches[index].branch.upstream = Some(renamed);
+    self.commit_log.clear();
+    self.load_remote_rows()
+  }
+
+  pub fn stage_selected_for_deletion(&mut self, stage: bool) {
+    if self.selected_group.is_some() {
+      return;
+    }
+    let maybe_selected = self.branches.get_mut(self.selected_index);
+    if maybe_selected.is_none() {
+      return;
+    }
+    let selected = maybe_selected.unwrap();
+    if selected.branch.is_head || selected.branch.name_is_lossy || selected.is_remote {
+      return;
+    }
+    if stage && selected.protected {
+      self.error = Some(Error::ProtectedBranch(selected.branch.name.clone()).to_string());
+      return;
+    }
+    selected.stage_for_deletion(stage);
+  }
+
+  pub fn deleted_selected(&mut self) -> Result<(), Error> {
+    let Some(selected) = self.get_selected_branch() else {
+      return Ok(());
+    };
+    let branch = selected.branch.clone();
+    let commit_id = self.repo.delete_branch(&branch)?;
+    self.notice = Some(format!("Deleted {}, {} to undo", branch.name, self.keybindings.label(KeyCommand::Undo)));
+    self.deleted = vec![(branch, commit_id)];
+    self.branches.remove(self.selected_index);
+    if self.selected_index >= self.branches.len() {
+      self.selected_index = self.branches.len().saturating_sub(1);
+    }
+    Ok(())
+  }
+
+  pub fn force_delete_selected(&mut self) -> Result<(), Error> {
+    let Some(selected) = self.get_selected_branch() else {
+      return Ok(());
+    };
+    let branch = selected.branch.clone();
+    let commit_id = self.repo.force_delete_branch(&branch)?;
+    self.notice = Some(format!("Deleted {}, {} to undo", branch.name, self.keybindings.label(KeyCommand::Undo)));
+    self.deleted = vec![(branch, commit_id)];
+    self.branches.remove(self.selected_index);
+    if self.selected_index >= self.branches.len() {
+      self.selected_index = self.branches.len().saturating_sub(1);
+    }
+    Ok(())
+  }
+
+  /// Replaces the selected branch with an archive tag, which isn't undone like a delete as the tag keeps the commits.
+  fn archive_selected(&mut self) -> Result<(), Error> {
+    let Some(selected) = self.get_selected_branch() else {
+      return Ok(());
+    };
+    let branch = selected.branch.clone();
+    self.repo.archive_branch(&branch)?;
+    self.notice = Some(format!("Archived {} as the tag {}{}", branch.name, ARCHIVE_TAG_PREFIX, branch.name));
+    self.branches.remove(self.selected_index);
+    if self.selected_index >= self.branches.len() {
+      self.selected_index = self.branches.len().saturating_sub(1);
+    }
+    Ok(())
+  }
+
+  fn copy_selected_name(&mut self) {
+    let Some(selected) = self.get_selected_branch() else {
+      return;
+    };
+    let name = selected.branch.name.clone();
+    match copy_to_clipboard(&name) {
+      Ok(()) => self.notice = Some(format!("Copied {}", name)),
+      Err(err) => self.maybe_handle_git_error(Some(err)),
+    }
+  }
+
+  /// Copies the selected branch's upstream ref, or its remote's URL or its name when copying the same branch again.
+  fn copy_selected_ref(&mut self) {
+    let Some(selected) = self.get_selected_branch() else {
+      return;
+    };
+    let name = selected.branch.name.clone();
+    // A remote row is its own upstream
+    let upstream = match selected.is_remote {
+      true => Some(GitRemoteBranch { name: name.clone(), remote: selected.remote.clone() }),
+      false => selected.branch.upstream.clone(),
+    };
+    let target = match &self.last_copy {
+      Some((last, target)) if *last == name => target.next(),
+      _ => CopyTarget::default(),
+    };
+    let url = match (&upstream, target) {
+      (Some(upstream), CopyTarget::RemoteUrl) => {
+        match self.upstream_url(upstream) {
+          Ok(url) => url,
+          Err(err) => {
+            self.maybe_handle_git_error(Some(err));
+            return;
+          },
+        }
+      },
+      _ => None,
+    };
+    let (copied, text) =
+      copy_text(target, &name, upstream.as_ref().map(|upstream| upstream.name.as_str()), url.as_deref());
+    match copy_to_clipboard(&text) {
+      Ok(()) => {
+        self.notice = Some(format!("Copied {}", text));
+        self.last_copy = Some((name, copied));
+      },
+      Err(err) => self.maybe_handle_git_error(Some(err)),
+    }
+  }
+
+  fn upstream_url(&self, upstream: &GitRemoteBranch) -> Result<Option<String>, Error> {
+    let upstream = match upstream.remote {
+      Some(_) => upstream.clone(),
+      None => GitRemoteBranch::on_remote(upstream.name.clone(), &self.repo.remotes()?),
+    };
+    match upstream.split() {
+      Some((remote, _)) => self.repo.remote_url(remote),
+      None => Ok(None),
+    }
+  }
+
+  /// Asks whether to `verb` the branch, warning about the commits it would orphan. `None` when a worktree has the
+  /// branch checked out, git refuses to delete it then.
+  fn delete_question(&mut self, verb: &str, branch: &GitBranch) -> Option<String> {
+    let risk = match self.repo.delete_risk(branch) {
+      Ok(risk) => risk,
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        return None;
+      },
+    };
+    match risk {
+      DeleteRisk::Safe => Some(format!("{} {}?", verb, branch.name)),
+      DeleteRisk::UnmergedCommits(count) => {
+        Some(format!(
+          "{} {}? {} commit{} only on it will be left in the reflog.",
+          verb,
+          branch.name,
+          count,
+          if count == 1 { "" } else { "s" }
+        ))
+      },
+      DeleteRisk::CheckedOutElsewhere(path) => {
+        self.error = Some(Error::CheckedOutInWorktree(branch.name.clone(), path.display().to_string()).to_string());
+        None
+      },
+    }
+  }
+
+  /// Asks the user to confirm `action` before it is sent, input is captured by the dialog until it is answered.
+  fn confirm(&mut self, message: String, action: Action) -> Option<Action> {
+    self.confirm_dialog.open(message, action);
+    Some(Action::StartInputMode)
+  }
+
+  /// Like [`BranchList::confirm`] for deleting `count` branches, which has to be typed out above the threshold.
+  fn confirm_bulk_delete(&mut self, count: usize, message: String, action: Action) -> Option<Action> {
+    let style = ConfirmStyle::for_count(count, self.bulk_delete_threshold);
+    self.confirm_dialog.open_with_style(message, action, style);
+    Some(Action::StartInputMode)
+  }
+
+  fn handle_confirm_key(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    match self.confirm_dialog.handle_key_event(key) {
+      DialogOutcome::Pending => Ok(None),
+      DialogOutcome::Confirmed(action) => {
+        if let Some(tx) = &self.action_tx {
+          tx.send(Action::EndInputMod)?;
+        }
+        Ok(Some(action))
+      },
+      DialogOutcome::Cancelled => Ok(Some(Action::EndInputMod)),
+    }
+  }
+
+  fn open_reflog(&mut self) -> Option<Action> {
+    match self.repo.reflog_entries("HEAD") {
+      Ok(entries) if entries.is_empty() => {
+        self.notice = Some(String::from("The reflog is empty"));
+        None
+      },
+      Ok(entries) => {
+        self.reflog_view.open(entries);
+        Some(Action::StartInputMode)
+      },
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        None
+      },
+    }
+  }
+
+  fn open_patch_view(&mut self) -> Option<Action> {
+    let selected =
+      self.get_selected_branch().filter(|selected| !selected.is_remote && !selected.branch.name_is_lossy)?;
+    let branch = selected.branch.clone();
+    match self.repo.diff_with_head(&branch, PATCH_LIMIT) {
+      Ok(patch) => {
+        self.patch_view.open(branch.name, patch);
+        Some(Action::StartInputMode)
+      },
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        None
+      },
+    }
+  }
+
+  /// The history key closes the history as well as esc.
+  fn handle_history_key(&mut self, key: KeyEvent) -> Option<Action> {
+    if self.keybindings.command_for(&key) == Some(KeyCommand::History) {
+      self.history.close();
+      return Some(Action::EndInputMod);
+    }
+    match self.history.handle_key_event(key) {
+      true => None,
+      false => Some(Action::EndInputMod),
+    }
+  }
+
+  fn handle_reflog_key(&mut self, key: KeyEvent) -> Option<Action> {
+    match self.reflog_view.handle_key_event(key) {
+      ReflogOutcome::Pending => None,
+      ReflogOutcome::Selected(entry) => {
+        // Still capturing input, now for the name of the branch
+        self.mode = Mode::Input;
+        self.branch_input.start_create_at(&entry.commit_id, &entry.short_id);
+        None
+      },
+      ReflogOutcome::Closed => Some(Action::EndInputMod),
+    }
+  }
+
+  fn open_palette(&mut self) -> Option<Action> {
+    let remote_branches = match self.repo.remote_branches() {
+      Ok(remote_branches) => remote_branches,
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        return None;
+      },
+    };
+    let local_branches: Vec<GitBranch> =
+      self.branches.iter().filter(|item| !item.is_remote).map(|item| item.branch.clone()).collect();
+    self.palette.open(palette_candidates(&local_branches, &remote_branches));
+    Some(Action::StartInputMode)
+  }
+
+  /// Looks up a query that could be a commit hash, offering the branch whose tip it is rather than a detached checkout.
+  fn resolve_palette_hash(&mut self) {
+    let Some(hash) = self.palette.unresolved_hash().map(String::from) else {
+      return;
+    };
+    let commit = match self.repo.resolve_commit(&hash) {
+      Ok(Some(commit_id)) => {
+        let tip = self.branches.iter().find(|item| {
+          !item.is_remote && !item.branch.name_is_lossy && item.branch.commit_id.as_deref() == Some(commit_id.as_str())
+        });
+        let action = match tip {
+          Some(item) => PaletteAction::Switch(item.branch.clone()),
+          None => {
+            let short_id = self.repo.short_id(&commit_id).unwrap_or_else(|_| hash.clone());
+            PaletteAction::Detach { commit_id, short_id }
+          },
+        };
+        Ok(Some(action))
+      },
+      Ok(None) => Ok(None),
+      Err(err) => Err(err.to_string()),
+    };
+    self.palette.set_commit(commit);
+  }
+
+  fn handle_palette_key(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    let action = match self.palette.handle_key_event(key) {
+      PaletteOutcome::Pending => {
+        self.resolve_palette_hash();
+        return Ok(None);
+      },
+      PaletteOutcome::Closed => return Ok(Some(Action::EndInputMod)),
+      PaletteOutcome::Selected(PaletteAction::Switch(branch)) => Action::CheckoutBranch(branch.name),
+      PaletteOutcome::Selected(PaletteAction::CreateTracking(remote)) => Action::CheckoutRemoteBranch(remote),
+      PaletteOutcome::Selected(PaletteAction::Detach { commit_id, .. }) => Action::CheckoutCommit(commit_id),
+    };
+    if let Some(tx) = &self.action_tx {
+      tx.send(Action::EndInputMod)?;
+    }
+    Ok(Some(action))
+  }
+
+  /// Lists the selected branch's commits to pick one from, cherry-picking needs a branch other than the current one.
+  fn open_commit_picker(&mut self, purpose: PickerPurpose) -> Option<Action> {
+    let selected = self.get_selected_branch().filter(|selected| {
+      !selected.branch.name_is_lossy && !(purpose == PickerPurpose::CherryPick && selected.branch.is_head)
+    })?;
+    let branch = selected.branch.clone();
+    match self.repo.branch_graph(&branch, PICKER_LIMIT) {
+      Ok(commits) => {
+        self.commit_picker.open(&branch.name, commits, purpose);
+        Some(Action::StartInputMode)
+      },
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        None
+      },
+    }
+  }
+
+  fn handle_picker_key(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    let action = match self.commit_picker.handle_key_event(key) {
+      PickerOutcome::Pending => return Ok(None),
+      PickerOutcome::Closed => return Ok(Some(Action::EndInputMod)),
+      PickerOutcome::Selected(commit, PickerPurpose::CherryPick) => Action::CherryPickCommit(commit.id),
+      PickerOutcome::Selected(commit, PickerPurpose::Branch) => {
+        // Still capturing input, now for the name of the branch
+        self.mode = Mode::Input;
+        self.branch_input.start_create_at(&commit.id, &commit.short_id);
+        return Ok(None);
+      },
+    };
+    if let Some(tx) = &self.action_tx {
+      tx.send(Action::EndInputMod)?;
+    }
+    Ok(Some(action))
+  }
+
+  fn handle_commit_search_key(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    let action = match self.commit_search.handle_key_event(key) {
+      SearchOutcome::Pending => return Ok(None),
+      SearchOutcome::Closed => return Ok(Some(Action::EndInputMod)),
+      SearchOutcome::Search(query) => {
+        match self.repo.search_commits(&query, SEARCH_LIMIT) {
+          Ok(results) => self.commit_search.show_results(results),
+          Err(err) => self.maybe_handle_git_error(Some(err)),
+        }
+        return Ok(None);
+      },
+      SearchOutcome::Selected(commit) => Action::FindCommitBranches(commit.id),
+    };
+    if let Some(tx) = &self.action_tx {
+      tx.send(Action::EndInputMod)?;
+    }
+    Ok(Some(action))
+  }
+
+  fn open_reset(&mut self) -> Option<Action> {
+    let branch = match self.repo.current_branch() {
+      Ok(branch) => branch.map_or_else(|| String::from("HEAD"), |branch| branch.name),
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        return None;
+      },
+    };
+    self.reset_prompt.open(&branch);
+    Some(Action::StartInputMode)
+  }
+
+  /// A hard reset throws away local changes, so it has to be confirmed by typing before it is sent.
+  fn handle_reset_key(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    let (target, mode) = match self.reset_prompt.handle_key_event(key) {
+      ResetOutcome::Pending => return Ok(None),
+      ResetOutcome::Closed => return Ok(Some(Action::EndInputMod)),
+      ResetOutcome::Submitted(target, mode) => (target, mode),
+    };
+    if mode == ResetMode::Hard {
+      let message = format!("Hard reset to {}? Uncommitted changes will be lost.", target);
+      let action = Action::ResetCurrentBranch(target, mode);
+      self.confirm_dialog.open_with_style(message, action, ConfirmStyle::TypedWord("RESET"));
+      return Ok(None);
+    }
+    if let Some(tx) = &self.action_tx {
+      tx.send(Action::EndInputMod)?;
+    }
+    Ok(Some(Action::ResetCurrentBranch(target, mode)))
+  }
+
+  fn open_bulk_rename(&mut self) -> Option<Action> {
+    let names = self
+      .branches
+      .iter()
+      .filter(|item| !item.is_remote && !item.branch.name_is_lossy)
+      .map(|item| item.branch.name.clone())
+      .collect();
+    self.bulk_rename.open(names);
+    Some(Action::StartInputMode)
+  }
+
+  fn handle_bulk_rename_key(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    let renames = match self.bulk_rename.handle_key_event(key) {
+      BulkRenameOutcome::Pending => return Ok(None),
+      BulkRenameOutcome::Closed => return Ok(Some(Action::EndInputMod)),
+      BulkRenameOutcome::Submitted(renames) => renames,
+    };
+    if let Some(tx) = &self.action_tx {
+      tx.send(Action::EndInputMod)?;
+    }
+    Ok(Some(Action::RenameBranches(renames)))
+  }
+
+  /// Renames each branch in turn, so one failing, e.g. as the new name is taken, leaves the others renamed.
+  fn rename_branches(&mut self, renames: &[(String, String)]) -> Result<(), Error> {
+    let mut renamed = 0;
+    let mut failures: Vec<String> = Vec::new();
+    for (old_name, new_name) in renames {
+      let Some(index) = self.branches.iter().position(|item| !item.is_remote && item.branch.name == *old_name) else {
+        failures.push(format!("{}: no longer exists", old_name));
+        continue;
+      };
+      match self.repo.rename_branch(&self.branches[index].branch, new_name) {
+        Ok(()) => {
+          renamed += 1;
+          self.branches[index].branch.name = new_name.clone();
+          if let Some(pin) = self.pinned.iter_mut().find(|pin| *pin == old_name) {
+            *pin = new_name.clone();
+          }
+        },
+        Err(err) => {
+          error!("Failed to rename {} to {}: {}", old_name, new_name, err);
+          failures.push(format!("{}: {}", old_name, err.to_string().trim()));
+        },
+      }
+    }
+    if failures.is_empty() {
+      self.notice = Some(format!("Renamed {}", renamed));
+    } else {
+      self.error = Some(format!("Renamed {}, failed {}\n{}", renamed, failures.len(), failures.join("\n")));
+    }
+    self.commit_log.clear();
+    // Reloaded as the renamed branches move in the list and the selection should follow them
+    self.reload_branches()
+  }
+
+  fn create_branch_at(&mut self, name: String, commit_id: &str) -> Result<(), Error> {
+    let short_id = self.repo.short_id(commit_id)?;
+    let created = self.repo.create_branch_at(&name, commit_id)?;
+    self.branches.push(BranchItem::new(created, true));
+    self.sort_branches();
+    self.selected_group = None;
+    self.selected_index = self.branches.iter().position(|b| b.branch.name == name).unwrap_or(0);
+    self.notice = Some(format!("Created {} at {}", name, short_id));
+    Ok(())
+  }
+
+  /// The branch merged branches are measured against, the default branch when there is one, otherwise HEAD.
+  fn merge_target(&self) -> Option<GitBranch> {
+    self
+      .default_branch
+      .clone()
+      .or_else(|| self.branches.iter().find(|item| item.branch.is_head).map(|item| item.branch.clone()))
+  }
+
+  fn checkout_upstream(&mut self) -> Result<(), Error> {
+    let Some(current) = self.repo.current_branch()? else {
+      return Err(Error::DetachedHead);
+    };
+    self.repo.checkout_upstream(&current)?;
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = false;
+    }
+    if let Some(upstream) = current.upstream {
+      self.notice = Some(format!("Checked out {} detached", upstream.name));
+    }
+    Ok(())
+  }
+
+  fn checkout_commit(&mut self, commit_id: &str, short_id: &str) -> Result<(), Error> {
+    self.repo.checkout_detached(commit_id)?;
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = false;
+    }
+    self.notice = Some(format!("Checked out {} detached", short_id));
+    Ok(())
+  }
+
+  /// Marks the commit being bisected, or the selected branch's tip before bisecting has started, as good or bad, then
+  /// checks out the next commit to test. Finding the first bad commit ends the bisect.
+  fn mark_bisect(&mut self, good: bool) -> Result<(), Error> {
+    let verdict = if good { "good" } else { "bad" };
+    let (commit_id, marked) = match self.bisect.as_mut().and_then(|bisect| bisect.testing.take()) {
+      Some(commit_id) => {
+        let short_id = self.repo.short_id(&commit_id)?;
+        (commit_id, short_id)
+      },
+      None => {
+        let Some(selected) = self.get_selected_branch().filter(|selected| !selected.is_remote) else {
+          return Err(Error::Git(String::from("Select a local branch to mark it while bisecting")));
+        };
+        let Some(commit_id) = selected.branch.commit_id.clone() else {
+          return Err(Error::UnbornBranch(selected.branch.name.clone()));
+        };
+        (commit_id, selected.branch.name.clone())
+      },
+    };
+    if self.bisect.is_none() {
+      let return_to = match self.repo.current_branch()? {
+        Some(current) => current.name,
+        None => self.repo.head_short_id()?.ok_or(Error::NoCommits)?,
+      };
+      self.bisect = Some(Bisect::new(return_to));
+    }
+    let Some(bisect) = self.bisect.as_mut() else {
+      return Ok(());
+    };
+    bisect.mark(commit_id, good);
+    let Some(bad) = bisect.range().map(String::from) else {
+      let other = if good { "bad" } else { "good" };
+      self.notice = Some(format!("Marked {} {}, mark a {} branch to start bisecting", marked, verdict, other));
+      return Ok(());
+    };
+    match self.repo.bisect_next(&bisect.good, &bad)? {
+      BisectStep::Test { commit_id, remaining } => {
+        self.repo.checkout_detached(&commit_id)?;
+        for existing_branch in self.branches.iter_mut() {
+          existing_branch.branch.is_head = false;
+        }
+        let short_id = self.repo.short_id(&commit_id)?;
+        bisect.testing = Some(commit_id);
+        self.notice =
+          Some(format!("Marked {} {}, testing {} with {} commits left", marked, verdict, short_id, remaining));
+      },
+      BisectStep::Found(commit_id) => {
+        let short_id = self.repo.short_id(&commit_id)?;
+        self.stop_bisect()?;
+        self.notice = Some(format!("{} is the first bad commit", short_id));
+      },
+    }
+    Ok(())
+  }
+
+  /// Ends the bisect, checking out what was checked out when it started. The bisect carries on when that fails, so it
+  /// can be stopped again once whatever was in the way is fixed.
+  fn stop_bisect(&mut self) -> Result<(), Error> {
+    let Some(bisect) = self.bisect.take() else {
+      return Ok(());
+    };
+    let result = match self.repo.branch_exists(&bisect.return_to) {
+      true => self.checkout_named(&bisect.return_to),
+      false => self.repo.checkout_detached(&bisect.return_to),
+    };
+    if let Err(err) = result {
+      self.bisect = Some(bisect);
+      return Err(err);
+    }
+    self.notice = Some(format!("Stopped bisecting, back on {}", bisect.return_to));
+    Ok(())
+  }
+
+  fn checkout_previous(&mut self) -> Result<(), Error> {
+    match self.repo.checkout_previous() {
+      // Nothing to go back to yet, not worth reporting
+      Err(Error::NoPreviousBranch) => return Ok(()),
+      result => result?,
+    }
+    let current = self.repo.current_branch()?.map(|branch| branch.name);
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = current.as_ref() == Some(&existing_branch.branch.name);
+    }
+    if let Some(index) = self.branches.iter().position(|item| item.branch.is_head) {
+      self.selected_group = None;
+      self.selected_index = index;
+    }
+    Ok(())
+  }
+
+  /// Aborts the merge, rebase or other operation in progress and marks the branch HEAD is back on.
+  fn abort_operation(&mut self) -> Result<(), Error> {
+    let state = self.repo.repo_state();
+    self.repo.abort_operation()?;
+    let current = self.repo.current_branch()?.map(|branch| branch.name);
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head =
+        !existing_branch.is_remote && current.as_ref() == Some(&existing_branch.branch.name);
+    }
+    self.notice = Some(format!("Aborted the {}", state));
+    Ok(())
+  }
+
+  fn cherry_pick(&mut self, commit_id: &str) -> Result<(), Error> {
+    let short_id = self.repo.short_id(commit_id)?;
+    let outcome = self.repo.cherry_pick(commit_id)?;
+    self.reload_branches()?;
+    self.notice = Some(match outcome {
+      MergeOutcome::Conflicts(paths) => {
+        format!(
+          "Cherry-picking {} stopped with conflicts in {}, {} to abort",
+          short_id,
+          paths.join(", "),
+          self.keybindings.label(KeyCommand::AbortOperation)
+        )
+      },
+      MergeOutcome::UpToDate => format!("The current branch already has the changes of {}", short_id),
+      MergeOutcome::Merged | MergeOutcome::FastForwarded => format!("Cherry-picked {}", short_id),
+    });
+    Ok(())
+  }
+
+  /// Selects the first branch containing the commit and lists all of them in the notice.
+  fn find_commit_branches(&mut self, commit_id: &str) -> Result<(), Error> {
+    let short_id = self.repo.short_id(commit_id)?;
+    let containing = self.repo.branches_containing(commit_id)?;
+    if containing.is_empty() {
+      self.notice = Some(format!("No local branch contains {}", short_id));
+      return Ok(());
+    }
+    let found = self
+      .branches
+      .iter()
+      .position(|item| !item.is_remote && containing.iter().any(|branch| branch.name == item.branch.name));
+    if let Some(index) = found {
+      self.selected_group = None;
+      self.selected_index = index;
+    }
+    let names: Vec<&str> = containing.iter().map(|branch| branch.name.as_str()).collect();
+    self.notice = Some(format!("{} is on {}", short_id, names.join(", ")));
+    Ok(())
+  }
+
+  fn reset_current(&mut self, target: &str, mode: ResetMode) -> Result<(), Error> {
+    self.repo.reset_current(target, mode)?;
+    self.reload_branches()?;
+    let branch = self.repo.current_branch()?.map_or_else(|| String::from("HEAD"), |branch| branch.name);
+    self.notice = Some(format!("Reset ({}) {} to {}", mode, branch, target));
+    Ok(())
+  }
+
+  /// Says which branches the fetch left behind their upstreams and how to fast-forward them.
+  fn report_fast_forwardable(&mut self) {
+    match self.repo.fast_forwardable() {
+      Ok(branches) if branches.is_empty() => {},
+      Ok(branches) => {
+        let names: Vec<String> = branches.into_iter().map(|branch| branch.name).collect();
+        let key = self.keybindings.label(KeyCommand::FastForwardAll);
+        self.notice = Some(format!("{} can be fast-forwarded, {} to do it", names.join(", "), key));
+      },
+      Err(err) => warn!("Unable to find the branches that can be fast-forwarded: {}", err),
+    }
+  }
+
+  /// Asks to fast-forward every branch behind its upstream, the checked out one is left out when it has local changes.
+  fn confirm_fast_forward(&mut self) -> Option<Action> {
+    let branches = match self.repo.fast_forwardable() {
+      Ok(branches) => branches,
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        return None;
+      },
+    };
+    if branches.is_empty() {
+      self.notice = Some(String::from("No branches are behind their upstreams without commits of their own"));
+      return None;
+    }
+    let names: Vec<String> = branches.into_iter().map(|branch| branch.name).collect();
+    let message = format!("Fast-forward {} to their upstreams?", names.join(", "));
+    self.confirm(message, Action::FastForwardBranches)
+  }
+
+  fn fast_forward_all(&mut self) -> Result<(), Error> {
+    let dirty = self.repo.is_working_tree_dirty()?;
+    let mut moved: Vec<String> = Vec::new();
+    let mut skipped = None;
+    let mut failures: Vec<String> = Vec::new();
+    for branch in self.repo.fast_forwardable()? {
+      if branch.is_head && dirty {
+        skipped = Some(branch.name);
+        continue;
+      }
+      match self.repo.fast_forward_branch(&branch) {
+        Ok(()) => moved.push(branch.name),
+        Err(err) => {
+          error!("Failed to fast-forward {}: {}", branch.name, err);
+          failures.push(format!("{}: {}", branch.name, err.to_string().trim()));
+        },
+      }
+    }
+    self.reload_branches()?;
+    let mut notice = match moved.is_empty() {
+      true => String::from("Fast-forwarded nothing"),
+      false => format!("Fast-forwarded {}", moved.join(", ")),
+    };
+    if let Some(skipped) = skipped {
+      notice.push_str(&format!(", skipped {} which has local changes", skipped));
+    }
+    if failures.is_empty() {
+      self.notice = Some(notice);
+    } else {
+      self.error = Some(format!("{}, failed {}\n{}", notice, failures.len(), failures.join("\n")));
+    }
+    Ok(())
+  }
+
+  fn checkout_default(&mut self) -> Result<(), Error> {
+    let Some(default_branch) = &self.default_branch else {
+      self.notice =
+        Some(String::from("No default branch, the default remote has no HEAD and there is no main or master"));
+      return Ok(());
+    };
+    let Some(index) = self.branches.iter().position(|item| item.branch.name == default_branch.name) else {
+      return Ok(());
+    };
+    self.selected_group = None;
+    self.selected_index = index;
+    self.checkout_selected()
+  }
+
+  /// Stages every branch merged into the merge target and asks to delete the staged branches.
+  fn stage_merged_branches(&mut self) -> Result<Option<Action>, Error> {
+    let Some(target) = self.merge_target() else {
+      return Ok(None);
+    };
+    let merged = self.repo.merged_branches(&target)?;
+    if merged.is_empty() {
+      self.notice = Some(format!("No branches are merged into {}", target.name));
+      return Ok(None);
+    }
+    for item in self.branches.iter_mut() {
+      let stageable = !item.branch.name_is_lossy && !item.protected && !item.is_remote;
+      if stageable && merged.iter().any(|branch| branch.name == item.branch.name) {
+        item.stage_for_deletion(true);
+      }
+    }
+    let staged = self.branches.iter().filter(|item| item.staged_for_deletion).count();
+    if staged == 0 {
+      self.notice = Some(format!("The branches merged into {} are all protected", target.name));
+      return Ok(None);
+    }
+    let message = format!("Delete {} staged branches? {} are merged into {}.", staged, merged.len(), target.name);
+    Ok(self.confirm_bulk_delete(staged, message, Action::DeleteStagedBranches))
+  }
+
+  pub fn delete_staged_branches(&mut self) {
+    let staged: Vec<GitBranch> =
+      self.branches.iter().filter(|item| item.staged_for_deletion).map(|item| item.branch.clone()).collect();
+    if staged.is_empty() {
+      return;
+    }
+
+    let mut deleted: Vec<(GitBranch, String)> = Vec::new();
+    let mut failures: Vec<String> = Vec::new();
+    for (branch, result) in self.repo.delete_branches(&staged) {
+      match result {
+        Ok(commit_id) => deleted.push((branch, commit_id)),
+        Err(err) => {
+          error!("Failed to delete {}: {}", branch.name, err);
+          failures.push(format!("{}: {}", branch.name, err.to_string().trim()));
+        },
+      }
+    }
+
+    self.branches.retain(|item| !deleted.iter().any(|(branch, _)| branch.name == item.branch.name));
+    if self.selected_index >= self.branches.len() {
+      self.selected_index = self.branches.len().saturating_sub(1);
+    }
+    if failures.is_empty() {
+      self.notice = Some(format!("Deleted {}, {} to undo", deleted.len(), self.keybindings.label(KeyCommand::Undo)));
+    } else {
+      self.error = Some(format!("Deleted {}, failed {}\n{}", deleted.len(), failures.len(), failures.join("\n")));
+    }
+    self.deleted = deleted;
+  }
+
+  /// Asks to delete the branches whose upstream is gone from every remote now that the fetch has pruned them. The
+  /// default and protected branches are left out as they are in the list.
+  fn confirm_clean_up_gone(&mut self, pruned: &[String]) -> Option<Action> {
+    let gone = match self.repo.gone_branches() {
+      Ok(gone) => gone,
+      Err(err) => {
+        self.maybe_handle_git_error(Some(err));
+        return None;
+      },
+    };
+    let names: Vec<String> = gone
+      .into_iter()
+      .filter(|branch| !self.branches.iter().any(|item| item.protected && item.branch.name == branch.name))
+      .map(|branch| branch.name)
+      .collect();
+    if names.is_empty() {
+      self.notice =
+        Some(format!("Pruned {} remote branches, no local branches are left without an upstream", pruned.len()));
+      return None;
+    }
+    let message = format!("Delete {}? Each upstream is gone from every remote.", names.join(", "));
+    self.confirm_bulk_delete(names.len(), message, Action::DeleteGoneBranches(names))
+  }
+
+  fn delete_gone_branches(&mut self, names: &[String]) -> Result<(), Error> {
+    let mut deleted: Vec<(GitBranch, String)> = Vec::new();
+    let mut failures: Vec<String> = Vec::new();
+    for (branch, result) in self.repo.delete_gone_branches(names)? {
+      match result {
+        Ok(commit_id) => deleted.push((branch, commit_id)),
+        Err(err) => {
+          error!("Failed to delete {}: {}", branch.name, err);
+          failures.push(format!("{}: {}", branch.name, err.to_string().trim()));
+        },
+      }
+    }
+    self.reload_branches()?;
+    let notice = match deleted.is_empty() {
+      true => String::from("Deleted nothing, the upstreams are back"),
+      false => {
+        let names: Vec<&str> = deleted.iter().map(|(branch, _)| branch.name.as_str()).collect();
+        format!("Deleted {}, {} to undo", names.join(", "), self.keybindings.label(KeyCommand::Undo))
+      },
+    };
+    if failures.is_empty() {
+      self.notice = Some(notice);
+    } else {
+      self.error = Some(format!("{}, failed {}\n{}", notice, failures.len(), failures.join("\n")));
+    }
+    self.deleted = deleted;
+    Ok(())
+  }
+
+  /// Recreates the branches removed by the last delete at the commits they pointed at.
+  fn undo_delete(&mut self) {
+    if self.deleted.is_empty() {
+      self.notice = Some(String::from("Nothing to undo"));
+      return;
+    }
+    let mut restored: Vec<String> = Vec::new();
+    let mut failures: Vec<String> = Vec::new();
+    for (branch, commit_id) in std::mem::take(&mut self.deleted) {
+      match self.repo.restore_branch(&branch.name, &commit_id) {
+        Ok(()) => {
+          restored.push(branch.name.clone());
+          // The upstream config went with the branch so only the name and commit come back
+          self.branches.push(BranchItem::new(
+            GitBranch { upstream: None, ahead_behind: None, upstream_commit_id: None, ..branch },
+            true,
+          ));
+        },
+        Err(err) => {
+          error!("Failed to restore {}: {}", branch.name, err);
+          failures.push(format!("{}: {}", branch.name, err.to_string().trim()));
+        },
+      }
+    }
+    self.sort_branches();
+    if let Some(first) = restored.first() {
+      self.selected_group = None;
+      self.selected_index = self.branches.iter().position(|item| &item.branch.name == first).unwrap_or(0);
+    }
+    if failures.is_empty() {
+      self.notice = Some(format!("Restored {}", restored.join(", ")));
+    } else {
+      self.error = Some(format!("Restored {}, failed {}\n{}", restored.len(), failures.len(), failures.join("\n")));
+    }
+  }
+
+  fn create_branch(&mut self, name: String) -> Result<(), Error> {
+    let branch = GitBranch::new(name.clone());
+    self.repo.create_and_checkout(&branch)?;
+    self.branches.push(BranchItem::new(branch, true));
+    self.sort_branches();
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = existing_branch.branch.name == name;
+    }
+    self.selected_index = self.branches.iter().position(|b| b.branch.name == name).unwrap_or(0);
+    Ok(())
+  }
+
+  /// Creates a branch from `base` and checks it out, or from HEAD like [`Self::create_branch`] when `base` is the
+  /// current branch.
+  fn create_branch_from(&mut self, name: String, base: &str) -> Result<(), Error> {
+    let Some(base_branch) = self.branches.iter().find(|item| !item.is_remote && item.branch.name == base) else {
+      return Err(Error::Git(format!("The base branch {} no longer exists", base)));
+    };
+    if base_branch.branch.is_head {
+      return self.create_branch(name);
+    }
+    let branch = self.repo.create_from_and_checkout(&name, &base_branch.branch.clone())?;
+    self.branches.push(BranchItem::new(branch, true));
+    self.sort_branches();
+    for existing_branch in self.branches.iter_mut() {
+      existing_branch.branch.is_head = !existing_branch.is_remote && existing_branch.branch.name == name;
+    }
+    self.selected_index = self.branches.iter().position(|b| b.branch.name == name).unwrap_or(0);
+    self.notice = Some(format!("Created {} from {}", name, base));
+    Ok(())
+  }
+
+  /// Starts naming a new branch, offering the configured default base when there is one.
+  fn init_new_branch(&mut self) {
+    self.mode = Mode::Input;
+    let local: Vec<&GitBranch> = self.branches.iter().filter(|item| !item.is_remote).map(|item| &item.branch).collect();
+    let selected = self.get_selected_branch().filter(|item| !item.is_remote).map(|item| item.branch.name.clone());
+    match new_branch_bases(self.default_base_branch.as_deref(), &local, selected.as_deref()) {
+      (_, Some(note)) => self.branch_input.start_create_with_note(note),
+      (bases, None) if bases.is_empty() => self.branch_input.init_style(),
+      (bases, None) => self.branch_input.start_create_from(bases),
+    }
+  }
+
+  /// Creates and checks out a branch named from the wip pattern.
+  fn create_wip_branch(&mut self) -> Result<(), Error> {
+    let name = self.repo.propose_branch_name(&self.wip_branch_pattern)?;
+    self.create_branch(name.clone())?;
+    self.notice = Some(format!("Created and checked out {}", name));
+    Ok(())
+  }
+
+  /// Makes a change the shown branches were used to decide on, once the repository is known not to have changed
+  /// since they were read. What the change leaves behind is then taken as seen, even when it failed part way. The
+  /// change is recorded in the history under `description` along with how it went.
+  fn change_repo(&mut self, description: String, change: impl FnOnce(&mut Self) -> Result<(), Error>) {
+    let shown_error = self.error.clone();
+    let result = self.repo.ensure_refs_unchanged().and_then(|()| {
+      let result = change(self);
+      if let Err(err) = self.repo.mark_refs_seen() {
+        warn!("Unable to read the refs after a change: {}", err);
+      }
+      result
+    });
+    // Changes to several branches show their failures themselves rather than failing as a whole
+    let error =
+      result.as_ref().err().map(Error::to_string).or_else(|| self.error.clone().filter(|_| self.error != shown_error));
+    self.history.record(description, error);
+    self.maybe_handle_git_error(result.err());
+  }
+
+  /// `verb` followed by the selected branch's name, to describe a change to it in the history.
+  fn about_selected(&self, verb: &str) -> String {
+    match self.get_selected_branch() {
+      Some(selected) => format!("{} {}", verb, selected.branch.name),
+      None => String::from(verb),
+    }
+  }
+
+  fn maybe_handle_git_error(&mut self, err: Option<Error>) {
+    if let Some(error) = err {
+      error!("{}", error);
+      self.error = Some(error.to_string());
+    }
+  }
+
+  fn render_list(&mut self, f: &mut Frame<'_>, area: Rect) {
+    self.list_area = area;
+    if self.loading && self.branches.is_empty() {
+      let loading = Paragraph::new(Text::from("Loading branches…"))
+        .block(Block::default().title("Local Branches").borders(Borders::ALL))
+        .style(Style::default().fg(self.theme.text).add_modifier(Modifier::DIM));
+      f.render_widget(loading, area);
+      return;
+    }
+    // The borders and the highlight symbol take three columns
+    let width = usize::from(area.width.saturating_sub(3));
+    let creating = self.mode == Mode::Input && self.branch_input.is_creating();
+    if let (Some(content), true) = (self.branch_input.input_state.value.clone(), creating) {
+      // TODO don't clone, figure out the index to place the pseudo branch in the list
+      let mut branches = self.branches.clone();
+      branches.push(BranchItem {
+        branch: GitBranch::new(content),
+        staged_for_creation: true,
+        staged_for_deletion: false,
+        is_valid_name: self.branch_input.input_state.is_valid.unwrap_or(false),
+        ..BranchItem::default()
+      });
+      branches.sort_by(name_order);
+      let branches = pinned_first(branches, &self.pinned, |item| &item.branch.name);
+      self.list_state.select(branches.iter().position(|bi| bi.staged_for_creation));
+      let render_items: Vec<ListItem> =
+        branches.iter().map(|git_branch| git_branch.render(&[], &self.theme, &self.layout, width)).collect();
+      render_branch_list(f, area, "Local Branches", render_items, &self.theme, &mut self.list_state);
+      return;
+    }
+
+    let rows = self.visible_rows();
+    self.list_state.select(self.selected_row(&rows));
+    self.load_ahead_behind(&rows, usize::from(area.height.saturating_sub(2)));
+    let render_items: Vec<ListItem> =
+      rows.iter().map(|row| render_row(&self.branches, self.grouped, &self.theme, &self.layout, width, row)).collect();
+    let title = match self.scope {
+      BranchScope::Local => "Local Branches",
+      BranchScope::Remote => "Remote Branches",
+      BranchScope::All => "All Branches",
+    };
+    let mut title = if self.unpushed_only { format!("{}, unpushed only", title) } else { String::from(title) };
+    if self.cleanup_only {
+      title.push_str(", cleanup candidates only");
+    }
+    if let Some(ticket) = &self.ticket_filter {
+      title.push_str(&format!(", ticket {} only", ticket));
+    }
+    if self.bisect.is_some() {
+      title.push_str(", bisecting");
+    }
+    if self.behind_sort {
+      title.push_str(", most behind first");
+    }
+    if self.loading {
+      title.push_str(", loading…");
+    }
+    render_branch_list(f, area, &title, render_items, &self.theme, &mut self.list_state);
+  }
+
+  /// Fills in the details of the layout's columns for the rows that can be on screen once the list has scrolled to the
+  /// selection.
+  fn load_ahead_behind(&mut self, rows: &[Row], height: usize) {
+    let offset = self.list_state.offset();
+    let selected = self.list_state.selected().unwrap_or(0);
+    let end = (offset.max(selected) + height).min(rows.len());
+    let start = offset.min(selected.saturating_sub(height)).min(end);
+    for row in &rows[start..end] {
+      if let Row::Branch { index, .. } = row {
+        if self.layout.shows(Column::AheadBehind) {
+          self.count_ahead_behind(*index);
+        }
+        if self.layout.shows(Column::Diffstat) {
+          self.count_diffstat(*index);
+        }
+        if self.layout.shows(Column::CommitAge) || self.layout.shows(Column::Author) {
+          self.load_last_commit(*index);
+        }
+        if self.layout.shows(Column::UniqueCommits) {
+          self.count_unique_commits(*index);
+        }
+        if self.layout.shows(Column::Description) {
+          self.load_description(*index);
+        }
+      }
+    }
+  }
+
+  fn count_ahead_behind(&mut self, index: usize) {
+    let branch = &self.branches[index].branch;
+    let repo = &self.repo;
+    let counts = self.ahead_behind.get_or_compute(branch, || {
+      repo.upstream_ahead_behind(branch).unwrap_or_else(|err| {
+        warn!("Unable to count commits ahead and behind the upstream of {}: {}", branch.name, err);
+        None
+      })
+    });
+    self.branches[index].branch.ahead_behind = counts;
+  }
+
+  fn count_diffstat(&mut self, index: usize) {
+    let branch = &self.branches[index].branch;
+    let repo = &self.repo;
+    let diffstat = self.diffstats.get_or_compute(branch, || {
+      repo.diffstat_vs_upstream(branch).unwrap_or_else(|err| {
+        warn!("Unable to diff {} with its upstream: {}", branch.name, err);
+        None
+      })
+    });
+    self.branches[index].diffstat = diffstat;
+  }
+
+  fn load_last_commit(&mut self, index: usize) {
+    let branch = &self.branches[index].branch;
+    let repo = &self.repo;
+    let last_commit = self.last_commits.get_or_compute(branch, || {
+      repo
+        .branch_commit_info(branch)
+        .map_err(|err| warn!("Unable to read the last commit of {}: {}", branch.name, err))
+        .ok()
+    });
+    self.branches[index].commit_time = last_commit.as_ref().map(|commit| commit.time);
+    self.branches[index].commit_author = last_commit.map(|commit| commit.author);
+  }
+
+  fn load_description(&mut self, index: usize) {
+    let Some(item) = self.branches.get(index).filter(|item| !item.is_remote) else {
+      return;
+    };
+    let repo = &self.repo;
+    let description = self.descriptions.entry(item.branch.name.clone()).or_insert_with(|| {
+      match repo.branch_description(&item.branch) {
+        Ok(description) => description.and_then(|description| description.lines().next().map(String::from)),
+        Err(err) => {
+          warn!("Unable to read the description of {}: {}", item.branch.name, err);
+          None
+        },
+      }
+    });
+    self.branches[index].description = description.clone();
+  }
+
+  fn count_unique_commits(&mut self, index: usize) {
+    let item = self.branches[index].clone();
+    self.branches[index].unique_commits = self.default_divergence(&item).map(|(ahead, _)| ahead);
+  }
+
+  fn toggle_unpushed_filter(&mut self) {
+    self.unpushed_only = !self.unpushed_only;
+    if self.unpushed_only {
+      // Filtering needs the counts of every branch, not just the ones that have been on screen
+      for index in 0..self.branches.len() {
+        self.count_ahead_behind(index);
+      }
+    }
+    let visible = self.visible_branches();
+    if self.selected_group.is_none() && !visible.iter().any(|(index, _)| *index == self.selected_index) {
+      self.selected_index = visible.first().map_or(self.branches.len(), |(index, _)| *index);
+    }
+  }
+
+  fn toggle_cleanup_filter(&mut self) {
+    self.cleanup_only = !self.cleanup_only;
+    let visible = self.visible_branches();
+    if self.selected_group.is_none() && !visible.iter().any(|(index, _)| *index == self.selected_index) {
+      self.selected_index = visible.first().map_or(self.branches.len(), |(index, _)| *index);
+    }
+  }
+
+  /// Narrows the list to the branches for the selected branch's ticket, or lists them all again when it is narrowed.
+  fn toggle_ticket_filter(&mut self) {
+    if self.ticket_filter.take().is_none() {
+      let Some(selected) = self.get_selected_branch() else {
+        return;
+      };
+      match selected.ticket.clone() {
+        Some(ticket) => self.ticket_filter = Some(ticket),
+        None => {
+          self.notice = Some(format!("{} has no ticket", selected.branch.name));
+          return;
+        },
+      }
+    }
+    let visible = self.visible_branches();
+    if self.selected_group.is_none() && !visible.iter().any(|(index, _)| *index == self.selected_index) {
+      self.selected_index = visible.first().map_or(self.branches.len(), |(index, _)| *index);
+    }
+  }
+
+  fn render_error(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if self.error.is_none() {
+      return;
+    }
+    let error_message = self.error.as_ref().unwrap().clone();
+    let text = Text::from(error_message);
+    let component = Paragraph::new(text)
+      .block(Block::bordered().title("Error"))
+      .style(Style::from(self.theme.error))
+      .wrap(Wrap { trim: true });
+    f.render_widget(component, area);
+  }
+
+  fn render_notice(&self, f: &mut Frame<'_>, area: Rect) {
+    let Some(notice) = &self.notice else {
+      return;
+    };
+    let component = Paragraph::new(Text::from(notice.clone()))
+      .block(Block::bordered())
+      .style(Style::from(self.theme.notice))
+      .wrap(Wrap { trim: true });
+    f.render_widget(component, area);
+  }
+
+  fn render_filter(&self, f: &mut Frame<'_>, area: Rect) {
+    let mut style = Style::default().fg(self.theme.text);
+    if self.mode != Mode::Filter {
+      style = style.add_modifier(Modifier::DIM);
+    }
+    f.render_widget(Paragraph::new(Line::from(format!("/{}", self.filter))).style(style), area);
+  }
+
+  fn render_search(&self, f: &mut Frame<'_>, area: Rect) {
+    let mut style = Style::default().fg(self.theme.text);
+    if self.mode != Mode::Search {
+      style = style.add_modifier(Modifier::DIM);
+    }
+    f.render_widget(Paragraph::new(Line::from(format!("search: {}", self.search))).style(style), area);
+  }
+}
+
+fn render_row<'a>(
+  branches: &'a [BranchItem],
+  grouped: bool,
+  theme: &Theme,
+  layout: &ListLayout,
+  width: usize,
+  row: &'a Row,
+) -> ListItem<'a> {
+  match row {
+    Row::Group { prefix, count, collapsed } => {
+      let marker = if *collapsed { "▸" } else { "▾" };
+      ListItem::from(Line::from(vec![
+        Span::styled(
+          format!("{} {}", marker, prefix),
+          Style::default().fg(theme.group_header).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(format!(" ({})", count), Style::default().add_modifier(Modifier::DIM)),
+      ]))
+    },
+    Row::Branch { index, matched } if grouped => {
+      let mut spans = vec![Span::raw("  ")];
+      spans.extend(branches[*index].line(matched, theme, layout, width.saturating_sub(2)).spans);
+      ListItem::from(Line::from(spans))
+    },
+    Row::Branch { index, matched } => branches[*index].render(matched, theme, layout, width),
+  }
+}
+
+/// Orders rows by branch name, then local before remote, which is total as no two rows share both.
+fn name_order(a: &BranchItem, b: &BranchItem) -> Ordering {
+  a.branch.name.cmp(&b.branch.name).then_with(|| a.is_remote.cmp(&b.is_remote))
+}
+
+/// The bases offered for a new branch: the `configured` default base, then the selected branch and then the current
+/// one, without repeats. Nothing is offered without a configured base, so the branch comes from HEAD as before, and a
+/// configured base that doesn't exist is explained by the returned note.
+fn new_branch_bases(
+  configured: Option<&str>,
+  local: &[&GitBranch],
+  selected: Option<&str>,
+) -> (Vec<String>, Option<String>) {
+  let Some(configured) = configured else {
+    return (Vec::new(), None);
+  };
+  if !local.iter().any(|branch| branch.name == configured) {
+    warn!("The default base branch {} doesn't exist, creating from HEAD", configured);
+    return (Vec::new(), Some(format!("The default base branch {} doesn't exist, creating from HEAD", configured)));
+  }
+  let head = local.iter().find(|branch| branch.is_head && !branch.is_unborn).map(|branch| branch.name.as_str());
+  let mut bases: Vec<String> = Vec::new();
+  for base in [Some(configured), selected, head].into_iter().flatten() {
+    if !bases.iter().any(|existing| existing == base) {
+      bases.push(String::from(base));
+    }
+  }
+  (bases, None)
+}
+
+/// The row a page jump from `selected` lands on in a list of `len` rows, clamped to the first and last rows.
+fn page_target(selected: usize, len: usize, page: usize, forward: bool) -> usize {
+  if forward {
+    (selected + page).min(len.saturating_sub(1))
+  } else {
+    selected.saturating_sub(page)
+  }
+}
+
+/// The scroll offset putting `selected` in the middle of a viewport `height` rows tall, without scrolling past the
+/// end of a list of `len` rows so the last page stays full.
+fn centered_offset(selected: usize, len: usize, height: usize) -> usize {
+  selected.saturating_sub(height / 2).min(len.saturating_sub(height))
+}
+
+/// Maps a terminal cell to the index of the list row drawn there, given the bordered `list_area` and the list's
+/// scroll `offset`. The index may be past the end of a list that doesn't fill the area.
+fn row_at(list_area: Rect, offset: usize, column: u16, row: u16) -> Option<usize> {
+  let inner = Block::default().borders(Borders::ALL).inner(list_area);
+  if !inner.contains(Position::new(column, row)) {
+    return None;
+  }
+  Some(offset + usize::from(row - inner.y))
+}
+
+fn render_branch_list(
+  f: &mut Frame<'_>,
+  area: Rect,
+  title: &str,
+  render_items: Vec<ListItem<'_>>,
+  theme: &Theme,
+  list_state: &mut ListState,
+) {
+  let list = List::new(render_items)
+    .block(Block::default().title(title).borders(Borders::ALL))
+    .style(Style::default().fg(theme.text))
+    .highlight_style(Style::default().fg(theme.selected).add_modifier(Modifier::BOLD))
+    .highlight_symbol("→")
+    .repeat_highlight_symbol(true);
+
+  f.render_stateful_widget(list, area, list_state);
+}
+
+impl Component for BranchList {
+  fn register_action_handler(&mut self, tx: UnboundedSender<Action>) -> color_eyre::Result<()> {
+    self.action_tx = Some(tx);
+    Ok(())
+  }
+
+  fn handle_key_events(&mut self, key: KeyEvent) -> color_eyre::Result<Option<Action>> {
+    self.clear_error();
+
+    if self.confirm_dialog.is_open() {
+      return self.handle_confirm_key(key);
+    }
+    if self.reflog_view.is_open() {
+      return Ok(self.handle_reflog_key(key));
+    }
+    if self.history.is_open() {
+      return Ok(self.handle_history_key(key));
+    }
+    if self.patch_view.is_open() {
+      return Ok((!self.patch_view.handle_key_event(key)).then_some(Action::EndInputMod));
+    }
+    if self.palette.is_open() {
+      return self.handle_palette_key(key);
+    }
+    if self.reset_prompt.is_open() {
+      return self.handle_reset_key(key);
+    }
+    if self.bulk_rename.is_open() {
+      return self.handle_bulk_rename_key(key);
+    }
+    if self.commit_picker.is_open() {
+      return self.handle_picker_key(key);
+    }
+    if self.commit_search.is_open() {
+      return self.handle_commit_search_key(key);
+    }
+
+    if self.mode == Mode::Input {
+      return Ok(Some(Action::UpdateNewBranchName(key)));
+    }
+    if self.mode == Mode::Filter {
+      return Ok(Some(Action::UpdateFilter(key)));
+    }
+    if self.mode == Mode::Search {
+      return Ok(Some(Action::UpdateSearch(key)));
+    }
+    let Some(command) = self.keybindings.command_for(&key) else {
+      return Ok(None);
+    };
+    if command.mutates() && self.repo.is_read_only() {
+      let key = self.keybindings.label(command);
+      self.notice = Some(format!("The repository is open read-only, {} is disabled", key));
+      return Ok(None);
+    }
+    match command {
+      KeyCommand::SelectNext => Ok(Some(Action::SelectNextBranch)),
+      KeyCommand::SelectPrevious => Ok(Some(Action::SelectPreviousBranch)),
+      KeyCommand::PageDown => Ok(Some(Action::SelectNextPage)),
+      KeyCommand::PageUp => Ok(Some(Action::SelectPreviousPage)),
+      KeyCommand::SelectFirst => Ok(Some(Action::SelectFirstBranch)),
+      KeyCommand::SelectLast => Ok(Some(Action::SelectLastBranch)),
+      KeyCommand::CenterSelection => Ok(Some(Action::CenterSelection)),
+      KeyCommand::Filter => Ok(Some(Action::StartFilter)),
+      KeyCommand::Search => Ok(Some(Action::StartSearch)),
+      KeyCommand::SearchNext => Ok(Some(Action::SearchNext)),
+      KeyCommand::SearchPrevious => Ok(Some(Action::SearchPrevious)),
+      KeyCommand::ToggleSelection => Ok(Some(Action::ToggleBranchSelection)),
+      KeyCommand::TogglePin => Ok(Some(Action::TogglePinnedBranch)),
+      KeyCommand::ToggleGrouping => Ok(Some(Action::ToggleBranchGrouping)),
+      KeyCommand::ToggleGraph => Ok(Some(Action::ToggleCommitGraph)),
+      KeyCommand::ToggleUnpushed => Ok(Some(Action::ToggleUnpushedFilter)),
+      KeyCommand::ToggleCleanup => Ok(Some(Action::ToggleCleanupFilter)),
+      KeyCommand::FilterTicket => Ok(Some(Action::ToggleTicketFilter)),
+      KeyCommand::ToggleBehindSort => Ok(Some(Action::ToggleBehindSort)),
+      KeyCommand::CycleScope => Ok(Some(Action::CycleBranchScope)),
+      KeyCommand::Refresh => Ok(Some(Action::Refresh)),
+      KeyCommand::ToggleGroup => {
+        if self.selected_group.is_none() {
+          return Ok(None);
+        }
+        Ok(Some(Action::ToggleSelectedGroup))
+      },
+      KeyCommand::CreateBranch => Ok(Some(Action::InitNewBranch)),
+      KeyCommand::CreateWipBranch => Ok(Some(Action::CreateWipBranch)),
+      KeyCommand::Rename => {
+        if self.get_selected_branch().is_none_or(|selected| selected.branch.name_is_lossy || selected.is_remote) {
+          return Ok(None);
+        }
+        Ok(Some(Action::InitRenameBranch))
+      },
+      KeyCommand::BulkRename => Ok(self.open_bulk_rename()),
+      KeyCommand::EditDescription => {
+        if self.get_selected_branch().is_none_or(|selected| selected.branch.name_is_lossy || selected.is_remote) {
+          return Ok(None);
+        }
+        Ok(Some(Action::InitDescribeBranch))
+      },
+      KeyCommand::CopyName => Ok(Some(Action::CopySelectedBranchName)),
+      KeyCommand::CopyRef => Ok(Some(Action::CopySelectedBranchRef)),
+      KeyCommand::Checkout => Ok(Some(Action::CheckoutSelectedBranch)),
+      KeyCommand::QuickCheckout if !self.quick_checkout => {
+        self.notice = Some(String::from("Quick checkout is off, set quick_checkout in the config to use it"));
+        Ok(None)
+      },
+      KeyCommand::QuickCheckout => Ok(Some(Action::QuickCheckoutSelectedBranch)),
+      KeyCommand::ForceCheckout => {
+        let Some(selected) = self.get_selected_branch() else {
+          return Ok(None);
+        };
+        if selected.branch.is_head || selected.branch.name_is_lossy || selected.is_remote {
+          return Ok(None);
+        }
+        let name = selected.branch.name.clone();
+        let message = format!("Force checkout {}? Local changes to tracked files will be lost.", name);
+        self.confirm_dialog.open_with_style(
+          message,
+          Action::ForceCheckoutBranch(name),
+          ConfirmStyle::TypedWord("FORCE"),
+        );
+        Ok(None)
+      },
+      KeyCommand::CheckoutDefault => Ok(Some(Action::CheckoutDefaultBranch)),
+      KeyCommand::CheckoutPrevious => Ok(Some(Action::CheckoutPreviousBranch)),
+      KeyCommand::CheckoutUpstream => Ok(Some(Action::CheckoutUpstream)),
+      KeyCommand::Palette => Ok(self.open_palette()),
+      KeyCommand::Reset => Ok(self.open_reset()),
+      KeyCommand::BisectGood => Ok(Some(Action::MarkBisectGood)),
+      KeyCommand::BisectBad => Ok(Some(Action::MarkBisectBad)),
+      KeyCommand::BisectStop => Ok(Some(Action::StopBisect)),
+      KeyCommand::CherryPick => Ok(self.open_commit_picker(PickerPurpose::CherryPick)),
+      KeyCommand::BranchFromCommit => Ok(self.open_commit_picker(PickerPurpose::Branch)),
+      KeyCommand::FindCommit => {
+        self.commit_search.open();
+        Ok(Some(Action::StartInputMode))
+      },
+      KeyCommand::Unstage => Ok(Some(Action::UnstageBranchForDeletion)),
+      KeyCommand::Undo => Ok(Some(Action::UndoDelete)),
+      KeyCommand::DeleteMerged => Ok(Some(Action::StageMergedBranches)),
+      KeyCommand::FastForwardAll => Ok(self.confirm_fast_forward()),
+      KeyCommand::CleanUpGone => {
+        // Refused before fetching rather than after, as the fetch can take a while
+        if let Err(err) = self.repo.ensure_writable() {
+          self.maybe_handle_git_error(Some(err));
+          return Ok(None);
+        }
+        Ok(Some(Action::CleanUpGoneBranches))
+      },
+      KeyCommand::Reflog => Ok(self.open_reflog()),
+      KeyCommand::PreviewCheckout => Ok(self.preview_checkout()),
+      KeyCommand::CompareWithHead => Ok(self.open_patch_view()),
+      KeyCommand::History => {
+        self.history.open();
+        Ok(Some(Action::StartInputMode))
+      },
+      KeyCommand::ShowCommit | KeyCommand::ShowCommitDiff => {
+        let Some(selected) = self.get_selected_branch().filter(|selected| !selected.branch.name_is_lossy) else {
+          return Ok(None);
+        };
+        match self.repo.commit_details(&selected.branch, command == KeyCommand::ShowCommitDiff) {
+          Ok(details) => Ok(Some(Action::ShowInViewer(details))),
+          Err(err) => {
+            self.maybe_handle_git_error(Some(err));
+            Ok(None)
+          },
+        }
+      },
+      KeyCommand::AbortOperation => {
+        let state = self.repo.repo_state();
+        if state.is_clean() {
+          self.notice = Some(Error::NoOperationInProgress.to_string());
+          return Ok(None);
+        }
+        let message = format!("Abort the {} in progress? Uncommitted changes will be lost.", state);
+        Ok(self.confirm(message, Action::AbortOperation))
+      },
+      KeyCommand::DeleteStaged => {
+        let staged = self.branches.iter().filter(|item| item.staged_for_deletion).count();
+        if staged == 0 {
+          return Ok(None);
+        }
+        Ok(self.confirm_bulk_delete(
+          staged,
+          format!("Delete {} staged branches?", staged),
+          Action::DeleteStagedBranches,
+        ))
+      },
+      KeyCommand::ForceDelete => {
+        let Some(selected) = self.get_selected_branch() else {
+          return Ok(None);
+        };
+        if selected.branch.is_head || selected.branch.name_is_lossy || selected.is_remote {
+          return Ok(None);
+        }
+        if selected.protected {
+          self.error = Some(Error::ProtectedBranch(selected.branch.name.clone()).to_string());
+          return Ok(None);
+        }
+        let branch = selected.branch.clone();
+        let Some(message) = self.delete_question("Force delete", &branch) else {
+          return Ok(None);
+        };
+        Ok(self.confirm(message, Action::ForceDeleteBranch))
+      },
+      KeyCommand::Archive => {
+        let Some(selected) = self.get_selected_branch() else {
+          return Ok(None);
+        };
+        if selected.branch.is_head || selected.branch.name_is_lossy || selected.is_remote {
+          return Ok(None);
+        }
+        if selected.protected {
+          self.error = Some(Error::ProtectedBranch(selected.branch.name.clone()).to_string());
+          return Ok(None);
+        }
+        Ok(Some(Action::ArchiveSelectedBranch))
+      },
+      KeyCommand::Delete => {
+        let Some(selected) = self.get_selected_branch().filter(|selected| !selected.is_remote) else {
+          return Ok(None);
+        };
+        if selected.staged_for_deletion {
+          let branch = selected.branch.clone();
+          let Some(message) = self.delete_question("Delete", &branch) else {
+            return Ok(None);
+          };
+          return Ok(self.confirm(message, Action::DeleteBranch));
+        }
+        Ok(Some(Action::StageBranchForDeletion))
+      },
+      // Handled by the app
+      KeyCommand::Quit | KeyCommand::Help => Ok(None),
+    }
+  }
+
+  fn handle_mouse_events(&mut self, mouse: MouseEvent) -> color_eyre::Result<Option<Action>> {
+    if self.mode != Mode::Selection
+      || self.confirm_dialog.is_open()
+      || self.reflog_view.is_open()
+      || self.history.is_open()
+      || self.patch_view.is_open()
+      || self.palette.is_open()
+      || self.reset_prompt.is_open()
+      || self.bulk_rename.is_open()
+      || self.commit_picker.is_open()
+      || self.commit_search.is_open()
+      || self.loading
+    {
+      return Ok(None);
+    }
+    match mouse.kind {
+      MouseEventKind::ScrollDown => Ok(Some(Action::SelectNextBranch)),
+      MouseEventKind::ScrollUp => Ok(Some(Action::SelectPreviousBranch)),
+      MouseEventKind::Down(MouseButton::Left) => {
+        self.clear_error();
+        let rows = self.visible_rows();
+        let Some(clicked) = row_at(self.list_area, self.list_state.offset(), mouse.column, mouse.row)
+          .filter(|clicked| *clicked < rows.len())
+        else {
+          return Ok(None);
+        };
+        self.select_row(&rows[clicked]);
+        let now = Instant::now();
+        let is_double_click = self.last_click.is_some_and(|(last_row, last_time)| {
+          last_row == clicked && now.duration_since(last_time) <= DOUBLE_CLICK_TIME
+        });
+        if is_double_click {
+          self.last_click = None;
+          return match rows[clicked] {
+            Row::Group { .. } => Ok(Some(Action::ToggleSelectedGroup)),
+            Row::Branch { .. } => Ok(Some(Action::CheckoutSelectedBranch)),
+          };
+        }
+        self.last_click = Some((clicked, now));
+        Ok(None)
+      },
+      _ => Ok(None),
+    }
+  }
+
+  fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+    // Undo only restores exactly what was deleted, once anything else changes it may no longer make sense
+    if matches!(
+      action,
+      Action::CheckoutSelectedBranch
+        | Action::CheckoutBranch(_)
+        | Action::CheckoutCommit(_)
+        | Action::CheckoutRemoteBranch(_)
+        | Action::CheckoutDefaultBranch
+        | Action::CheckoutPreviousBranch
+        | Action::CreateBranch(_)
+        | Action::CreateBranchAt(..)
+        | Action::CreateWipBranch
+        | Action::FastForwardBranches
+        | Action::ForceCheckoutBranch(_)
+        | Action::MarkBisectBad
+        | Action::MarkBisectGood
+        | Action::RenameBranches(_)
+        | Action::RenameSelectedBranch(_)
+    ) {
+      self.deleted.clear();
+    }
+    match action {
+      Action::BranchesLoaded(branches) => {
+        self.load_branches(branches);
+        Ok(None)
+      },
+      Action::BranchesLoading(batch) if self.loading => {
+        // Shown as they arrive, the full list replaces them once every branch has been read
+        self.branches.extend(batch.into_iter().map(|branch| BranchItem::new(branch, true)));
+        Ok(None)
+      },
+      Action::Quit => {
+        self.save_state();
+        Ok(None)
+      },
+      Action::Error(message) => {
+        self.loading = false;
+        error!("{}", message);
+        self.error = Some(message);
+        Ok(None)
+      },
+      Action::SelectPreviousBranch => {
+        self.select_previous();
+        Ok(None)
+      },
+      Action::SelectNextBranch => {
+        self.select_next();
+        Ok(None)
+      },
+      Action::SelectPreviousPage => {
+        self.select_page(false);
+        Ok(None)
+      },
+      Action::SelectNextPage => {
+        self.select_page(true);
+        Ok(None)
+      },
+      Action::SelectFirstBranch => {
+        self.select_first();
+        Ok(None)
+      },
+      Action::SelectLastBranch => {
+        self.select_last();
+        Ok(None)
+      },
+      Action::CenterSelection => {
+        self.center_selection();
+        Ok(None)
+      },
+      Action::InitNewBranch => {
+        self.init_new_branch();
+        Ok(Some(Action::StartInputMode))
+      },
+      Action::InitRenameBranch => {
+        let Some(selected) = self.get_selected_branch() else {
+          return Ok(None);
+        };
+        let name = selected.branch.name.clone();
+        self.mode = Mode::Input;
+        self.branch_input.start_rename(&name);
+        Ok(Some(Action::StartInputMode))
+      },
+      Action::InitDescribeBranch => {
+        let Some(selected) = self.get_selected_branch() else {
+          return Ok(None);
+        };
+        let branch = selected.branch.clone();
+        let current = match self.repo.branch_description(&branch) {
+          Ok(current) => current,
+          Err(err) => {
+            self.maybe_handle_git_error(Some(err));
+            return Ok(None);
+          },
+        };
+        self.mode = Mode::Input;
+        self.branch_input.start_describe(&branch.name, current.as_deref());
+        Ok(Some(Action::StartInputMode))
+      },
+      Action::SetBranchDescription(name, description) => {
+        self.mode = Mode::Selection;
+        self.change_repo(format!("Describe {}", name), |list| list.set_description(&name, description.as_deref()));
+        Ok(Some(Action::EndInputMod))
+      },
+      Action::EndInputMod => {
+        self.mode = Mode::Selection;
+        Ok(None)
+      },
+      Action::StartFilter => {
+        self.mode = Mode::Filter;
+        Ok(Some(Action::StartInputMode))
+      },
+      Action::UpdateFilter(key_event) => Ok(self.update_filter(key_event)),
+      Action::StartSearch => {
+        self.search.clear();
+        self.mode = Mode::Search;
+        Ok(Some(Action::StartInputMode))
+      },
+      Action::UpdateSearch(key_event) => Ok(self.update_search(key_event)),
+      Action::SearchNext => {
+        self.cycle_search(true);
+        Ok(None)
+      },
+      Action::SearchPrevious => {
+        self.cycle_search(false);
+        Ok(None)
+      },
+      Action::CycleBranchScope => Ok(self.cycle_scope()),
+      Action::Refresh => {
+        let result = self.reload_branches();
+        self.maybe_handle_git_error(result.err());
+        Ok(None)
+      },
+      // Reloaded to pick up the moved upstreams, there is nothing to reload until the first load is done
+      Action::RemoteFetched if !self.loading => {
+        let result = self.reload_branches();
+        self.maybe_handle_git_error(result.err());
+        self.report_fast_forwardable();
+        Ok(None)
+      },
+      Action::RemotePruned(pruned) if !self.loading => {
+        let result = self.reload_branches();
+        self.maybe_handle_git_error(result.err());
+        Ok(self.confirm_clean_up_gone(&pruned))
+      },
+      Action::ToggleCleanupFilter => {
+        self.toggle_cleanup_filter();
+        Ok(None)
+      },
+      Action::ToggleTicketFilter => {
+        self.toggle_ticket_filter();
+        Ok(None)
+      },
+      Action::ToggleBehindSort => {
+        self.toggle_behind_sort();
+        Ok(None)
+      },
+      Action::ToggleUnpushedFilter => {
+        self.toggle_unpushed_filter();
+        Ok(None)
+      },
+      Action::ToggleBranchGrouping => {
+        self.toggle_grouping();
+        Ok(None)
+      },
+      Action::ToggleCommitGraph => {
+        self.commit_log.toggle_graph();
+        Ok(None)
+      },
+      Action::TogglePinnedBranch => {
+        self.toggle_pin();
+        Ok(None)
+      },
+      Action::ToggleSelectedGroup => {
+        self.toggle_selected_group();
+        Ok(None)
+      },
+      Action::UpdateNewBranchName(key_event) => Ok(self.branch_input.handle_key_event(key_event, &*self.repo)),
+      Action::CheckoutSelectedBranch => {
+        let name = self.get_selected_branch().map(|selected| selected.branch.name.clone());
+        self.change_repo(self.about_selected("Check out"), |list| list.checkout_selected());
+        Ok(name.and_then(|name| self.track_question(&name)))
+      },
+      Action::CheckoutBranch(name) => {
+        self.change_repo(format!("Check out {}", name), |list| list.checkout_named(&name));
+        Ok(self.track_question(&name))
+      },
+      Action::QuickCheckoutSelectedBranch => {
+        self.change_repo(self.about_selected("Quick check out"), |list| list.quick_checkout_selected());
+        Ok(None)
+      },
+      Action::ForceCheckoutBranch(name) => {
+        self.change_repo(format!("Force check out {}", name), |list| list.force_checkout(&name));
+        Ok(None)
+      },
+      Action::CheckoutRemoteBranch(remote) => {
+        self.change_repo(format!("Check out {}", remote.name), |list| list.checkout_remote(remote));
+        Ok(None)
+      },
+      Action::CherryPickCommit(commit_id) => {
+        let short_id = self.repo.short_id(&commit_id).unwrap_or_else(|_| commit_id.clone());
+        self.change_repo(format!("Cherry-pick {}", short_id), |list| list.cherry_pick(&commit_id));
+        Ok(None)
+      },
+      Action::FindCommitBranches(commit_id) => {
+        let result = self.find_commit_branches(&commit_id);
+        self.maybe_handle_git_error(result.err());
+        Ok(None)
+      },
+      Action::DeleteGoneBranches(names) => {
+        self.change_repo(String::from("Delete the branches whose upstream is gone"), |list| {
+          list.delete_gone_branches(&names)
+        });
+        Ok(None)
+      },
+      Action::FastForwardBranches => {
+        self.change_repo(String::from("Fast-forward the branches behind their upstreams"), |list| {
+          list.fast_forward_all()
+        });
+        Ok(None)
+      },
+      Action::ResetCurrentBranch(target, mode) => {
+        self.change_repo(format!("Reset ({}) to {}", mode, target), |list| list.reset_current(&target, mode));
+        Ok(None)
+      },
+      Action::AbortOperation => {
+        self.change_repo(String::from("Abort the operation in progress"), |list| list.abort_operation());
+        Ok(None)
+      },
+      Action::CheckoutDefaultBranch => {
+        self.change_repo(String::from("Check out the default branch"), |list| list.checkout_default());
+        Ok(None)
+      },
+      Action::CheckoutCommit(commit_id) => {
+        let short_id = self.repo.short_id(&commit_id).unwrap_or_else(|_| commit_id.clone());
+        self
+          .change_repo(format!("Check out {} detached", short_id), |list| list.checkout_commit(&commit_id, &short_id));
+        Ok(None)
+      },
+      Action::CheckoutUpstream => {
+        self.change_repo(self.about_selected("Check out the upstream of"), |list| list.checkout_upstream());
+        Ok(None)
+      },
+      Action::CheckoutPreviousBranch => {
+        self.change_repo(String::from("Check out the previous branch"), |list| list.checkout_previous());
+        Ok(None)
+      },
+      Action::MarkBisectGood => {
+        self.change_repo(String::from("Mark good while bisecting"), |list| list.mark_bisect(true));
+        Ok(None)
+      },
+      Action::MarkBisectBad => {
+        self.change_repo(String::from("Mark bad while bisecting"), |list| list.mark_bisect(false));
+        Ok(None)
+      },
+      Action::StopBisect if self.bisect.is_some() => {
+        self.change_repo(String::from("Stop bisecting"), |list| list.stop_bisect());
+        Ok(None)
+      },
+      Action::CreateBranch(name) => {
+        self.mode = Mode::Selection;
+        self.change_repo(format!("Create {}", name), |list| list.create_branch(name));
+        Ok(Some(Action::EndInputMod))
+      },
+      Action::CreateBranchFrom(name, base) => {
+        self.mode = Mode::Selection;
+        self.change_repo(format!("Create {} from {}", name, base), |list| list.create_branch_from(name, &base));
+        Ok(Some(Action::EndInputMod))
+      },
+      Action::CreateWipBranch => {
+        self.change_repo(String::from("Create a wip branch"), |list| list.create_wip_branch());
+        Ok(None)
+      },
+      Action::CreateBranchAt(name, commit_id) => {
+        self.mode = Mode::Selection;
+        let short_id = self.repo.short_id(&commit_id).unwrap_or_else(|_| commit_id.clone());
+        self.change_repo(format!("Create {} at {}", name, short_id), |list| list.create_branch_at(name, &commit_id));
+        Ok(Some(Action::EndInputMod))
+      },
+      Action::RenameBranches(renames) => {
+        self.change_repo(format!("Rename {} branches", renames.len()), |list| list.rename_branches(&renames));
+        Ok(None)
+      },
+      Action::RenameSelectedBranch(name) => {
+        self.mode = Mode::Selection;
+        let old_name = self.get_selected_branch().map(|selected| selected.branch.name.clone());
+        self.change_repo(format!("{} to {}", self.about_selected("Rename"), name), |list| {
+          list.rename_selected(name.clone())
+        });
+        if let Some(message) = old_name.and_then(|old_name| self.rename_upstream_question(&old_name, &name)) {
+          return Ok(self.confirm(message, Action::RenameUpstream(name)));
+        }
+        Ok(Some(Action::EndInputMod))
+      },
+      Action::SetBranchUpstream(name, remote) => {
+        self
+          .change_repo(format!("Track {} from {}", remote.name, name), |list| list.set_branch_upstream(&name, &remote));
+        Ok(None)
+      },
+      Action::RenameUpstream(name) => {
+        self.change_repo(format!("Rename the upstream of {}", name), |list| list.rename_upstream(&name));
+        Ok(None)
+      },
+      Action::StageBranchForDeletion => {
+        self.stage_selected_for_deletion(true);
+        Ok(None)
+      },
+      Action::UnstageBranchForDeletion => {
+        self.stage_selected_for_deletion(false);
+        Ok(None)
+      },
+      Action::DeleteBranch => {
+        self.change_repo(self.about_selected("Delete"), |list| list.deleted_selected());
+        Ok(None)
+      },
+      Action::CopySelectedBranchName => {
+        self.copy_selected_name();
+        Ok(None)
+      },
+      Action::CopySelectedBranchRef => {
+        self.copy_selected_ref();
+        Ok(None)
+      },
+      Action::ArchiveSelectedBranch => {
+        self.change_repo(self.about_selected("Archive"), |list| list.archive_selected());
+        Ok(None)
+      },
+      Action::ForceDeleteBranch => {
+        self.change_repo(self.about_selected("Force delete"), |list| list.force_delete_selected());
+        Ok(None)
+      },
+      Action::DeleteStagedBranches => {
+        self.change_repo(String::from("Delete the staged branches"), |list| {
+          list.delete_staged_branches();
+          Ok(())
+        });
+        Ok(None)
+      },
+      Action::StageMergedBranches => {
+        match self.stage_merged_branches() {
+          Ok(action) => Ok(action),
+          Err(err) => {
+            self.maybe_handle_git_error(Some(err));
+            Ok(None)
+          },
+        }
+      },
+      Action::UndoDelete => {
+        self.change_repo(String::from("Undo the last delete"), |list| {
+          list.undo_delete();
+          Ok(())
+        });
+        Ok(None)
+      },
+      Action::ToggleBranchSelection => {
+        let staged = self.get_selected_branch().is_some_and(|selected| selected.staged_for_deletion);
+        self.stage_selected_for_deletion(!staged);
+        Ok(None)
+      },
+      _ => Ok(None),
+    }
+  }
+
+  fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+    if self.mode == Mode::Input {
+      let layout =
+        Layout::new(Direction::Vertical, [Constraint::Fill(1), Constraint::Length(3), Constraint::Length(1)])
+          .margin(1)
+          .split(area);
+      self.render_list(f, layout[0]);
+      self.branch_input.render(f, layout[1]);
+      self.instruction_footer.render(
+        f,
+        layout[2],
+        &self.branches,
+        self.get_selected_branch(),
+        self.grouped,
+        self.selected_group.is_some(),
+      );
+      return Ok(());
+    }
+
+    let show_filter = self.mode == Mode::Filter || !self.filter.is_empty();
+    let show_search = self.mode == Mode::Search || !self.search.is_empty();
+    let mut constraints = vec![Constraint::Fill(1)];
+    if let Some(error) = &self.error {
+      constraints.push(Constraint::Length(u16::try_from(error.lines().count() + 2)?));
+    }
+    if let Some(notice) = &self.notice {
+      constraints.push(Constraint::Length(u16::try_from(notice.lines().count() + 2)?));
+    }
+    if show_filter {
+      constraints.push(Constraint::Length(1));
+    }
+    if show_search {
+      constraints.push(Constraint::Length(1));
+    }
+    constraints.push(Constraint::Length(1));
+    let layout = Layout::new(Direction::Vertical, constraints).margin(1).split(area);
+
+    if layout[0].width >= MIN_WIDTH_FOR_LOG {
+      let [list_area, log_area] =
+        Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(layout[0]);
+      self.render_list(f, list_area);
+      let selected = self.get_selected_branch().map(|item| item.branch.clone());
+      self.commit_log.render(f, log_area, &*self.repo, selected.as_ref(), self.default_branch.as_ref());
+    } else {
+      self.render_list(f, layout[0]);
+    }
+    let mut next_area = 1;
+    if self.error.is_some() {
+      self.render_error(f, layout[next_area]);
+      next_area += 1;
+    }
+    if self.notice.is_some() {
+      self.render_notice(f, layout[next_area]);
+      next_area += 1;
+    }
+    if show_filter {
+      self.render_filter(f, layout[next_area]);
+      next_area += 1;
+    }
+    if show_search {
+      self.render_search(f, layout[next_area]);
+      next_area += 1;
+    }
+    self.instruction_footer.render(
+      f,
+      layout[next_area],
+      &self.branches,
+      self.get_selected_branch(),
+      self.grouped,
+      self.selected_group.is_some(),
+    );
+    self.confirm_dialog.render(f, area);
+    self.reflog_view.render(f, area);
+    self.history.render(f, area);
+    self.patch_view.render(f, area);
+    self.palette.render(f, area);
+    self.reset_prompt.render(f, area);
+    self.bulk_rename.render(f, area);
+    self.commit_picker.render(f, area);
+    self.commit_search.render(f, area);
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    config::layout::Density,
+    test_support::{git_in, render, rows, Fixture},
+  };
+
+  /// A list of the fixture's branches as the app shows them once they have loaded.
+  fn loaded(fixture: &Fixture) -> BranchList {
+    let repo = fixture.cli();
+    let branches = repo.local_branches().unwrap();
+    let mut list = BranchList::new(Box::new(repo), &Config::default());
+    list.update(Action::BranchesLoaded(branches)).unwrap();
+    list
+  }
+
+  fn select(list: &mut BranchList, name: &str) {
+    list.selected_index = list.branches.iter().position(|item| item.branch.name == name).expect("No such branch");
+  }
+
+  fn names(list: &BranchList) -> Vec<&str> {
+    list.branches.iter().map(|item| item.branch.name.as_str()).collect()
+  }
+
+  #[test]
+  fn a_failed_bulk_delete_still_deletes_the_rest() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "merged"]);
+    fixture.branch_with_commit("unmerged");
+    let mut list = loaded(&fixture);
+    for name in ["merged", "unmerged"] {
+      select(&mut list, name);
+      list.stage_selected_for_deletion(true);
+    }
+    list.delete_staged_branches();
+    assert_eq!(names(&list), ["main", "unmerged"]);
+    let error = list.error.clone().unwrap();
+    assert!(error.starts_with("Deleted 1, failed 1\nunmerged: "), "{}", error);
+  }
+
+  #[test]
+  fn clicks_map_to_rows_past_the_border_and_scroll_offset() {
+    let area = Rect::new(2, 3, 20, 10);
+    assert_eq!(row_at(area, 0, 5, 4), Some(0));
+    assert_eq!(row_at(area, 0, 5, 11), Some(7));
+    assert_eq!(row_at(area, 25, 5, 4), Some(25));
+    assert_eq!(row_at(area, 25, 5, 6), Some(27));
+  }
+
+  #[test]
+  fn clicks_on_the_border_or_outside_hit_no_row() {
+    let area = Rect::new(2, 3, 20, 10);
+    assert_eq!(row_at(area, 0, 5, 3), None);
+    assert_eq!(row_at(area, 0, 5, 12), None);
+    assert_eq!(row_at(area, 0, 2, 5), None);
+    assert_eq!(row_at(area, 0, 40, 5), None);
+  }
+
+  #[test]
+  fn submitting_a_rename_renames_the_selected_branch() {
+    let fixture = Fixture::new();
+    fixture.branch_with_commit("feature");
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    list.update(Action::RenameSelectedBranch(String::from("renamed"))).unwrap();
+    assert_eq!(fixture.git(&["branch", "--list", "--format=%(refname:short)"]), "main\nrenamed");
+    assert!(names(&list).contains(&"renamed"));
+  }
+
+  #[test]
+  fn undoing_a_delete_brings_the_branch_back_at_its_commit() {
+    let fixture = Fixture::new();
+    let tip = fixture.branch_with_commit("feature");
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    list.update(Action::ForceDeleteBranch).unwrap();
+    assert_eq!(names(&list), ["main"]);
+    list.update(Action::UndoDelete).unwrap();
+    assert_eq!(fixture.git(&["rev-parse", "feature"]), tip);
+    assert_eq!(names(&list), ["feature", "main"]);
+  }
+
+  #[test]
+  fn a_delete_cant_be_undone_after_another_change() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    list.update(Action::DeleteBranch).unwrap();
+    list.update(Action::CreateBranch(String::from("other"))).unwrap();
+    list.update(Action::UndoDelete).unwrap();
+    assert_eq!(fixture.git(&["branch", "--list", "feature"]), "");
+    assert_eq!(list.notice.as_deref(), Some("Nothing to undo"));
+  }
+
+  #[test]
+  fn the_merged_branches_are_staged_and_deleted_once_confirmed() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "merged"]);
+    fixture.branch_with_commit("unmerged");
+    let mut list = loaded(&fixture);
+    assert_eq!(list.update(Action::StageMergedBranches).unwrap(), Some(Action::StartInputMode));
+    assert!(list.confirm_dialog.is_open());
+    let staged: Vec<&str> =
+      list.branches.iter().filter(|item| item.staged_for_deletion).map(|item| item.branch.name.as_str()).collect();
+    assert_eq!(staged, ["merged"]);
+    list.update(Action::DeleteStagedBranches).unwrap();
+    assert_eq!(names(&list), ["main", "unmerged"]);
+  }
+
+  fn selected_name(list: &BranchList) -> &str {
+    list.branches[list.selected_index].branch.name.as_str()
+  }
+
+  #[test]
+  fn searching_moves_the_selection_without_hiding_branches() {
+    let fixture = Fixture::new();
+    for name in ["feature/one", "fix", "feature/two"] {
+      fixture.git(&["branch", name]);
+    }
+    let mut list = loaded(&fixture);
+    list.update(Action::StartSearch).unwrap();
+    for c in "FEAT".chars() {
+      list.update(Action::UpdateSearch(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))).unwrap();
+    }
+    assert_eq!(selected_name(&list), "feature/one");
+    assert_eq!(list.visible_rows().len(), 4);
+    list.update(Action::UpdateSearch(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))).unwrap();
+    list.update(Action::SearchNext).unwrap();
+    assert_eq!(selected_name(&list), "feature/two");
+    list.update(Action::SearchNext).unwrap();
+    assert_eq!(selected_name(&list), "feature/one");
+    list.update(Action::SearchPrevious).unwrap();
+    assert_eq!(selected_name(&list), "feature/two");
+  }
+
+  fn remembering(fixture: &Fixture) -> BranchList {
+    let repo = fixture.cli();
+    let branches = repo.local_branches().unwrap();
+    let store = UiStateStore::new(fixture.dir.path().join("ui_state.json"), &fixture.path);
+    let mut list = BranchList::new(Box::new(repo), &Config::default()).remember_state(store);
+    list.update(Action::BranchesLoaded(branches)).unwrap();
+    list
+  }
+
+  #[test]
+  fn the_selection_is_restored_when_the_branch_still_exists() {
+    let fixture = Fixture::new();
+    for name in ["feature", "other"] {
+      fixture.git(&["branch", name]);
+    }
+    let mut list = remembering(&fixture);
+    select(&mut list, "other");
+    list.save_state();
+    assert_eq!(selected_name(&remembering(&fixture)), "other");
+    fixture.git(&["branch", "-D", "other"]);
+    assert_eq!(selected_name(&remembering(&fixture)), "feature");
+  }
+
+  #[test]
+  fn refreshing_picks_up_branches_made_outside_the_app_and_keeps_the_selection() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    fixture.git(&["branch", "added-elsewhere"]);
+    fixture.git(&["checkout", "-q", "feature"]);
+    list.update(Action::Refresh).unwrap();
+    assert_eq!(names(&list), ["added-elsewhere", "feature", "main"]);
+    assert_eq!(selected_name(&list), "feature");
+    assert!(list.branches.iter().find(|item| item.branch.name == "feature").unwrap().branch.is_head);
+  }
+
+  #[test]
+  fn pinning_moves_the_branch_to_the_top_and_drops_pins_of_deleted_branches() {
+    let fixture = Fixture::new();
+    for name in ["alpha", "beta", "gamma"] {
+      fixture.git(&["branch", name]);
+    }
+    let mut list = loaded(&fixture);
+    for name in ["gamma", "beta"] {
+      select(&mut list, name);
+      list.update(Action::TogglePinnedBranch).unwrap();
+      assert_eq!(selected_name(&list), name);
+    }
+    assert_eq!(names(&list), ["gamma", "beta", "alpha", "main"]);
+    assert!(list.branches[0].pinned && !list.branches[2].pinned);
+    fixture.git(&["branch", "-D", "gamma"]);
+    list.update(Action::Refresh).unwrap();
+    assert_eq!(list.pinned, ["beta"]);
+    select(&mut list, "beta");
+    list.update(Action::TogglePinnedBranch).unwrap();
+    assert_eq!(names(&list), ["alpha", "beta", "main"]);
+  }
+
+  #[test]
+  fn a_hard_reset_waits_for_the_typed_confirmation() {
+    let fixture = Fixture::new();
+    let mut list = loaded(&fixture);
+    list.open_reset();
+    // Mixed is the default, one tab on is hard
+    list.handle_reset_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)).unwrap();
+    let submitted = list.handle_reset_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+    assert_eq!(submitted, None);
+    assert!(list.confirm_dialog.is_open());
+  }
+
+  #[test]
+  fn a_mixed_reset_is_sent_straight_away() {
+    let fixture = Fixture::new();
+    let mut list = loaded(&fixture);
+    list.open_reset();
+    let submitted = list.handle_reset_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+    assert_eq!(submitted, Some(Action::ResetCurrentBranch(String::from("HEAD~1"), ResetMode::Mixed)));
+    assert!(!list.confirm_dialog.is_open());
+  }
+
+  #[test]
+  fn the_delete_question_warns_about_orphaned_commits_or_refuses() {
+    let fixture = Fixture::new();
+    fixture.git(&["checkout", "-q", "-b", "unmerged"]);
+    fixture.commit("one.txt", "one", "One");
+    fixture.git(&["checkout", "-q", "main"]);
+    fixture.git(&["branch", "elsewhere"]);
+    fixture.git(&["worktree", "add", "-q", fixture.dir.path().join("elsewhere").to_str().unwrap(), "elsewhere"]);
+    let mut list = loaded(&fixture);
+    let unmerged = list.repo.local_branches().unwrap().into_iter().find(|branch| branch.name == "unmerged").unwrap();
+    assert_eq!(
+      list.delete_question("Delete", &unmerged).unwrap(),
+      "Delete unmerged? 1 commit only on it will be left in the reflog."
+    );
+    let elsewhere = list.repo.local_branches().unwrap().into_iter().find(|branch| branch.name == "elsewhere").unwrap();
+    assert_eq!(list.delete_question("Delete", &elsewhere), None);
+    assert!(list.error.clone().unwrap().contains("worktree"), "{:?}", list.error);
+  }
+
+  #[test]
+  fn page_jumps_move_a_page_and_stop_at_the_ends() {
+    assert_eq!(page_target(5, 300, 20, true), 25);
+    assert_eq!(page_target(290, 300, 20, true), 299);
+    assert_eq!(page_target(25, 300, 20, false), 5);
+    assert_eq!(page_target(5, 300, 20, false), 0);
+    // A list shorter than the page
+    assert_eq!(page_target(1, 3, 20, true), 2);
+    assert_eq!(page_target(0, 0, 20, true), 0);
+  }
+
+  #[test]
+  fn the_offset_centers_the_selection_without_scrolling_past_either_end() {
+    assert_eq!(centered_offset(50, 300, 20), 40);
+    assert_eq!(centered_offset(3, 300, 20), 0);
+    assert_eq!(centered_offset(299, 300, 20), 280);
+    assert_eq!(centered_offset(2, 5, 20), 0);
+  }
+
+  #[test]
+  fn home_and_end_select_the_first_and_last_rows() {
+    let fixture = Fixture::new();
+    for name in ["a", "b", "c"] {
+      fixture.git(&["branch", name]);
+    }
+    let mut list = loaded(&fixture);
+    list.update(Action::SelectLastBranch).unwrap();
+    assert_eq!(selected_name(&list), "main");
+    list.update(Action::SelectFirstBranch).unwrap();
+    assert_eq!(selected_name(&list), "a");
+    // Already at the top, a page up stays there
+    list.update(Action::SelectPreviousPage).unwrap();
+    assert_eq!(selected_name(&list), "a");
+    // Two rows fit inside the borders
+    list.list_area = Rect::new(0, 0, 20, 4);
+    list.update(Action::SelectNextPage).unwrap();
+    assert_eq!(selected_name(&list), "c");
+    list.update(Action::SelectNextPage).unwrap();
+    assert_eq!(selected_name(&list), "main");
+  }
+
+  #[test]
+  fn the_configured_base_is_offered_first_without_repeats() {
+    let main = GitBranch { is_head: true, ..GitBranch::new(String::from("main")) };
+    let feature = GitBranch::new(String::from("feature"));
+    let local = [&main, &feature];
+    assert_eq!(new_branch_bases(None, &local, Some("feature")), (Vec::new(), None));
+    assert_eq!(
+      new_branch_bases(Some("main"), &local, Some("feature")),
+      (vec![String::from("main"), String::from("feature")], None)
+    );
+    assert_eq!(new_branch_bases(Some("feature"), &local, Some("feature")).0, ["feature", "main"]);
+  }
+
+  #[test]
+  fn a_missing_configured_base_falls_back_to_head_with_a_note() {
+    let main = GitBranch { is_head: true, ..GitBranch::new(String::from("main")) };
+    let (bases, note) = new_branch_bases(Some("develop"), &[&main], None);
+    assert_eq!(bases, Vec::<String>::new());
+    assert_eq!(note.as_deref(), Some("The default base branch develop doesn't exist, creating from HEAD"));
+  }
+
+  #[test]
+  fn a_branch_created_from_the_base_starts_at_its_tip() {
+    let fixture = Fixture::new();
+    let base = fixture.head();
+    fixture.git(&["checkout", "-q", "-b", "current"]);
+    fixture.commit("current.txt", "current", "Work on current");
+    let mut list = loaded(&fixture);
+    list.create_branch_from(String::from("from-main"), "main").unwrap();
+    assert_eq!(fixture.git(&["rev-parse", "HEAD"]), base);
+    assert_eq!(fixture.git(&["branch", "--show-current"]), "from-main");
+    assert_eq!(selected_name(&list), "from-main");
+  }
+
+  #[test]
+  fn a_forced_checkout_is_only_sent_once_typed_out() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    assert_eq!(list.handle_key_events(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::ALT)).unwrap(), None);
+    assert!(list.confirm_dialog.is_open());
+    for c in "FORCE".chars() {
+      assert_eq!(list.handle_key_events(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)).unwrap(), None);
+    }
+    let sent = list.handle_key_events(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+    assert_eq!(sent, Some(Action::ForceCheckoutBranch(String::from("feature"))));
+  }
+
+  #[test]
+  fn the_head_row_is_drawn_marked_and_in_the_head_color() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    // Narrower than the commit log needs, so only the list is drawn
+    let buffer = render(&mut list, 40, 8);
+    let rows = rows(&buffer);
+    assert_eq!(rows[1], " ┌Local Branches──────────────────────┐");
+    assert_eq!(rows[2], " │→feature                            │");
+    assert_eq!(rows[3], " │ main (HEAD) (protected)            │");
+    let theme = Theme::default();
+    assert_eq!(buffer[Position::new(3, 3)].fg, theme.head);
+    assert_eq!(buffer[Position::new(3, 2)].fg, theme.selected);
+    assert!(buffer[Position::new(3, 2)].modifier.contains(Modifier::BOLD));
+  }
+
+  #[test]
+  fn acting_on_a_branch_deleted_outside_asks_for_a_refresh() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    fixture.git(&["branch", "-D", "feature"]);
+    fixture.git(&["branch", "other"]);
+    list.update(Action::RenameSelectedBranch(String::from("renamed"))).unwrap();
+    assert_eq!(list.error.as_deref(), Some(Error::RepositoryChanged.to_string().as_str()));
+    assert_eq!(fixture.git(&["branch", "--format=%(refname:short)"]), "main\nother");
+  }
+
+  #[test]
+  fn the_filtered_rows_underline_the_matched_chars() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature/login"]);
+    fixture.git(&["branch", "fix-logo"]);
+    let mut list = loaded(&fixture);
+    for c in "flog".chars() {
+      list.update(Action::UpdateFilter(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))).unwrap();
+    }
+    let visible = list.visible_branches();
+    assert_eq!(visible.len(), 2);
+    let buffer = render(&mut list, 40, 8);
+    let rows = rows(&buffer);
+    // The rows start inside the border, with the names after the column for the highlight symbol
+    for (row, (index, matched)) in visible.iter().enumerate() {
+      let name = &list.branches[*index].branch.name;
+      let y = 2 + u16::try_from(row).unwrap();
+      assert_eq!(rows[usize::from(y)].chars().skip(3).take(name.chars().count()).collect::<String>(), *name);
+      for i in 0..name.chars().count() {
+        let cell = &buffer[Position::new(3 + u16::try_from(i).unwrap(), y)];
+        assert_eq!(cell.modifier.contains(Modifier::UNDERLINED), matched.contains(&i), "{} char {}", name, i);
+      }
+    }
+  }
+
+  #[test]
+  fn the_behind_sort_lists_the_most_behind_first_and_toggles_back_to_names() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "two-behind"]);
+    fixture.commit("a.txt", "a\n", "First on main");
+    fixture.git(&["branch", "one-behind"]);
+    fixture.commit("b.txt", "b\n", "Second on main");
+    fixture.git(&["branch", "level"]);
+    fixture.git(&["checkout", "-q", "--orphan", "unrelated"]);
+    fixture.commit("c.txt", "c\n", "No shared history");
+    fixture.git(&["checkout", "-q", "main"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "one-behind");
+
+    list.update(Action::ToggleBehindSort).unwrap();
+    assert_eq!(names(&list), ["two-behind", "one-behind", "level", "main", "unrelated"]);
+    assert_eq!(selected_name(&list), "one-behind");
+
+    list.update(Action::ToggleBehindSort).unwrap();
+    assert_eq!(names(&list), ["level", "main", "one-behind", "two-behind", "unrelated"]);
+    assert_eq!(selected_name(&list), "one-behind");
+  }
+
+  #[test]
+  fn each_change_is_recorded_in_the_history_with_how_it_went() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    let mut list = loaded(&fixture);
+    list.update(Action::CheckoutBranch(String::from("feature"))).unwrap();
+    list.update(Action::CreateBranch(String::from("main"))).unwrap();
+    list.history.open();
+
+    let rows = rows(&render(&mut list, 100, 30));
+    let entries: Vec<&String> = rows.iter().filter(|row| row.contains('✓') || row.contains('✗')).collect();
+    assert_eq!(entries.len(), 2, "{:#?}", rows);
+    // Newest first
+    assert!(entries[0].contains("✗") && entries[0].contains("Create main: "), "{}", entries[0]);
+    assert!(entries[1].contains("✓") && entries[1].contains("Check out feature"), "{}", entries[1]);
+  }
+
+  #[test]
+  fn checking_out_a_branch_without_an_upstream_offers_the_same_named_remote_branch() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.git(&["branch", "feature"]);
+    fixture.git(&["push", "-q", "origin", "feature"]);
+    let mut list = loaded(&fixture);
+    list.update(Action::CheckoutBranch(String::from("feature"))).unwrap();
+    assert!(list.confirm_dialog.is_open());
+
+    let DialogOutcome::Confirmed(action) = list.confirm_dialog.handle_key_event(KeyEvent::from(KeyCode::Char('y')))
+    else {
+      panic!("The question wasn't confirmed");
+    };
+    list.update(action).unwrap();
+    assert_eq!(fixture.git(&["rev-parse", "--abbrev-ref", "feature@{upstream}"]), "origin/feature");
+  }
+
+  #[test]
+  fn quick_checkout_is_only_sent_once_turned_on() {
+    let fixture = Fixture::new();
+    let shift_s = KeyEvent::new(KeyCode::Char('S'), KeyModifiers::SHIFT);
+    let mut list = loaded(&fixture);
+    assert_eq!(list.handle_key_events(shift_s).unwrap(), None);
+    assert!(list.notice.as_deref().unwrap().starts_with("Quick checkout is off"));
+
+    let config = Config { quick_checkout: true, ..Config::default() };
+    let mut list = BranchList::new(Box::new(fixture.cli()), &config);
+    assert_eq!(list.handle_key_events(shift_s).unwrap(), Some(Action::QuickCheckoutSelectedBranch));
+  }
+
+  #[test]
+  fn a_bulk_rename_renames_the_rest_when_one_fails() {
+    let fixture = Fixture::new();
+    for name in ["feat/login", "feat/logout", "feature/logout"] {
+      fixture.git(&["branch", name]);
+    }
+    let mut list = loaded(&fixture);
+    let renames = vec![
+      (String::from("feat/login"), String::from("feature/login")),
+      (String::from("feat/logout"), String::from("feature/logout")),
+    ];
+    list.update(Action::RenameBranches(renames)).unwrap();
+    assert_eq!(names(&list), ["feat/logout", "feature/login", "feature/logout", "main"]);
+    let error = list.error.clone().unwrap();
+    assert!(error.starts_with("Renamed 1, failed 1\nfeat/logout: "), "{}", error);
+  }
+
+  #[test]
+  fn the_rows_count_the_commits_the_default_branch_doesnt_have() {
+    let fixture = Fixture::new();
+    fixture.git(&["checkout", "-q", "-b", "feature"]);
+    fixture.commit("one.txt", "one", "One");
+    fixture.commit("two.txt", "two", "Two");
+    fixture.git(&["checkout", "-q", "main"]);
+    let repo = fixture.cli();
+    let branches = repo.local_branches().unwrap();
+    let mut config = Config::default();
+    config.list_layout.unique_commits = true;
+    let mut list = BranchList::new(Box::new(repo), &config);
+    list.update(Action::BranchesLoaded(branches)).unwrap();
+
+    let rows = rows(&render(&mut list, 60, 8));
+    assert!(rows[2].starts_with(" │→feature 2 commits"), "{}", rows[2]);
+    // The default branch has nothing of its own to count
+    assert!(rows[3].starts_with(" │ main (HEAD) (protected) -"), "{}", rows[3]);
+  }
+
+  #[test]
+  fn a_branch_is_created_at_the_commit_picked_from_the_log() {
+    let fixture = Fixture::new();
+    fixture.git(&["checkout", "-q", "-b", "feature"]);
+    let picked = fixture.commit("one.txt", "one", "One");
+    fixture.commit("two.txt", "two", "Two");
+    fixture.git(&["checkout", "-q", "main"]);
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    let press = |list: &mut BranchList, code: KeyCode| list.handle_key_events(KeyEvent::from(code)).unwrap();
+    assert_eq!(press(&mut list, KeyCode::Char('b')), Some(Action::StartInputMode));
+    // The newest commit is listed first
+    press(&mut list, KeyCode::Down);
+    assert_eq!(press(&mut list, KeyCode::Enter), None);
+    // Typing in the name goes through an action for each key
+    let mut typed = Vec::new();
+    for code in "fix".chars().map(KeyCode::Char).chain([KeyCode::Enter]) {
+      let typing = press(&mut list, code).unwrap();
+      typed.extend(list.update(typing).unwrap());
+    }
+    let sent = typed.pop().unwrap();
+    assert_eq!(sent, Action::CreateBranchAt(String::from("fix"), picked.clone()));
+
+    list.update(sent).unwrap();
+    assert_eq!(fixture.git(&["rev-parse", "fix"]), picked);
+    assert_eq!(selected_name(&list), "fix");
+  }
+
+  #[test]
+  fn the_filter_ignores_accents_only_when_configured() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "résumé-page"]);
+    let filtered = |config: &Config| {
+      let repo = fixture.cli();
+      let branches = repo.local_branches().unwrap();
+      let mut list = BranchList::new(Box::new(repo), config);
+      list.update(Action::BranchesLoaded(branches)).unwrap();
+      for c in "RESUME".chars() {
+        list.update(Action::UpdateFilter(KeyEvent::from(KeyCode::Char(c)))).unwrap();
+      }
+      list.visible_branches().iter().map(|(index, _)| list.branches[*index].branch.name.clone()).collect::<Vec<_>>()
+    };
+    assert!(filtered(&Config::default()).is_empty());
+    let config = Config { match_ignores_accents: true, ..Config::default() };
+    assert_eq!(filtered(&config), ["résumé-page"]);
+  }
+
+  #[test]
+  fn the_ticket_filter_narrows_to_the_selected_branchs_ticket() {
+    let fixture = Fixture::new();
+    for name in ["ABC-1-login", "ABC-1-logout", "ABC-2-signup"] {
+      fixture.git(&["branch", name]);
+    }
+    let repo = fixture.cli();
+    let branches = repo.local_branches().unwrap();
+    let config = Config { ticket_pattern: Some(String::from(r"[A-Z]+-\d+")), ..Config::default() };
+    let mut list = BranchList::new(Box::new(repo), &config);
+    list.update(Action::BranchesLoaded(branches)).unwrap();
+    let visible = |list: &BranchList| {
+      list.visible_branches().iter().map(|(index, _)| list.branches[*index].branch.name.clone()).collect::<Vec<_>>()
+    };
+
+    select(&mut list, "ABC-1-logout");
+    list.update(Action::ToggleTicketFilter).unwrap();
+    assert_eq!(visible(&list), ["ABC-1-login", "ABC-1-logout"]);
+    list.update(Action::ToggleTicketFilter).unwrap();
+    assert_eq!(visible(&list).len(), 4);
+
+    select(&mut list, "main");
+    list.update(Action::ToggleTicketFilter).unwrap();
+    assert_eq!(list.notice.as_deref(), Some("main has no ticket"));
+    assert_eq!(visible(&list).len(), 4);
+  }
+
+  #[test]
+  fn bisecting_between_two_branches_finds_the_first_bad_commit_and_goes_back() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "known-good"]);
+    let commits: Vec<String> =
+      (1..=8).map(|n| fixture.commit(&format!("{}.txt", n), "n", &format!("Commit {}", n))).collect();
+    let first_bad = commits[4].clone();
+    let mut list = loaded(&fixture);
+
+    select(&mut list, "known-good");
+    list.update(Action::MarkBisectGood).unwrap();
+    select(&mut list, "main");
+    list.update(Action::MarkBisectBad).unwrap();
+    let mut steps = 0;
+    while let Some(testing) = list.bisect.as_ref().and_then(|bisect| bisect.testing.clone()) {
+      assert_eq!(fixture.head(), testing);
+      let is_bad = commits.iter().position(|commit| *commit == testing).unwrap() >= 4;
+      list.update(if is_bad { Action::MarkBisectBad } else { Action::MarkBisectGood }).unwrap();
+      steps += 1;
+      assert!(steps <= 4, "Bisecting 8 commits took too many steps");
+    }
+    let short_id = fixture.git(&["rev-parse", "--short", &first_bad]);
+    assert_eq!(list.notice, Some(format!("{} is the first bad commit", short_id)));
+    assert_eq!(list.error, None);
+    assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+  }
+
+  #[test]
+  fn previewing_a_checkout_confirms_carried_changes_and_refuses_blocking_ones() {
+    let fixture = Fixture::new();
+    fixture.commit("shared.txt", "shared\n", "Add shared");
+    fixture.git(&["checkout", "-q", "-b", "feature"]);
+    fixture.commit("conflict.txt", "changed on feature\n", "Add conflict");
+    fixture.git(&["checkout", "-q", "main"]);
+    fixture.write("shared.txt", "edited\n");
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    list.handle_key_events(KeyEvent::from(KeyCode::Char('v'))).unwrap();
+    assert!(list.confirm_dialog.is_open());
+    let DialogOutcome::Confirmed(action) = list.confirm_dialog.handle_key_event(KeyEvent::from(KeyCode::Char('y')))
+    else {
+      panic!("The question wasn't confirmed");
+    };
+    assert_eq!(action, Action::CheckoutBranch(String::from("feature")));
+
+    fixture.write("conflict.txt", "untracked\n");
+    let mut list = loaded(&fixture);
+    select(&mut list, "feature");
+    list.handle_key_events(KeyEvent::from(KeyCode::Char('v'))).unwrap();
+    assert!(!list.confirm_dialog.is_open());
+    assert_eq!(
+      list.notice.as_deref(),
+      Some("Changes to conflict.txt stop checking out feature, the changes to shared.txt would be carried over")
+    );
+  }
+
+  fn type_into_palette(list: &mut BranchList, text: &str) {
+    for c in text.chars() {
+      assert_eq!(list.handle_key_events(KeyEvent::from(KeyCode::Char(c))).unwrap(), None);
+    }
+  }
+
+  #[test]
+  fn a_hash_in_the_palette_offers_the_branch_at_it_or_a_detached_checkout() {
+    let fixture = Fixture::new();
+    let first = fixture.head();
+    let feature = fixture.branch_with_commit("feature");
+    let mut list = loaded(&fixture);
+    let ctrl_p = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL);
+
+    assert_eq!(list.handle_key_events(ctrl_p).unwrap(), Some(Action::StartInputMode));
+    type_into_palette(&mut list, &feature[..7]);
+    let sent = list.handle_key_events(KeyEvent::from(KeyCode::Enter)).unwrap();
+    assert_eq!(sent, Some(Action::CheckoutBranch(String::from("feature"))));
+
+    fixture.commit("second.txt", "second\n", "Second on main");
+    let mut list = loaded(&fixture);
+    list.handle_key_events(ctrl_p).unwrap();
+    type_into_palette(&mut list, &first);
+    assert!(matches!(list.palette.selected_action(), Some(PaletteAction::Detach { .. })));
+    let sent = list.handle_key_events(KeyEvent::from(KeyCode::Enter)).unwrap();
+    assert_eq!(sent, Some(Action::CheckoutCommit(first.clone())));
+    list.update(sent.unwrap()).unwrap();
+    assert_eq!(fixture.head(), first);
+    assert!(fixture.git(&["status", "--branch", "--porcelain"]).starts_with("## HEAD (no branch)"));
+  }
+
+  #[test]
+  fn an_ambiguous_hash_in_the_palette_is_shown_as_an_error() {
+    let fixture = Fixture::new();
+    let prefix = fixture.ambiguous_prefix();
+    let mut list = loaded(&fixture);
+    list.handle_key_events(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL)).unwrap();
+    type_into_palette(&mut list, &prefix);
+    assert_eq!(list.palette.selected_action(), None);
+    let shown = rows(&render(&mut list, 160, 30)).join("\n");
+    assert!(shown.contains(&format!("short object ID {} is ambiguous", prefix)), "{}", shown);
+  }
+
+  #[test]
+  fn branches_as_far_behind_stay_in_name_order_across_refreshes() {
+    let fixture = Fixture::new();
+    for name in ["zeta", "alpha", "mid"] {
+      fixture.git(&["branch", name]);
+    }
+    fixture.commit("a.txt", "a\n", "Ahead of them all");
+    let mut list = loaded(&fixture);
+    list.update(Action::ToggleBehindSort).unwrap();
+    assert_eq!(names(&list), ["alpha", "mid", "zeta", "main"]);
+    let branches = fixture.cli().local_branches().unwrap().into_iter().rev().collect();
+    list.update(Action::BranchesLoaded(branches)).unwrap();
+    assert_eq!(names(&list), ["alpha", "mid", "zeta", "main"]);
+  }
+
+  #[test]
+  fn rows_with_the_same_name_put_the_local_branch_first() {
+    let local = BranchItem { branch: GitBranch::new(String::from("feature")), ..BranchItem::default() };
+    let remote = BranchItem { is_remote: true, ..local.clone() };
+    assert_eq!(name_order(&local, &remote), Ordering::Less);
+    assert_eq!(name_order(&remote, &local), Ordering::Greater);
+    assert_eq!(name_order(&local, &local), Ordering::Equal);
+  }
+
+  #[test]
+  fn a_fetch_reports_the_branches_behind_and_fast_forwarding_skips_a_dirty_checkout() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.git(&["branch", "behind"]);
+    fixture.git(&["push", "-q", "-u", "origin", "behind"]);
+    let other = fixture.other_clone();
+    for name in ["behind", "main"] {
+      git_in(&other, &["checkout", "-q", name]);
+      git_in(&other, &["commit", "-q", "--allow-empty", "-m", &format!("Upstream work on {}", name)]);
+      git_in(&other, &["push", "-q", "origin", name]);
+    }
+    fixture.git(&["fetch", "-q", "origin"]);
+    let mut list = loaded(&fixture);
+    list.update(Action::RemoteFetched).unwrap();
+    let key = list.keybindings.label(KeyCommand::FastForwardAll);
+    assert_eq!(list.notice, Some(format!("behind, main can be fast-forwarded, {} to do it", key)));
+
+    fixture.write("README.md", "local changes\n");
+    list.handle_key_events(KeyEvent::new(KeyCode::Char('F'), KeyModifiers::SHIFT)).unwrap();
+    let DialogOutcome::Confirmed(action) = list.confirm_dialog.handle_key_event(KeyEvent::from(KeyCode::Char('y')))
+    else {
+      panic!("The question wasn't confirmed");
+    };
+    assert_eq!(action, Action::FastForwardBranches);
+    list.update(action).unwrap();
+    assert_eq!(list.notice.as_deref(), Some("Fast-forwarded behind, skipped main which has local changes"));
+    assert_eq!(fixture.git(&["rev-parse", "behind"]), fixture.git(&["rev-parse", "origin/behind"]));
+    assert_ne!(fixture.head(), fixture.git(&["rev-parse", "origin/main"]));
+  }
+
+  /// The all scope of a repository where `feature` tracks `origin/feature` and `origin/fresh` has no local branch.
+  fn all_scope(fixture: &Fixture, config: &Config) -> Vec<String> {
+    let branches = fixture.cli().local_branches().unwrap();
+    let mut list = BranchList::new(Box::new(fixture.cli()), config);
+    list.update(Action::BranchesLoaded(branches)).unwrap();
+    list.update(Action::CycleBranchScope).unwrap();
+    list.update(Action::CycleBranchScope).unwrap();
+    assert_eq!(list.scope, BranchScope::All);
+    // Without the right border, so the rows end with the names
+    rows(&render(&mut list, 100, 12)).iter().map(|row| row.trim_end_matches('│').trim_end().to_string()).collect()
+  }
+
+  #[test]
+  fn the_all_scope_folds_tracked_remotes_into_their_local_rows_unless_configured() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.git(&["branch", "feature"]);
+    fixture.git(&["push", "-q", "-u", "origin", "feature"]);
+    fixture.git(&["push", "-q", "origin", "main:fresh"]);
+    fixture.git(&["fetch", "-q", "origin"]);
+    // Compact leaves the upstream out, so it is only shown for a folded in remote branch
+    let compact = ListLayout { density: Density::Compact, ..ListLayout::default() };
+
+    let folded = all_scope(&fixture, &Config { list_layout: compact.clone(), ..Config::default() });
+    assert_eq!(folded[2], " │→feature [origin/feature]");
+    assert_eq!(folded[3], " │ main (HEAD) (protected) [origin/main]");
+    assert_eq!(folded[4], " │ origin/fresh");
+    assert!(!folded.iter().any(|row| row.contains("tracked by")), "{:#?}", folded);
+
+    let listed = all_scope(&fixture, &Config { list_layout: compact, show_tracked_remotes: true, ..Config::default() });
+    // Still selected from the remote scope the cycle went through, as it is listed here too
+    assert_eq!(&listed[2..7], [
+      " │ feature",
+      " │ main (HEAD) (protected)",
+      " │→origin/feature",
+      " │ origin/fresh",
+      " │ origin/main",
+    ]);
+  }
+
+  #[test]
+  fn a_prune_offers_to_delete_the_gone_branches_and_the_delete_can_be_undone() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    for name in ["gone", "kept"] {
+      fixture.git(&["branch", name]);
+      fixture.git(&["push", "-q", "-u", "origin", name]);
+    }
+    fixture.git(&["push", "-q", "origin", "--delete", "gone"]);
+    fixture.git(&["fetch", "-q", "--prune", "origin"]);
+    let mut list = loaded(&fixture);
+    let sent = list.update(Action::RemotePruned(vec![String::from("origin/gone")])).unwrap();
+    assert_eq!(sent, Some(Action::StartInputMode));
+    let DialogOutcome::Confirmed(action) = list.confirm_dialog.handle_key_event(KeyEvent::from(KeyCode::Char('y')))
+    else {
+      panic!("The question wasn't confirmed");
+    };
+    assert_eq!(action, Action::DeleteGoneBranches(vec![String::from("gone")]));
+    list.update(action).unwrap();
+    let undo = list.keybindings.label(KeyCommand::Undo);
+    assert_eq!(list.notice, Some(format!("Deleted gone, {} to undo", undo)));
+    assert_eq!(names(&list), ["kept", "main"]);
+
+    list.update(Action::UndoDelete).unwrap();
+    assert_eq!(names(&list), ["gone", "kept", "main"]);
+  }
+
+  #[test]
+  fn a_prune_without_gone_branches_only_says_so() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    let mut list = loaded(&fixture);
+    assert_eq!(list.update(Action::RemotePruned(Vec::new())).unwrap(), None);
+    assert!(!list.confirm_dialog.is_open());
+    assert_eq!(
+      list.notice.as_deref(),
+      Some("Pruned 0 remote branches, no local branches are left without an upstream")
+    );
+  }
 }
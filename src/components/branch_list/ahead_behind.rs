@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use crate::git::git_repo::GitBranch;
+
+/// The branch and upstream commits that the counts were worked out for.
+type Tips = (Option<String>, Option<String>);
+
+/// Details worked out from a branch's commits by branch name, like how far ahead and behind its upstream it is, kept
+/// until the branch or its upstream moves to another commit.
+#[derive(Debug)]
+pub struct AheadBehindCache<T = Option<(usize, usize)>> {
+  counts: HashMap<String, (Tips, T)>,
+}
+
+impl<T> Default for AheadBehindCache<T> {
+  fn default() -> Self {
+    AheadBehindCache { counts: HashMap::new() }
+  }
+}
+
+impl<T: Clone> AheadBehindCache<T> {
+  /// The cached value for the branch, or the result of `compute` when the branch is new or its tips have changed.
+  pub fn get_or_compute(&mut self, branch: &GitBranch, compute: impl FnOnce() -> T) -> T {
+    let tips = (branch.commit_id.clone(), branch.upstream_commit_id.clone());
+    if let Some((cached_tips, counts)) = self.counts.get(&branch.name) {
+      if *cached_tips == tips {
+        return counts.clone();
+      }
+    }
+    let counts = compute();
+    self.counts.insert(branch.name.clone(), (tips, counts.clone()));
+    counts
+  }
+}
+
+/// How many commits each branch has that the default branch doesn't and the other way round, by the branch and
+/// default branch commits they were counted between, so they are only counted again once either moves.
+#[derive(Debug, Default)]
+pub struct DivergenceCache {
+  counts: HashMap<(String, String), Option<(usize, usize)>>,
+}
+
+impl DivergenceCache {
+  /// The cached counts for the two commits, or the result of `compute` when they haven't been counted yet.
+  pub fn get_or_compute(
+    &mut self,
+    tip: &str,
+    base_tip: &str,
+    compute: impl FnOnce() -> Option<(usize, usize)>,
+  ) -> Option<(usize, usize)> {
+    *self.counts.entry((String::from(tip), String::from(base_tip))).or_insert_with(compute)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(commit_id: &str, upstream_commit_id: &str) -> GitBranch {
+    GitBranch {
+      commit_id: Some(String::from(commit_id)),
+      upstream_commit_id: Some(String::from(upstream_commit_id)),
+      ..GitBranch::new(String::from("feature"))
+    }
+  }
+
+  #[test]
+  fn the_same_tips_return_the_cached_counts() {
+    let mut cache = AheadBehindCache::default();
+    assert_eq!(cache.get_or_compute(&at("a", "b"), || Some((1, 2))), Some((1, 2)));
+    assert_eq!(cache.get_or_compute(&at("a", "b"), || panic!("recomputed")), Some((1, 2)));
+  }
+
+  #[test]
+  fn a_moved_branch_or_upstream_is_recomputed() {
+    let mut cache = AheadBehindCache::default();
+    cache.get_or_compute(&at("a", "b"), || Some((1, 2)));
+    assert_eq!(cache.get_or_compute(&at("c", "b"), || Some((2, 2))), Some((2, 2)));
+    assert_eq!(cache.get_or_compute(&at("c", "d"), || None), None);
+    assert_eq!(cache.get_or_compute(&at("c", "d"), || Some((9, 9))), None);
+  }
+
+  #[test]
+  fn divergence_is_cached_by_both_commits() {
+    let mut cache = DivergenceCache::default();
+    assert_eq!(cache.get_or_compute("a", "main", || Some((1, 0))), Some((1, 0)));
+    assert_eq!(cache.get_or_compute("a", "main", || panic!("recomputed")), Some((1, 0)));
+    assert_eq!(cache.get_or_compute("a", "main2", || Some((3, 1))), Some((3, 1)));
+  }
+}
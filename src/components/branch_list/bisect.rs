@@ -0,0 +1,32 @@
+/// A bisect for the first bad commit between branches marked good and bad, kept here rather than in git's own bisect
+/// state so it is forgotten when the app quits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bisect {
+  /// The commits known to be good.
+  pub good: Vec<String>,
+  /// The newest commit known to be bad.
+  pub bad: Option<String>,
+  /// The commit checked out to be marked next.
+  pub testing: Option<String>,
+  /// The branch, or commit when HEAD was detached, checked out again when the bisect ends.
+  pub return_to: String,
+}
+
+impl Bisect {
+  pub fn new(return_to: String) -> Self {
+    Bisect { good: Vec::new(), bad: None, testing: None, return_to }
+  }
+
+  pub fn mark(&mut self, commit_id: String, good: bool) {
+    if good {
+      self.good.push(commit_id);
+    } else {
+      self.bad = Some(commit_id);
+    }
+  }
+
+  /// The commit known to be bad once a good one is known too, so there is a range to bisect.
+  pub fn range(&self) -> Option<&str> {
+    self.bad.as_deref().filter(|_| !self.good.is_empty())
+  }
+}
@@ -1,15 +1,18 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
   layout::Rect,
-  prelude::Color,
   style::Style,
   widgets::{Block, Borders},
 };
+use tracing::warn;
 use tui_textarea::{CursorMove, Input, TextArea};
 
 use crate::{
   action::Action,
-  git::git_repo::{GitBranch, GitRepo},
+  config::{snippets::NameSnippets, theme::Theme},
+  git::git_repo::{GitRepo, InvalidBranchName},
   tui::Frame,
 };
 
@@ -19,21 +22,136 @@ pub struct InputState {
   pub is_valid: Option<bool>,
 }
 
+/// What the submitted name is used for.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+enum InputPurpose {
+  /// A new branch from HEAD that is then checked out.
+  #[default]
+  Create,
+  /// A new name for the branch currently called this.
+  Rename(String),
+  /// A new branch at the commit, left unchecked out.
+  CreateAt { commit_id: String, short_id: String },
+  /// A new branch from one of the `bases` that is then checked out, tab moves `chosen` to the next base.
+  CreateFrom { bases: Vec<String>, chosen: usize },
+  /// A description for the branch called this rather than a name, submitting nothing removes it.
+  Describe(String),
+}
+
+impl InputPurpose {
+  fn title(&self) -> Option<String> {
+    match self {
+      InputPurpose::Create => None,
+      InputPurpose::Rename(_) => Some(String::from("Rename")),
+      InputPurpose::CreateAt { short_id, .. } => Some(format!("New branch at {}", short_id)),
+      InputPurpose::CreateFrom { bases, chosen } if bases.len() > 1 => {
+        Some(format!("New branch from {} (tab: change base)", bases[*chosen]))
+      },
+      InputPurpose::CreateFrom { bases, chosen } => Some(format!("New branch from {}", bases[*chosen])),
+      InputPurpose::Describe(name) => Some(format!("Description of {} (empty to remove)", name)),
+    }
+  }
+}
+
 pub struct BranchInput {
   pub text_input: TextArea<'static>,
   pub input_state: InputState,
+  purpose: InputPurpose,
+  /// Shown in the title when nothing more pressing is, until the input is closed.
+  note: Option<String>,
+  snippets: NameSnippets,
+  theme: Theme,
 }
 
 impl BranchInput {
-  pub fn new() -> Self {
-    BranchInput { text_input: TextArea::default(), input_state: InputState::default() }
+  pub fn new(theme: Theme, snippets: NameSnippets) -> Self {
+    BranchInput {
+      text_input: TextArea::default(),
+      input_state: InputState::default(),
+      purpose: InputPurpose::default(),
+      note: None,
+      snippets,
+      theme,
+    }
   }
 
   pub fn init_style(&mut self) {
-    self.text_input.set_style(Style::default().fg(Color::White));
+    self.text_input.set_style(Style::default().fg(self.theme.text));
     self.text_input.set_block(Block::default().borders(Borders::ALL));
   }
 
+  /// Pre-fills the input with the branch's name so it can be edited in place.
+  pub fn start_rename(&mut self, name: &str) {
+    self.init_style();
+    self.purpose = InputPurpose::Rename(name.to_string());
+    self.text_input.set_block(Block::default().title("Rename").borders(Borders::ALL));
+    self.text_input.insert_str(name);
+    self.input_state = InputState { value: Some(name.to_string()), is_valid: Some(true) };
+  }
+
+  /// Asks for the name of a branch to create at `commit_id`.
+  pub fn start_create_at(&mut self, commit_id: &str, short_id: &str) {
+    self.init_style();
+    self.purpose = InputPurpose::CreateAt { commit_id: commit_id.to_string(), short_id: short_id.to_string() };
+    self.set_purpose_title();
+  }
+
+  /// Asks for the name of a branch to create from HEAD, explaining why with `note`.
+  pub fn start_create_with_note(&mut self, note: String) {
+    self.init_style();
+    self.note = Some(note);
+    self.set_purpose_title();
+  }
+
+  /// Asks for the name of a branch to create from the first of `bases`, which must not be empty.
+  pub fn start_create_from(&mut self, bases: Vec<String>) {
+    self.init_style();
+    self.purpose = InputPurpose::CreateFrom { bases, chosen: 0 };
+    self.set_purpose_title();
+  }
+
+  fn set_purpose_title(&mut self) {
+    let mut block = Block::default().borders(Borders::ALL);
+    if let Some(title) = self.purpose.title().or(self.note.clone()) {
+      block = block.title(title);
+    }
+    self.text_input.set_block(block);
+  }
+
+  fn next_base(&mut self, repo: &dyn GitRepo) {
+    if let InputPurpose::CreateFrom { bases, chosen } = &mut self.purpose {
+      *chosen = (*chosen + 1) % bases.len();
+    }
+    match self.get_text() {
+      Some(_) => self.validate_branch_name(repo),
+      None => self.set_purpose_title(),
+    }
+  }
+
+  /// Asks for a description of the branch, pre-filled with its current one.
+  pub fn start_describe(&mut self, name: &str, current: Option<&str>) {
+    self.init_style();
+    self.purpose = InputPurpose::Describe(name.to_string());
+    self.set_purpose_title();
+    if let Some(current) = current {
+      self.text_input.insert_str(current);
+    }
+    self.input_state = InputState { value: current.map(String::from), is_valid: Some(true) };
+  }
+
+  /// Whether the input is naming a branch that doesn't exist yet, rather than renaming or describing one.
+  pub fn is_creating(&self) -> bool {
+    !matches!(self.purpose, InputPurpose::Rename(_) | InputPurpose::Describe(_))
+  }
+
+  fn clear(&mut self) {
+    self.input_state.value = None;
+    self.purpose = InputPurpose::Create;
+    self.note = None;
+    self.text_input.move_cursor(CursorMove::Head);
+    self.text_input.delete_line_by_end();
+  }
+
   fn get_text(&self) -> Option<String> {
     let input = String::from(self.text_input.lines().first()?.trim());
     if input.is_empty() {
@@ -42,58 +160,122 @@ impl BranchInput {
     Some(input)
   }
 
-  fn validate_branch_name(&mut self, repo: &dyn GitRepo, current_branches: Vec<&GitBranch>) {
-    if self.text_input.lines().first().is_none() {
+  fn validate_branch_name(&mut self, repo: &dyn GitRepo) {
+    if self.text_input.lines().is_empty() {
       return;
     }
     let proposed_name = self.text_input.lines().first().unwrap();
-    let is_valid = repo.validate_branch_name(proposed_name);
-    let is_unique_name = !current_branches.iter().any(|b| b.name.eq(proposed_name));
-    if is_valid.is_err() || !is_valid.unwrap() || !is_unique_name {
-      self.text_input.set_style(Style::default().fg(Color::LightRed));
-      self.input_state.is_valid = Some(false);
-      return;
+    let reason = match repo.validate_branch_name_detailed(proposed_name) {
+      Ok(None) => None,
+      // Keeping the current name while renaming is allowed, it just does nothing
+      Ok(Some(InvalidBranchName::AlreadyExists)) if self.purpose == InputPurpose::Rename(proposed_name.clone()) => {
+        self.set_invalid_reason(None, None);
+        return;
+      },
+      Ok(Some(reason)) => Some(reason.to_string()),
+      Err(err) => Some(err.to_string()),
+    };
+    let warning = match &reason {
+      Some(_) => None,
+      None => {
+        repo.branch_name_warning(proposed_name).unwrap_or_else(|err| {
+          warn!("Unable to check {} against the remote branches: {}", proposed_name, err);
+          None
+        })
+      },
+    };
+    self.set_invalid_reason(reason, warning.map(|warning| warning.to_string()));
+  }
+
+  /// Colors the input by whether the name can be used and shows the reason in the title when it can't. A warning
+  /// is shown the same way but still lets the name be submitted.
+  fn set_invalid_reason(&mut self, reason: Option<String>, warning: Option<String>) {
+    let color = match (&reason, &warning) {
+      (Some(_), _) => self.theme.invalid_name,
+      (None, Some(_)) => self.theme.warning,
+      (None, None) => self.theme.valid_name,
+    };
+    self.text_input.set_style(Style::default().fg(color));
+    let mut block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(color));
+    if let Some(title) = reason.clone().or(warning).or(self.purpose.title()).or(self.note.clone()) {
+      block = block.title(title);
     }
-    self.text_input.set_style(Style::default().fg(Color::LightGreen));
-    self.input_state.is_valid = Some(true);
+    self.text_input.set_block(block);
+    self.input_state.is_valid = Some(reason.is_none());
   }
 
-  pub fn handle_key_event(
-    &mut self,
-    key_event: KeyEvent,
-    repo: &dyn GitRepo,
-    current_branches: Vec<&GitBranch>,
-  ) -> Option<Action> {
+  /// Inserts the snippet bound to the key at the cursor, returning whether there was one.
+  fn insert_snippet(&mut self, key_event: &KeyEvent, repo: &dyn GitRepo) -> bool {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0);
+    let short_sha = || {
+      repo.head_short_id().unwrap_or_else(|err| {
+        warn!("Unable to read HEAD for a branch name snippet: {}", err);
+        None
+      })
+    };
+    let Some(text) = self.snippets.expand(key_event, now, short_sha) else {
+      return false;
+    };
+    self.text_input.insert_str(text);
+    self.text_changed(repo);
+    true
+  }
+
+  fn text_changed(&mut self, repo: &dyn GitRepo) {
+    if !matches!(self.purpose, InputPurpose::Describe(_)) {
+      self.validate_branch_name(repo);
+    }
+    let new_branch_name = self.get_text();
+    if new_branch_name.is_some() {
+      self.input_state.value = new_branch_name;
+    }
+  }
+
+  pub fn handle_key_event(&mut self, key_event: KeyEvent, repo: &dyn GitRepo) -> Option<Action> {
+    let describing = matches!(self.purpose, InputPurpose::Describe(_));
+    if !describing && self.insert_snippet(&key_event, repo) {
+      return None;
+    }
     match key_event {
       KeyEvent { code: KeyCode::Esc, modifiers: KeyModifiers::NONE, kind: _, state: _ } => {
-        self.input_state.value = None;
         // purposely don't send the key, we want to delete the line
-        self.text_input.move_cursor(CursorMove::Head);
-        self.text_input.delete_line_by_end();
+        self.clear();
         Some(Action::EndInputMod)
       },
       KeyEvent { code: KeyCode::Enter, modifiers: _, kind: _, state: _ } => {
-        if self.input_state.is_valid.is_some() && !self.input_state.is_valid? {
-          // TODO report error
+        let new_branch_name = self.get_text();
+        // The reason is shown on the input, an empty input still just cancels
+        if new_branch_name.is_some() && self.input_state.is_valid == Some(false) {
           return None;
         }
-        let new_branch_name = self.get_text();
+        let purpose = std::mem::take(&mut self.purpose);
         // purposely don't send the key, we want to delete the line
-        self.text_input.move_cursor(CursorMove::Head);
-        self.text_input.delete_line_by_end();
-        if let Some(name) = new_branch_name {
-          return Some(Action::CreateBranch(name));
+        self.clear();
+        match (new_branch_name, purpose) {
+          (Some(name), InputPurpose::Rename(original)) if name != original => {
+            return Some(Action::RenameSelectedBranch(name))
+          },
+          (Some(_), InputPurpose::Rename(_)) => {},
+          (Some(name), InputPurpose::Create) => return Some(Action::CreateBranch(name)),
+          (Some(name), InputPurpose::CreateAt { commit_id, .. }) => {
+            return Some(Action::CreateBranchAt(name, commit_id))
+          },
+          (Some(name), InputPurpose::CreateFrom { mut bases, chosen }) => {
+            return Some(Action::CreateBranchFrom(name, bases.swap_remove(chosen)))
+          },
+          (description, InputPurpose::Describe(name)) => return Some(Action::SetBranchDescription(name, description)),
+          (None, _) => {},
         }
 
         Some(Action::EndInputMod)
       },
+      KeyEvent { code: KeyCode::Tab, .. } if matches!(self.purpose, InputPurpose::CreateFrom { .. }) => {
+        self.next_base(repo);
+        None
+      },
       _ => {
         if self.text_input.input(Input::from(key_event)) {
-          self.validate_branch_name(repo, current_branches);
-          let new_branch_name = self.get_text();
-          if new_branch_name.is_some() {
-            self.input_state.value = new_branch_name;
-          }
+          self.text_changed(repo);
         }
         None
       },
@@ -104,3 +286,76 @@ impl BranchInput {
     f.render_widget(&self.text_input, area);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::Fixture;
+
+  fn press(input: &mut BranchInput, repo: &dyn GitRepo, code: KeyCode) -> Option<Action> {
+    input.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE), repo)
+  }
+
+  fn type_text(input: &mut BranchInput, repo: &dyn GitRepo, text: &str) {
+    for c in text.chars() {
+      press(input, repo, KeyCode::Char(c));
+    }
+  }
+
+  fn renaming(name: &str) -> BranchInput {
+    let mut input = BranchInput::new(Theme::default(), NameSnippets::default());
+    input.start_rename(name);
+    input
+  }
+
+  #[test]
+  fn an_invalid_new_name_turns_the_input_red_and_cant_be_submitted() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    let mut input = renaming("main");
+    type_text(&mut input, &repo, "..");
+    assert_eq!(input.input_state.is_valid, Some(false));
+    assert_eq!(input.text_input.style().fg, Some(Theme::default().invalid_name));
+    assert_eq!(press(&mut input, &repo, KeyCode::Enter), None);
+  }
+
+  #[test]
+  fn a_valid_new_name_is_submitted_as_a_rename() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    let mut input = renaming("main");
+    type_text(&mut input, &repo, "-renamed");
+    assert_eq!(input.input_state.is_valid, Some(true));
+    assert_eq!(input.text_input.style().fg, Some(Theme::default().valid_name));
+    assert_eq!(
+      press(&mut input, &repo, KeyCode::Enter),
+      Some(Action::RenameSelectedBranch(String::from("main-renamed")))
+    );
+  }
+
+  #[test]
+  fn escape_cancels_the_rename() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    let mut input = renaming("main");
+    type_text(&mut input, &repo, "-renamed");
+    assert_eq!(press(&mut input, &repo, KeyCode::Esc), Some(Action::EndInputMod));
+    assert_eq!(input.get_text(), None);
+  }
+
+  #[test]
+  fn a_snippet_is_inserted_and_the_result_still_validated() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    let snippets = NameSnippets::from_config([(String::from("ctrl-j"), String::from("jira/"))].into());
+    let mut input = BranchInput::new(Theme::default(), snippets);
+    input.init_style();
+    input.handle_key_event(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL), &repo);
+    type_text(&mut input, &repo, "ABC-123");
+    assert_eq!(input.get_text().as_deref(), Some("jira/ABC-123"));
+    assert_eq!(input.input_state.is_valid, Some(true));
+    input.handle_key_event(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL), &repo);
+    type_text(&mut input, &repo, "..");
+    assert_eq!(input.input_state.is_valid, Some(false));
+  }
+}
@@ -1,10 +1,25 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use ratatui::{
-  style::{Color, Modifier, Style},
+  style::{Modifier, Style},
   text::{Line, Span},
   widgets::ListItem,
 };
 
-use crate::git::git_repo::GitBranch;
+use super::commit_log::format_short_age;
+use crate::{
+  config::{
+    layout::{Age, Column, ListLayout, Truncation},
+    theme::Theme,
+  },
+  git::git_repo::{GitBranch, GitRemoteBranch, UpstreamStatus},
+};
+
+/// Long names are never cut shorter than this to make room for the details after them.
+const MIN_NAME_WIDTH: usize = 12;
+
+/// Descriptions are cut to this many chars so they leave room for the name.
+const DESCRIPTION_WIDTH: usize = 30;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct BranchItem {
@@ -12,36 +27,300 @@ pub struct BranchItem {
   pub staged_for_deletion: bool,
   pub staged_for_creation: bool,
   pub is_valid_name: bool,
+  /// Matches a protected pattern or is the default branch, so it can't be deleted.
+  pub protected: bool,
+  /// A remote branch row, listed by its remote name like `origin/main`.
+  pub is_remote: bool,
+  /// For a remote branch row, the local branch that tracks it.
+  pub tracked_by: Option<String>,
+  /// For a local branch row, the remote branch it tracks is folded into it rather than listed.
+  pub collapsed_remote: bool,
+  /// For a remote branch row, the remote it is on.
+  pub remote: Option<String>,
+  /// Kept at the top of the list.
+  pub pinned: bool,
+  /// The lines inserted and deleted compared with the upstream, only worked out when the layout shows them.
+  pub diffstat: Option<(usize, usize)>,
+  /// When the branch was last committed to in seconds since the unix epoch, only looked up when the layout shows
+  /// commit ages.
+  pub commit_time: Option<i64>,
+  /// Who made the last commit, only looked up when the layout shows authors.
+  pub commit_author: Option<String>,
+  /// The first line of its description, only read when the layout shows descriptions.
+  pub description: Option<String>,
+  /// The ticket id found in its name by the configured ticket pattern.
+  pub ticket: Option<String>,
+  /// The commits it has that the default branch doesn't, only counted when the layout shows them.
+  pub unique_commits: Option<usize>,
+  pub is_default: bool,
+  /// Its upstream is gone from every remote, see [`GitBranch::is_orphaned_tracking`].
+  pub cleanup_candidate: bool,
 }
 
 impl BranchItem {
   pub fn new(branch: GitBranch, is_valid_name: bool) -> Self {
-    BranchItem { branch, staged_for_deletion: false, staged_for_creation: false, is_valid_name }
+    BranchItem {
+      branch,
+      staged_for_deletion: false,
+      staged_for_creation: false,
+      is_valid_name,
+      protected: false,
+      is_remote: false,
+      tracked_by: None,
+      collapsed_remote: false,
+      remote: None,
+      pinned: false,
+      diffstat: None,
+      commit_time: None,
+      commit_author: None,
+      description: None,
+      ticket: None,
+      unique_commits: None,
+      is_default: false,
+      cleanup_candidate: false,
+    }
   }
 
-  pub fn render(&self) -> ListItem {
-    let mut text = Line::default();
+  pub fn remote(remote: GitRemoteBranch, tracked_by: Option<String>) -> Self {
+    BranchItem {
+      is_remote: true,
+      tracked_by,
+      remote: remote.remote,
+      ..BranchItem::new(GitBranch::new(remote.name), true)
+    }
+  }
+
+  /// Renders the branch in `width` columns, `matched` holds the char indices of the name to highlight from a filter
+  /// query.
+  pub fn render(&self, matched: &[usize], theme: &Theme, layout: &ListLayout, width: usize) -> ListItem<'_> {
+    ListItem::from(self.line(matched, theme, layout, width))
+  }
+
+  /// The name shortened to fit in `width` columns alongside its details, which are shortened to fit first. The
+  /// `matched` char indices from the filter are underlined and bold wherever they survive the shortening.
+  pub fn line(&self, matched: &[usize], theme: &Theme, layout: &ListLayout, width: usize) -> Line<'_> {
+    let details = self.details(theme, layout);
+    let marker_width = if self.pinned { 2 } else { 0 };
+    let details_width: usize = details.iter().map(Span::width).sum();
+    let name_width = width.saturating_sub(marker_width + details_width).max(MIN_NAME_WIDTH);
+    let (name, matched) = truncate_name(&self.branch.name, matched, name_width, layout.truncation);
+
     let mut parts = Vec::new();
-    let mut name = Span::styled(self.branch.name.clone(), Style::default());
+    let mut name_style = Style::default();
+    if self.branch.is_head {
+      name_style = Style::default().fg(theme.head);
+    }
     if self.staged_for_deletion {
-      name = name.style(Style::default().fg(Color::Red));
+      name_style = Style::default().fg(theme.staged_for_deletion);
     }
     if self.staged_for_creation {
-      name = name.style(Style::default().fg(if self.is_valid_name { Color::LightGreen } else { Color::LightRed }));
+      name_style = Style::default().fg(if self.is_valid_name { theme.valid_name } else { theme.invalid_name });
+    }
+    if self.pinned {
+      parts.push(Span::styled("★ ", Style::default().fg(theme.head)));
+    }
+    parts.extend(highlight_name(&name, &matched, name_style));
+    parts.extend(details);
+    Line::from(parts)
+  }
+
+  /// The ticket and markers, then the details of the layout's columns in order, shown after the name.
+  fn details(&self, theme: &Theme, layout: &ListLayout) -> Vec<Span<'static>> {
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let columns = layout.columns();
+    let mut parts = Vec::new();
+    if let Some(ticket) = &self.ticket {
+      parts.push(Span::styled(format!(" {}", ticket), Style::default().fg(theme.ticket).add_modifier(Modifier::BOLD)));
     }
-    parts.push(name);
     if self.branch.is_head {
-      parts.push(Span::styled(" (HEAD)", Style::default().add_modifier(Modifier::DIM)));
+      parts.push(Span::styled(" (HEAD)", dim));
+    }
+    if self.protected {
+      parts.push(Span::styled(" (protected)", dim));
+    }
+    if self.branch.name_is_lossy {
+      parts.push(Span::styled(" (invalid UTF-8)", Style::default().fg(theme.invalid_name).add_modifier(Modifier::DIM)));
     }
-    if self.branch.upstream.is_some() {
-      let upstream = self.branch.upstream.clone();
-      parts.push(Span::styled(format!(" [{}]", upstream.unwrap().name), Style::default().add_modifier(Modifier::DIM)));
+    // A gone upstream is worth knowing about even when the upstream isn't shown, as is a folded in remote branch
+    if !columns.contains(&Column::Upstream) {
+      if let Some(upstream) = self.branch.upstream.as_ref().filter(|_| self.collapsed_remote) {
+        parts.push(Span::styled(format!(" [{}]", upstream.name), dim));
+      }
+      self.push_gone(&mut parts, theme);
+    }
+    for column in columns {
+      match column {
+        Column::Upstream => {
+          if let Some(local) = &self.tracked_by {
+            parts.push(Span::styled(format!(" (tracked by {})", local), dim));
+          }
+          if let Some(upstream) = &self.branch.upstream {
+            parts.push(Span::styled(format!(" [{}]", upstream.name), dim));
+          }
+          self.push_gone(&mut parts, theme);
+        },
+        Column::AheadBehind => {
+          if let Some((ahead, behind)) = self.branch.ahead_behind {
+            if ahead > 0 {
+              parts.push(Span::styled(format!(" ↑{}", ahead), Style::default().fg(theme.ahead)));
+            }
+            if behind > 0 {
+              parts.push(Span::styled(format!(" ↓{}", behind), Style::default().fg(theme.behind)));
+            }
+          }
+        },
+        Column::Diffstat => {
+          if let Some((insertions, deletions)) = self.diffstat.filter(|stat| *stat != (0, 0)) {
+            parts.push(Span::styled(
+              format!(" +{}", insertions),
+              Style::default().fg(theme.ahead).add_modifier(Modifier::DIM),
+            ));
+            parts.push(Span::styled(
+              format!(" -{}", deletions),
+              Style::default().fg(theme.behind).add_modifier(Modifier::DIM),
+            ));
+          }
+        },
+        Column::UniqueCommits if !self.is_remote => {
+          match self.unique_commits {
+            _ if self.is_default => parts.push(Span::styled(" -", dim)),
+            Some(1) => parts.push(Span::styled(" 1 commit", dim)),
+            Some(count) => parts.push(Span::styled(format!(" {} commits", count), dim)),
+            None => {},
+          }
+        },
+        Column::UniqueCommits => {},
+        Column::CommitAge => {
+          if let Some(time) = self.commit_time {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() as i64);
+            let age = now - time;
+            let style = match layout.age_buckets.bucket(age) {
+              Age::Fresh => Style::default().fg(theme.ahead),
+              Age::Ordinary => Style::default(),
+              Age::Stale => dim,
+            };
+            parts.push(Span::styled(format!(" {}", format_short_age(age)), style));
+          }
+        },
+        Column::Author => {
+          if let Some(author) = &self.commit_author {
+            parts.push(Span::styled(format!(" {}", author), dim));
+          }
+        },
+        Column::Description => {
+          if let Some(description) = &self.description {
+            let (description, _) = truncate_name(description, &[], DESCRIPTION_WIDTH, Truncation::End);
+            parts.push(Span::styled(format!(" · {}", description), dim.add_modifier(Modifier::ITALIC)));
+          }
+        },
+      }
+    }
+    parts
+  }
+
+  fn push_gone(&self, parts: &mut Vec<Span<'static>>, theme: &Theme) {
+    if self.cleanup_candidate {
+      parts.push(Span::styled(" gone everywhere", Style::default().fg(theme.warning).add_modifier(Modifier::BOLD)));
+    } else if self.branch.upstream_status == UpstreamStatus::Gone {
+      parts.push(Span::styled(" gone", Style::default().fg(theme.warning)));
     }
-    text = text.spans(parts);
-    ListItem::from(text)
   }
 
   pub fn stage_for_deletion(&mut self, stage: bool) {
     self.staged_for_deletion = stage;
   }
 }
+
+/// Shortens `name` to `width` chars with an ellipsis where chars were cut, moving the `matched` indices to where
+/// those chars end up. Matched chars that were cut are dropped.
+pub fn truncate_name(name: &str, matched: &[usize], width: usize, truncation: Truncation) -> (String, Vec<usize>) {
+  let chars: Vec<char> = name.chars().collect();
+  if chars.len() <= width {
+    return (String::from(name), matched.to_vec());
+  }
+  let keep = width.saturating_sub(1);
+  // The end of a name is what usually tells it apart from its neighbours, so it gets the larger share
+  let head = match truncation {
+    Truncation::Middle => keep / 3,
+    Truncation::End => keep,
+  };
+  let tail_start = chars.len() - (keep - head);
+  let mut truncated: String = chars[..head].iter().collect();
+  truncated.push('…');
+  truncated.extend(&chars[tail_start..]);
+  let matched = matched
+    .iter()
+    .filter_map(|index| {
+      match *index {
+        index if index < head => Some(index),
+        index if index >= tail_start => Some(index - tail_start + head + 1),
+        _ => None,
+      }
+    })
+    .collect();
+  (truncated, matched)
+}
+
+/// Splits `name` into spans so the chars at the `matched` indices are underlined and bold.
+pub fn highlight_name(name: &str, matched: &[usize], style: Style) -> Vec<Span<'static>> {
+  if matched.is_empty() {
+    return vec![Span::styled(name.to_string(), style)];
+  }
+  let highlight = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+  let mut spans = Vec::new();
+  let mut run = String::new();
+  let mut run_is_match = false;
+  for (index, c) in name.chars().enumerate() {
+    let is_match = matched.contains(&index);
+    if is_match != run_is_match && !run.is_empty() {
+      spans.push(Span::styled(std::mem::take(&mut run), if run_is_match { highlight } else { style }));
+    }
+    run_is_match = is_match;
+    run.push(c);
+  }
+  spans.push(Span::styled(run, if run_is_match { highlight } else { style }));
+  spans
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::layout::Columns;
+
+  #[test]
+  fn middle_truncation_keeps_more_of_the_end_at_each_width() {
+    let name = "feature/login-form";
+    let cases = [(18, "feature/login-form"), (12, "fea…gin-form"), (10, "fea…n-form"), (4, "f…rm"), (1, "…")];
+    for (width, expected) in cases {
+      let (truncated, _) = truncate_name(name, &[], width, Truncation::Middle);
+      assert_eq!(truncated, expected, "width {}", width);
+      assert!(truncated.chars().count() <= width);
+    }
+  }
+
+  #[test]
+  fn end_truncation_keeps_the_start() {
+    assert_eq!(truncate_name("feature/login-form", &[], 10, Truncation::End).0, "feature/l…");
+  }
+
+  #[test]
+  fn matched_chars_follow_the_truncation_and_cut_ones_are_dropped() {
+    let (truncated, matched) = truncate_name("feature/login-form", &[0, 8, 17], 10, Truncation::Middle);
+    assert_eq!(truncated, "fea…n-form");
+    assert_eq!(matched, [0, 9]);
+  }
+
+  #[test]
+  fn the_details_follow_the_configured_column_order() {
+    let branch = GitBranch { ahead_behind: Some((2, 0)), ..GitBranch::new(String::from("feature")) };
+    let item = BranchItem { commit_author: Some(String::from("Ada")), ..BranchItem::new(branch, true) };
+    let text = |names: &[&str]| {
+      let columns = Columns::from_config(names.iter().map(|name| String::from(*name)).collect());
+      let layout = ListLayout { columns: Some(columns), ..ListLayout::default() };
+      item.details(&Theme::default(), &layout).iter().map(|span| span.content.to_string()).collect::<String>()
+    };
+    assert_eq!(text(&["author", "ahead_behind"]), " Ada ↑2");
+    assert_eq!(text(&["ahead_behind", "author"]), " ↑2 Ada");
+    assert_eq!(text(&["description"]), "");
+  }
+}
@@ -0,0 +1,262 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, Paragraph},
+};
+use regex::Regex;
+
+use crate::{config::theme::Theme, tui::Frame};
+
+/// How the pattern is matched against branch names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PatternKind {
+  /// Names starting with the pattern have it swapped for the replacement, like `feat/` to `feature/`.
+  #[default]
+  Prefix,
+  /// The first match of the pattern is replaced, the replacement can use capture groups like `$1`.
+  Regex,
+}
+
+impl PatternKind {
+  fn next(self) -> Self {
+    match self {
+      PatternKind::Prefix => PatternKind::Regex,
+      PatternKind::Regex => PatternKind::Prefix,
+    }
+  }
+}
+
+/// The renames of `names` the pattern makes as old and new names, leaving out the names it doesn't change. An empty
+/// pattern renames nothing, and an invalid regex is an error to show while it is being typed.
+pub fn plan_renames(
+  kind: PatternKind,
+  pattern: &str,
+  replacement: &str,
+  names: &[String],
+) -> Result<Vec<(String, String)>, regex::Error> {
+  if pattern.is_empty() {
+    return Ok(Vec::new());
+  }
+  let regex = match kind {
+    PatternKind::Prefix => None,
+    PatternKind::Regex => Some(Regex::new(pattern)?),
+  };
+  let rename = |name: &str| {
+    match &regex {
+      Some(regex) => Some(regex.replace(name, replacement).into_owned()),
+      None => name.strip_prefix(pattern).map(|rest| format!("{}{}", replacement, rest)),
+    }
+  };
+  Ok(
+    names
+      .iter()
+      .filter_map(|name| rename(name).filter(|new_name| new_name != name).map(|new_name| (name.clone(), new_name)))
+      .collect(),
+  )
+}
+
+/// What happened to the open bulk rename after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkRenameOutcome {
+  Pending,
+  /// The previewed renames were confirmed, as old and new names.
+  Submitted(Vec<(String, String)>),
+  Closed,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Field {
+  #[default]
+  Pattern,
+  Replacement,
+}
+
+/// A popup renaming every local branch matching a pattern at once, previewing the renames as the pattern is typed.
+#[derive(Debug, Default)]
+pub struct BulkRename {
+  /// The local branch names the pattern is matched against.
+  names: Vec<String>,
+  pattern: String,
+  replacement: String,
+  kind: PatternKind,
+  field: Field,
+  open: bool,
+  theme: Theme,
+}
+
+impl BulkRename {
+  pub fn new(theme: Theme) -> Self {
+    BulkRename { theme, ..BulkRename::default() }
+  }
+
+  pub fn open(&mut self, names: Vec<String>) {
+    self.names = names;
+    self.pattern.clear();
+    self.replacement.clear();
+    self.field = Field::Pattern;
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  fn plan(&self) -> Result<Vec<(String, String)>, regex::Error> {
+    plan_renames(self.kind, &self.pattern, &self.replacement, &self.names)
+  }
+
+  fn focused_text(&mut self) -> &mut String {
+    match self.field {
+      Field::Pattern => &mut self.pattern,
+      Field::Replacement => &mut self.replacement,
+    }
+  }
+
+  /// Typing edits the focused field, tab moves between the pattern and replacement, ctrl-r switches between prefix
+  /// and regex matching, enter renames the previewed branches and esc closes.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> BulkRenameOutcome {
+    match key.code {
+      KeyCode::Esc => {
+        self.open = false;
+        return BulkRenameOutcome::Closed;
+      },
+      KeyCode::Enter => {
+        if let Ok(renames) = self.plan() {
+          if !renames.is_empty() {
+            self.open = false;
+            return BulkRenameOutcome::Submitted(renames);
+          }
+        }
+      },
+      KeyCode::Tab | KeyCode::BackTab => {
+        self.field = match self.field {
+          Field::Pattern => Field::Replacement,
+          Field::Replacement => Field::Pattern,
+        }
+      },
+      KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => self.kind = self.kind.next(),
+      KeyCode::Backspace => {
+        self.focused_text().pop();
+      },
+      KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+        self.focused_text().push(c)
+      },
+      _ => {},
+    }
+    BulkRenameOutcome::Pending
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+    let plan = self.plan();
+    let title = match &plan {
+      Ok(renames) if !renames.is_empty() => format!("Rename {} branches", renames.len()),
+      _ => String::from("Rename branches"),
+    };
+    let kind = match self.kind {
+      PatternKind::Prefix => "prefix",
+      PatternKind::Regex => "regex",
+    };
+    let block = Block::bordered()
+      .title(title)
+      .title_bottom(format!("tab: Switch field | ctrl-r: Match by {} | enter: Rename | esc: Close", kind));
+    let [pattern_area, replacement_area, preview_area] =
+      Layout::vertical([Constraint::Length(1), Constraint::Length(1), Constraint::Fill(1)]).areas(block.inner(area));
+
+    let text = Style::default().fg(self.theme.text);
+    let focused = |field: Field| if self.field == field { text.add_modifier(Modifier::BOLD) } else { text };
+    let dim = text.add_modifier(Modifier::DIM);
+    let preview: Vec<Line> = match &plan {
+      // Regex errors point at the problem over several lines
+      Err(err) => {
+        err
+          .to_string()
+          .lines()
+          .map(|line| Line::styled(line.to_string(), Style::default().fg(self.theme.error)))
+          .collect()
+      },
+      Ok(renames) if renames.is_empty() => vec![Line::styled("No branches match", dim)],
+      Ok(renames) => {
+        renames
+          .iter()
+          .map(|(old_name, new_name)| {
+            Line::from(vec![Span::styled(old_name.clone(), dim), Span::raw(" → "), Span::raw(new_name.clone())])
+          })
+          .collect()
+      },
+    };
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(
+      Paragraph::new(format!("{} > {}", kind, self.pattern)).style(focused(Field::Pattern)),
+      pattern_area,
+    );
+    f.render_widget(
+      Paragraph::new(format!("with > {}", self.replacement)).style(focused(Field::Replacement)),
+      replacement_area,
+    );
+    f.render_widget(Paragraph::new(preview).style(text), preview_area);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| String::from(*name)).collect()
+  }
+
+  fn renames(renames: &[(&str, &str)]) -> Vec<(String, String)> {
+    renames.iter().map(|(old_name, new_name)| (String::from(*old_name), String::from(*new_name))).collect()
+  }
+
+  #[test]
+  fn a_prefix_is_swapped_on_the_names_starting_with_it() {
+    let branches = names(&["feat/login", "feat/logout", "fix/feat/typo", "main"]);
+    let planned = plan_renames(PatternKind::Prefix, "feat/", "feature/", &branches).unwrap();
+    assert_eq!(planned, renames(&[("feat/login", "feature/login"), ("feat/logout", "feature/logout")]));
+  }
+
+  #[test]
+  fn a_regex_replaces_its_first_match_with_capture_groups() {
+    let branches = names(&["ABC-12-login", "main", "ABC-7"]);
+    let planned = plan_renames(PatternKind::Regex, r"^ABC-(\d+)", "abc/$1", &branches).unwrap();
+    assert_eq!(planned, renames(&[("ABC-12-login", "abc/12-login"), ("ABC-7", "abc/7")]));
+  }
+
+  #[test]
+  fn an_empty_pattern_renames_nothing_and_a_bad_regex_is_an_error() {
+    let branches = names(&["main"]);
+    assert_eq!(plan_renames(PatternKind::Prefix, "", "x", &branches).unwrap(), Vec::new());
+    assert!(plan_renames(PatternKind::Regex, "feat/(", "x", &branches).is_err());
+    // A replacement giving the same name back isn't a rename
+    assert_eq!(plan_renames(PatternKind::Regex, "main", "main", &branches).unwrap(), Vec::new());
+  }
+
+  #[test]
+  fn enter_submits_the_previewed_renames_once_something_matches() {
+    let mut bulk_rename = BulkRename::default();
+    bulk_rename.open(names(&["feat/login", "main"]));
+    let press = |bulk_rename: &mut BulkRename, code: KeyCode| bulk_rename.handle_key_event(KeyEvent::from(code));
+    assert_eq!(press(&mut bulk_rename, KeyCode::Enter), BulkRenameOutcome::Pending);
+    for c in "feat/".chars() {
+      press(&mut bulk_rename, KeyCode::Char(c));
+    }
+    press(&mut bulk_rename, KeyCode::Tab);
+    for c in "feature/".chars() {
+      press(&mut bulk_rename, KeyCode::Char(c));
+    }
+    assert_eq!(
+      press(&mut bulk_rename, KeyCode::Enter),
+      BulkRenameOutcome::Submitted(renames(&[("feat/login", "feature/login")]))
+    );
+    assert!(!bulk_rename.is_open());
+  }
+}
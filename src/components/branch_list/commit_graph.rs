@@ -0,0 +1,143 @@
+use crate::git::git_repo::GraphCommit;
+
+/// Draws `commits`, newest first with children before parents, as text lines like `git log --graph --oneline`. Each
+/// line of history gets a lane two columns wide, `*` marks a commit, `\` a merge opening a lane for its other parent
+/// and `/` a lane joining another once they reach a shared parent.
+pub fn graph_lines(commits: &[GraphCommit]) -> Vec<String> {
+  // The commit each lane is waiting for, `None` once a lane has joined another
+  let mut lanes: Vec<Option<&str>> = Vec::new();
+  let mut lines = Vec::new();
+  for commit in commits {
+    let column = match lanes.iter().position(|lane| *lane == Some(commit.id.as_str())) {
+      Some(column) => column,
+      None => free_lane(&mut lanes, 0),
+    };
+
+    // Other lanes waiting for this commit join its lane
+    let joining: Vec<usize> =
+      (column + 1..lanes.len()).filter(|lane| lanes[*lane] == Some(commit.id.as_str())).collect();
+    if !joining.is_empty() {
+      let before: Vec<bool> = lanes.iter().map(Option::is_some).collect();
+      for lane in &joining {
+        lanes[*lane] = None;
+      }
+      lines.push(connector(&before, &lanes, &joining, '/'));
+      trim_lanes(&mut lanes);
+    }
+
+    let mut row: String = (0..lanes.len().max(column + 1))
+      .map(|lane| {
+        match lanes.get(lane) {
+          _ if lane == column => "* ",
+          Some(Some(_)) => "| ",
+          _ => "  ",
+        }
+      })
+      .collect();
+    row.push_str(&commit.short_id);
+    row.push(' ');
+    row.push_str(&commit.summary);
+    lines.push(row);
+
+    let mut parents = commit.parent_ids.iter();
+    lanes[column] = parents.next().map(String::as_str);
+    let before: Vec<bool> = lanes.iter().map(Option::is_some).collect();
+    let mut opened = Vec::new();
+    for parent in parents {
+      // A parent another lane is already waiting for is drawn when that lane reaches it
+      if lanes.contains(&Some(parent.as_str())) {
+        continue;
+      }
+      let lane = free_lane(&mut lanes, column + 1);
+      lanes[lane] = Some(parent);
+      opened.push(lane);
+    }
+    if !opened.is_empty() {
+      lines.push(connector(&before, &lanes, &opened, '\\'));
+    }
+    trim_lanes(&mut lanes);
+  }
+  lines
+}
+
+/// The first lane from `start` on that isn't waiting for a commit, adding one when they all are.
+fn free_lane(lanes: &mut Vec<Option<&str>>, start: usize) -> usize {
+  match (start..lanes.len()).find(|lane| lanes[*lane].is_none()) {
+    Some(lane) => lane,
+    None => {
+      lanes.push(None);
+      lanes.len() - 1
+    },
+  }
+}
+
+fn trim_lanes(lanes: &mut Vec<Option<&str>>) {
+  while lanes.last().is_some_and(Option::is_none) {
+    lanes.pop();
+  }
+}
+
+/// A line between two rows, continuing the lanes active on both sides and drawing `edge` just left of each of the
+/// `changed` lanes, which are never the first.
+fn connector(before: &[bool], after: &[Option<&str>], changed: &[usize], edge: char) -> String {
+  let width = before.len().max(after.len()) * 2;
+  let mut cells = vec![' '; width];
+  for lane in 0..width / 2 {
+    let continues = before.get(lane).copied().unwrap_or(false) && after.get(lane).is_some_and(Option::is_some);
+    if continues && !changed.contains(&lane) {
+      cells[lane * 2] = '|';
+    }
+  }
+  for lane in changed {
+    cells[lane * 2 - 1] = edge;
+  }
+  cells.into_iter().collect::<String>().trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{git::git_repo::GitRepo, test_support::Fixture};
+
+  fn commit(id: &str, parents: &[&str]) -> GraphCommit {
+    let parent_ids = parents.iter().map(|parent| String::from(*parent)).collect();
+    GraphCommit::new(String::from(id), String::from(id), parent_ids, format!("Commit {}", id))
+  }
+
+  #[test]
+  fn a_merge_opens_a_lane_that_joins_back_at_the_fork() {
+    let commits = [commit("m", &["c", "b"]), commit("c", &["a"]), commit("b", &["a"]), commit("a", &[])];
+    assert_eq!(graph_lines(&commits), [
+      "* m Commit m",
+      "|\\",
+      "* | c Commit c",
+      "| * b Commit b",
+      "|/",
+      "* a Commit a"
+    ]);
+  }
+
+  #[test]
+  fn a_straight_history_is_a_single_lane() {
+    let commits = [commit("c", &["b"]), commit("b", &["a"]), commit("a", &[])];
+    assert_eq!(graph_lines(&commits), ["* c Commit c", "* b Commit b", "* a Commit a"]);
+  }
+
+  #[test]
+  fn the_graph_of_a_merged_branch_shows_both_sides() {
+    let fixture = Fixture::new();
+    fixture.branch_with_commit("feature");
+    fixture.commit("main.txt", "main", "Work on main");
+    fixture.git(&["merge", "-q", "--no-ff", "-m", "Merge feature", "feature"]);
+    for repo in [Box::new(fixture.git2()) as Box<dyn GitRepo>, Box::new(fixture.cli())] {
+      let main = repo.local_branches().unwrap().into_iter().find(|branch| branch.name == "main").unwrap();
+      let lines = graph_lines(&repo.branch_graph(&main, 10).unwrap());
+      let mut shape: Vec<&str> =
+        lines.iter().map(|line| line.split(|c: char| c.is_ascii_hexdigit()).next().unwrap()).collect();
+      // The two sides were committed in the same second, so either can come first
+      shape[2..4].sort();
+      assert_eq!(shape, ["* ", "|\\", "* | ", "| * ", "|/", "* "], "{:#?}", lines);
+      assert!(lines[0].ends_with("Merge feature"), "{:#?}", lines);
+    }
+  }
+}
@@ -0,0 +1,234 @@
+use std::{
+  collections::HashMap,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use ratatui::{
+  layout::Rect,
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Borders, Paragraph},
+};
+
+use crate::{
+  components::branch_list::commit_graph::graph_lines,
+  config::theme::Theme,
+  git::git_repo::{CommitInfo, Divergence, GitBranch, GitRepo},
+  tui::Frame,
+};
+
+/// How many commits the preview shows.
+const LOG_LIMIT: usize = 20;
+
+/// How many commits the graph shows, each takes a single line so it fits more than the log.
+const GRAPH_LIMIT: usize = 40;
+
+/// A side panel with the recent commits of the selected branch, as a log or a graph, under how far it has diverged
+/// from the default branch. Everything is read the first time a branch is shown and kept until [`CommitLog::clear`].
+#[derive(Debug, Default)]
+pub struct CommitLog {
+  theme: Theme,
+  logs: HashMap<String, Result<Vec<CommitInfo>, String>>,
+  graphs: HashMap<String, Result<Vec<String>, String>>,
+  /// The divergence line by branch name.
+  divergences: HashMap<String, Result<String, String>>,
+  /// The descriptions from the git config by branch name.
+  descriptions: HashMap<String, Result<Option<String>, String>>,
+  show_graph: bool,
+}
+
+impl CommitLog {
+  pub fn new(theme: Theme) -> Self {
+    CommitLog {
+      theme,
+      logs: HashMap::new(),
+      graphs: HashMap::new(),
+      divergences: HashMap::new(),
+      descriptions: HashMap::new(),
+      show_graph: false,
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.logs.clear();
+    self.graphs.clear();
+    self.divergences.clear();
+    self.descriptions.clear();
+  }
+
+  /// Drops the kept description of the branch so the new one is read the next time it is shown.
+  pub fn forget_description(&mut self, name: &str) {
+    self.descriptions.remove(name);
+  }
+
+  pub fn toggle_graph(&mut self) {
+    self.show_graph = !self.show_graph;
+  }
+
+  /// Renders the log of `branch`, comparing it with `base` when they are different branches.
+  pub fn render(
+    &mut self,
+    f: &mut Frame<'_>,
+    area: Rect,
+    repo: &dyn GitRepo,
+    branch: Option<&GitBranch>,
+    base: Option<&GitBranch>,
+  ) {
+    let title = if self.show_graph { "Commit Graph" } else { "Recent Commits" };
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let mut lines = Vec::new();
+    if let Some(branch) = branch.filter(|branch| !branch.name_is_lossy) {
+      let description = self
+        .descriptions
+        .entry(branch.name.clone())
+        .or_insert_with(|| repo.branch_description(branch).map_err(|err| err.to_string()));
+      match description {
+        Ok(Some(description)) => {
+          lines.extend(
+            description
+              .lines()
+              .map(|line| Line::styled(line.to_string(), Style::default().add_modifier(Modifier::ITALIC))),
+          );
+          lines.push(Line::default());
+        },
+        Ok(None) => {},
+        Err(err) => lines.push(Line::styled(err.clone(), Style::default().fg(self.theme.error))),
+      }
+    }
+    if let (Some(branch), Some(base)) = (branch, base) {
+      if branch.name != base.name && !branch.name_is_lossy {
+        let divergence = self.divergences.entry(branch.name.clone()).or_insert_with(|| {
+          repo
+            .divergence(branch, base)
+            .and_then(|divergence| {
+              Ok(divergence_text(&divergence, &repo.short_id(&divergence.merge_base)?, &base.name))
+            })
+            .map_err(|err| err.to_string())
+        });
+        let (Ok(text) | Err(text)) = divergence;
+        lines.push(Line::styled(text.clone(), dim));
+        lines.push(Line::default());
+      }
+    }
+    lines.extend(match branch {
+      None => vec![],
+      Some(branch) if branch.name_is_lossy => vec![Line::styled("Can't read the log of this branch", dim)],
+      Some(branch) if self.show_graph => {
+        let graph = self.graphs.entry(branch.name.clone()).or_insert_with(|| {
+          repo.branch_graph(branch, GRAPH_LIMIT).map(|commits| graph_lines(&commits)).map_err(|err| err.to_string())
+        });
+        match graph {
+          Ok(lines) => lines.iter().map(|line| Line::from(line.clone())).collect(),
+          Err(err) => vec![Line::styled(err.clone(), Style::default().fg(self.theme.error))],
+        }
+      },
+      Some(branch) => {
+        let log = self
+          .logs
+          .entry(branch.name.clone())
+          .or_insert_with(|| repo.branch_log(branch, LOG_LIMIT).map_err(|err| err.to_string()));
+        match log {
+          Ok(commits) => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0);
+            // Summaries are often long, so the details go on their own line rather than being cut off
+            commits
+              .iter()
+              .flat_map(|commit| {
+                [
+                  Line::from(commit.summary.clone()),
+                  Line::from(Span::styled(format!("  {}, {}", commit.author, format_age(now - commit.time)), dim)),
+                ]
+              })
+              .collect()
+          },
+          Err(err) => vec![Line::styled(err.clone(), Style::default().fg(self.theme.error))],
+        }
+      },
+    });
+    let log = Paragraph::new(lines).block(block).style(Style::default().fg(self.theme.text));
+    f.render_widget(log, area);
+  }
+}
+
+/// Describes the divergence like "3 ahead and 1 behind main since 1a2b3c4".
+fn divergence_text(divergence: &Divergence, short_merge_base: &str, base_name: &str) -> String {
+  format!("{} ahead and {} behind {} since {}", divergence.ahead, divergence.behind, base_name, short_merge_base)
+}
+
+/// A rough, human readable age like "3 days ago" for a duration in seconds.
+pub fn format_age(seconds: i64) -> String {
+  const MINUTE: i64 = 60;
+  const HOUR: i64 = 60 * MINUTE;
+  const DAY: i64 = 24 * HOUR;
+  const MONTH: i64 = 30 * DAY;
+  const YEAR: i64 = 365 * DAY;
+  let (count, unit) = match seconds {
+    s if s < MINUTE => return String::from("just now"),
+    s if s < HOUR => (s / MINUTE, "minute"),
+    s if s < DAY => (s / HOUR, "hour"),
+    s if s < MONTH => (s / DAY, "day"),
+    s if s < YEAR => (s / MONTH, "month"),
+    s => (s / YEAR, "year"),
+  };
+  format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// A compact age like "2d ago" or "3w ago" for a duration in seconds. A commit from the future, as with clock skew
+/// between machines, is shown as "now" rather than as a negative age.
+pub fn format_short_age(seconds: i64) -> String {
+  const MINUTE: i64 = 60;
+  const HOUR: i64 = 60 * MINUTE;
+  const DAY: i64 = 24 * HOUR;
+  const WEEK: i64 = 7 * DAY;
+  const MONTH: i64 = 30 * DAY;
+  const YEAR: i64 = 365 * DAY;
+  let (count, unit) = match seconds {
+    s if s < MINUTE => return String::from("now"),
+    s if s < HOUR => (s / MINUTE, "m"),
+    s if s < DAY => (s / HOUR, "h"),
+    s if s < WEEK => (s / DAY, "d"),
+    s if s < MONTH => (s / WEEK, "w"),
+    s if s < YEAR => (s / MONTH, "mo"),
+    s => (s / YEAR, "y"),
+  };
+  format!("{}{} ago", count, unit)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DAY: i64 = 86_400;
+
+  #[test]
+  fn short_ages_use_the_largest_whole_unit() {
+    let cases = [
+      (0, "now"),
+      (59, "now"),
+      (60, "1m ago"),
+      (3 * 3600 + 59, "3h ago"),
+      (2 * DAY, "2d ago"),
+      (7 * DAY, "1w ago"),
+      (29 * DAY, "4w ago"),
+      (45 * DAY, "1mo ago"),
+      (800 * DAY, "2y ago"),
+    ];
+    for (seconds, expected) in cases {
+      assert_eq!(format_short_age(seconds), expected, "{} seconds", seconds);
+    }
+  }
+
+  #[test]
+  fn a_commit_from_the_future_is_now() {
+    assert_eq!(format_short_age(-3600), "now");
+    assert_eq!(format_age(-3600), "just now");
+  }
+
+  #[test]
+  fn long_ages_name_the_unit_in_full() {
+    assert_eq!(format_age(60), "1 minute ago");
+    assert_eq!(format_age(3 * DAY), "3 days ago");
+    assert_eq!(format_age(400 * DAY), "1 year ago");
+  }
+}
@@ -0,0 +1,121 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, List, ListItem, ListState},
+};
+
+use crate::{config::theme::Theme, git::git_repo::GraphCommit, tui::Frame};
+
+/// What the picked commit is for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PickerPurpose {
+  /// Cherry-picking onto the current branch.
+  #[default]
+  CherryPick,
+  /// Starting a new branch at.
+  Branch,
+}
+
+/// What happened to the open picker after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PickerOutcome {
+  Pending,
+  /// This commit was picked for the [`PickerPurpose`] the picker was opened with.
+  Selected(GraphCommit, PickerPurpose),
+  Closed,
+}
+
+/// A popup listing the commits of a branch so one can be cherry-picked onto the current branch or have a branch
+/// started at it.
+#[derive(Debug, Default)]
+pub struct CommitPicker {
+  branch: String,
+  purpose: PickerPurpose,
+  commits: Vec<GraphCommit>,
+  list_state: ListState,
+  open: bool,
+  theme: Theme,
+}
+
+impl CommitPicker {
+  pub fn new(theme: Theme) -> Self {
+    CommitPicker { theme, ..CommitPicker::default() }
+  }
+
+  pub fn open(&mut self, branch: &str, commits: Vec<GraphCommit>, purpose: PickerPurpose) {
+    self.branch = String::from(branch);
+    self.purpose = purpose;
+    self.commits = commits;
+    self.list_state.select(Some(0));
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// Moves with the arrow keys, picks the selected commit on enter and closes on esc.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> PickerOutcome {
+    let last = self.commits.len().saturating_sub(1);
+    let selected = self.list_state.selected().unwrap_or(0);
+    match key.code {
+      KeyCode::Esc => {
+        self.open = false;
+        return PickerOutcome::Closed;
+      },
+      KeyCode::Enter => {
+        self.open = false;
+        return match self.commits.get(selected) {
+          Some(commit) => PickerOutcome::Selected(commit.clone(), self.purpose),
+          None => PickerOutcome::Closed,
+        };
+      },
+      KeyCode::Down => self.list_state.select(Some((selected + 1).min(last))),
+      KeyCode::Up => self.list_state.select(Some(selected.saturating_sub(1))),
+      KeyCode::PageDown => self.list_state.select(Some((selected + 10).min(last))),
+      KeyCode::PageUp => self.list_state.select(Some(selected.saturating_sub(10))),
+      _ => {},
+    }
+    PickerOutcome::Pending
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let items: Vec<ListItem> = self
+      .commits
+      .iter()
+      .map(|commit| {
+        let mut parts = vec![
+          Span::styled(commit.short_id.clone(), Style::default().fg(self.theme.head)),
+          Span::raw(format!(" {}", commit.summary)),
+        ];
+        if commit.parent_ids.len() > 1 {
+          parts.push(Span::styled(" (merge)", dim));
+        }
+        ListItem::from(Line::from(parts))
+      })
+      .collect();
+    let (title, hint) = match self.purpose {
+      PickerPurpose::CherryPick => {
+        (format!("Cherry-pick from {}", self.branch), "enter: Cherry-pick onto the current branch | esc: Close")
+      },
+      PickerPurpose::Branch => (format!("Branch from {}", self.branch), "enter: Create a branch here | esc: Close"),
+    };
+    let block = Block::bordered().title(title).title_bottom(hint);
+    let list = List::new(items)
+      .block(block)
+      .style(Style::default().fg(self.theme.text))
+      .highlight_style(Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD))
+      .highlight_symbol("→");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut self.list_state);
+  }
+}
@@ -0,0 +1,128 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{config::theme::Theme, git::git_repo::GraphCommit, tui::Frame};
+
+/// What happened to the open search after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchOutcome {
+  Pending,
+  /// The commit messages should be searched for this text.
+  Search(String),
+  /// The branches containing this commit should be found.
+  Selected(GraphCommit),
+  Closed,
+}
+
+/// A popup searching commit messages to find which branches have a commit.
+#[derive(Debug, Default)]
+pub struct CommitSearch {
+  query: String,
+  /// The commits matching the query, `None` until it has been searched for.
+  results: Option<Vec<GraphCommit>>,
+  list_state: ListState,
+  open: bool,
+  theme: Theme,
+}
+
+impl CommitSearch {
+  pub fn new(theme: Theme) -> Self {
+    CommitSearch { theme, ..CommitSearch::default() }
+  }
+
+  pub fn open(&mut self) {
+    self.query.clear();
+    self.results = None;
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  pub fn show_results(&mut self, results: Vec<GraphCommit>) {
+    self.list_state.select(Some(0));
+    self.results = Some(results);
+  }
+
+  /// Typing edits the query, enter searches for it or picks the selected result and esc closes.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> SearchOutcome {
+    let count = self.results.as_ref().map_or(0, Vec::len);
+    let selected = self.list_state.selected().unwrap_or(0);
+    match key.code {
+      KeyCode::Esc => {
+        self.open = false;
+        return SearchOutcome::Closed;
+      },
+      KeyCode::Enter => {
+        if let Some(commit) = self.results.as_ref().and_then(|results| results.get(selected)) {
+          self.open = false;
+          return SearchOutcome::Selected(commit.clone());
+        }
+        if self.results.is_none() && !self.query.trim().is_empty() {
+          return SearchOutcome::Search(self.query.trim().to_string());
+        }
+      },
+      KeyCode::Down => self.list_state.select(Some((selected + 1).min(count.saturating_sub(1)))),
+      KeyCode::Up => self.list_state.select(Some(selected.saturating_sub(1))),
+      KeyCode::Backspace => {
+        self.query.pop();
+        self.results = None;
+      },
+      KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+        self.query.push(c);
+        self.results = None;
+      },
+      _ => {},
+    }
+    SearchOutcome::Pending
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+    let hint = if self.results.as_ref().is_some_and(|results| !results.is_empty()) {
+      "enter: Find the branches containing it | esc: Close"
+    } else {
+      "enter: Search | esc: Close"
+    };
+    let block = Block::bordered().title("Find a commit").title_bottom(hint);
+    let [query_area, results_area] =
+      Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(block.inner(area));
+
+    let text = Style::default().fg(self.theme.text);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(Paragraph::new(format!("message > {}", self.query)).style(text), query_area);
+    match &self.results {
+      Some(results) if results.is_empty() => {
+        f.render_widget(Paragraph::new("No commits match").style(text.add_modifier(Modifier::DIM)), results_area);
+      },
+      Some(results) => {
+        let items: Vec<ListItem> = results
+          .iter()
+          .map(|commit| {
+            ListItem::from(Line::from(vec![
+              Span::styled(commit.short_id.clone(), Style::default().fg(self.theme.head)),
+              Span::raw(format!(" {}", commit.summary)),
+            ]))
+          })
+          .collect();
+        let list = List::new(items)
+          .style(text)
+          .highlight_style(Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD))
+          .highlight_symbol("→");
+        f.render_stateful_widget(list, results_area, &mut self.list_state);
+      },
+      None => {},
+    }
+  }
+}
@@ -0,0 +1,70 @@
+/// What copying a branch puts on the clipboard, cycled by copying the same branch again.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CopyTarget {
+  #[default]
+  Upstream,
+  RemoteUrl,
+  Name,
+}
+
+impl CopyTarget {
+  pub fn next(self) -> Self {
+    match self {
+      CopyTarget::Upstream => CopyTarget::RemoteUrl,
+      CopyTarget::RemoteUrl => CopyTarget::Name,
+      CopyTarget::Name => CopyTarget::Upstream,
+    }
+  }
+}
+
+/// The text copied for `target` and what it turned out to be. A branch without an upstream, or whose remote URL
+/// isn't known, falls back to its local name.
+pub fn copy_text(target: CopyTarget, name: &str, upstream: Option<&str>, url: Option<&str>) -> (CopyTarget, String) {
+  match (target, upstream, url) {
+    (CopyTarget::Upstream, Some(upstream), _) => (CopyTarget::Upstream, String::from(upstream)),
+    (CopyTarget::RemoteUrl, Some(_), Some(url)) => (CopyTarget::RemoteUrl, String::from(url)),
+    _ => (CopyTarget::Name, String::from(name)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const URL: &str = "git@example.com:team/repo.git";
+
+  #[test]
+  fn each_target_copies_its_text_for_a_tracking_branch() {
+    let upstream = Some("origin/feature-x");
+    assert_eq!(
+      copy_text(CopyTarget::Upstream, "feature-x", upstream, Some(URL)),
+      (CopyTarget::Upstream, String::from("origin/feature-x"))
+    );
+    assert_eq!(
+      copy_text(CopyTarget::RemoteUrl, "feature-x", upstream, Some(URL)),
+      (CopyTarget::RemoteUrl, String::from(URL))
+    );
+    assert_eq!(
+      copy_text(CopyTarget::Name, "feature-x", upstream, Some(URL)),
+      (CopyTarget::Name, String::from("feature-x"))
+    );
+  }
+
+  #[test]
+  fn without_an_upstream_or_url_the_name_is_copied() {
+    for target in [CopyTarget::Upstream, CopyTarget::RemoteUrl, CopyTarget::Name] {
+      assert_eq!(copy_text(target, "feature-x", None, None), (CopyTarget::Name, String::from("feature-x")));
+    }
+    assert_eq!(
+      copy_text(CopyTarget::RemoteUrl, "feature-x", Some("origin/feature-x"), None),
+      (CopyTarget::Name, String::from("feature-x"))
+    );
+  }
+
+  #[test]
+  fn copying_again_cycles_through_the_targets() {
+    assert_eq!(CopyTarget::default().next(), CopyTarget::RemoteUrl);
+    assert_eq!(CopyTarget::RemoteUrl.next(), CopyTarget::Name);
+    assert_eq!(CopyTarget::Name.next(), CopyTarget::Upstream);
+  }
+}
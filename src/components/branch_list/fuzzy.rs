@@ -0,0 +1,150 @@
+use std::cmp::Reverse;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Characters that split a branch name into words, a match right after one of these scores higher.
+const SEPARATORS: [char; 4] = ['/', '-', '_', '.'];
+
+/// Ranks the candidates whose names contain the characters of `query` in order, ignoring case and, with
+/// `ignore_accents`, accents so `resume` matches `Résumé`. Each result carries the char indices of the name that
+/// matched so they can be highlighted. An empty query returns every candidate in its original order.
+pub fn fuzzy_match<'a, T: Clone>(
+  query: &str,
+  candidates: &'a [T],
+  name: impl Fn(&'a T) -> &'a str,
+  ignore_accents: bool,
+) -> Vec<(T, Vec<usize>)> {
+  let query: Vec<char> = query.chars().map(|c| fold(c, ignore_accents)).collect();
+  if query.is_empty() {
+    return candidates.iter().map(|candidate| (candidate.clone(), Vec::new())).collect();
+  }
+  let mut scored: Vec<(i64, &T, Vec<usize>)> = candidates
+    .iter()
+    .filter_map(|candidate| {
+      score(&query, name(candidate), ignore_accents).map(|(score, indices)| (score, candidate, indices))
+    })
+    .collect();
+  // Higher scores first, then shorter names as they are closer to the query
+  scored.sort_by_key(|(score, candidate, _)| (Reverse(*score), name(candidate).chars().count()));
+  scored.into_iter().map(|(_, candidate, indices)| (candidate.clone(), indices)).collect()
+}
+
+/// The char compared in place of `c`, lowercased and with `ignore_accents` stripped of its accents. Each char folds
+/// to exactly one so match indices line up with the original name, chars that would fold to several are kept as they
+/// are.
+pub fn fold(c: char, ignore_accents: bool) -> char {
+  // Accents go first as some chars, like `İ`, only lowercase to a single char without theirs
+  let mut base = c.nfd().filter(|c| !is_combining_mark(*c));
+  let c = match (base.next(), base.next()) {
+    (Some(base), None) if ignore_accents => base,
+    _ => c,
+  };
+  let mut lower = c.to_lowercase();
+  match (lower.next(), lower.next()) {
+    (Some(lower), None) => lower,
+    _ => c,
+  }
+}
+
+/// `text` with each char [`fold`]ed, for comparing names without caring about case or accents.
+pub fn fold_str(text: &str, ignore_accents: bool) -> String {
+  text.chars().map(|c| fold(c, ignore_accents)).collect()
+}
+
+/// Scores `candidate` against an already folded `query`, or `None` when it isn't a subsequence. Every position the
+/// first query char matches at is tried so "fl" matches the word in "fix/flaky" rather than the first "f".
+fn score(query: &[char], candidate: &str, ignore_accents: bool) -> Option<(i64, Vec<usize>)> {
+  let chars: Vec<char> = candidate.chars().map(|c| fold(c, ignore_accents)).collect();
+  best_score(query, &chars)
+}
+
+fn best_score(query: &[char], chars: &[char]) -> Option<(i64, Vec<usize>)> {
+  (0..chars.len())
+    .filter(|start| chars[*start] == query[0])
+    .filter_map(|start| score_from(query, chars, start))
+    .max_by_key(|(score, indices)| (*score, Reverse(indices.first().copied())))
+}
+
+/// Greedily matches `query` in `chars` from `start`. Consecutive matches and matches at the start of a word are
+/// rewarded, unmatched leading characters are penalised.
+fn score_from(query: &[char], chars: &[char], start: usize) -> Option<(i64, Vec<usize>)> {
+  let mut indices: Vec<usize> = Vec::with_capacity(query.len());
+  let mut score = 0;
+  let mut query_chars = query.iter().peekable();
+  for (index, c) in chars.iter().enumerate().skip(start) {
+    let Some(&&wanted) = query_chars.peek() else {
+      break;
+    };
+    if *c != wanted {
+      continue;
+    }
+    score += 1;
+    if indices.last().is_some_and(|last| last + 1 == index) {
+      score += 5;
+    }
+    if index == 0 || SEPARATORS.contains(&chars[index - 1]) {
+      score += 8;
+    }
+    indices.push(index);
+    query_chars.next();
+  }
+  if query_chars.peek().is_some() {
+    return None;
+  }
+  score -= start as i64;
+  Some((score, indices))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn names<'a>(query: &str, candidates: &'a [&'a str]) -> Vec<(&'a str, Vec<usize>)> {
+    fuzzy_match(query, candidates, |name| name, false)
+  }
+
+  #[test]
+  fn an_empty_query_returns_everything_in_order() {
+    let candidates = ["main", "feature/login", "fix/flaky"];
+    assert_eq!(names("", &candidates), candidates.iter().map(|name| (*name, Vec::new())).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn word_starts_and_runs_rank_higher() {
+    let candidates = ["xfxlx", "fix/flaky", "main", "flaky"];
+    let ranked: Vec<&str> = names("fl", &candidates).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(ranked, ["flaky", "fix/flaky", "xfxlx"]);
+  }
+
+  #[test]
+  fn match_indices_point_at_the_matched_chars() {
+    assert_eq!(names("fl", &["fix/flaky"]), [("fix/flaky", vec![4, 5])]);
+    assert_eq!(names("FLog", &["feature/login"]), [("feature/login", vec![0, 8, 9, 10])]);
+  }
+
+  #[test]
+  fn names_missing_a_query_char_are_left_out() {
+    assert!(names("zz", &["main", "feature/login"]).is_empty());
+  }
+
+  #[test]
+  fn case_is_ignored_with_the_indices_of_the_original_chars() {
+    assert_eq!(names("FEATURE", &["feature"]), [("feature", vec![0, 1, 2, 3, 4, 5, 6])]);
+    assert_eq!(names("login", &["ABC-1/Login"]), [("ABC-1/Login", vec![6, 7, 8, 9, 10])]);
+  }
+
+  #[test]
+  fn accents_are_ignored_when_asked() {
+    assert_eq!(fuzzy_match("resume", &["Résumé"], |name| name, true), [("Résumé", vec![0, 1, 2, 3, 4, 5])]);
+    assert!(fuzzy_match("resume", &["Résumé"], |name| name, false).is_empty());
+  }
+
+  #[test]
+  fn decomposed_accents_keep_the_indices_of_the_original_chars() {
+    // `e` followed by a combining acute accent, two chars where the composed `é` is one
+    let decomposed = "re\u{301}sume\u{301}";
+    assert_eq!(fuzzy_match("resume", &[decomposed], |name| name, true), [(decomposed, vec![0, 1, 3, 4, 5, 6])]);
+    assert_eq!(fold_str("RÉSUMÉ", true), "resume");
+    assert_eq!(fold_str("RÉSUMÉ", false), "résumé");
+  }
+}
@@ -0,0 +1,60 @@
+/// The group for branch names without a `/`.
+pub const ROOT_GROUP: &str = "(root)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchGroup<T> {
+  pub prefix: String,
+  pub items: Vec<T>,
+}
+
+/// The part of a branch name before the first `/`, or [`ROOT_GROUP`] when there isn't one.
+pub fn branch_prefix(name: &str) -> &str {
+  match name.split_once('/') {
+    Some((prefix, _)) if !prefix.is_empty() => prefix,
+    _ => ROOT_GROUP,
+  }
+}
+
+/// Buckets `items` by their prefix, usually [`branch_prefix`] of their name. The root group comes first and the rest
+/// are sorted by prefix, items keep their relative order within a group.
+pub fn group_by_prefix<T>(items: Vec<T>, prefix_of: impl Fn(&T) -> String) -> Vec<BranchGroup<T>> {
+  let mut groups: Vec<BranchGroup<T>> = Vec::new();
+  for item in items {
+    let prefix = prefix_of(&item);
+    match groups.iter_mut().find(|group| group.prefix == prefix) {
+      Some(group) => group.items.push(item),
+      None => groups.push(BranchGroup { prefix, items: vec![item] }),
+    }
+  }
+  groups.sort_by(|a, b| (a.prefix != ROOT_GROUP, &a.prefix).cmp(&(b.prefix != ROOT_GROUP, &b.prefix)));
+  groups
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn group(names: &[&str]) -> Vec<(String, Vec<String>)> {
+    let items = names.iter().map(|name| String::from(*name)).collect();
+    group_by_prefix(items, |name| String::from(branch_prefix(name)))
+      .into_iter()
+      .map(|group| (group.prefix, group.items))
+      .collect()
+  }
+
+  #[test]
+  fn branches_are_bucketed_by_the_prefix_before_the_first_slash() {
+    let groups = group(&["release/1.0", "feature/login", "main", "feature/search/v2", "bugfix/crash", "dev"]);
+    let counts: Vec<(&str, usize)> = groups.iter().map(|(prefix, items)| (prefix.as_str(), items.len())).collect();
+    assert_eq!(counts, [(ROOT_GROUP, 2), ("bugfix", 1), ("feature", 2), ("release", 1)]);
+    assert_eq!(groups[2].1, ["feature/login", "feature/search/v2"]);
+    assert_eq!(groups[0].1, ["main", "dev"]);
+  }
+
+  #[test]
+  fn a_leading_slash_has_no_prefix() {
+    assert_eq!(branch_prefix("/odd"), ROOT_GROUP);
+    assert_eq!(branch_prefix("plain"), ROOT_GROUP);
+    assert_eq!(branch_prefix("feature/login"), "feature");
+  }
+}
@@ -0,0 +1,133 @@
+use std::{
+  collections::VecDeque,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, List, ListItem, ListState},
+};
+
+use crate::{components::branch_list::commit_log::format_age, config::theme::Theme, tui::Frame};
+
+/// How many operations are remembered, the oldest are forgotten first.
+pub const HISTORY_LENGTH: usize = 100;
+
+/// A change made to the repository this session and how it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+  pub description: String,
+  /// When it was made, in seconds since the epoch.
+  pub time: i64,
+  /// Why it failed, `None` when it succeeded.
+  pub error: Option<String>,
+}
+
+/// A popup listing the changes made to the repository this session, newest first.
+#[derive(Debug, Default)]
+pub struct History {
+  operations: VecDeque<Operation>,
+  list_state: ListState,
+  open: bool,
+  theme: Theme,
+}
+
+impl History {
+  pub fn new(theme: Theme) -> Self {
+    History { theme, ..History::default() }
+  }
+
+  pub fn record(&mut self, description: String, error: Option<String>) {
+    if self.operations.len() == HISTORY_LENGTH {
+      self.operations.pop_front();
+    }
+    self.operations.push_back(Operation { description, time: now(), error });
+  }
+
+  pub fn open(&mut self) {
+    self.list_state.select(Some(0));
+    self.open = true;
+  }
+
+  pub fn close(&mut self) {
+    self.open = false;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// Moves with the arrow keys and closes on esc, returning whether it is still open.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> bool {
+    let last = self.operations.len().saturating_sub(1);
+    let selected = self.list_state.selected().unwrap_or(0);
+    match key.code {
+      KeyCode::Esc => self.open = false,
+      KeyCode::Down => self.list_state.select(Some((selected + 1).min(last))),
+      KeyCode::Up => self.list_state.select(Some(selected.saturating_sub(1))),
+      KeyCode::PageDown => self.list_state.select(Some((selected + 10).min(last))),
+      KeyCode::PageUp => self.list_state.select(Some(selected.saturating_sub(10))),
+      _ => {},
+    }
+    self.open
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+
+    let now = now();
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let items: Vec<ListItem> = self
+      .operations
+      .iter()
+      .rev()
+      .map(|operation| {
+        let mut parts = match &operation.error {
+          None => vec![Span::styled("✓", Style::default().fg(self.theme.ahead))],
+          Some(_) => vec![Span::styled("✗", Style::default().fg(self.theme.error))],
+        };
+        parts.push(Span::styled(format!(" {:>14} ", format_age(now - operation.time)), dim));
+        parts.push(Span::raw(operation.description.clone()));
+        if let Some(error) = &operation.error {
+          parts.push(Span::styled(format!(": {}", error), Style::default().fg(self.theme.error)));
+        }
+        ListItem::from(Line::from(parts))
+      })
+      .collect();
+    let title = if items.is_empty() { "Nothing changed this session" } else { "This session" };
+    let list = List::new(items)
+      .block(Block::bordered().title(title).title_bottom("esc: Close"))
+      .style(Style::default().fg(self.theme.text))
+      .highlight_style(Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD))
+      .highlight_symbol("→");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut self.list_state);
+  }
+}
+
+fn now() -> i64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_the_latest_operations_are_remembered() {
+    let mut history = History::default();
+    for n in 0..HISTORY_LENGTH + 2 {
+      history.record(format!("Change {}", n), None);
+    }
+    assert_eq!(history.operations.len(), HISTORY_LENGTH);
+    assert_eq!(history.operations.front().unwrap().description, "Change 2");
+    assert_eq!(history.operations.back().unwrap().description, format!("Change {}", HISTORY_LENGTH + 1));
+  }
+}
@@ -3,33 +3,85 @@ use ratatui::{
   prelude::{Line, Span},
 };
 
-use crate::{components::branch_list::branch_item::BranchItem, tui::Frame};
+use crate::{
+  components::branch_list::branch_item::BranchItem,
+  config::keybindings::{KeyBindings, KeyCommand},
+  tui::Frame,
+};
 
 #[derive(Debug, Default)]
-pub struct InstructionFooter {}
+pub struct InstructionFooter {
+  keybindings: KeyBindings,
+  read_only: bool,
+  /// Offers the quick checkout key, only shown when it is enabled.
+  quick_checkout: bool,
+}
 
 impl InstructionFooter {
-  pub fn render(&self, f: &mut Frame<'_>, area: Rect, branches: &[BranchItem], selected: Option<&BranchItem>) {
-    let mut commands = vec![Span::raw("esc: Quit")];
-    commands.push(Span::raw(" | ⇧ + c: Checkout new"));
+  pub fn new(keybindings: KeyBindings, read_only: bool, quick_checkout: bool) -> Self {
+    InstructionFooter { keybindings, read_only, quick_checkout }
+  }
+
+  pub fn render(
+    &self,
+    f: &mut Frame<'_>,
+    area: Rect,
+    branches: &[BranchItem],
+    selected: Option<&BranchItem>,
+    grouped: bool,
+    group_selected: bool,
+  ) {
+    let hint = |command: KeyCommand, description: &str| format!("{}: {}", self.keybindings.label(command), description);
+    let mut commands = Vec::new();
+    // Read-only mode leaves out everything that would be refused
+    let mut add = |command: KeyCommand, description: &str| {
+      if !(self.read_only && command.mutates()) {
+        commands.push(hint(command, description));
+      }
+    };
+    add(KeyCommand::Quit, "Quit");
+    add(KeyCommand::Help, "Help");
+    add(KeyCommand::CreateBranch, "Checkout new");
+    add(KeyCommand::Filter, "Filter");
+    add(KeyCommand::Search, "Search");
+    add(KeyCommand::ToggleGrouping, if grouped { "Ungroup" } else { "Group by prefix" });
+    if group_selected {
+      add(KeyCommand::ToggleGroup, "Expand/collapse");
+    }
     if selected.is_some() && selected.unwrap().staged_for_deletion {
-      commands.push(Span::raw(" | d: Delete"));
-      commands.push(Span::raw(" | ⇧ + d: Unstage for deletion"));
+      add(KeyCommand::Delete, "Delete");
+      add(KeyCommand::Unstage, "Unstage for deletion");
+    }
+
+    let deletable = selected.is_some_and(|branch| {
+      !branch.branch.is_head && !branch.branch.name_is_lossy && !branch.protected && !branch.is_remote
+    });
+    if deletable && !selected.unwrap().staged_for_deletion {
+      add(KeyCommand::Delete, "Stage for deletion");
     }
 
-    if selected.is_some() && !selected.unwrap().branch.is_head {
-      commands.push(Span::raw(" | d: Stage for deletion"));
+    if let Some(selected) = selected {
+      add(KeyCommand::Checkout, "Checkout");
+      if self.quick_checkout && !selected.is_remote && !selected.branch.is_head {
+        add(KeyCommand::QuickCheckout, "Quick checkout, stashing changes");
+      }
+      if !selected.is_remote {
+        add(KeyCommand::Rename, "Rename");
+      }
+      add(KeyCommand::CopyName, "Copy name");
+      add(KeyCommand::CopyRef, "Copy upstream");
     }
 
-    if selected.is_some() {
-      commands.push(Span::raw(" | c: Checkout"));
+    if deletable {
+      add(KeyCommand::ToggleSelection, "Toggle selection");
+      add(KeyCommand::ForceDelete, "Force delete");
     }
 
     if branches.iter().any(|b| b.staged_for_deletion) {
-      commands.push(Span::raw(" | ^ + d: Delete all staged branches"));
+      add(KeyCommand::DeleteStaged, "Delete all staged branches");
     }
 
-    let footer = Line::from(commands);
+    let footer = Line::from(Span::raw(commands.join(" | ")));
     f.render_widget(footer, area);
   }
 }
@@ -0,0 +1,288 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{
+  components::branch_list::{branch_item::highlight_name, fuzzy::fuzzy_match},
+  config::theme::Theme,
+  git::git_repo::{GitBranch, GitRemoteBranch},
+  tui::Frame,
+};
+
+/// What picking an entry in the palette does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteAction {
+  /// Checks out the local branch.
+  Switch(GitBranch),
+  /// Creates a local branch tracking the remote branch and checks it out.
+  CreateTracking(GitRemoteBranch),
+  /// Checks out the commit a typed hash names without a branch.
+  Detach { commit_id: String, short_id: String },
+}
+
+impl PaletteAction {
+  /// The name the entry is matched and listed by.
+  pub fn name(&self) -> &str {
+    match self {
+      PaletteAction::Switch(branch) => &branch.name,
+      PaletteAction::CreateTracking(remote) => &remote.name,
+      PaletteAction::Detach { short_id, .. } => short_id,
+    }
+  }
+
+  fn label(&self) -> String {
+    match self {
+      PaletteAction::Switch(_) => String::from("switch"),
+      PaletteAction::CreateTracking(remote) => {
+        let local_name = remote.split().map_or(remote.name.as_str(), |(_, name)| name);
+        format!("create {} tracking it", local_name)
+      },
+      PaletteAction::Detach { .. } => String::from("checkout (detached)"),
+    }
+  }
+}
+
+/// Every local branch followed by the remote branches that don't have a local branch of the same name yet, those
+/// are reached by switching to the local one. Remote HEADs like `origin/HEAD` are left out.
+pub fn palette_candidates(local: &[GitBranch], remote: &[GitRemoteBranch]) -> Vec<PaletteAction> {
+  let switches = local.iter().filter(|branch| !branch.name_is_lossy).cloned().map(PaletteAction::Switch);
+  let creates = remote
+    .iter()
+    .filter(|remote| {
+      remote.split().is_some_and(|(_, name)| name != "HEAD" && !local.iter().any(|branch| branch.name == name))
+    })
+    .cloned()
+    .map(PaletteAction::CreateTracking);
+  switches.chain(creates).collect()
+}
+
+/// The shortest abbreviation git accepts for a commit hash.
+const MIN_HASH_LENGTH: usize = 4;
+
+/// Whether the query could be a full or abbreviated commit hash, so it is looked up as one.
+fn looks_like_hash(query: &str) -> bool {
+  (MIN_HASH_LENGTH..=40).contains(&query.len()) && query.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// What happened to the open palette after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteOutcome {
+  Pending,
+  Selected(PaletteAction),
+  Closed,
+}
+
+/// A popup to check out any local or remote branch by typing part of its name, or a commit by typing its hash.
+#[derive(Debug, Default)]
+pub struct Palette {
+  candidates: Vec<PaletteAction>,
+  matches: Vec<(PaletteAction, Vec<usize>)>,
+  query: String,
+  list_state: ListState,
+  open: bool,
+  theme: Theme,
+  ignore_accents: bool,
+  /// The query last looked up as a commit hash, with the entry for the commit it names or why that failed.
+  commit: Option<(String, Result<Option<PaletteAction>, String>)>,
+}
+
+impl Palette {
+  pub fn new(theme: Theme, ignore_accents: bool) -> Self {
+    Palette { theme, ignore_accents, ..Palette::default() }
+  }
+
+  pub fn open(&mut self, candidates: Vec<PaletteAction>) {
+    self.candidates = candidates;
+    self.query.clear();
+    self.update_matches();
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// The query when it looks like a commit hash that hasn't been looked up yet.
+  pub fn unresolved_hash(&self) -> Option<&str> {
+    let resolved = self.commit.as_ref().is_some_and(|(query, _)| *query == self.query);
+    (!resolved && looks_like_hash(&self.query)).then_some(self.query.as_str())
+  }
+
+  /// Sets what the query names as a commit hash, an entry offered before the branches or an error to show.
+  pub fn set_commit(&mut self, commit: Result<Option<PaletteAction>, String>) {
+    self.commit = Some((self.query.clone(), commit));
+    self.update_matches();
+  }
+
+  /// The lookup of the current query as a commit hash, stale ones are ignored.
+  fn current_commit(&self) -> Option<&Result<Option<PaletteAction>, String>> {
+    self.commit.as_ref().filter(|(query, _)| *query == self.query).map(|(_, commit)| commit)
+  }
+
+  /// The entry enter would pick.
+  pub fn selected_action(&self) -> Option<&PaletteAction> {
+    self.list_state.selected().and_then(|selected| self.matches.get(selected)).map(|(action, _)| action)
+  }
+
+  /// Typing narrows the entries, the arrow keys move, enter picks the selected entry and esc closes.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> PaletteOutcome {
+    let last = self.matches.len().saturating_sub(1);
+    let selected = self.list_state.selected().unwrap_or(0);
+    match key.code {
+      KeyCode::Esc => {
+        self.open = false;
+        return PaletteOutcome::Closed;
+      },
+      KeyCode::Enter => {
+        self.open = false;
+        return match self.selected_action() {
+          Some(action) => PaletteOutcome::Selected(action.clone()),
+          None => PaletteOutcome::Closed,
+        };
+      },
+      KeyCode::Down => self.list_state.select(Some((selected + 1).min(last))),
+      KeyCode::Up => self.list_state.select(Some(selected.saturating_sub(1))),
+      KeyCode::Backspace => {
+        self.query.pop();
+        self.update_matches();
+      },
+      KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+        self.query.push(c);
+        self.update_matches();
+      },
+      _ => {},
+    }
+    PaletteOutcome::Pending
+  }
+
+  fn update_matches(&mut self) {
+    self.matches = fuzzy_match(&self.query, &self.candidates, PaletteAction::name, self.ignore_accents);
+    if let Some(Ok(Some(action))) = self.current_commit().cloned() {
+      // A hash naming a branch tip offers that branch, which may also have matched by name
+      self.matches.retain(|(candidate, _)| *candidate != action);
+      self.matches.insert(0, (action, Vec::new()));
+    }
+    self.list_state.select(if self.matches.is_empty() { None } else { Some(0) });
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+    let block = Block::bordered().title("Checkout").title_bottom("enter: Checkout | esc: Close");
+    let error = match self.current_commit() {
+      Some(Err(error)) => Some(error.clone()),
+      _ => None,
+    };
+    let [query_area, error_area, list_area] = Layout::vertical([
+      Constraint::Length(1),
+      Constraint::Length(if error.is_some() { 1 } else { 0 }),
+      Constraint::Fill(1),
+    ])
+    .areas(block.inner(area));
+
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let items: Vec<ListItem> = self
+      .matches
+      .iter()
+      .map(|(action, matched)| {
+        let mut parts = highlight_name(action.name(), matched, Style::default());
+        parts.push(Span::styled(format!(" {}", action.label()), dim));
+        ListItem::from(Line::from(parts))
+      })
+      .collect();
+    let list = List::new(items)
+      .style(Style::default().fg(self.theme.text))
+      .highlight_style(Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD))
+      .highlight_symbol("→");
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(
+      Paragraph::new(format!("> {}", self.query)).style(Style::default().fg(self.theme.text)),
+      query_area,
+    );
+    if let Some(error) = error {
+      f.render_widget(Paragraph::new(error).style(Style::default().fg(self.theme.error)), error_area);
+    }
+    f.render_stateful_widget(list, list_area, &mut self.list_state);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn local(names: &[&str]) -> Vec<GitBranch> {
+    names.iter().map(|name| GitBranch::new(String::from(*name))).collect()
+  }
+
+  fn remote(names: &[&str]) -> Vec<GitRemoteBranch> {
+    names.iter().map(|name| GitRemoteBranch::new(String::from(*name))).collect()
+  }
+
+  fn names(candidates: &[PaletteAction]) -> Vec<&str> {
+    candidates.iter().map(PaletteAction::name).collect()
+  }
+
+  #[test]
+  fn candidates_are_the_local_branches_then_remote_branches_without_one() {
+    let candidates =
+      palette_candidates(&local(&["main", "feature"]), &remote(&["origin/HEAD", "origin/main", "origin/fresh"]));
+    assert_eq!(names(&candidates), ["main", "feature", "origin/fresh"]);
+    assert!(matches!(candidates[0], PaletteAction::Switch(_)));
+    assert!(matches!(candidates[2], PaletteAction::CreateTracking(_)));
+  }
+
+  #[test]
+  fn each_entry_says_what_picking_it_does() {
+    let candidates = palette_candidates(&local(&["main"]), &remote(&["origin/fresh"]));
+    assert_eq!(candidates[0].label(), "switch");
+    assert_eq!(candidates[1].label(), "create fresh tracking it");
+  }
+
+  fn typing(palette: &mut Palette, text: &str) {
+    for c in text.chars() {
+      palette.handle_key_event(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+    }
+  }
+
+  #[test]
+  fn enter_picks_the_best_match_or_the_one_moved_to() {
+    let candidates = palette_candidates(&local(&["main", "feature"]), &remote(&["origin/fresh", "origin/fix"]));
+    let mut palette = Palette::default();
+    palette.open(candidates.clone());
+    typing(&mut palette, "fix");
+    assert_eq!(palette.selected_action().map(PaletteAction::name), Some("origin/fix"));
+    let picked = palette.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+    assert_eq!(
+      picked,
+      PaletteOutcome::Selected(PaletteAction::CreateTracking(GitRemoteBranch::new(String::from("origin/fix"))))
+    );
+    assert!(!palette.is_open());
+
+    palette.open(candidates);
+    typing(&mut palette, "f");
+    palette.handle_key_event(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+    let second = palette.selected_action().cloned();
+    assert!(second.is_some());
+    assert_eq!(
+      palette.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+      PaletteOutcome::Selected(second.unwrap())
+    );
+  }
+
+  #[test]
+  fn enter_without_a_match_closes_the_palette() {
+    let mut palette = Palette::default();
+    palette.open(palette_candidates(&local(&["main"]), &[]));
+    typing(&mut palette, "zzz");
+    assert_eq!(palette.selected_action(), None);
+    assert_eq!(palette.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)), PaletteOutcome::Closed);
+  }
+}
@@ -0,0 +1,117 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::Line,
+  widgets::{Block, Clear, Paragraph},
+};
+
+use crate::{config::theme::Theme, git::git_repo::Patch, tui::Frame};
+
+/// A popup showing the patch between HEAD and a branch, scrolled a line, a page or a file at a time.
+#[derive(Debug, Default)]
+pub struct PatchView {
+  branch: String,
+  patch: Patch,
+  /// The first line shown.
+  scroll: usize,
+  /// How many lines fit, from the last render.
+  height: usize,
+  open: bool,
+  theme: Theme,
+}
+
+impl PatchView {
+  pub fn new(theme: Theme) -> Self {
+    PatchView { theme, ..PatchView::default() }
+  }
+
+  pub fn open(&mut self, branch: String, patch: Patch) {
+    self.branch = branch;
+    self.patch = patch;
+    self.scroll = 0;
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// The line starting the file after the first line shown, or before it going back.
+  fn file_start(&self, forward: bool) -> Option<usize> {
+    let is_start = |index: &usize| self.patch.lines[*index].starts_with("diff --git ");
+    match forward {
+      true => (self.scroll + 1..self.patch.lines.len()).find(is_start),
+      false => (0..self.scroll).rev().find(is_start),
+    }
+  }
+
+  /// Scrolls with the arrow and page keys, jumps between files with tab and shift-tab and closes on esc, returning
+  /// whether it is still open.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> bool {
+    let last = self.patch.lines.len().saturating_sub(1);
+    let page = self.height.max(1);
+    match key.code {
+      KeyCode::Esc => self.open = false,
+      KeyCode::Down => self.scroll = (self.scroll + 1).min(last),
+      KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+      KeyCode::PageDown => self.scroll = (self.scroll + page).min(last),
+      KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(page),
+      KeyCode::Home => self.scroll = 0,
+      KeyCode::Tab => self.scroll = self.file_start(true).unwrap_or(self.scroll),
+      KeyCode::BackTab => self.scroll = self.file_start(false).unwrap_or(0),
+      _ => {},
+    }
+    self.open
+  }
+
+  fn style_line(&self, line: &str) -> Style {
+    let text = Style::default().fg(self.theme.text);
+    if line.starts_with("diff --git ") {
+      text.add_modifier(Modifier::BOLD)
+    } else if line.starts_with("+++ ") || line.starts_with("--- ") || line.starts_with("index ") {
+      text.add_modifier(Modifier::DIM)
+    } else if line.starts_with("@@") {
+      Style::default().fg(self.theme.group_header)
+    } else if line.starts_with('+') {
+      Style::default().fg(self.theme.ahead)
+    } else if line.starts_with('-') {
+      Style::default().fg(self.theme.error)
+    } else {
+      text
+    }
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(90)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let title = match (self.patch.lines.is_empty(), self.patch.truncated) {
+      (true, _) => format!("{} is the same as HEAD", self.branch),
+      (false, false) => format!("HEAD compared with {}", self.branch),
+      (false, true) => format!("HEAD compared with {}, truncated at {} lines", self.branch, self.patch.lines.len()),
+    };
+    let block = Block::bordered()
+      .title(title)
+      .title_bottom("↑↓/pgup/pgdn: Scroll | tab/shift-tab: Next/previous file | esc: Close");
+    let inner = block.inner(area);
+    self.height = usize::from(inner.height);
+
+    let mut lines: Vec<Line> = self
+      .patch
+      .lines
+      .iter()
+      .skip(self.scroll)
+      .take(self.height)
+      .map(|line| Line::styled(line.clone(), self.style_line(line)))
+      .collect();
+    if self.patch.truncated && self.scroll + self.height > self.patch.lines.len() {
+      lines.push(Line::styled("… the rest of the diff is too long to show", Style::default().fg(self.theme.warning)));
+    }
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(Paragraph::new(lines), inner);
+  }
+}
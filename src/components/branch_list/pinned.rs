@@ -0,0 +1,29 @@
+/// Moves the items named in `pinned` to the front in pin order, the rest keep their order after them.
+pub fn pinned_first<T>(items: Vec<T>, pinned: &[String], name_of: impl Fn(&T) -> &str) -> Vec<T> {
+  let (mut front, rest): (Vec<T>, Vec<T>) =
+    items.into_iter().partition(|item| pinned.iter().any(|pin| pin == name_of(item)));
+  front.sort_by_key(|item| pinned.iter().position(|pin| pin == name_of(item)));
+  front.extend(rest);
+  front
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ordered(items: &[&'static str], pinned: &[&str]) -> Vec<&'static str> {
+    let pinned: Vec<String> = pinned.iter().map(|pin| String::from(*pin)).collect();
+    pinned_first(items.to_vec(), &pinned, |item| item)
+  }
+
+  #[test]
+  fn pinned_items_come_first_in_pin_order() {
+    assert_eq!(ordered(&["a", "b", "c", "d", "e"], &["d", "b"]), ["d", "b", "a", "c", "e"]);
+  }
+
+  #[test]
+  fn without_pins_the_order_is_kept_and_missing_pins_are_ignored() {
+    assert_eq!(ordered(&["c", "a", "b"], &[]), ["c", "a", "b"]);
+    assert_eq!(ordered(&["c", "a", "b"], &["gone", "b"]), ["b", "c", "a"]);
+  }
+}
@@ -0,0 +1,101 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, List, ListItem, ListState},
+};
+
+use crate::{
+  components::branch_list::commit_log::format_age, config::theme::Theme, git::git_repo::ReflogEntry, tui::Frame,
+};
+
+/// What happened to the open view after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReflogOutcome {
+  Pending,
+  /// A branch should be created at this entry's commit.
+  Selected(ReflogEntry),
+  Closed,
+}
+
+/// A popup listing the HEAD reflog so a branch can be recreated at a commit it used to point at.
+#[derive(Debug, Default)]
+pub struct ReflogView {
+  entries: Vec<ReflogEntry>,
+  list_state: ListState,
+  open: bool,
+  theme: Theme,
+}
+
+impl ReflogView {
+  pub fn new(theme: Theme) -> Self {
+    ReflogView { entries: Vec::new(), list_state: ListState::default(), open: false, theme }
+  }
+
+  pub fn open(&mut self, entries: Vec<ReflogEntry>) {
+    self.entries = entries;
+    self.list_state.select(Some(0));
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// Moves with the arrow keys, picks the selected entry on enter and closes on esc.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> ReflogOutcome {
+    let last = self.entries.len().saturating_sub(1);
+    let selected = self.list_state.selected().unwrap_or(0);
+    match key.code {
+      KeyCode::Esc => {
+        self.open = false;
+        return ReflogOutcome::Closed;
+      },
+      KeyCode::Enter => {
+        self.open = false;
+        return match self.entries.get(selected) {
+          Some(entry) => ReflogOutcome::Selected(entry.clone()),
+          None => ReflogOutcome::Closed,
+        };
+      },
+      KeyCode::Down => self.list_state.select(Some((selected + 1).min(last))),
+      KeyCode::Up => self.list_state.select(Some(selected.saturating_sub(1))),
+      KeyCode::PageDown => self.list_state.select(Some((selected + 10).min(last))),
+      KeyCode::PageUp => self.list_state.select(Some(selected.saturating_sub(10))),
+      _ => {},
+    }
+    ReflogOutcome::Pending
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0);
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let items: Vec<ListItem> = self
+      .entries
+      .iter()
+      .map(|entry| {
+        ListItem::from(Line::from(vec![
+          Span::styled(entry.short_id.clone(), Style::default().fg(self.theme.head)),
+          Span::styled(format!(" {:>14} ", format_age(now - entry.time)), dim),
+          Span::raw(entry.message.clone()),
+        ]))
+      })
+      .collect();
+    let list = List::new(items)
+      .block(Block::bordered().title("Reflog").title_bottom("enter: Create branch here | esc: Close"))
+      .style(Style::default().fg(self.theme.text))
+      .highlight_style(Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD))
+      .highlight_symbol("→");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, &mut self.list_state);
+  }
+}
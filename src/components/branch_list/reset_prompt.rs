@@ -0,0 +1,100 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, Paragraph},
+};
+
+use crate::{config::theme::Theme, git::git_repo::ResetMode, tui::Frame};
+
+/// The target the prompt opens with, undoing the latest commit.
+const DEFAULT_TARGET: &str = "HEAD~1";
+
+/// What happened to the open reset prompt after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResetOutcome {
+  Pending,
+  Submitted(String, ResetMode),
+  Closed,
+}
+
+/// A popup asking which commit to reset the current branch to and how.
+#[derive(Debug, Default)]
+pub struct ResetPrompt {
+  branch: String,
+  target: String,
+  mode: ResetMode,
+  open: bool,
+  theme: Theme,
+}
+
+impl ResetPrompt {
+  pub fn new(theme: Theme) -> Self {
+    ResetPrompt { theme, ..ResetPrompt::default() }
+  }
+
+  pub fn open(&mut self, branch: &str) {
+    self.branch = String::from(branch);
+    self.target = String::from(DEFAULT_TARGET);
+    self.mode = ResetMode::default();
+    self.open = true;
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// Typing edits the target, tab cycles the mode, enter submits and esc closes.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> ResetOutcome {
+    match key.code {
+      KeyCode::Esc => {
+        self.open = false;
+        return ResetOutcome::Closed;
+      },
+      KeyCode::Enter if !self.target.trim().is_empty() => {
+        self.open = false;
+        return ResetOutcome::Submitted(self.target.trim().to_string(), self.mode);
+      },
+      KeyCode::Tab => self.mode = self.mode.next(),
+      KeyCode::Backspace => {
+        self.target.pop();
+      },
+      KeyCode::Char(c) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+        self.target.push(c);
+      },
+      _ => {},
+    }
+    ResetOutcome::Pending
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let [area] = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Length(4)]).flex(Flex::Center).areas(area);
+    let block = Block::bordered()
+      .title(format!("Reset {}", self.branch))
+      .title_bottom("tab: Change mode | enter: Reset | esc: Close");
+    let [target_area, mode_area] =
+      Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(block.inner(area));
+
+    let text = Style::default().fg(self.theme.text);
+    let modes: Vec<Span> = [ResetMode::Soft, ResetMode::Mixed, ResetMode::Hard]
+      .into_iter()
+      .flat_map(|mode| {
+        let style = if mode == self.mode {
+          Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        } else {
+          text.add_modifier(Modifier::DIM)
+        };
+        [Span::styled(format!(" {} ", mode), style), Span::raw(" ")]
+      })
+      .collect();
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(Paragraph::new(format!("to > {}", self.target)).style(text), target_area);
+    f.render_widget(Paragraph::new(Line::from(modes)), mode_area);
+  }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  components::branch_list::branch_item::BranchItem,
+  git::git_repo::{GitBranch, GitRemoteBranch},
+};
+
+/// Which branches the list shows, cycled with a key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BranchScope {
+  #[default]
+  Local,
+  Remote,
+  All,
+}
+
+impl BranchScope {
+  pub fn next(self) -> Self {
+    match self {
+      BranchScope::Local => BranchScope::Remote,
+      BranchScope::Remote => BranchScope::All,
+      BranchScope::All => BranchScope::Local,
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      BranchScope::Local => "local",
+      BranchScope::Remote => "remote",
+      BranchScope::All => "all",
+    }
+  }
+
+  pub fn includes_remotes(self) -> bool {
+    self != BranchScope::Local
+  }
+
+  /// Whether the row is listed. A remote branch a local branch tracks is left out of the combined list as the local
+  /// row already shows it as its upstream, unless `show_tracked` keeps both.
+  pub fn shows(self, item: &BranchItem, show_tracked: bool) -> bool {
+    match self {
+      BranchScope::Local => !item.is_remote,
+      BranchScope::Remote => item.is_remote,
+      BranchScope::All => !item.is_remote || show_tracked || item.tracked_by.is_none(),
+    }
+  }
+
+  /// Whether the remote branches local branches track are folded into the local rows.
+  pub fn collapses_tracked(self, show_tracked: bool) -> bool {
+    self == BranchScope::All && !show_tracked
+  }
+}
+
+/// Pairs each remote branch with the local branch tracking it, if any. Remote HEADs like `origin/HEAD` are left out
+/// as they only point at another remote branch.
+pub fn link_remotes(local: &[GitBranch], remote: &[GitRemoteBranch]) -> Vec<(GitRemoteBranch, Option<String>)> {
+  remote
+    .iter()
+    .filter(|remote| !remote.name.ends_with("/HEAD"))
+    .map(|remote| {
+      let tracked_by = local
+        .iter()
+        .find(|branch| branch.upstream.as_ref().is_some_and(|upstream| upstream.name == remote.name))
+        .map(|branch| branch.name.clone());
+      (remote.clone(), tracked_by)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn remotes(names: &[&str]) -> Vec<GitRemoteBranch> {
+    names.iter().map(|name| GitRemoteBranch::new(String::from(*name))).collect()
+  }
+
+  fn tracking(name: &str, upstream: &str) -> GitBranch {
+    GitBranch { upstream: Some(GitRemoteBranch::new(String::from(upstream))), ..GitBranch::new(String::from(name)) }
+  }
+
+  /// The local rows followed by the linked remote rows, as the list builds them.
+  fn rows() -> Vec<BranchItem> {
+    let local = vec![tracking("main", "origin/main"), GitBranch::new(String::from("scratch"))];
+    let linked = link_remotes(&local, &remotes(&["origin/HEAD", "origin/main", "origin/fresh"]));
+    let remote_rows = linked.into_iter().map(|(remote, tracked_by)| BranchItem::remote(remote, tracked_by));
+    local.into_iter().map(|branch| BranchItem::new(branch, true)).chain(remote_rows).collect()
+  }
+
+  fn shown(scope: BranchScope) -> Vec<String> {
+    rows().into_iter().filter(|item| scope.shows(item, false)).map(|item| item.branch.name).collect()
+  }
+
+  #[test]
+  fn remote_branches_are_linked_to_the_local_branch_tracking_them() {
+    let local = vec![tracking("main", "origin/main")];
+    let linked = link_remotes(&local, &remotes(&["origin/HEAD", "origin/main", "origin/fresh"]));
+    assert_eq!(linked, [
+      (GitRemoteBranch::new(String::from("origin/main")), Some(String::from("main"))),
+      (GitRemoteBranch::new(String::from("origin/fresh")), None)
+    ]);
+  }
+
+  #[test]
+  fn each_scope_shows_its_branches_and_all_shows_tracked_remotes_once() {
+    assert_eq!(shown(BranchScope::Local), ["main", "scratch"]);
+    assert_eq!(shown(BranchScope::Remote), ["origin/main", "origin/fresh"]);
+    assert_eq!(shown(BranchScope::All), ["main", "scratch", "origin/fresh"]);
+  }
+
+  #[test]
+  fn the_scope_cycles_through_local_remote_and_all() {
+    assert_eq!(BranchScope::default(), BranchScope::Local);
+    assert_eq!(BranchScope::Local.next(), BranchScope::Remote);
+    assert_eq!(BranchScope::Remote.next(), BranchScope::All);
+    assert_eq!(BranchScope::All.next(), BranchScope::Local);
+    assert!(!BranchScope::Local.includes_remotes());
+    assert!(BranchScope::All.includes_remotes());
+  }
+
+  #[test]
+  fn all_can_keep_the_tracked_remotes_listed() {
+    let shown: Vec<String> =
+      rows().into_iter().filter(|item| BranchScope::All.shows(item, true)).map(|item| item.branch.name).collect();
+    assert_eq!(shown, ["main", "scratch", "origin/main", "origin/fresh"]);
+    assert!(BranchScope::All.collapses_tracked(false));
+    assert!(!BranchScope::All.collapses_tracked(true));
+    assert!(!BranchScope::Remote.collapses_tracked(false));
+  }
+}
@@ -0,0 +1,46 @@
+use super::fuzzy::fold_str;
+
+/// The position of the first name containing `query`, ignoring case and, with `ignore_accents`, accents.
+pub fn first_match(names: &[&str], query: &str, ignore_accents: bool) -> Option<usize> {
+  let query = fold_str(query, ignore_accents);
+  names.iter().position(|name| fold_str(name, ignore_accents).contains(&query))
+}
+
+/// The position of the next name containing `query` after `current`, or before it when going backwards, wrapping
+/// around the ends. `current` itself is only returned when it is the sole match.
+pub fn cycle_match(names: &[&str], query: &str, current: usize, forward: bool, ignore_accents: bool) -> Option<usize> {
+  let query = fold_str(query, ignore_accents);
+  let len = names.len();
+  (1..=len)
+    .map(|step| if forward { (current + step) % len } else { (current + len - step % len) % len })
+    .find(|position| fold_str(names[*position], ignore_accents).contains(&query))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NAMES: [&str; 5] = ["main", "feature/Login", "fix/typo", "feature/logout", "release"];
+
+  #[test]
+  fn the_first_name_containing_the_query_matches_ignoring_case() {
+    assert_eq!(first_match(&NAMES, "LOG", false), Some(1));
+    assert_eq!(first_match(&NAMES, "typo", false), Some(2));
+    assert_eq!(first_match(&NAMES, "missing", false), None);
+  }
+
+  #[test]
+  fn cycling_moves_to_the_next_and_previous_match_and_wraps() {
+    assert_eq!(cycle_match(&NAMES, "log", 1, true, false), Some(3));
+    assert_eq!(cycle_match(&NAMES, "log", 3, true, false), Some(1));
+    assert_eq!(cycle_match(&NAMES, "log", 3, false, false), Some(1));
+    assert_eq!(cycle_match(&NAMES, "log", 1, false, false), Some(3));
+    assert_eq!(cycle_match(&NAMES, "log", 0, false, false), Some(3));
+  }
+
+  #[test]
+  fn cycling_stays_on_the_only_match_and_finds_nothing_without_one() {
+    assert_eq!(cycle_match(&NAMES, "typo", 2, true, false), Some(2));
+    assert_eq!(cycle_match(&NAMES, "missing", 2, true, false), None);
+  }
+}
@@ -0,0 +1,64 @@
+use regex::Regex;
+use tracing::warn;
+
+use super::branch_item::BranchItem;
+
+/// Finds ticket ids like `ABC-123` in branch names with the configured regex. The first capture group is the ticket
+/// when the regex has one, otherwise the whole match is.
+#[derive(Debug, Default, Clone)]
+pub struct TicketPattern {
+  regex: Option<Regex>,
+}
+
+impl TicketPattern {
+  /// Compiles the configured pattern, an invalid one is logged and finds no tickets.
+  pub fn from_config(pattern: Option<&str>) -> Self {
+    let regex = pattern.and_then(|pattern| {
+      Regex::new(pattern).map_err(|err| warn!("Ignoring the invalid ticket pattern '{}': {}", pattern, err)).ok()
+    });
+    TicketPattern { regex }
+  }
+
+  pub fn ticket(&self, name: &str) -> Option<String> {
+    let captures = self.regex.as_ref()?.captures(name)?;
+    let ticket = captures.get(1).or_else(|| captures.get(0))?;
+    Some(String::from(ticket.as_str()))
+  }
+}
+
+/// Whether the ticket filter shows the branch, every branch is shown without one.
+pub fn matches_ticket(item: &BranchItem, filter: Option<&str>) -> bool {
+  filter.is_none_or(|filter| item.ticket.as_deref() == Some(filter))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::git::git_repo::GitBranch;
+
+  #[test]
+  fn the_first_capture_group_or_the_whole_match_is_the_ticket() {
+    let whole = TicketPattern::from_config(Some(r"[A-Z]+-\d+"));
+    assert_eq!(whole.ticket("feature/ABC-123-login").as_deref(), Some("ABC-123"));
+    assert_eq!(whole.ticket("main"), None);
+    let grouped = TicketPattern::from_config(Some(r"^\w+/(\d+)-"));
+    assert_eq!(grouped.ticket("fix/4521-typo").as_deref(), Some("4521"));
+  }
+
+  #[test]
+  fn no_or_an_invalid_pattern_finds_no_tickets() {
+    assert_eq!(TicketPattern::from_config(None).ticket("ABC-123"), None);
+    assert_eq!(TicketPattern::from_config(Some("ABC-(")).ticket("ABC-123"), None);
+  }
+
+  #[test]
+  fn the_ticket_filter_only_shows_branches_for_its_ticket() {
+    let item = |ticket: Option<&str>| {
+      BranchItem { ticket: ticket.map(String::from), ..BranchItem::new(GitBranch::new(String::from("branch")), true) }
+    };
+    assert!(matches_ticket(&item(Some("ABC-1")), Some("ABC-1")));
+    assert!(!matches_ticket(&item(Some("ABC-2")), Some("ABC-1")));
+    assert!(!matches_ticket(&item(None), Some("ABC-1")));
+    assert!(matches_ticket(&item(None), None));
+  }
+}
@@ -0,0 +1,115 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{components::branch_list::branch_item::BranchItem, error::Error};
+
+/// What the branch list remembers about a repository between runs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+  pub selected_branch: Option<String>,
+  pub collapsed_groups: Vec<String>,
+  pub grouped: bool,
+  /// Branches kept at the top of the list, in the order they were pinned.
+  pub pinned: Vec<String>,
+}
+
+impl UiState {
+  /// Where the remembered branch is in `branches`, `None` when nothing was remembered or the branch is gone.
+  pub fn selected_index(&self, branches: &[BranchItem]) -> Option<usize> {
+    let name = self.selected_branch.as_ref()?;
+    branches.iter().position(|item| item.branch.name == *name)
+  }
+}
+
+/// A state file holding the [`UiState`] of every repository the app has been opened in, by repository path.
+#[derive(Debug, Clone)]
+pub struct UiStateStore {
+  path: PathBuf,
+  repo: String,
+}
+
+impl UiStateStore {
+  pub fn new(path: PathBuf, repo_path: &Path) -> Self {
+    let repo_path = repo_path.canonicalize().unwrap_or_else(|_| repo_path.to_path_buf());
+    UiStateStore { path, repo: repo_path.display().to_string() }
+  }
+
+  /// The remembered state, a missing or unreadable state file just means starting fresh.
+  pub fn load(&self) -> UiState {
+    self.read_all().remove(&self.repo).unwrap_or_default()
+  }
+
+  pub fn save(&self, state: &UiState) -> Result<(), Error> {
+    let mut all = self.read_all();
+    all.insert(self.repo.clone(), state.clone());
+    if let Some(parent) = self.path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(&self.path, serde_json::to_string_pretty(&all)?)?;
+    Ok(())
+  }
+
+  fn read_all(&self) -> BTreeMap<String, UiState> {
+    let Ok(contents) = fs::read_to_string(&self.path) else {
+      return BTreeMap::new();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|err| {
+      debug!("Ignoring the unreadable state file {}: {}", self.path.display(), err);
+      BTreeMap::new()
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{git::git_repo::GitBranch, test_support::TempDir};
+
+  fn remembered() -> UiState {
+    UiState {
+      selected_branch: Some(String::from("feature")),
+      collapsed_groups: vec![String::from("fix")],
+      grouped: true,
+      pinned: vec![String::from("main")],
+    }
+  }
+
+  #[test]
+  fn saved_state_loads_back_for_the_same_repository_only() {
+    let dir = TempDir::new();
+    let path = dir.path().join("cache").join("ui_state.json");
+    let store = UiStateStore::new(path.clone(), &dir.path().join("one"));
+    store.save(&remembered()).unwrap();
+    assert_eq!(UiStateStore::new(path.clone(), &dir.path().join("one")).load(), remembered());
+    assert_eq!(UiStateStore::new(path, &dir.path().join("two")).load(), UiState::default());
+  }
+
+  #[test]
+  fn a_missing_or_corrupt_state_file_starts_fresh() {
+    let dir = TempDir::new();
+    let path = dir.path().join("ui_state.json");
+    let store = UiStateStore::new(path.clone(), dir.path());
+    assert_eq!(store.load(), UiState::default());
+    fs::write(&path, "{ not json").unwrap();
+    assert_eq!(store.load(), UiState::default());
+    store.save(&remembered()).unwrap();
+    assert_eq!(store.load(), remembered());
+  }
+
+  #[test]
+  fn the_remembered_branch_is_found_unless_it_is_gone() {
+    let branches: Vec<BranchItem> =
+      ["main", "feature"].into_iter().map(|name| BranchItem::new(GitBranch::new(String::from(name)), true)).collect();
+    assert_eq!(remembered().selected_index(&branches), Some(1));
+    let stale = UiState { selected_branch: Some(String::from("deleted")), ..remembered() };
+    assert_eq!(stale.selected_index(&branches), None);
+    assert_eq!(UiState::default().selected_index(&branches), None);
+  }
+}
@@ -0,0 +1,238 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Text},
+  widgets::{Block, Clear, Paragraph, Wrap},
+};
+
+use crate::{action::Action, config::theme::Theme, tui::Frame};
+
+/// Bulk deletes of more branches than this need the count typed out when the config doesn't set a threshold.
+pub const DEFAULT_BULK_DELETE_THRESHOLD: usize = 10;
+
+/// The word that confirms a typed confirmation, as well as the count.
+const CONFIRM_WORD: &str = "DELETE";
+
+/// How much friction a prompt puts in front of its action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmStyle {
+  YesNo,
+  /// The count, or `DELETE`, has to be typed before the action runs.
+  Typed(usize),
+  /// The word has to be typed before the action runs.
+  TypedWord(&'static str),
+}
+
+impl ConfirmStyle {
+  /// A plain yes/no for up to `threshold` branches and a typed confirmation for more.
+  pub fn for_count(count: usize, threshold: usize) -> Self {
+    if count > threshold {
+      ConfirmStyle::Typed(count)
+    } else {
+      ConfirmStyle::YesNo
+    }
+  }
+}
+
+/// What happened to an open dialog after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DialogOutcome {
+  /// The key didn't answer the prompt, the dialog stays open.
+  Pending,
+  /// The user agreed, the guarded action should now run.
+  Confirmed(Action),
+  Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Confirmation {
+  message: String,
+  action: Action,
+  style: ConfirmStyle,
+  /// What has been typed so far for a typed confirmation.
+  typed: String,
+}
+
+/// A yes/no prompt guarding a destructive action, the action is only handed back once the user confirms it.
+#[derive(Debug, Default)]
+pub struct ConfirmDialog {
+  pending: Option<Confirmation>,
+  theme: Theme,
+}
+
+impl ConfirmDialog {
+  pub fn new(theme: Theme) -> Self {
+    ConfirmDialog { pending: None, theme }
+  }
+
+  /// Opens the dialog asking `message`, replacing any prompt that is already open.
+  pub fn open(&mut self, message: impl Into<String>, action: Action) {
+    self.open_with_style(message, action, ConfirmStyle::YesNo);
+  }
+
+  pub fn open_with_style(&mut self, message: impl Into<String>, action: Action, style: ConfirmStyle) {
+    self.pending = Some(Confirmation { message: message.into(), action, style, typed: String::new() });
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.pending.is_some()
+  }
+
+  /// Confirms on y or enter and cancels on n or esc, any other key leaves the dialog open. A typed confirmation
+  /// instead collects text until enter, and only confirms when the text matches.
+  pub fn handle_key_event(&mut self, key: KeyEvent) -> DialogOutcome {
+    let Some(mut pending) = self.pending.take() else {
+      return DialogOutcome::Cancelled;
+    };
+    let expected = match pending.style {
+      ConfirmStyle::YesNo => None,
+      ConfirmStyle::Typed(count) => Some(vec![count.to_string(), String::from(CONFIRM_WORD)]),
+      ConfirmStyle::TypedWord(word) => Some(vec![String::from(word)]),
+    };
+    if let Some(expected) = expected {
+      match key.code {
+        KeyCode::Esc => return DialogOutcome::Cancelled,
+        KeyCode::Enter if expected.contains(&pending.typed) => return DialogOutcome::Confirmed(pending.action),
+        KeyCode::Backspace => {
+          pending.typed.pop();
+        },
+        KeyCode::Char(c) => pending.typed.push(c),
+        _ => {},
+      }
+      self.pending = Some(pending);
+      return DialogOutcome::Pending;
+    }
+    match key.code {
+      KeyCode::Char('y' | 'Y') | KeyCode::Enter => DialogOutcome::Confirmed(pending.action),
+      KeyCode::Char('n' | 'N') | KeyCode::Esc => DialogOutcome::Cancelled,
+      _ => {
+        self.pending = Some(pending);
+        DialogOutcome::Pending
+      },
+    }
+  }
+
+  pub fn render(&self, f: &mut Frame<'_>, area: Rect) {
+    let Some(pending) = &self.pending else {
+      return;
+    };
+    let [area] = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center).areas(area);
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let text = match pending.style {
+      ConfirmStyle::YesNo => {
+        Text::from(vec![
+          Line::from(pending.message.clone()),
+          Line::default(),
+          Line::styled("y/enter: Confirm | n/esc: Cancel", dim),
+        ])
+      },
+      ConfirmStyle::Typed(count) => {
+        Text::from(vec![
+          Line::from(pending.message.clone()),
+          Line::default(),
+          Line::from(format!("> {}", pending.typed)),
+          Line::styled(format!("Type {} or {} then enter to confirm | esc: Cancel", count, CONFIRM_WORD), dim),
+        ])
+      },
+      ConfirmStyle::TypedWord(word) => {
+        Text::from(vec![
+          Line::from(pending.message.clone()),
+          Line::default(),
+          Line::from(format!("> {}", pending.typed)),
+          Line::styled(format!("Type {} then enter to confirm | esc: Cancel", word), dim),
+        ])
+      },
+    };
+    let height = u16::try_from(text.lines.len() + 3).unwrap_or(u16::MAX);
+    let [area] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(area);
+    let dialog = Paragraph::new(text)
+      .block(Block::bordered().title("Confirm").border_style(Style::from(self.theme.warning)))
+      .style(Style::default().fg(self.theme.text))
+      .wrap(Wrap { trim: true });
+    f.render_widget(Clear, area);
+    f.render_widget(dialog, area);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crossterm::event::KeyModifiers;
+
+  use super::*;
+
+  fn press(dialog: &mut ConfirmDialog, code: KeyCode) -> DialogOutcome {
+    dialog.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+  }
+
+  fn opened() -> ConfirmDialog {
+    let mut dialog = ConfirmDialog::default();
+    dialog.open("Delete feature?", Action::DeleteBranch);
+    dialog
+  }
+
+  #[test]
+  fn y_and_enter_confirm_with_the_guarded_action() {
+    for code in [KeyCode::Char('y'), KeyCode::Char('Y'), KeyCode::Enter] {
+      let mut dialog = opened();
+      assert_eq!(press(&mut dialog, code), DialogOutcome::Confirmed(Action::DeleteBranch));
+      assert!(!dialog.is_open());
+    }
+  }
+
+  #[test]
+  fn n_and_esc_cancel() {
+    for code in [KeyCode::Char('n'), KeyCode::Esc] {
+      let mut dialog = opened();
+      assert_eq!(press(&mut dialog, code), DialogOutcome::Cancelled);
+      assert!(!dialog.is_open());
+    }
+  }
+
+  #[test]
+  fn other_keys_leave_the_dialog_open() {
+    let mut dialog = opened();
+    assert_eq!(press(&mut dialog, KeyCode::Char('x')), DialogOutcome::Pending);
+    assert!(dialog.is_open());
+    assert_eq!(press(&mut dialog, KeyCode::Enter), DialogOutcome::Confirmed(Action::DeleteBranch));
+  }
+
+  #[test]
+  fn up_to_the_threshold_is_a_yes_no_and_past_it_is_typed() {
+    assert_eq!(ConfirmStyle::for_count(1, DEFAULT_BULK_DELETE_THRESHOLD), ConfirmStyle::YesNo);
+    assert_eq!(ConfirmStyle::for_count(10, 10), ConfirmStyle::YesNo);
+    assert_eq!(ConfirmStyle::for_count(11, 10), ConfirmStyle::Typed(11));
+    assert_eq!(ConfirmStyle::for_count(2, 1), ConfirmStyle::Typed(2));
+  }
+
+  fn typing(dialog: &mut ConfirmDialog, text: &str) -> DialogOutcome {
+    for c in text.chars() {
+      assert_eq!(press(dialog, KeyCode::Char(c)), DialogOutcome::Pending);
+    }
+    press(dialog, KeyCode::Enter)
+  }
+
+  fn opened_typed() -> ConfirmDialog {
+    let mut dialog = ConfirmDialog::default();
+    dialog.open_with_style("Delete 12 branches?", Action::DeleteStagedBranches, ConfirmStyle::Typed(12));
+    dialog
+  }
+
+  #[test]
+  fn a_typed_confirmation_needs_the_count_or_the_word() {
+    for text in ["12", "DELETE"] {
+      let mut dialog = opened_typed();
+      assert_eq!(typing(&mut dialog, text), DialogOutcome::Confirmed(Action::DeleteStagedBranches));
+    }
+  }
+
+  #[test]
+  fn a_typed_confirmation_ignores_y_and_a_wrong_count() {
+    let mut dialog = opened_typed();
+    assert_eq!(typing(&mut dialog, "y"), DialogOutcome::Pending);
+    press(&mut dialog, KeyCode::Backspace);
+    assert_eq!(typing(&mut dialog, "11"), DialogOutcome::Pending);
+    assert!(dialog.is_open());
+    assert_eq!(press(&mut dialog, KeyCode::Esc), DialogOutcome::Cancelled);
+  }
+}
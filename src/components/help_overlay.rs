@@ -0,0 +1,134 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+  layout::{Constraint, Flex, Layout, Rect},
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::{Block, Clear, Paragraph},
+};
+use strum::IntoEnumIterator;
+
+use crate::{
+  config::{
+    keybindings::{KeyBindings, KeyCommand},
+    theme::Theme,
+  },
+  tui::Frame,
+};
+
+/// A popup listing every command with the keys bound to it in the active config.
+#[derive(Debug, Default)]
+pub struct HelpOverlay {
+  keybindings: KeyBindings,
+  theme: Theme,
+  open: bool,
+  scroll: u16,
+}
+
+impl HelpOverlay {
+  pub fn new(keybindings: KeyBindings, theme: Theme) -> Self {
+    HelpOverlay { keybindings, theme, open: false, scroll: 0 }
+  }
+
+  pub fn is_open(&self) -> bool {
+    self.open
+  }
+
+  pub fn open(&mut self) {
+    self.open = true;
+    self.scroll = 0;
+  }
+
+  /// The keys and description of each command, unbound commands are left out.
+  pub fn entries(&self) -> Vec<(String, &'static str)> {
+    KeyCommand::iter()
+      .filter_map(|command| {
+        let labels = self.keybindings.labels(command);
+        if labels.is_empty() {
+          return None;
+        }
+        Some((labels.join(", "), command.description()))
+      })
+      .collect()
+  }
+
+  /// Closes on esc or the help key and scrolls with the arrow and page keys.
+  pub fn handle_key_event(&mut self, key: KeyEvent) {
+    if key.code == KeyCode::Esc || self.keybindings.command_for(&key) == Some(KeyCommand::Help) {
+      self.open = false;
+      return;
+    }
+    match key.code {
+      KeyCode::Down => self.scroll = self.scroll.saturating_add(1),
+      KeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+      KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+      KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+      KeyCode::Home => self.scroll = 0,
+      _ => {},
+    }
+  }
+
+  pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
+    if !self.open {
+      return;
+    }
+    let entries = self.entries();
+    let key_width = entries.iter().map(|(keys, _)| keys.chars().count()).max().unwrap_or(0);
+    let lines: Vec<Line> = entries
+      .into_iter()
+      .map(|(keys, description)| {
+        Line::from(vec![
+          Span::styled(format!("{:<width$}", keys, width = key_width), Style::default().add_modifier(Modifier::BOLD)),
+          Span::raw("  "),
+          Span::raw(description),
+        ])
+      })
+      .collect();
+
+    // Grow to fit the bindings plus the border, but never past the terminal
+    let height = u16::try_from(lines.len() + 2).unwrap_or(u16::MAX).min(area.height);
+    let [area] = Layout::horizontal([Constraint::Percentage(70)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(area);
+
+    let max_scroll = u16::try_from(lines.len()).unwrap_or(u16::MAX).saturating_sub(height.saturating_sub(2));
+    self.scroll = self.scroll.min(max_scroll);
+    let help = Paragraph::new(lines)
+      .block(Block::bordered().title("Help").title_bottom("esc: Close | ↑/↓: Scroll"))
+      .style(Style::default().fg(self.theme.text))
+      .scroll((self.scroll, 0));
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crossterm::event::KeyModifiers;
+
+  use super::*;
+
+  fn overlay(config: &str) -> HelpOverlay {
+    HelpOverlay::new(serde_json::from_str(config).unwrap(), Theme::default())
+  }
+
+  #[test]
+  fn the_entries_show_the_configured_keys() {
+    let overlay = overlay(r#"{ "select_next": ["j", "down"], "checkout": "shift-c" }"#);
+    let entries = overlay.entries();
+    assert!(entries.contains(&(String::from("j, ↓"), KeyCommand::SelectNext.description())));
+    assert!(entries.contains(&(String::from("⇧ + c"), KeyCommand::Checkout.description())));
+    // Its only key went to checkout, so create branch is unbound and left out
+    assert!(!entries.iter().any(|(_, description)| *description == KeyCommand::CreateBranch.description()));
+  }
+
+  #[test]
+  fn esc_and_the_help_key_close_it() {
+    for code in [KeyCode::Esc, KeyCode::Char('?')] {
+      let mut overlay = overlay("{}");
+      overlay.open();
+      overlay.handle_key_event(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+      assert!(overlay.is_open());
+      overlay.handle_key_event(KeyEvent::new(code, KeyModifiers::NONE));
+      assert!(!overlay.is_open());
+    }
+  }
+}
@@ -1,6 +1,6 @@
 use ratatui::{
   layout::Rect,
-  style::{Color, Modifier, Style},
+  style::{Modifier, Style},
   text::{Line, Span},
   widgets::{Block, Borders, List, ListItem, ListState},
   Frame,
@@ -8,6 +8,7 @@ use ratatui::{
 
 use crate::{
   components::Component,
+  config::{theme::Theme, Config},
   git::git_repo::{GitRepo, GitStash},
 };
 
@@ -21,7 +22,7 @@ impl StashItem {
     StashItem { git_stash }
   }
 
-  pub fn render(&self) -> ListItem {
+  pub fn render(&self) -> ListItem<'_> {
     let mut text = Line::default();
     let mut parts = Vec::new();
     let index = Span::styled(self.git_stash.index.to_string(), Style::default());
@@ -44,13 +45,14 @@ impl StashItem {
 pub struct StashList {
   stashes: Vec<StashItem>,
   list_state: ListState,
+  theme: Theme,
 }
 
 impl StashList {
-  pub fn new(mut repo: Box<dyn GitRepo>) -> Self {
+  pub fn new(mut repo: Box<dyn GitRepo>, config: &Config) -> Self {
     let stashes: Vec<StashItem> =
       repo.stashes().unwrap().iter().map(|git_stash| StashItem::new(git_stash.clone())).collect();
-    StashList { stashes, list_state: ListState::default() }
+    StashList { stashes, list_state: ListState::default(), theme: config.theme.clone() }
   }
 }
 
@@ -59,8 +61,8 @@ impl Component for StashList {
     let render_items: Vec<ListItem> = self.stashes.iter().map(|stash| stash.render()).collect();
     let list = List::new(render_items)
       .block(Block::default().title("Stashes").borders(Borders::ALL))
-      .style(Style::default().fg(Color::White))
-      .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+      .style(Style::default().fg(self.theme.text))
+      .highlight_style(Style::default().fg(self.theme.selected).add_modifier(Modifier::BOLD))
       .highlight_symbol("→")
       .repeat_highlight_symbol(true);
     f.render_stateful_widget(list, area, &mut self.list_state);
@@ -0,0 +1,366 @@
+use std::{
+  path::{Path, PathBuf},
+  time::{Duration, Instant},
+};
+
+use directories::BaseDirs;
+use ratatui::{
+  layout::Rect,
+  style::{Modifier, Style},
+  text::{Line, Span},
+  widgets::Paragraph,
+  Frame,
+};
+use tracing::warn;
+
+use crate::{
+  action::Action,
+  components::{branch_list::scope::BranchScope, Component},
+  config::{theme::Theme, Config},
+  error::Error,
+  git::{
+    git_repo::{GitRepo, RepoState},
+    progress::TransferProgress,
+  },
+};
+
+/// How often the status is re-read while nothing else is happening, to pick up changes made outside the app.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The frames of the spinner shown while fetching, one is picked by how long the fetch has been running.
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// How long each spinner frame is shown.
+const SPINNER_FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// How long "refreshed" stays in the bar after an explicit refresh.
+const REFRESHED_FLASH: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadState {
+  Branch(String),
+  /// HEAD points straight at a commit, holds its short id.
+  Detached(String),
+  /// HEAD points at a branch with no commits yet, holds its name.
+  Unborn(String),
+}
+
+impl Default for HeadState {
+  fn default() -> Self {
+    HeadState::Unborn(String::from("HEAD"))
+  }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepoStatus {
+  pub head: HeadState,
+  pub dirty: bool,
+  pub local_branches: usize,
+  pub remote_branches: usize,
+  pub path: String,
+  /// The merge, rebase or similar the repository is part way through.
+  pub operation: RepoState,
+}
+
+impl RepoStatus {
+  pub fn load(repo: &dyn GitRepo, path: &Path) -> Result<Self, Error> {
+    let summary = repo.summary()?;
+    let head = match repo.current_branch()? {
+      Some(branch) if branch.is_unborn => HeadState::Unborn(branch.name),
+      Some(branch) => HeadState::Branch(branch.name),
+      None => {
+        match repo.head_short_id()? {
+          Some(short_id) => HeadState::Detached(short_id),
+          None => HeadState::Unborn(String::from("HEAD")),
+        }
+      },
+    };
+    Ok(RepoStatus {
+      head,
+      dirty: !summary.clean,
+      local_branches: summary.local_branches,
+      remote_branches: summary.remote_branches,
+      path: short_path(path),
+      operation: repo.repo_state(),
+    })
+  }
+}
+
+/// Formats the status as `<branch> | <clean or dirty> | <local> local, <remote> remote | <path>`.
+pub fn status_text(status: &RepoStatus) -> String {
+  let head = match &status.head {
+    HeadState::Branch(name) => name.clone(),
+    HeadState::Detached(short_id) => format!("detached at {}", short_id),
+    HeadState::Unborn(name) => format!("{} (no commits yet)", name),
+  };
+  let state = if status.dirty { "dirty" } else { "clean" };
+  format!(
+    " {} | {} | {} local, {} remote | {}",
+    head, state, status.local_branches, status.remote_branches, status.path
+  )
+}
+
+/// Swaps the home directory prefix for `~` to keep the path short.
+fn short_path(path: &Path) -> String {
+  let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+  match home.as_deref().and_then(|home| path.strip_prefix(home).ok()) {
+    Some(relative) if relative.as_os_str().is_empty() => String::from("~"),
+    Some(relative) => format!("~/{}", relative.display()),
+    None => path.display().to_string(),
+  }
+}
+
+fn spinner_frame(elapsed: Duration) -> char {
+  let frame = elapsed.as_millis() / SPINNER_FRAME_TIME.as_millis();
+  SPINNER_FRAMES[frame as usize % SPINNER_FRAMES.len()]
+}
+
+/// Always visible bar at the bottom of the screen showing where HEAD is and whether there are local changes.
+pub struct StatusBar {
+  repo: Box<dyn GitRepo>,
+  path: PathBuf,
+  status: RepoStatus,
+  last_refresh: Instant,
+  /// When the user last asked for a refresh, to briefly confirm it happened.
+  refreshed_at: Option<Instant>,
+  /// Mirrors the branch list's scope, which announces changes with [`Action::BranchScopeChanged`].
+  scope: BranchScope,
+  /// When the running background fetch started, `None` when there isn't one.
+  fetch_started: Option<Instant>,
+  /// How much the background fetch has received, shown next to the spinner once the remote has said.
+  fetch_progress: TransferProgress,
+  /// Why the last background fetch failed, shown here rather than as an error as the app works fine without it.
+  fetch_error: Option<String>,
+  theme: Theme,
+}
+
+impl StatusBar {
+  /// Shows the root of the repository, wherever in it the app was started.
+  pub fn new(repo: Box<dyn GitRepo>, config: &Config) -> Self {
+    let path = repo.workdir().unwrap_or_else(|| repo.path()).to_path_buf();
+    let mut status_bar = StatusBar {
+      repo,
+      path,
+      status: RepoStatus::default(),
+      last_refresh: Instant::now(),
+      refreshed_at: None,
+      scope: BranchScope::default(),
+      fetch_started: None,
+      fetch_progress: TransferProgress::default(),
+      fetch_error: None,
+      theme: config.theme.clone(),
+    };
+    status_bar.refresh();
+    status_bar
+  }
+
+  /// Shows the progress the background fetch reports to `progress`.
+  pub fn fetch_progress(mut self, progress: TransferProgress) -> Self {
+    self.fetch_progress = progress;
+    self
+  }
+
+  fn refresh(&mut self) {
+    self.last_refresh = Instant::now();
+    match RepoStatus::load(&*self.repo, &self.path) {
+      Ok(status) => self.status = status,
+      Err(err) => warn!("Failed to read the repository status: {}", err),
+    }
+  }
+}
+
+impl Component for StatusBar {
+  fn update(&mut self, action: Action) -> color_eyre::Result<Option<Action>> {
+    match action {
+      Action::Tick if self.last_refresh.elapsed() >= REFRESH_INTERVAL => self.refresh(),
+      Action::BranchScopeChanged(scope) => self.scope = scope,
+      Action::FetchStarted => {
+        self.fetch_started = Some(Instant::now());
+        self.fetch_progress.reset();
+        self.fetch_error = None;
+      },
+      Action::FetchFailed(err) => {
+        self.fetch_started = None;
+        // Git's errors can run over several lines, the first says what went wrong
+        self.fetch_error = Some(err.lines().next().unwrap_or_default().trim().to_string());
+      },
+      Action::RemoteFetched | Action::RemotePruned(_) => self.fetch_started = None,
+      Action::Refresh => {
+        self.refresh();
+        self.refreshed_at = Some(Instant::now());
+      },
+      // These may have moved HEAD or touched the working tree
+      Action::AbortOperation
+      | Action::BranchesLoaded(_)
+      | Action::CheckoutBranch(_)
+      | Action::CheckoutCommit(_)
+      | Action::CheckoutDefaultBranch
+      | Action::CheckoutPreviousBranch
+      | Action::CheckoutRemoteBranch(_)
+      | Action::CheckoutSelectedBranch
+      | Action::CheckoutUpstream
+      | Action::CherryPickCommit(_)
+      | Action::CreateBranch(_)
+      | Action::CreateBranchFrom(..)
+      | Action::CreateWipBranch
+      | Action::DeleteGoneBranches(_)
+      | Action::FastForwardBranches
+      | Action::ForceCheckoutBranch(_)
+      | Action::MarkBisectBad
+      | Action::MarkBisectGood
+      | Action::QuickCheckoutSelectedBranch
+      | Action::RenameBranches(_)
+      | Action::RenameSelectedBranch(_)
+      | Action::RenameUpstream(_)
+      | Action::ResetCurrentBranch(..)
+      | Action::Resume
+      | Action::SetBranchUpstream(..)
+      | Action::StopBisect
+      | Action::UndoDelete => self.refresh(),
+      _ => {},
+    }
+    Ok(None)
+  }
+
+  fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> color_eyre::Result<()> {
+    let color = if self.status.dirty { self.theme.warning } else { self.theme.text };
+    let mut parts = Vec::new();
+    if self.repo.is_dry_run() {
+      parts.push(Span::styled(" DRY RUN |", Style::default().fg(self.theme.warning).add_modifier(Modifier::BOLD)));
+    }
+    if self.repo.is_read_only() {
+      parts.push(Span::styled(" READ ONLY |", Style::default().fg(self.theme.warning).add_modifier(Modifier::BOLD)));
+    }
+    if !self.status.operation.is_clean() {
+      let banner = format!(" {} IN PROGRESS |", self.status.operation.to_string().to_uppercase());
+      parts.push(Span::styled(banner, Style::default().fg(self.theme.warning).add_modifier(Modifier::BOLD)));
+    }
+    parts.push(Span::raw(format!(" {} branches |", self.scope.label())));
+    parts.push(Span::styled(status_text(&self.status), Style::default().fg(color)));
+    if let Some(started) = self.fetch_started {
+      let mut text = format!(" | {} fetching", spinner_frame(started.elapsed()));
+      if let Some(transfer) = self.fetch_progress.get() {
+        text.push_str(&format!(" {}", transfer));
+      }
+      parts.push(Span::styled(text, Style::default().fg(self.theme.text).add_modifier(Modifier::DIM)));
+    }
+    if let Some(err) = &self.fetch_error {
+      parts.push(Span::styled(format!(" | fetch failed: {}", err), Style::default().fg(self.theme.warning)));
+    }
+    if self.refreshed_at.is_some_and(|refreshed_at| refreshed_at.elapsed() < REFRESHED_FLASH) {
+      parts.push(Span::styled(" | refreshed", Style::default().fg(self.theme.text).add_modifier(Modifier::BOLD)));
+    }
+    f.render_widget(Paragraph::new(Line::from(parts)), area);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ratatui::layout::Position;
+
+  use super::*;
+  use crate::test_support::{render, rows, Fixture};
+
+  fn status(head: HeadState, dirty: bool) -> RepoStatus {
+    RepoStatus {
+      head,
+      dirty,
+      local_branches: 3,
+      remote_branches: 5,
+      path: String::from("~/code/repo"),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn the_text_shows_the_branch_and_whether_the_tree_is_clean() {
+    assert_eq!(
+      status_text(&status(HeadState::Branch(String::from("main")), false)),
+      " main | clean | 3 local, 5 remote | ~/code/repo"
+    );
+    assert_eq!(
+      status_text(&status(HeadState::Branch(String::from("main")), true)),
+      " main | dirty | 3 local, 5 remote | ~/code/repo"
+    );
+  }
+
+  #[test]
+  fn a_detached_head_shows_the_short_id() {
+    assert_eq!(
+      status_text(&status(HeadState::Detached(String::from("1a2b3c4")), false)),
+      " detached at 1a2b3c4 | clean | 3 local, 5 remote | ~/code/repo"
+    );
+  }
+
+  #[test]
+  fn loading_reads_the_head_and_local_changes() {
+    let fixture = Fixture::new();
+    fixture.git(&["checkout", "-q", "--detach"]);
+    fixture.write("README.md", "local changes\n");
+    let status = RepoStatus::load(&fixture.cli(), &fixture.path).unwrap();
+    assert_eq!(status.head, HeadState::Detached(fixture.head()[..7].to_string()));
+    assert!(status.dirty);
+    assert_eq!(status.local_branches, 1);
+  }
+
+  #[test]
+  fn a_refresh_rereads_the_status_and_flashes_refreshed() {
+    let fixture = Fixture::new();
+    let mut bar = StatusBar::new(Box::new(fixture.cli()), &Config::default());
+    assert_eq!(bar.status.local_branches, 1);
+    fixture.git(&["branch", "added-elsewhere"]);
+    bar.update(Action::Refresh).unwrap();
+    assert_eq!(bar.status.local_branches, 2);
+    assert!(bar.refreshed_at.is_some_and(|refreshed_at| refreshed_at.elapsed() < REFRESHED_FLASH));
+  }
+
+  #[test]
+  fn a_failed_fetch_keeps_the_first_line_of_the_error_until_the_next_fetch() {
+    let fixture = Fixture::new();
+    let mut bar = StatusBar::new(Box::new(fixture.cli()), &Config::default());
+    bar.update(Action::FetchStarted).unwrap();
+    assert!(bar.fetch_started.is_some());
+    bar.update(Action::FetchFailed(String::from("Could not resolve host\nfatal: unable to access"))).unwrap();
+    assert_eq!((bar.fetch_started, bar.fetch_error.as_deref()), (None, Some("Could not resolve host")));
+    bar.update(Action::FetchStarted).unwrap();
+    assert_eq!(bar.fetch_error, None);
+  }
+
+  #[test]
+  fn the_bar_is_drawn_with_the_branch_and_a_read_only_banner() {
+    let fixture = Fixture::new();
+    let mut bar = StatusBar::new(Box::new(fixture.cli().read_only(true)), &Config::default());
+    let buffer = render(&mut bar, 80, 1);
+    let rows = rows(&buffer);
+    assert!(rows[0].starts_with(" READ ONLY | local branches | main | clean | 1 local, 0 remote | "), "{}", rows[0]);
+    assert_eq!(buffer[Position::new(1, 0)].fg, Theme::default().warning);
+  }
+
+  #[test]
+  fn a_running_fetch_shows_a_spinner_and_the_transfer_so_far() {
+    let fixture = Fixture::new();
+    let progress = TransferProgress::default();
+    let mut bar = StatusBar::new(Box::new(fixture.cli()), &Config::default()).fetch_progress(progress.clone());
+    progress.update(5, 5, 100);
+    bar.update(Action::FetchStarted).unwrap();
+    // The last fetch's progress is forgotten, so only the spinner shows until the remote replies
+    let row = rows(&render(&mut bar, 200, 1)).remove(0);
+    assert!(SPINNER_FRAMES.iter().any(|frame| row.ends_with(&format!("| {} fetching", frame))), "{}", row);
+
+    progress.update(12, 40, 2048);
+    let row = rows(&render(&mut bar, 200, 1)).remove(0);
+    assert!(row.ends_with(" fetching 12/40 objects, 2.0 KiB"), "{}", row);
+
+    bar.update(Action::RemoteFetched).unwrap();
+    let row = rows(&render(&mut bar, 200, 1)).remove(0);
+    assert!(!row.contains("fetching"), "{}", row);
+  }
+
+  #[test]
+  fn the_spinner_moves_on_a_frame_every_frame_time() {
+    assert_eq!(spinner_frame(Duration::ZERO), SPINNER_FRAMES[0]);
+    assert_eq!(spinner_frame(SPINNER_FRAME_TIME * 3), SPINNER_FRAMES[3]);
+    assert_eq!(spinner_frame(SPINNER_FRAME_TIME * 11 + Duration::from_millis(1)), SPINNER_FRAMES[1]);
+  }
+}
@@ -3,6 +3,21 @@ use std::path::PathBuf;
 use color_eyre::eyre::Result;
 use serde::Deserialize;
 
+use crate::{
+  config::{keybindings::KeyBindings, layout::ListLayout, snippets::NameSnippets, theme::Theme},
+  git::git_repo::GitRepo,
+  viewer::Viewer,
+};
+
+/// The name of a repository's own config file, kept as `.git-branch-manager.toml` in the repository root to share it
+/// with everyone working on the repository, or in the git directory for a single clone.
+pub const REPO_CONFIG_FILE: &str = "git-branch-manager.toml";
+
+pub mod keybindings;
+pub mod layout;
+pub mod snippets;
+pub mod theme;
+
 #[derive(Clone, Debug, Deserialize, Default)]
 pub struct AppConfig {
   #[serde(default)]
@@ -15,10 +30,82 @@ pub struct AppConfig {
 pub struct Config {
   #[serde(default, flatten)]
   pub config: AppConfig,
+  #[serde(default)]
+  pub keybindings: KeyBindings,
+  #[serde(default)]
+  pub theme: Theme,
+  /// How the branch list fits names and their details into its width.
+  #[serde(default)]
+  pub list_layout: ListLayout,
+  /// Glob patterns for branches that can't be deleted, the default branch is always protected.
+  #[serde(default)]
+  pub protected_branches: Vec<String>,
+  /// The name for branches made with the create wip branch command, `{date}` and `{sha}` are filled in.
+  #[serde(default)]
+  pub wip_branch_pattern: Option<String>,
+  /// New branches are offered this branch as their base rather than the current one.
+  #[serde(default)]
+  pub default_base_branch: Option<String>,
+  /// Keys that insert text into the branch name input, like `alt-j` for a `jira/` prefix.
+  #[serde(default)]
+  pub name_snippets: NameSnippets,
+  /// Deleting more staged branches than this asks for the count to be typed rather than a yes or no.
+  #[serde(default)]
+  pub bulk_delete_confirm_threshold: Option<usize>,
+  /// Counts untracked files as local changes, which blocks checkouts that would otherwise carry them along.
+  #[serde(default)]
+  pub untracked_files_are_dirty: bool,
+  /// Enables the quick checkout key, which stashes local changes on the branch left and restores them on return.
+  #[serde(default)]
+  pub quick_checkout: bool,
+  /// The filter, search and palette match names regardless of accents, so `resume` finds `résumé`. Case is always
+  /// ignored.
+  #[serde(default)]
+  pub match_ignores_accents: bool,
+  /// A regex finding ticket ids in branch names, like `[A-Z]+-\d+`. The first capture group is the ticket when there
+  /// is one.
+  #[serde(default)]
+  pub ticket_pattern: Option<String>,
+  /// Lets the reset key move a detached HEAD instead of refusing because there is no branch to reset.
+  #[serde(default)]
+  pub allow_detached_reset: bool,
+  /// Commit ids are shown abbreviated to at least this many characters, and more when needed to tell them apart.
+  #[serde(default)]
+  pub abbrev_length: Option<usize>,
+  /// The remote fetches, pulls, pushes and prunes use for a branch that doesn't fetch from a remote of its own, like
+  /// `upstream`. `origin` when unset.
+  #[serde(default)]
+  pub default_remote: Option<String>,
+  /// Lists the remote branches local branches track in the all scope too, rather than only as the local branches'
+  /// upstreams.
+  #[serde(default)]
+  pub show_tracked_remotes: bool,
+  /// Fetches from the default remote in the background when the app starts, so the remote branches are fresh.
+  #[serde(default)]
+  pub fetch_on_startup: bool,
+  /// Whether commits are opened in `$PAGER` or `$EDITOR`.
+  #[serde(default)]
+  pub commit_viewer: Viewer,
+  /// The least severe level written to the log file, like `debug` or `warn`. The environment overrides it.
+  #[serde(default)]
+  pub log_level: Option<String>,
+}
+
+/// The global config with the repository's own config files merged on top, the one in the git directory winning over
+/// the one in the repository root. Values they leave out come from the global config.
+pub fn load_config(repo: &dyn GitRepo) -> Result<Config, config::ConfigError> {
+  let mut repo_files = Vec::new();
+  if let Some(workdir) = repo.workdir() {
+    repo_files.push(workdir.join(format!(".{}", REPO_CONFIG_FILE)));
+  }
+  repo_files.push(repo.path().join(REPO_CONFIG_FILE));
+  Config::load(&repo_files)
 }
 
 impl Config {
-  pub fn new() -> Result<Self, config::ConfigError> {
+  /// Reads the global config files followed by the TOML files in `overrides`, each overriding what came before.
+  /// Missing files are skipped.
+  fn load(overrides: &[PathBuf]) -> Result<Self, config::ConfigError> {
     let data_dir = crate::utils::get_data_dir();
     let config_dir = crate::utils::get_config_dir();
     let mut builder = config::Config::builder()
@@ -39,12 +126,97 @@ impl Config {
         found_config = true
       }
     }
+    for path in overrides {
+      builder = builder.add_source(config::File::from(path.as_path()).format(config::FileFormat::Toml).required(false));
+      found_config |= path.exists();
+    }
     if !found_config {
       log::error!("No configuration file found. Application may not behave as expected");
     }
 
-    let cfg: Self = builder.build()?.try_deserialize()?;
+    let cfg: Self = match builder.build().and_then(|built| built.try_deserialize()) {
+      Ok(cfg) => cfg,
+      Err(err) => {
+        log::warn!("Invalid configuration, using the defaults: {}", err);
+        Config {
+          config: AppConfig { _data_dir: data_dir, _config_dir: config_dir },
+          keybindings: KeyBindings::default(),
+          theme: Theme::default(),
+          list_layout: ListLayout::default(),
+          protected_branches: Vec::new(),
+          wip_branch_pattern: None,
+          default_base_branch: None,
+          name_snippets: NameSnippets::default(),
+          bulk_delete_confirm_threshold: None,
+          untracked_files_are_dirty: false,
+          allow_detached_reset: false,
+          quick_checkout: false,
+          match_ignores_accents: false,
+          ticket_pattern: None,
+          abbrev_length: None,
+          default_remote: None,
+          show_tracked_remotes: false,
+          fetch_on_startup: false,
+          commit_viewer: Viewer::default(),
+          log_level: None,
+        }
+      },
+    };
 
     Ok(cfg)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::*;
+  use crate::test_support::{Fixture, TempDir};
+
+  #[test]
+  fn an_invalid_config_falls_back_to_the_defaults() {
+    let dir = TempDir::new();
+    let path = dir.path().join(REPO_CONFIG_FILE);
+    fs::write(&path, "keybindings = 5\n").unwrap();
+    let config = Config::load(&[path]).unwrap();
+    assert_eq!(config.keybindings, KeyBindings::default());
+  }
+
+  #[test]
+  fn keybindings_are_read_from_a_config_file() {
+    let dir = TempDir::new();
+    let path = dir.path().join(REPO_CONFIG_FILE);
+    fs::write(&path, "[keybindings]\nselect_next = [\"j\", \"down\"]\n").unwrap();
+    let config = Config::load(&[path]).unwrap();
+    assert_eq!(config.keybindings.labels(keybindings::KeyCommand::SelectNext), ["j", "↓"]);
+  }
+
+  #[test]
+  fn the_repositorys_config_wins_over_the_global_one_and_its_git_directory_over_its_root() {
+    let fixture = Fixture::new();
+    fixture.write(
+      &format!(".{}", REPO_CONFIG_FILE),
+      "protected_branches = [\"release/*\"]\ndefault_remote = \"upstream\"\n",
+    );
+    fs::write(fixture.path.join(".git").join(REPO_CONFIG_FILE), "default_remote = \"fork\"\n").unwrap();
+    let global = Config::load(&[]).unwrap();
+    let config = load_config(&fixture.cli()).unwrap();
+    assert_eq!(config.protected_branches, ["release/*"]);
+    assert_eq!(config.default_remote.as_deref(), Some("fork"));
+    // What neither file sets comes from the global config
+    assert_eq!(config.keybindings, global.keybindings);
+    assert_eq!(config.abbrev_length, global.abbrev_length);
+  }
+
+  #[test]
+  fn a_repository_without_its_own_config_uses_the_global_one() {
+    let fixture = Fixture::new();
+    let global = Config::load(&[]).unwrap();
+    for config in [load_config(&fixture.cli()).unwrap(), load_config(&fixture.git2()).unwrap()] {
+      assert_eq!(config.protected_branches, global.protected_branches);
+      assert_eq!(config.default_remote, global.default_remote);
+      assert_eq!(config.keybindings, global.keybindings);
+    }
+  }
+}
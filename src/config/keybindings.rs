@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Deserializer};
+use strum::{EnumIter, EnumString, IntoEnumIterator};
+use tracing::warn;
+
+/// The things a key can be bound to, named in the config file by their snake case names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum KeyCommand {
+  Quit,
+  SelectNext,
+  SelectPrevious,
+  PageDown,
+  PageUp,
+  SelectFirst,
+  SelectLast,
+  CenterSelection,
+  Checkout,
+  /// Checks out the selected branch after confirming that local changes will be thrown away.
+  ForceCheckout,
+  QuickCheckout,
+  PreviewCheckout,
+  CheckoutDefault,
+  CheckoutPrevious,
+  CheckoutUpstream,
+  Palette,
+  CreateBranch,
+  CreateWipBranch,
+  BranchFromCommit,
+  Rename,
+  BulkRename,
+  EditDescription,
+  /// Stages the selected branch for deletion, or deletes it when it is already staged.
+  Delete,
+  Unstage,
+  DeleteStaged,
+  ForceDelete,
+  Archive,
+  DeleteMerged,
+  CleanUpGone,
+  Undo,
+  Reflog,
+  History,
+  ShowCommit,
+  ShowCommitDiff,
+  CompareWithHead,
+  CherryPick,
+  FindCommit,
+  Reset,
+  FastForwardAll,
+  BisectGood,
+  BisectBad,
+  BisectStop,
+  AbortOperation,
+  Filter,
+  Search,
+  SearchNext,
+  SearchPrevious,
+  ToggleGrouping,
+  ToggleGraph,
+  ToggleUnpushed,
+  ToggleCleanup,
+  FilterTicket,
+  ToggleBehindSort,
+  CycleScope,
+  Refresh,
+  ToggleGroup,
+  ToggleSelection,
+  TogglePin,
+  CopyName,
+  CopyRef,
+  Help,
+}
+
+impl KeyCommand {
+  fn default_keys(self) -> &'static [&'static str] {
+    match self {
+      KeyCommand::Quit => &["esc", "ctrl-c"],
+      KeyCommand::SelectNext => &["down"],
+      KeyCommand::SelectPrevious => &["up"],
+      KeyCommand::PageDown => &["pagedown"],
+      KeyCommand::PageUp => &["pageup"],
+      KeyCommand::SelectFirst => &["home"],
+      KeyCommand::SelectLast => &["end"],
+      KeyCommand::CenterSelection => &["z"],
+      KeyCommand::Checkout => &["c"],
+      KeyCommand::ForceCheckout => &["alt-c"],
+      KeyCommand::QuickCheckout => &["shift-s"],
+      KeyCommand::PreviewCheckout => &["v"],
+      KeyCommand::CheckoutDefault => &["m"],
+      KeyCommand::CheckoutPrevious => &["-"],
+      KeyCommand::CheckoutUpstream => &["shift-u"],
+      KeyCommand::Palette => &["ctrl-p"],
+      KeyCommand::CreateBranch => &["shift-c"],
+      KeyCommand::CreateWipBranch => &["w"],
+      KeyCommand::BranchFromCommit => &["b"],
+      KeyCommand::Rename => &["r"],
+      KeyCommand::BulkRename => &["alt-r"],
+      KeyCommand::EditDescription => &["e"],
+      KeyCommand::Delete => &["d"],
+      KeyCommand::Unstage => &["shift-d"],
+      KeyCommand::DeleteStaged => &["ctrl-d"],
+      KeyCommand::ForceDelete => &["alt-d"],
+      KeyCommand::Archive => &["a"],
+      KeyCommand::DeleteMerged => &["shift-m"],
+      KeyCommand::CleanUpGone => &["ctrl-g"],
+      KeyCommand::Undo => &["u"],
+      KeyCommand::Reflog => &["shift-r"],
+      KeyCommand::History => &["h"],
+      KeyCommand::ShowCommit => &["o"],
+      KeyCommand::ShowCommitDiff => &["shift-o"],
+      KeyCommand::CompareWithHead => &["shift-h"],
+      KeyCommand::CherryPick => &["alt-p"],
+      KeyCommand::FindCommit => &["ctrl-f"],
+      KeyCommand::Reset => &["shift-x"],
+      KeyCommand::FastForwardAll => &["shift-f"],
+      KeyCommand::BisectGood => &["alt-g"],
+      KeyCommand::BisectBad => &["alt-b"],
+      KeyCommand::BisectStop => &["alt-x"],
+      KeyCommand::AbortOperation => &["shift-a"],
+      KeyCommand::Filter => &["f"],
+      KeyCommand::Search => &["/"],
+      KeyCommand::SearchNext => &["n"],
+      KeyCommand::SearchPrevious => &["shift-n"],
+      KeyCommand::ToggleGrouping => &["g"],
+      KeyCommand::ToggleGraph => &["shift-g"],
+      KeyCommand::ToggleUnpushed => &["p"],
+      KeyCommand::ToggleCleanup => &["k"],
+      KeyCommand::FilterTicket => &["t"],
+      KeyCommand::ToggleBehindSort => &["s"],
+      KeyCommand::CycleScope => &["tab"],
+      KeyCommand::Refresh => &["ctrl-r"],
+      KeyCommand::ToggleGroup => &["enter"],
+      KeyCommand::ToggleSelection => &["space"],
+      KeyCommand::TogglePin => &["shift-p"],
+      KeyCommand::CopyName => &["y"],
+      KeyCommand::CopyRef => &["shift-y"],
+      KeyCommand::Help => &["?"],
+    }
+  }
+
+  /// Whether the command changes the repository, or stages a change, so it is disabled in read-only mode.
+  pub fn mutates(self) -> bool {
+    matches!(
+      self,
+      KeyCommand::Checkout
+        | KeyCommand::ForceCheckout
+        | KeyCommand::QuickCheckout
+        | KeyCommand::CheckoutDefault
+        | KeyCommand::CheckoutPrevious
+        | KeyCommand::CheckoutUpstream
+        | KeyCommand::Palette
+        | KeyCommand::CreateBranch
+        | KeyCommand::CreateWipBranch
+        | KeyCommand::BranchFromCommit
+        | KeyCommand::Rename
+        | KeyCommand::BulkRename
+        | KeyCommand::EditDescription
+        | KeyCommand::Delete
+        | KeyCommand::DeleteStaged
+        | KeyCommand::ForceDelete
+        | KeyCommand::Archive
+        | KeyCommand::DeleteMerged
+        | KeyCommand::CleanUpGone
+        | KeyCommand::Undo
+        | KeyCommand::CherryPick
+        | KeyCommand::Reset
+        | KeyCommand::FastForwardAll
+        | KeyCommand::BisectGood
+        | KeyCommand::BisectBad
+        | KeyCommand::BisectStop
+        | KeyCommand::AbortOperation
+        | KeyCommand::ToggleSelection
+    )
+  }
+
+  /// What the command does, for the help overlay.
+  pub fn description(self) -> &'static str {
+    match self {
+      KeyCommand::Quit => "Quit",
+      KeyCommand::SelectNext => "Select next",
+      KeyCommand::SelectPrevious => "Select previous",
+      KeyCommand::PageDown => "Select a page down",
+      KeyCommand::PageUp => "Select a page up",
+      KeyCommand::SelectFirst => "Select the first branch",
+      KeyCommand::SelectLast => "Select the last branch",
+      KeyCommand::CenterSelection => "Scroll the selection to the middle of the list",
+      KeyCommand::Checkout => "Checkout the selected branch",
+      KeyCommand::ForceCheckout => "Checkout the selected branch, discarding local changes",
+      KeyCommand::QuickCheckout => {
+        "Check out the selected branch, stashing local changes and restoring the ones left on it, when enabled"
+      },
+      KeyCommand::PreviewCheckout => {
+        "Preview which local changes checking out the selected branch carries over and which stop it"
+      },
+      KeyCommand::CheckoutDefault => "Checkout the default branch",
+      KeyCommand::CheckoutPrevious => "Checkout the previously checked out branch",
+      KeyCommand::CheckoutUpstream => "Checkout the upstream of the current branch detached",
+      KeyCommand::Palette => "Checkout any local or remote branch by name, or a commit by its hash",
+      KeyCommand::CreateBranch => "Create and checkout a new branch",
+      KeyCommand::CreateWipBranch => "Create and checkout a branch with a generated name",
+      KeyCommand::BranchFromCommit => "Create a branch at one of the selected branch's commits",
+      KeyCommand::Rename => "Rename the selected branch",
+      KeyCommand::BulkRename => "Rename every branch matching a prefix or regex, previewing the new names",
+      KeyCommand::EditDescription => "Describe the selected branch, shown above its commits",
+      KeyCommand::Delete => "Stage for deletion, or delete a staged branch",
+      KeyCommand::Unstage => "Unstage for deletion",
+      KeyCommand::DeleteStaged => "Delete all staged branches",
+      KeyCommand::ForceDelete => "Force delete the selected branch",
+      KeyCommand::Archive => "Delete the selected branch, keeping it as an archive/ tag",
+      KeyCommand::DeleteMerged => "Delete every branch merged into the default branch",
+      KeyCommand::CleanUpGone => "Fetch with prune and delete the branches whose upstream is gone from every remote",
+      KeyCommand::Undo => "Undo the last delete",
+      KeyCommand::Reflog => "Recover a branch from the reflog",
+      KeyCommand::History => "Show or hide the changes made this session and whether they worked",
+      KeyCommand::ShowCommit => "Open the latest commit of the selected branch in the pager",
+      KeyCommand::ShowCommitDiff => "Open the latest commit and its diff in the pager",
+      KeyCommand::CompareWithHead => "Show the diff between HEAD and the selected branch",
+      KeyCommand::CherryPick => "Cherry-pick a commit of the selected branch onto the current branch",
+      KeyCommand::FindCommit => "Search commit messages to find the branches containing a commit",
+      KeyCommand::Reset => "Reset the current branch to another commit",
+      KeyCommand::FastForwardAll => "Fast-forward every branch that is behind its upstream",
+      KeyCommand::BisectGood => {
+        "Mark the selected branch good to bisect from, or the commit being bisected good and check out the next"
+      },
+      KeyCommand::BisectBad => {
+        "Mark the selected branch bad to bisect to, or the commit being bisected bad and check out the next"
+      },
+      KeyCommand::BisectStop => "Stop bisecting and check out the branch bisecting started on",
+      KeyCommand::AbortOperation => "Abort the merge, rebase or other operation in progress",
+      KeyCommand::Filter => "Filter branches",
+      KeyCommand::Search => "Jump to branches containing text",
+      KeyCommand::SearchNext => "Jump to the next search match",
+      KeyCommand::SearchPrevious => "Jump to the previous search match",
+      KeyCommand::ToggleGrouping => "Group branches by prefix",
+      KeyCommand::ToggleGraph => "Show the commit graph of the selected branch instead of its log",
+      KeyCommand::ToggleUnpushed => "Only show branches with unpushed commits",
+      KeyCommand::ToggleCleanup => "Only show cleanup candidates, branches whose upstream is gone from every remote",
+      KeyCommand::FilterTicket => "Only show branches for the selected branch's ticket, or all of them again",
+      KeyCommand::ToggleBehindSort => {
+        "Sort by how far behind the default branch each branch is, most behind first, or back by name"
+      },
+      KeyCommand::CycleScope => "Switch between local, remote and all branches",
+      KeyCommand::Refresh => "Re-read the branches for changes made outside the app",
+      KeyCommand::ToggleGroup => "Expand or collapse the selected group",
+      KeyCommand::ToggleSelection => "Toggle selection for bulk deletion",
+      KeyCommand::TogglePin => "Pin the selected branch to the top of the list, or unpin it",
+      KeyCommand::CopyName => "Copy the branch name to the clipboard",
+      KeyCommand::CopyRef => "Copy the upstream ref, pressing again copies the remote URL and then the branch name",
+      KeyCommand::Help => "Show or hide this help",
+    }
+  }
+}
+
+/// Keys for each [`KeyCommand`]. Commands missing from the config keep their default keys, and a key bound in the
+/// config is taken away from any default command that used it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+  bindings: HashMap<KeyCommand, Vec<KeyEvent>>,
+}
+
+impl Default for KeyBindings {
+  fn default() -> Self {
+    let bindings = KeyCommand::iter()
+      .map(|command| {
+        let keys = command.default_keys().iter().map(|key| parse_key(key).expect("default keys are valid")).collect();
+        (command, keys)
+      })
+      .collect();
+    KeyBindings { bindings }
+  }
+}
+
+impl KeyBindings {
+  /// Builds bindings from the config file's `command -> keys` entries. Unknown commands and unparsable keys are
+  /// logged and skipped so a typo never stops the app from starting.
+  pub fn from_config(config: HashMap<String, Vec<String>>) -> Self {
+    let mut key_bindings = KeyBindings::default();
+    for (name, keys) in config {
+      let Ok(command) = name.parse::<KeyCommand>() else {
+        warn!("Ignoring keybinding for unknown command '{}'", name);
+        continue;
+      };
+      let parsed: Result<Vec<KeyEvent>, String> = keys.iter().map(|key| parse_key(key)).collect();
+      match parsed {
+        Ok(parsed) => key_bindings.bind(command, parsed),
+        Err(err) => warn!("Ignoring keybinding for '{}', using the default: {}", name, err),
+      }
+    }
+    key_bindings
+  }
+
+  fn bind(&mut self, command: KeyCommand, keys: Vec<KeyEvent>) {
+    for bound in self.bindings.values_mut() {
+      bound.retain(|key| !keys.contains(key));
+    }
+    self.bindings.insert(command, keys);
+  }
+
+  /// The command bound to `key`, if any.
+  pub fn command_for(&self, key: &KeyEvent) -> Option<KeyCommand> {
+    let key = normalize(key.code, key.modifiers);
+    self.bindings.iter().find(|(_, keys)| keys.contains(&key)).map(|(command, _)| *command)
+  }
+
+  /// A short label for the first key bound to `command` for the instruction footer, empty when it is unbound.
+  pub fn label(&self, command: KeyCommand) -> String {
+    self.bindings.get(&command).and_then(|keys| keys.first()).map(key_label).unwrap_or_default()
+  }
+
+  /// Labels for every key bound to `command`.
+  pub fn labels(&self, command: KeyCommand) -> Vec<String> {
+    self.bindings.get(&command).map(|keys| keys.iter().map(key_label).collect()).unwrap_or_default()
+  }
+}
+
+impl<'de> Deserialize<'de> for KeyBindings {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Keys {
+      One(String),
+      Many(Vec<String>),
+    }
+
+    let raw = HashMap::<String, Keys>::deserialize(deserializer)?;
+    let config = raw
+      .into_iter()
+      .map(|(name, keys)| {
+        let keys = match keys {
+          Keys::One(key) => vec![key],
+          Keys::Many(keys) => keys,
+        };
+        (name, keys)
+      })
+      .collect();
+    Ok(KeyBindings::from_config(config))
+  }
+}
+
+/// Parses keys like `j`, `shift-c`, `ctrl-d`, `alt-enter` or `space` into the event crossterm reports for them.
+pub fn parse_key(raw: &str) -> Result<KeyEvent, String> {
+  let lower = raw.to_ascii_lowercase();
+  let mut parts: Vec<&str> = lower.split('-').collect();
+  // A trailing "-" means the key itself is a dash, e.g. "ctrl--"
+  let key = if lower.ends_with("--") || lower == "-" {
+    parts.truncate(parts.len().saturating_sub(2));
+    "-"
+  } else {
+    parts.pop().unwrap_or_default()
+  };
+
+  let mut modifiers = KeyModifiers::NONE;
+  for modifier in parts {
+    modifiers |= match modifier {
+      "ctrl" | "control" => KeyModifiers::CONTROL,
+      "alt" => KeyModifiers::ALT,
+      "shift" => KeyModifiers::SHIFT,
+      _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, raw)),
+    };
+  }
+
+  let code = match key {
+    "esc" => KeyCode::Esc,
+    "enter" => KeyCode::Enter,
+    "space" => KeyCode::Char(' '),
+    "tab" => KeyCode::Tab,
+    "backspace" => KeyCode::Backspace,
+    "delete" | "del" => KeyCode::Delete,
+    "up" => KeyCode::Up,
+    "down" => KeyCode::Down,
+    "left" => KeyCode::Left,
+    "right" => KeyCode::Right,
+    "home" => KeyCode::Home,
+    "end" => KeyCode::End,
+    "pageup" => KeyCode::PageUp,
+    "pagedown" => KeyCode::PageDown,
+    _ => {
+      // Use the original text so an uppercase letter implies shift
+      let original = &raw[raw.len() - key.len()..];
+      let mut chars = original.chars();
+      match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => return Err(format!("unknown key '{}' in '{}'", key, raw)),
+      }
+    },
+  };
+  Ok(normalize(code, modifiers))
+}
+
+/// Terminals report shifted letters inconsistently, as uppercase, with the shift modifier or both. Letters are stored
+/// lowercase with shift, other chars drop the shift as it is already part of the char.
+pub fn normalize(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+  match code {
+    KeyCode::Char(c) if c.is_alphabetic() => {
+      let shifted = c.is_uppercase() || modifiers.contains(KeyModifiers::SHIFT);
+      let modifiers = if shifted { modifiers | KeyModifiers::SHIFT } else { modifiers };
+      KeyEvent::new(KeyCode::Char(c.to_ascii_lowercase()), modifiers)
+    },
+    KeyCode::Char(c) => KeyEvent::new(KeyCode::Char(c), modifiers - KeyModifiers::SHIFT),
+    _ => KeyEvent::new(code, modifiers),
+  }
+}
+
+fn key_label(key: &KeyEvent) -> String {
+  let mut label = String::new();
+  if key.modifiers.contains(KeyModifiers::CONTROL) {
+    label.push_str("^ + ");
+  }
+  if key.modifiers.contains(KeyModifiers::ALT) {
+    label.push_str("alt + ");
+  }
+  if key.modifiers.contains(KeyModifiers::SHIFT) {
+    label.push_str("⇧ + ");
+  }
+  let code = match key.code {
+    KeyCode::Char(' ') => "space".to_string(),
+    KeyCode::Char(c) => c.to_string(),
+    KeyCode::Esc => "esc".to_string(),
+    KeyCode::Enter => "enter".to_string(),
+    KeyCode::Up => "↑".to_string(),
+    KeyCode::Down => "↓".to_string(),
+    KeyCode::Left => "←".to_string(),
+    KeyCode::Right => "→".to_string(),
+    other => format!("{:?}", other).to_lowercase(),
+  };
+  label.push_str(&code);
+  label
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+    KeyEvent::new(code, modifiers)
+  }
+
+  #[test]
+  fn keys_parse_with_their_modifiers() {
+    assert_eq!(parse_key("j"), Ok(key(KeyCode::Char('j'), KeyModifiers::NONE)));
+    assert_eq!(parse_key("shift-c"), Ok(key(KeyCode::Char('c'), KeyModifiers::SHIFT)));
+    assert_eq!(parse_key("C"), Ok(key(KeyCode::Char('c'), KeyModifiers::SHIFT)));
+    assert_eq!(parse_key("ctrl-d"), Ok(key(KeyCode::Char('d'), KeyModifiers::CONTROL)));
+    assert_eq!(parse_key("alt-enter"), Ok(key(KeyCode::Enter, KeyModifiers::ALT)));
+    assert_eq!(parse_key("space"), Ok(key(KeyCode::Char(' '), KeyModifiers::NONE)));
+    assert_eq!(parse_key("ctrl--"), Ok(key(KeyCode::Char('-'), KeyModifiers::CONTROL)));
+    assert!(parse_key("hyper-j").is_err());
+    assert!(parse_key("jk").is_err());
+  }
+
+  #[test]
+  fn configured_keys_replace_the_defaults_of_their_command() {
+    let bindings: KeyBindings =
+      serde_json::from_str(r#"{ "select_next": ["j", "down"], "select_previous": "k" }"#).unwrap();
+    assert_eq!(bindings.command_for(&key(KeyCode::Char('j'), KeyModifiers::NONE)), Some(KeyCommand::SelectNext));
+    assert_eq!(bindings.command_for(&key(KeyCode::Down, KeyModifiers::NONE)), Some(KeyCommand::SelectNext));
+    assert_eq!(bindings.command_for(&key(KeyCode::Char('k'), KeyModifiers::NONE)), Some(KeyCommand::SelectPrevious));
+    assert_eq!(bindings.command_for(&key(KeyCode::Up, KeyModifiers::NONE)), None);
+    // Commands left out keep their defaults
+    assert_eq!(bindings.command_for(&key(KeyCode::Char('c'), KeyModifiers::NONE)), Some(KeyCommand::Checkout));
+  }
+
+  #[test]
+  fn a_key_bound_in_the_config_is_taken_from_its_default_command() {
+    let bindings: KeyBindings = serde_json::from_str(r#"{ "checkout": "shift-c" }"#).unwrap();
+    assert_eq!(bindings.command_for(&key(KeyCode::Char('C'), KeyModifiers::SHIFT)), Some(KeyCommand::Checkout));
+    assert!(bindings.labels(KeyCommand::CreateBranch).is_empty());
+  }
+
+  #[test]
+  fn unknown_commands_and_bad_keys_fall_back_to_the_defaults() {
+    let bindings: KeyBindings = serde_json::from_str(r#"{ "launch_rockets": "x", "checkout": "hyper-c" }"#).unwrap();
+    assert_eq!(bindings, KeyBindings::default());
+  }
+}
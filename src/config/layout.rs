@@ -0,0 +1,192 @@
+use serde::{Deserialize, Deserializer};
+use strum::EnumString;
+use tracing::warn;
+
+/// How much is shown next to each branch name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Density {
+  /// The upstream and tracking branch names are shown alongside the ahead and behind counts.
+  #[default]
+  Comfortable,
+  /// Only the markers and counts are shown, leaving more room for the names.
+  Compact,
+}
+
+/// Which part of a branch name too long for the list is cut.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+  /// Keeps the start and the end, which usually tells branches apart, like `feat…ogin-form`.
+  #[default]
+  Middle,
+  /// Keeps the start, like `feature/lo…`.
+  End,
+}
+
+/// The ages in days that split last commits into fresh, ordinary and stale for coloring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AgeBuckets {
+  /// Commits younger than this many days are fresh.
+  pub fresh_days: u64,
+  /// Commits at least this many days old are stale.
+  pub stale_days: u64,
+}
+
+impl Default for AgeBuckets {
+  fn default() -> Self {
+    AgeBuckets { fresh_days: 7, stale_days: 90 }
+  }
+}
+
+/// How old a last commit is by the [`AgeBuckets`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Age {
+  Fresh,
+  Ordinary,
+  Stale,
+}
+
+impl AgeBuckets {
+  pub fn bucket(&self, seconds: i64) -> Age {
+    let days = u64::try_from(seconds.max(0)).unwrap_or_default() / 86_400;
+    if days < self.fresh_days {
+      Age::Fresh
+    } else if days >= self.stale_days {
+      Age::Stale
+    } else {
+      Age::Ordinary
+    }
+  }
+}
+
+/// A detail that can be shown after each branch name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Column {
+  /// The upstream of a local branch, or the local branch tracking a remote one.
+  Upstream,
+  /// The commits ahead and behind the upstream, like `↑2 ↓1`.
+  AheadBehind,
+  Diffstat,
+  UniqueCommits,
+  CommitAge,
+  /// Who made the last commit.
+  Author,
+  /// The first line of the description set with `git branch --edit-description`.
+  Description,
+}
+
+/// The configured order of the details after each branch name, which replaces the shown details picked by the
+/// density and the other layout options.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Columns {
+  columns: Vec<Column>,
+}
+
+impl Columns {
+  /// Builds the columns from the config file's names, unknown names are logged and skipped.
+  pub fn from_config(names: Vec<String>) -> Self {
+    let columns = names
+      .into_iter()
+      .filter_map(|name| {
+        match name.parse::<Column>() {
+          Ok(column) => Some(column),
+          Err(_) => {
+            warn!("Ignoring unknown list column '{}'", name);
+            None
+          },
+        }
+      })
+      .collect();
+    Columns { columns }
+  }
+}
+
+impl<'de> Deserialize<'de> for Columns {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Ok(Columns::from_config(Vec::<String>::deserialize(deserializer)?))
+  }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ListLayout {
+  pub density: Density,
+  pub truncation: Truncation,
+  /// Shows the lines each branch has inserted and deleted compared with its upstream, like `+12 -3`.
+  pub diffstat: bool,
+  /// Shows how long ago each branch was last committed to, like `2d ago`, colored by `age_buckets`.
+  pub commit_age: bool,
+  /// Shows how many commits each branch has that the default branch doesn't, like `5 commits`.
+  pub unique_commits: bool,
+  pub age_buckets: AgeBuckets,
+  /// The details shown after each branch name in this order, overriding `density` and the options above.
+  pub columns: Option<Columns>,
+}
+
+impl ListLayout {
+  /// The details shown after each branch name in order, the configured columns or those the other options pick.
+  pub fn columns(&self) -> Vec<Column> {
+    if let Some(columns) = &self.columns {
+      return columns.columns.clone();
+    }
+    let mut columns = Vec::new();
+    if self.density == Density::Comfortable {
+      columns.push(Column::Upstream);
+    }
+    columns.push(Column::AheadBehind);
+    if self.diffstat {
+      columns.push(Column::Diffstat);
+    }
+    if self.unique_commits {
+      columns.push(Column::UniqueCommits);
+    }
+    if self.commit_age {
+      columns.push(Column::CommitAge);
+    }
+    columns
+  }
+
+  /// Whether `column` is shown, so the data behind the columns that aren't is never worked out.
+  pub fn shows(&self, column: Column) -> bool {
+    self.columns().contains(&column)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn compact_density_drops_the_upstream_column() {
+    let comfortable = ListLayout::default();
+    let compact = ListLayout { density: Density::Compact, ..ListLayout::default() };
+    assert_eq!(comfortable.columns(), [Column::Upstream, Column::AheadBehind]);
+    assert_eq!(compact.columns(), [Column::AheadBehind]);
+  }
+
+  #[test]
+  fn ages_fall_into_the_configured_buckets() {
+    let buckets = AgeBuckets { fresh_days: 2, stale_days: 10 };
+    assert_eq!(buckets.bucket(-3600), Age::Fresh);
+    assert_eq!(buckets.bucket(86_400), Age::Fresh);
+    assert_eq!(buckets.bucket(2 * 86_400), Age::Ordinary);
+    assert_eq!(buckets.bucket(10 * 86_400), Age::Stale);
+  }
+
+  #[test]
+  fn configured_columns_keep_their_order_and_skip_unknown_names() {
+    let layout: ListLayout = config::Config::builder()
+      .add_source(config::File::from_str(r#"columns = ["author", "bogus", "ahead_behind"]"#, config::FileFormat::Toml))
+      .build()
+      .unwrap()
+      .try_deserialize()
+      .unwrap();
+    assert_eq!(layout.columns(), [Column::Author, Column::AheadBehind]);
+    assert!(layout.shows(Column::Author));
+    // Configured columns replace the ones the density would show
+    assert!(!layout.shows(Column::Upstream));
+  }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crossterm::event::KeyEvent;
+use serde::{Deserialize, Deserializer};
+use tracing::warn;
+
+use super::keybindings::{normalize, parse_key};
+use crate::git::wip_name::fill_pattern;
+
+/// Text a key inserts into the branch name input, like a `jira/` prefix. Templates are filled in like the wip branch
+/// pattern, `{date}` with the UTC date and `{sha}` with the short id of HEAD.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NameSnippets {
+  snippets: Vec<(KeyEvent, String)>,
+}
+
+impl NameSnippets {
+  /// Builds the snippets from the config file's `key -> template` entries, keys that can't be parsed are logged and
+  /// skipped.
+  pub fn from_config(config: HashMap<String, String>) -> Self {
+    let snippets = config
+      .into_iter()
+      .filter_map(|(key, template)| {
+        match parse_key(&key) {
+          Ok(key) => Some((key, template)),
+          Err(err) => {
+            warn!("Ignoring the branch name snippet for '{}': {}", key, err);
+            None
+          },
+        }
+      })
+      .collect();
+    NameSnippets { snippets }
+  }
+
+  /// The filled in template bound to `key`, given the unix `time`. The short id of HEAD is only looked up when the
+  /// template uses it, and `{sha}` is left empty without one.
+  pub fn expand(&self, key: &KeyEvent, time: i64, short_sha: impl FnOnce() -> Option<String>) -> Option<String> {
+    let key = normalize(key.code, key.modifiers);
+    let (_, template) = self.snippets.iter().find(|(bound, _)| *bound == key)?;
+    let short_sha = if template.contains("{sha}") { short_sha().unwrap_or_default() } else { String::new() };
+    Some(fill_pattern(template, time, &short_sha))
+  }
+}
+
+impl<'de> Deserialize<'de> for NameSnippets {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Ok(NameSnippets::from_config(HashMap::<String, String>::deserialize(deserializer)?))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crossterm::event::{KeyCode, KeyModifiers};
+
+  use super::*;
+
+  fn snippets() -> NameSnippets {
+    NameSnippets::from_config(HashMap::from([
+      (String::from("ctrl-j"), String::from("jira/")),
+      (String::from("ctrl-d"), String::from("{date}-")),
+      (String::from("ctrl-s"), String::from("try/{sha}")),
+      (String::from("hyper-x"), String::from("ignored")),
+    ]))
+  }
+
+  fn ctrl(c: char) -> KeyEvent {
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+  }
+
+  #[test]
+  fn a_bound_key_expands_its_filled_in_template() {
+    let snippets = snippets();
+    let no_sha = || panic!("The sha is only looked up when the template uses it");
+    assert_eq!(snippets.expand(&ctrl('j'), 1_700_000_000, no_sha).as_deref(), Some("jira/"));
+    assert_eq!(snippets.expand(&ctrl('d'), 1_700_000_000, no_sha).as_deref(), Some("2023-11-14-"));
+    assert_eq!(snippets.expand(&ctrl('s'), 0, || Some(String::from("abc1234"))).as_deref(), Some("try/abc1234"));
+    assert_eq!(snippets.expand(&ctrl('s'), 0, || None).as_deref(), Some("try/"));
+  }
+
+  #[test]
+  fn unbound_and_unparseable_keys_expand_to_nothing() {
+    let snippets = snippets();
+    assert_eq!(snippets.snippets.len(), 3);
+    assert_eq!(snippets.expand(&ctrl('x'), 0, || None), None);
+    assert_eq!(snippets.expand(&KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE), 0, || None), None);
+  }
+}
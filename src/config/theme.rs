@@ -0,0 +1,151 @@
+use std::{collections::HashMap, str::FromStr};
+
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer};
+use tracing::warn;
+
+/// Colors for each semantic role in the UI. The config file can pick a `base` theme, `dark` (the default) or `light`,
+/// and override single roles with a named color like `light-red` or a hex color like `#RRGGBB`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+  pub text: Color,
+  pub selected: Color,
+  pub head: Color,
+  pub staged_for_deletion: Color,
+  pub valid_name: Color,
+  pub invalid_name: Color,
+  pub ahead: Color,
+  pub behind: Color,
+  pub group_header: Color,
+  pub error: Color,
+  pub notice: Color,
+  pub warning: Color,
+  /// Ticket ids found in branch names.
+  pub ticket: Color,
+}
+
+impl Default for Theme {
+  fn default() -> Self {
+    Theme::dark()
+  }
+}
+
+impl Theme {
+  pub fn dark() -> Self {
+    Theme {
+      text: Color::White,
+      selected: Color::White,
+      head: Color::White,
+      staged_for_deletion: Color::Red,
+      valid_name: Color::LightGreen,
+      invalid_name: Color::LightRed,
+      ahead: Color::Green,
+      behind: Color::Yellow,
+      group_header: Color::Cyan,
+      error: Color::Red,
+      notice: Color::Green,
+      warning: Color::Yellow,
+      ticket: Color::LightMagenta,
+    }
+  }
+
+  /// Darker colors that stay readable on a light background.
+  pub fn light() -> Self {
+    Theme {
+      text: Color::Black,
+      selected: Color::Black,
+      head: Color::Blue,
+      staged_for_deletion: Color::Red,
+      valid_name: Color::Green,
+      invalid_name: Color::Red,
+      ahead: Color::Green,
+      behind: Color::Magenta,
+      group_header: Color::Blue,
+      error: Color::Red,
+      notice: Color::Green,
+      warning: Color::Magenta,
+      ticket: Color::Cyan,
+    }
+  }
+
+  /// Builds a theme from the config file's `role -> color` entries. Unknown roles and invalid colors are logged and
+  /// the role keeps the base theme's color.
+  pub fn from_config(config: HashMap<String, String>) -> Self {
+    let mut theme = match config.get("base").map(String::as_str) {
+      None | Some("dark") => Theme::dark(),
+      Some("light") => Theme::light(),
+      Some(other) => {
+        warn!("Unknown base theme '{}', using the dark theme", other);
+        Theme::dark()
+      },
+    };
+    for (role, value) in config.iter().filter(|(role, _)| role.as_str() != "base") {
+      let Some(slot) = theme.role_mut(role) else {
+        warn!("Ignoring color for unknown theme role '{}'", role);
+        continue;
+      };
+      match parse_color(value) {
+        Some(color) => *slot = color,
+        None => warn!("Ignoring invalid color '{}' for theme role '{}'", value, role),
+      }
+    }
+    theme
+  }
+
+  fn role_mut(&mut self, role: &str) -> Option<&mut Color> {
+    let slot = match role {
+      "text" => &mut self.text,
+      "selected" => &mut self.selected,
+      "head" => &mut self.head,
+      "staged_for_deletion" => &mut self.staged_for_deletion,
+      "valid_name" => &mut self.valid_name,
+      "invalid_name" => &mut self.invalid_name,
+      "ahead" => &mut self.ahead,
+      "behind" => &mut self.behind,
+      "group_header" => &mut self.group_header,
+      "error" => &mut self.error,
+      "notice" => &mut self.notice,
+      "warning" => &mut self.warning,
+      "ticket" => &mut self.ticket,
+      _ => return None,
+    };
+    Some(slot)
+  }
+}
+
+impl<'de> Deserialize<'de> for Theme {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Ok(Theme::from_config(HashMap::<String, String>::deserialize(deserializer)?))
+  }
+}
+
+/// Parses a named color, e.g. `red` or `light-blue`, or a hex color like `#1e90ff`.
+pub fn parse_color(raw: &str) -> Option<Color> {
+  Color::from_str(raw.trim()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hex_and_named_colors_parse() {
+    assert_eq!(parse_color("#1e90ff"), Some(Color::Rgb(0x1e, 0x90, 0xff)));
+    assert_eq!(parse_color("red"), Some(Color::Red));
+    assert_eq!(parse_color(" light-blue "), Some(Color::LightBlue));
+    assert_eq!(parse_color("#12345"), None);
+    assert_eq!(parse_color("not-a-color"), None);
+  }
+
+  #[test]
+  fn roles_override_the_base_theme() {
+    let theme: Theme = serde_json::from_str(r##"{ "base": "light", "head": "#ff0000", "error": "yellow" }"##).unwrap();
+    assert_eq!(theme, Theme { head: Color::Rgb(255, 0, 0), error: Color::Yellow, ..Theme::light() });
+  }
+
+  #[test]
+  fn invalid_colors_and_unknown_roles_keep_the_default() {
+    let theme: Theme = serde_json::from_str(r#"{ "head": "blurple", "sparkle": "red", "base": "neon" }"#).unwrap();
+    assert_eq!(theme, Theme::default());
+  }
+}
@@ -5,9 +5,259 @@ pub enum Error {
   #[error(transparent)]
   Git2(#[from] git2::Error),
 
+  /// A git2 failure along with what was being attempted, like "Failed to check out main".
+  #[error("{context}: {}", source.message())]
+  Git2Context { context: String, source: git2::Error },
+
   #[error("Git operation failed: {0}")]
   Git(String),
 
+  /// A git failure along with what was being attempted, like "Failed to check out main".
+  #[error("{context}: {}", .message.trim())]
+  GitContext { context: String, message: String },
+
+  /// Like [`Error::GitContext`] for a git failure saying a branch, commit or other ref it was given doesn't exist.
+  #[error("{context}: {}", .message.trim())]
+  GitNotFound { context: String, message: String },
+
+  /// Like [`Error::GitContext`] for a git failure saying local changes are in the way.
+  #[error("{context}: {}", .message.trim())]
+  GitLocalChanges { context: String, message: String },
+
   #[error(transparent)]
   ParsingError(#[from] std::string::FromUtf8Error),
+
+  #[error(transparent)]
+  Serialization(#[from] serde_json::Error),
+
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+
+  #[error("Clipboard error: {0}")]
+  Clipboard(String),
+
+  /// The pager or editor showing a commit couldn't be started or failed.
+  #[error("Viewer error: {0}")]
+  Viewer(String),
+
+  /// Nothing has been checked out before the current branch, so there is nothing to switch back to.
+  #[error("There is no previously checked out branch")]
+  NoPreviousBranch,
+
+  /// HEAD points at a branch that has no commits yet, as in a freshly initialized repository.
+  #[error("The repository has no commits yet")]
+  NoCommits,
+
+  /// HEAD points at this branch but it has no commits yet, so there is nothing for a new branch to start from.
+  #[error("Cannot create a branch from {0} as it has no commits yet")]
+  UnbornBranch(String),
+
+  #[error("The branch {0} is protected")]
+  ProtectedBranch(String),
+
+  #[error("The repository is not a submodule")]
+  NotASubmodule,
+
+  #[error("{0} is not inside a git repository")]
+  NotARepository(String),
+
+  /// Nothing like a merge or rebase is in progress, so there is nothing to abort.
+  #[error("There is no merge, rebase or other operation in progress")]
+  NoOperationInProgress,
+
+  #[error("The branch shares no history with {0}")]
+  NoCommonHistory(String),
+
+  /// HEAD points straight at a commit, so there is no branch for the operation to move.
+  #[error("HEAD is detached, there is no current branch")]
+  DetachedHead,
+
+  /// Git won't delete a branch a linked worktree has checked out.
+  #[error("The branch {0} is checked out in the worktree at {1}")]
+  CheckedOutInWorktree(String, String),
+
+  /// Moving the checked out branch would leave the working tree out of step with it.
+  #[error("The branch {0} is checked out, reset it instead of moving it")]
+  CheckedOutBranch(String),
+
+  #[error("The branch {0} has no upstream")]
+  NoUpstream(String),
+
+  /// The branch has commits its upstream doesn't, so moving it to the upstream would lose them.
+  #[error("The branch {0} has diverged from its upstream and can't be fast-forwarded")]
+  NotFastForward(String),
+
+  /// The checked out branch is only fast-forwarded with a clean working tree, as its files move with it.
+  #[error("The branch {0} is checked out with local changes, commit or stash them before fast-forwarding it")]
+  FastForwardBlocked(String),
+
+  /// A force push with lease was refused as the remote branch isn't where it was last fetched, so someone else pushed.
+  #[error("{0} on {1} has moved since it was last fetched, fetch and check the new commits before force pushing")]
+  StaleLease(String, String),
+
+  /// The branch and its upstream have diverged, as after a rebase, so a plain push was rejected.
+  #[error("Push of {0} to {1} was rejected as it has diverged from its upstream, it needs a force push")]
+  PushRequiresForce(String, String),
+
+  /// Switching to the branch would overwrite local changes to these files, so nothing was checked out.
+  #[error("Checking out {0} would overwrite local changes, commit or stash them first:\n{}", .1.join("\n"))]
+  CheckoutBlocked(String, Vec<String>),
+
+  /// The repository was opened read-only, so nothing that would change it is allowed.
+  #[error("The repository is open read-only")]
+  ReadOnly,
+
+  /// A bare repository has no working tree to check anything out into.
+  #[error("Cannot checkout in a bare repository")]
+  BareRepository,
+
+  /// HEAD or a local branch moved since the branches were last read, so acting on them could go wrong.
+  #[error("The repository was changed outside the app, refresh before trying again")]
+  RepositoryChanged,
+
+  /// The branch for a stash was created and checked out but the stash didn't apply cleanly, so it was kept.
+  #[error("Created {0} but the stash didn't apply cleanly and has been kept: {1}")]
+  StashNotApplied(String, String),
+
+  /// A quick checkout switched to the branch but the changes stashed when it was last left didn't apply cleanly, so
+  /// the stash was kept.
+  #[error("Checked out {0} but its stashed changes didn't apply cleanly and have been kept: {1}")]
+  AutoStashNotApplied(String, String),
+}
+
+/// Exit codes of the non-interactive modes like `--json`, so scripts can tell what went wrong without parsing the
+/// message. 2 is left out as clap already exits with it for bad arguments.
+pub mod exit_code {
+  pub const SUCCESS: u8 = 0;
+  /// Anything without a more specific code, like a failing git command.
+  pub const FAILURE: u8 = 1;
+  pub const NOT_A_REPOSITORY: u8 = 3;
+  /// A branch, commit or other ref that was asked for doesn't exist.
+  pub const NOT_FOUND: u8 = 4;
+  /// Local changes are in the way.
+  pub const DIRTY_TREE: u8 = 5;
+  /// The change was refused, as for protected branches or a read-only repository.
+  pub const REFUSED: u8 = 6;
+  /// The repository isn't in a state the operation can work with, like a detached HEAD or a bare repository.
+  pub const REPOSITORY_STATE: u8 = 7;
+}
+
+impl Error {
+  /// The code a non-interactive mode exits with when it fails with this error, one of [`exit_code`].
+  pub fn exit_code(&self) -> u8 {
+    match self {
+      Error::NotARepository(_) => exit_code::NOT_A_REPOSITORY,
+      Error::Git2(source) | Error::Git2Context { source, .. } if source.code() == git2::ErrorCode::NotFound => {
+        exit_code::NOT_FOUND
+      },
+      Error::GitNotFound { .. } => exit_code::NOT_FOUND,
+      Error::GitLocalChanges { .. }
+      | Error::CheckoutBlocked(..)
+      | Error::AutoStashNotApplied(..)
+      | Error::StashNotApplied(..)
+      | Error::FastForwardBlocked(_) => exit_code::DIRTY_TREE,
+      Error::ProtectedBranch(_)
+      | Error::ReadOnly
+      | Error::CheckedOutInWorktree(..)
+      | Error::CheckedOutBranch(_)
+      | Error::RepositoryChanged
+      | Error::PushRequiresForce(..)
+      | Error::NotFastForward(_)
+      | Error::StaleLease(..) => exit_code::REFUSED,
+      Error::NoPreviousBranch
+      | Error::NoCommits
+      | Error::UnbornBranch(_)
+      | Error::NotASubmodule
+      | Error::NoOperationInProgress
+      | Error::NoCommonHistory(_)
+      | Error::DetachedHead
+      | Error::NoUpstream(_)
+      | Error::BareRepository => exit_code::REPOSITORY_STATE,
+      _ => exit_code::FAILURE,
+    }
+  }
+}
+
+/// Parts of git's error output, and of the messages the backends write the same way, saying a ref doesn't exist.
+const NOT_FOUND_MESSAGES: [&str; 15] = [
+  "did not match any file(s) known to git",
+  "invalid reference",
+  "unknown revision",
+  "not a valid object name",
+  "needed a single revision",
+  "bad revision",
+  "couldn't find remote ref",
+  "no such remote",
+  "no such branch",
+  "no branch named",
+  "unable to find branch",
+  "remote ref does not exist",
+  "does not exist",
+  "not found",
+  "is not a commit",
+];
+
+/// Parts of the error output saying local changes are in the way, likewise.
+const LOCAL_CHANGES_MESSAGES: [&str; 6] = [
+  "would be overwritten",
+  "commit your changes or stash them",
+  "you have unstaged changes",
+  "uncommitted changes",
+  "with local changes",
+  "would overwrite local changes",
+];
+
+/// Runs `operation`, adding `context` to a git2 failure or a git command's error output. Git's output only says what
+/// went wrong in words, so a missing ref or local changes in the way are told apart by them, see
+/// [`Error::GitNotFound`] and [`Error::GitLocalChanges`]. Other errors already say what went wrong and pass through
+/// unchanged so they can still be matched on.
+pub fn with_context<T>(context: impl Into<String>, operation: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+  operation().map_err(|err| {
+    match err {
+      Error::Git2(source) => Error::Git2Context { context: context.into(), source },
+      Error::Git(message) => {
+        let context = context.into();
+        let lowercase = message.to_lowercase();
+        if NOT_FOUND_MESSAGES.iter().any(|part| lowercase.contains(part)) {
+          Error::GitNotFound { context, message }
+        } else if LOCAL_CHANGES_MESSAGES.iter().any(|part| lowercase.contains(part)) {
+          Error::GitLocalChanges { context, message }
+        } else {
+          Error::GitContext { context, message }
+        }
+      },
+      err => err,
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn context_is_added_to_git_failures_only() {
+    let err = with_context("Failed to check out main", || Err::<(), _>(Error::Git(String::from("fatal: bad\n"))));
+    assert_eq!(err.unwrap_err().to_string(), "Failed to check out main: fatal: bad");
+    let err = with_context("Failed to list the tags", || Err::<(), _>(Error::Git2(git2::Error::from_str("broken"))));
+    assert_eq!(err.unwrap_err().to_string(), "Failed to list the tags: broken");
+    let err = with_context("Failed to delete main", || Err::<(), _>(Error::ProtectedBranch(String::from("main"))));
+    assert!(matches!(err, Err(Error::ProtectedBranch(_))));
+    assert_eq!(with_context("Unused", || Ok(1)).unwrap(), 1);
+  }
+
+  #[test]
+  fn git_failures_are_typed_by_what_they_say() {
+    let failure =
+      |message: &str| with_context("Failed", || Err::<(), _>(Error::Git(String::from(message)))).unwrap_err();
+    let missing = failure("error: pathspec 'nope' did not match any file(s) known to git\n");
+    assert!(matches!(missing, Error::GitNotFound { .. }), "{:?}", missing);
+    assert_eq!(missing.to_string(), "Failed: error: pathspec 'nope' did not match any file(s) known to git");
+    let dirty = failure("error: Your local changes to the following files would be overwritten by merge:\n");
+    assert!(matches!(dirty, Error::GitLocalChanges { .. }), "{:?}", dirty);
+    let other = failure("fatal: unable to access the remote\n");
+    assert!(matches!(other, Error::GitContext { .. }), "{:?}", other);
+    let nothing_to_stash = failure("There are no local changes to stash");
+    assert!(matches!(nothing_to_stash, Error::GitContext { .. }), "{:?}", nothing_to_stash);
+  }
 }
@@ -0,0 +1,61 @@
+use serde::Serialize;
+
+use crate::{
+  error::Error,
+  git::git_repo::{CommitInfo, GitBranch, GitRepo},
+};
+
+/// A local branch as written by the json export, along with the commit it points at.
+#[derive(Debug, Serialize)]
+pub struct BranchExport {
+  #[serde(flatten)]
+  pub branch: GitBranch,
+  /// `None` when the commit can't be looked up, e.g. for branches with lossy names.
+  pub last_commit: Option<CommitInfo>,
+}
+
+/// The local branches of `repo` with their ahead and behind counts and last commits.
+pub fn branch_exports(repo: &dyn GitRepo) -> Result<Vec<BranchExport>, Error> {
+  Ok(
+    repo
+      .local_branches()?
+      .into_iter()
+      .map(|mut branch| {
+        // Listing leaves the counts out, an export is a one off so they are worth counting for every branch
+        branch.ahead_behind = repo.upstream_ahead_behind(&branch).ok().flatten();
+        let last_commit = repo.branch_commit_info(&branch).ok();
+        BranchExport { branch, last_commit }
+      })
+      .collect(),
+  )
+}
+
+/// Serializes the local branches of `repo` as pretty printed json.
+pub fn branches_json(repo: &dyn GitRepo) -> Result<String, Error> {
+  Ok(serde_json::to_string_pretty(&branch_exports(repo)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::Fixture;
+
+  #[test]
+  fn the_json_has_each_branch_with_its_upstream_and_last_commit() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.commit("a.txt", "a", "Ahead of origin");
+    let json: serde_json::Value = serde_json::from_str(&branches_json(&fixture.git2()).unwrap()).unwrap();
+    let branches = json.as_array().unwrap();
+    assert_eq!(branches.len(), 1);
+    let main = &branches[0];
+    assert_eq!(main["name"], "main");
+    assert_eq!(main["is_head"], true);
+    assert_eq!(main["upstream"]["name"], "origin/main");
+    assert_eq!(main["upstream_status"], "tracked");
+    assert_eq!(main["ahead_behind"], serde_json::json!([1, 0]));
+    assert_eq!(main["commit_id"], fixture.head());
+    assert_eq!(main["last_commit"]["summary"], "Ahead of origin");
+    assert_eq!(main["last_commit"]["author"], "Test Author");
+  }
+}
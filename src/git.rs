@@ -1,3 +1,8 @@
+pub mod credentials;
 pub mod git2_repo;
 pub mod git_cli_repo;
 pub mod git_repo;
+pub mod progress;
+pub mod protected;
+pub mod timing;
+pub mod wip_name;
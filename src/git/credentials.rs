@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use directories::BaseDirs;
+use git2::{Config, Cred, CredentialType, RemoteCallbacks};
+use tracing::{info, warn};
+
+/// The authentication strategy used for remote operations.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteAuth {
+  /// Try the ssh agent, then the default ssh key, then the git credential helper.
+  #[default]
+  Auto,
+  SshAgent,
+  SshKey,
+  CredentialHelper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CredentialMethod {
+  SshAgent,
+  SshKey,
+  CredentialHelper,
+}
+
+impl RemoteAuth {
+  fn methods(&self) -> &'static [CredentialMethod] {
+    match self {
+      RemoteAuth::Auto => &[CredentialMethod::SshAgent, CredentialMethod::SshKey, CredentialMethod::CredentialHelper],
+      RemoteAuth::SshAgent => &[CredentialMethod::SshAgent],
+      RemoteAuth::SshKey => &[CredentialMethod::SshKey],
+      RemoteAuth::CredentialHelper => &[CredentialMethod::CredentialHelper],
+    }
+  }
+}
+
+impl CredentialMethod {
+  fn credential_type(&self) -> CredentialType {
+    match self {
+      CredentialMethod::SshAgent | CredentialMethod::SshKey => CredentialType::SSH_KEY,
+      CredentialMethod::CredentialHelper => CredentialType::USER_PASS_PLAINTEXT,
+    }
+  }
+}
+
+/// Picks the next untried method the remote will accept, `None` once everything has been tried.
+fn next_method(
+  auth: RemoteAuth,
+  allowed_types: CredentialType,
+  tried: &[CredentialMethod],
+) -> Option<CredentialMethod> {
+  auth
+    .methods()
+    .iter()
+    .copied()
+    .find(|method| !tried.contains(method) && allowed_types.contains(method.credential_type()))
+}
+
+fn default_ssh_key() -> Option<PathBuf> {
+  let ssh_dir = BaseDirs::new()?.home_dir().join(".ssh");
+  ["id_ed25519", "id_ecdsa", "id_rsa"].iter().map(|key| ssh_dir.join(key)).find(|key| key.exists())
+}
+
+/// Builds callbacks for fetch and push that authenticate using the given strategy.
+pub fn remote_callbacks<'a>(config: Config, auth: RemoteAuth) -> RemoteCallbacks<'a> {
+  // libgit2 keeps asking for credentials until one works, so only try each method once to avoid looping forever
+  let mut tried: Vec<CredentialMethod> = Vec::new();
+  let mut callbacks = RemoteCallbacks::new();
+  callbacks.credentials(move |url, username_from_url, allowed_types| {
+    let username = username_from_url.unwrap_or("git");
+    while let Some(method) = next_method(auth, allowed_types, &tried) {
+      tried.push(method);
+      info!("Trying {:?} credentials for {}", method, url);
+      let cred = match method {
+        CredentialMethod::SshAgent => Cred::ssh_key_from_agent(username),
+        CredentialMethod::SshKey => {
+          match default_ssh_key() {
+            Some(key) => Cred::ssh_key(username, None, &key, None),
+            None => Err(git2::Error::from_str("No default ssh key found")),
+          }
+        },
+        CredentialMethod::CredentialHelper => Cred::credential_helper(&config, url, username_from_url),
+      };
+      match cred {
+        Ok(cred) => return Ok(cred),
+        Err(err) => warn!("Unable to use {:?} credentials: {}", method, err),
+      }
+    }
+    Err(git2::Error::from_str("No usable credentials were found"))
+  });
+  callbacks
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn auto_tries_the_agent_then_the_key_then_the_helper() {
+    let allowed = CredentialType::SSH_KEY | CredentialType::USER_PASS_PLAINTEXT;
+    let mut tried = Vec::new();
+    while let Some(method) = next_method(RemoteAuth::Auto, allowed, &tried) {
+      tried.push(method);
+    }
+    assert_eq!(tried, [CredentialMethod::SshAgent, CredentialMethod::SshKey, CredentialMethod::CredentialHelper]);
+  }
+
+  #[test]
+  fn only_methods_the_remote_allows_are_tried() {
+    assert_eq!(
+      next_method(RemoteAuth::Auto, CredentialType::USER_PASS_PLAINTEXT, &[]),
+      Some(CredentialMethod::CredentialHelper)
+    );
+    assert_eq!(next_method(RemoteAuth::SshAgent, CredentialType::USER_PASS_PLAINTEXT, &[]), None);
+  }
+
+  #[test]
+  fn a_forced_method_is_only_tried_once() {
+    let allowed = CredentialType::SSH_KEY;
+    assert_eq!(next_method(RemoteAuth::SshKey, allowed, &[]), Some(CredentialMethod::SshKey));
+    assert_eq!(next_method(RemoteAuth::SshKey, allowed, &[CredentialMethod::SshKey]), None);
+  }
+}
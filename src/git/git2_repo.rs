@@ -1,124 +1,2217 @@
-use std::env::current_dir;
+use std::{
+  cmp::Reverse,
+  env::{current_dir, var_os},
+  fs, iter,
+  num::NonZeroUsize,
+  path::{Path, PathBuf},
+  thread::{self, available_parallelism},
+};
 
-use git2::{Branch, BranchType, Repository};
-use tracing::{error, info};
+use git2::{
+  build::CheckoutBuilder, Branch, BranchType, CheckoutNotificationType, CherrypickOptions, Commit, Delta, DiffFormat,
+  ErrorClass, ErrorCode, FetchOptions, FetchPrune, Index, Mailmap, Oid, PushOptions, PushUpdate, ReferenceType,
+  RemoteCallbacks, Repository, RepositoryState, ResetType, Sort, StashApplyOptions, Status, StatusOptions,
+};
+use tracing::{error, info, warn};
 
-use super::git_repo::GitStash;
+use super::git_repo::{
+  ChangeType, CheckoutPreview, CommitInfo, DiffSummary, Divergence, FileChange, GitStash, GitTag, GraphCommit,
+  MergeOutcome, Patch, PullOutcome, RefSignature, ReflogEntry, RepoState, RepoSummary, ResetMode, SeenRefs,
+  WorktreeInfo, DEFAULT_ABBREV_LENGTH,
+};
 use crate::{
-  error::Error,
-  git::git_repo::{GitBranch, GitRemoteBranch, GitRepo},
+  error::{with_context, Error},
+  git::{
+    credentials::{remote_callbacks, RemoteAuth},
+    git_repo::{is_non_fast_forward, BranchCache, GitBranch, GitRemoteBranch, GitRepo, UpstreamStatus},
+    progress::TransferProgress,
+    protected::ProtectedBranches,
+    timing::timed,
+    wip_name::format_date,
+  },
 };
 
+/// Below this many branches opening a repository per thread costs more than resolving the upstreams saves.
+const PARALLEL_BRANCH_THRESHOLD: usize = 64;
+
+/// What's needed to open a [`Git2Repo`] again on another thread. A git2 repository can't be sent between threads but
+/// the handle can, and opening it is cheap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoHandle {
+  git_dir: PathBuf,
+  workdir: Option<PathBuf>,
+  auth: RemoteAuth,
+  protected: ProtectedBranches,
+  dry_run: bool,
+  read_only: bool,
+  untracked_is_dirty: bool,
+  allow_detached_reset: bool,
+  abbrev_length: usize,
+  remote: Option<String>,
+}
+
+impl RepoHandle {
+  /// Opens a fresh repository with the same settings as the one the handle came from.
+  pub fn open(&self) -> Result<Git2Repo, Error> {
+    let repo = Repository::open(&self.git_dir)?;
+    // A work tree set from GIT_WORK_TREE isn't recorded in the git dir
+    if let Some(workdir) = self.workdir.as_deref().filter(|workdir| repo.workdir() != Some(*workdir)) {
+      repo.set_workdir(workdir, false)?;
+    }
+    Ok(Git2Repo {
+      auth: self.auth,
+      protected: self.protected.clone(),
+      dry_run: self.dry_run,
+      read_only: self.read_only,
+      untracked_is_dirty: self.untracked_is_dirty,
+      allow_detached_reset: self.allow_detached_reset,
+      abbrev_length: self.abbrev_length,
+      remote: self.remote.clone(),
+      ..Git2Repo::new(repo)
+    })
+  }
+}
+
 pub struct Git2Repo {
   repo: Repository,
+  auth: RemoteAuth,
+  branch_cache: BranchCache,
+  seen_refs: SeenRefs,
+  protected: ProtectedBranches,
+  dry_run: bool,
+  read_only: bool,
+  untracked_is_dirty: bool,
+  allow_detached_reset: bool,
+  abbrev_length: usize,
+  remote: Option<String>,
+  /// Where fetches and pushes report how much they have transferred.
+  progress: Option<TransferProgress>,
 }
 
 impl Git2Repo {
   pub fn from_cwd() -> Result<Git2Repo, Error> {
     let path_buf = current_dir().expect("Unable to get current working directory");
-    let repo = Repository::discover(path_buf.as_path())?;
-    Ok(Git2Repo { repo })
+    Git2Repo::from_path(path_buf.as_path())
+  }
+
+  /// Opens the repository containing `path`, failing if it isn't inside a git repository.
+  ///
+  /// Like the git cli, `GIT_DIR` and `GIT_WORK_TREE` take precedence over discovery from `path` when set.
+  pub fn from_path(path: &Path) -> Result<Git2Repo, Error> {
+    // open_from_env also applies GIT_WORK_TREE, discovery needs it applied by hand.
+    let repo = if var_os("GIT_DIR").is_some() {
+      Repository::open_from_env()?
+    } else {
+      let repo = Repository::discover(path).map_err(|err| {
+        match err.code() {
+          ErrorCode::NotFound => Error::NotARepository(path.display().to_string()),
+          _ => Error::Git2(err),
+        }
+      })?;
+      if let Some(work_tree) = var_os("GIT_WORK_TREE") {
+        repo.set_workdir(Path::new(&work_tree), false)?;
+      }
+      repo
+    };
+    Ok(Git2Repo::new(repo))
+  }
+
+  fn new(repo: Repository) -> Git2Repo {
+    Git2Repo {
+      repo,
+      auth: RemoteAuth::default(),
+      branch_cache: BranchCache::default(),
+      seen_refs: SeenRefs::default(),
+      protected: ProtectedBranches::default(),
+      dry_run: false,
+      read_only: false,
+      untracked_is_dirty: false,
+      allow_detached_reset: false,
+      abbrev_length: DEFAULT_ABBREV_LENGTH,
+      remote: None,
+      progress: None,
+    }
+  }
+
+  /// Forces a specific authentication method for remote operations.
+  pub fn auth(mut self, auth: RemoteAuth) -> Self {
+    self.auth = auth;
+    self
+  }
+
+  /// Refuses to delete branches matching these patterns, on top of the default branch.
+  pub fn protected(mut self, protected: ProtectedBranches) -> Self {
+    self.protected = protected;
+    self
+  }
+
+  /// Only logs checkouts and branch creates, renames and deletes instead of making them.
+  pub fn dry_run(mut self, dry_run: bool) -> Self {
+    self.dry_run = dry_run;
+    self
+  }
+
+  /// Refuses everything that would change the repository or a remote with [`Error::ReadOnly`], where a dry run
+  /// pretends to make the change.
+  pub fn read_only(mut self, read_only: bool) -> Self {
+    self.read_only = read_only;
+    self
+  }
+
+  /// Counts untracked files as local changes in [`GitRepo::is_working_tree_dirty`], ignored files never count.
+  pub fn untracked_is_dirty(mut self, untracked_is_dirty: bool) -> Self {
+    self.untracked_is_dirty = untracked_is_dirty;
+    self
+  }
+
+  /// Network operations given no remote use this one rather than `origin`, unless the branch has its own.
+  pub fn remote(mut self, remote: Option<String>) -> Self {
+    self.remote = remote;
+    self
+  }
+
+  /// Reports how much fetches and pushes have transferred to `progress` as they run.
+  pub fn progress(mut self, progress: TransferProgress) -> Self {
+    self.progress = Some(progress);
+    self
+  }
+
+  /// Lets [`GitRepo::reset_current`] move a detached HEAD rather than refusing.
+  pub fn allow_detached_reset(mut self, allow_detached_reset: bool) -> Self {
+    self.allow_detached_reset = allow_detached_reset;
+    self
+  }
+
+  /// Abbreviates commit ids to at least this many characters, longer when that is still ambiguous. Git never
+  /// abbreviates to fewer than 4.
+  pub fn abbrev_length(mut self, abbrev_length: usize) -> Self {
+    self.abbrev_length = abbrev_length.max(4);
+    self
+  }
+
+  /// A handle for opening this repository on another thread, see [`RepoHandle`].
+  pub fn handle(&self) -> RepoHandle {
+    RepoHandle {
+      git_dir: self.repo.path().to_path_buf(),
+      workdir: self.repo.workdir().map(Path::to_path_buf),
+      auth: self.auth,
+      protected: self.protected.clone(),
+      dry_run: self.dry_run,
+      read_only: self.read_only,
+      untracked_is_dirty: self.untracked_is_dirty,
+      allow_detached_reset: self.allow_detached_reset,
+      abbrev_length: self.abbrev_length,
+      remote: self.remote.clone(),
+    }
+  }
+
+  fn is_merged_into_head(&self, branch: &Branch) -> Result<bool, Error> {
+    let Some(head_oid) = self.repo.head()?.target() else {
+      return Ok(false);
+    };
+    self.is_merged_into(branch, head_oid)
+  }
+
+  fn is_merged_into(&self, branch: &Branch, target_oid: Oid) -> Result<bool, Error> {
+    let Some(branch_oid) = branch.get().target() else {
+      return Ok(false);
+    };
+    Ok(branch_oid == target_oid || self.repo.graph_descendant_of(target_oid, branch_oid)?)
+  }
+
+  fn remote_callbacks<'a>(&self) -> Result<RemoteCallbacks<'a>, Error> {
+    let mut callbacks = remote_callbacks(self.repo.config()?, self.auth);
+    self.report_progress(&mut callbacks);
+    Ok(callbacks)
+  }
+
+  fn report_progress(&self, callbacks: &mut RemoteCallbacks<'_>) {
+    if let Some(progress) = &self.progress {
+      let fetch_progress = progress.clone();
+      callbacks.transfer_progress(move |stats| {
+        fetch_progress.update(stats.received_objects(), stats.total_objects(), stats.received_bytes());
+        true
+      });
+      let push_progress = progress.clone();
+      callbacks.push_transfer_progress(move |objects, total_objects, bytes| {
+        push_progress.update(objects, total_objects, bytes)
+      });
+    }
+  }
+
+  fn fetch_with_prune(&self, remote_name: &str, prune: FetchPrune) -> Result<(), Error> {
+    info!("Fetching from remote {}", remote_name);
+    let mut remote = self.repo.find_remote(remote_name)?;
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(self.remote_callbacks()?).prune(prune);
+    // An empty refspec list makes libgit2 use the remote's configured fetch refspecs
+    let fetch_result = remote.fetch::<&str>(&[], Some(&mut fetch_options), None);
+    if let Err(err) = fetch_result {
+      error!("Failed to fetch from {}: {}", remote_name, err);
+      return Err(remote_error(remote_name, err));
+    }
+    info!("Successfully fetched from remote {}", remote_name);
+    Ok(())
+  }
+
+  /// Pushes a single refspec, returning the reason the remote gave if it rejected the update.
+  fn push_refspec(&self, remote_name: &str, refspec: &str) -> Result<Option<String>, git2::Error> {
+    self.push_refspec_negotiated(remote_name, refspec, |_| Ok(()))
+  }
+
+  /// Pushes a single refspec like [`Git2Repo::push_refspec`], letting `negotiate` see where the remote refs are
+  /// before anything is sent and fail to call the push off.
+  fn push_refspec_negotiated(
+    &self,
+    remote_name: &str,
+    refspec: &str,
+    negotiate: impl FnMut(&[PushUpdate<'_>]) -> Result<(), git2::Error>,
+  ) -> Result<Option<String>, git2::Error> {
+    let mut remote = self.repo.find_remote(remote_name)?;
+    let mut rejection: Option<String> = None;
+    {
+      let mut callbacks = remote_callbacks(self.repo.config()?, self.auth);
+      self.report_progress(&mut callbacks);
+      callbacks.push_negotiation(negotiate);
+      callbacks.push_update_reference(|_refname, status| {
+        rejection = status.map(String::from);
+        Ok(())
+      });
+      let mut push_options = PushOptions::new();
+      push_options.remote_callbacks(callbacks);
+      remote.push(&[refspec], Some(&mut push_options))?;
+    }
+    Ok(rejection)
+  }
+
+  /// Makes the branch on the remote it was just pushed to its upstream when it didn't have one.
+  fn track_pushed(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error> {
+    if branch.upstream.is_some() {
+      return Ok(());
+    }
+    let upstream_name = format!("{}/{}", remote_name, branch.name);
+    let mut local_branch = self.repo.find_branch(&branch.name, BranchType::Local)?;
+    if let Err(err) = local_branch.set_upstream(Some(&upstream_name)) {
+      warn!("Pushed {} but failed to set its upstream to {}: {}", branch.name, upstream_name, err);
+    }
+    Ok(())
+  }
+
+  /// Splits a remote branch like `origin/feature` into its remote name and the branch name on that remote.
+  fn split_remote_branch<'a>(&self, remote: &'a GitRemoteBranch) -> Result<(String, &'a str), Error> {
+    if let Some((remote_name, branch_name)) = remote.split().filter(|_| remote.remote.is_some()) {
+      return Ok((String::from(remote_name), branch_name));
+    }
+    let remote_name = self.repo.branch_remote_name(&format!("refs/remotes/{}", remote.name))?;
+    let remote_name = String::from(remote_name.as_str().unwrap_or_default());
+    let Some(branch_name) = remote.name.strip_prefix(&format!("{}/", remote_name)) else {
+      error!("Unable to determine the remote for branch {}", remote.name);
+      return Err(Error::Git(format!("Unable to determine the remote for {}", remote.name)));
+    };
+    Ok((remote_name, branch_name))
+  }
+
+  /// The shortest unambiguous abbreviation of the object, padded out to the configured length. `short_id` starts from
+  /// `core.abbrev`, so a length below that has no effect.
+  fn abbreviate(&self, oid: Oid) -> Result<String, Error> {
+    let short_id = self.repo.find_object(oid, None)?.short_id()?;
+    let short_id = short_id.as_str().unwrap_or_default();
+    if short_id.len() >= self.abbrev_length {
+      return Ok(String::from(short_id));
+    }
+    Ok(oid.to_string().chars().take(self.abbrev_length).collect())
+  }
+
+  /// The repository's mailmap from `.mailmap` and the `mailmap.file` and `mailmap.blob` settings. One that can't be
+  /// read is logged and leaves names as they were committed, as does having none.
+  fn mailmap(&self) -> Result<Mailmap, Error> {
+    match self.repo.mailmap() {
+      Ok(mailmap) => Ok(mailmap),
+      Err(err) => {
+        warn!("Unable to read the mailmap, showing names as committed: {}", err);
+        Ok(Mailmap::new()?)
+      },
+    }
+  }
+
+  /// The commit a local branch, or failing that a remote branch, points at. Remote branches are listed by name too, like
+  /// the cli resolves either.
+  fn branch_tip(&self, name: &str) -> Result<Commit<'_>, Error> {
+    let found = self.repo.find_branch(name, BranchType::Local);
+    let found = found.or_else(|_| self.repo.find_branch(name, BranchType::Remote))?;
+    Ok(found.get().peel_to_commit()?)
+  }
+
+  /// Resets HEAD, the index and the working tree to the commit `spec` resolves to.
+  fn hard_reset_to(&self, spec: &str) -> Result<(), Error> {
+    let target = self.repo.revparse_single(spec)?.peel_to_commit()?;
+    self.repo.reset(target.as_object(), ResetType::Hard, None)?;
+    Ok(())
+  }
+
+  /// Undoes a rebase like `git rebase --abort`, from the branch and commit it recorded when it started.
+  fn abort_rebase(&self) -> Result<(), Error> {
+    let git_dir = self.repo.path();
+    let Some(state_dir) = ["rebase-merge", "rebase-apply"].iter().map(|dir| git_dir.join(dir)).find(|dir| dir.is_dir())
+    else {
+      return Err(Error::NoOperationInProgress);
+    };
+    let read_state = |file: &str| fs::read_to_string(state_dir.join(file)).map(|contents| contents.trim().to_string());
+    let (orig_head, head_name) = (read_state("orig-head")?, read_state("head-name")?);
+    // A hard reset also cleans up the rebase state, so everything needed is read first
+    self.hard_reset_to(&orig_head)?;
+    // Rebasing a detached HEAD records `detached HEAD` instead of a branch, which the reset has already restored
+    if head_name.starts_with("refs/") {
+      self.repo.set_head(&head_name)?;
+    }
+    Ok(())
+  }
+
+  /// Ends a bisect like `git bisect reset`, going back to whatever was checked out when it started.
+  fn reset_bisect(&self) -> Result<(), Error> {
+    let git_dir = self.repo.path();
+    let start = fs::read_to_string(git_dir.join("BISECT_START"))?.trim().to_string();
+    let mut checkout_builder = CheckoutBuilder::new();
+    checkout_builder.safe();
+    match self.repo.find_branch(&start, BranchType::Local) {
+      Ok(branch) => {
+        let commit = branch.get().peel_to_commit()?;
+        self.repo.checkout_tree(commit.as_object(), Some(&mut checkout_builder))?;
+        self.repo.set_head(&format!("refs/heads/{}", start))?;
+      },
+      // It started from a detached HEAD, which is recorded as a commit id
+      Err(_) => {
+        let commit = self.repo.find_commit(Oid::from_str(&start)?)?;
+        self.repo.checkout_tree(commit.as_object(), Some(&mut checkout_builder))?;
+        self.repo.set_head_detached(commit.id())?;
+      },
+    }
+    for reference in self.repo.references_glob("refs/bisect/*")? {
+      reference?.delete()?;
+    }
+    for entry in fs::read_dir(git_dir)? {
+      let entry = entry?;
+      if entry.file_name().to_string_lossy().starts_with("BISECT_") {
+        fs::remove_file(entry.path())?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Finds a worktree other than the one this repo was opened in that has the branch checked out.
+  fn worktree_with_branch(&self, branch_name: &str) -> Result<Option<WorktreeInfo>, Error> {
+    let current_workdir = self.repo.workdir().and_then(|workdir| workdir.canonicalize().ok());
+    let worktree = self.worktrees()?.into_iter().find(|worktree| {
+      worktree.branch.as_deref() == Some(branch_name) && worktree.path.canonicalize().ok() != current_workdir
+    });
+    Ok(worktree)
+  }
+
+  /// The branch HEAD names while it has no commits, read from the symbolic target of HEAD.
+  fn unborn_branch(&self) -> Result<GitBranch, Error> {
+    let head = self.repo.find_reference("HEAD")?;
+    let target = head.symbolic_target_bytes().unwrap_or_default();
+    let target = target.strip_prefix(b"refs/heads/").unwrap_or(target);
+    Ok(GitBranch {
+      is_head: true,
+      name_is_lossy: std::str::from_utf8(target).is_err(),
+      is_unborn: true,
+      ..GitBranch::new(String::from_utf8_lossy(target).into_owned())
+    })
+  }
+
+  fn create_git_branch(&self, branch: &Branch) -> Option<GitBranch> {
+    let name_is_lossy = branch.name().ok()?.is_none();
+    let name = String::from_utf8_lossy(branch.name_bytes().ok()?).into_owned();
+    let (upstream, upstream_status) = self.extract_upstream(branch);
+    let commit_id = branch.get().target().map(|oid| oid.to_string());
+    let upstream_commit_id =
+      branch.upstream().ok().and_then(|upstream| upstream.get().target()).map(|oid| oid.to_string());
+    Some(GitBranch {
+      name,
+      is_head: branch.is_head(),
+      upstream,
+      upstream_status,
+      ahead_behind: None,
+      commit_id,
+      upstream_commit_id,
+      name_is_lossy,
+      is_unborn: false,
+    })
+  }
+
+  /// Like [`GitRepo::local_branches_iter`] but splits the upstream lookups across `threads` threads, each opening
+  /// its own repository from a [`RepoHandle`] as a git2 repository can't be shared between threads.
+  fn create_git_branches_in_parallel(&self, branches: &[Branch], threads: usize) -> Result<Vec<GitBranch>, Error> {
+    let ref_names: Vec<Option<&str>> = branches.iter().map(|branch| branch.get().name()).collect();
+    let chunk_size = ref_names.len().div_ceil(threads).max(1);
+    let handle = &self.handle();
+    let chunks = thread::scope(|scope| {
+      let handles: Vec<_> = ref_names
+        .chunks(chunk_size)
+        .map(|chunk| {
+          scope.spawn(move || -> Result<Vec<Option<GitBranch>>, Error> {
+            let repo = handle.open()?;
+            let loaded = chunk.iter().map(|ref_name| {
+              let reference = repo.repo.find_reference((*ref_name)?).ok()?;
+              repo.create_git_branch(&Branch::wrap(reference))
+            });
+            Ok(loaded.collect())
+          })
+        })
+        .collect();
+      handles
+        .into_iter()
+        .map(|handle| handle.join().expect("A branch loading thread panicked"))
+        .collect::<Result<Vec<_>, Error>>()
+    })?;
+    // Refs that aren't valid UTF-8 can't be looked up by name from another handle, so they are built here instead
+    let loaded = chunks.into_iter().flatten().zip(branches);
+    Ok(loaded.filter_map(|(loaded, branch)| loaded.or_else(|| self.create_git_branch(branch))).collect())
   }
 
-  fn create_git_branch(&self, result: Result<(Branch, BranchType), git2::Error>) -> Option<GitBranch> {
-    let (branch, _branch_type) = result.ok()?;
-    let name = branch.name().ok()??;
-    let upstream = extract_upstream_branch(&branch);
-    Some(GitBranch { name: String::from(name), is_head: branch.is_head(), upstream })
+  /// The configured upstream of a branch and whether its ref still resolves.
+  fn extract_upstream(&self, local_branch: &Branch) -> (Option<GitRemoteBranch>, UpstreamStatus) {
+    if let Some(upstream) = extract_upstream_branch(local_branch) {
+      return (Some(upstream), UpstreamStatus::Tracked);
+    }
+    // The upstream ref can't be found, but the branch config may still name one
+    let Some(ref_name) = local_branch.get().name() else {
+      return (None, UpstreamStatus::None);
+    };
+    let Ok(upstream_ref) = self.repo.branch_upstream_name(ref_name) else {
+      return (None, UpstreamStatus::None);
+    };
+    let Some(upstream_ref) = upstream_ref.as_str() else {
+      return (None, UpstreamStatus::None);
+    };
+    let name = upstream_ref
+      .strip_prefix("refs/remotes/")
+      .or_else(|| upstream_ref.strip_prefix("refs/heads/"))
+      .unwrap_or(upstream_ref);
+    (Some(GitRemoteBranch::new(String::from(name))), UpstreamStatus::Gone)
+  }
+
+  /// Checks out a local branch. A safe checkout refuses to overwrite local changes, with [`Error::CheckoutBlocked`]
+  /// listing the files in the way, while a forced one throws them away.
+  fn switch_branch(&self, branch_name: &str, force: bool) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    let operation = if force { "force_checkout_branch" } else { "checkout_branch_from_name" };
+    with_context(format!("Failed to check out {}", branch_name), || {
+      timed(operation, || {
+        self.refresh();
+        info!("Checking out branch {}", branch_name);
+        let branch = self.repo.find_branch(branch_name, BranchType::Local)?;
+        let branch_ref = branch.get();
+        info!("Found branch with ref {}", branch_ref.name().unwrap());
+
+        if let Some(worktree) = self.worktree_with_branch(branch_name)? {
+          error!("Branch {} is checked out in worktree {}", branch_name, worktree.path.display());
+          return Err(Error::Git(format!(
+            "Branch {} is checked out in worktree {}",
+            branch_name,
+            worktree.path.display()
+          )));
+        }
+        if self.dry_run {
+          info!("Dry run, not checking out branch {}", branch_name);
+          return Ok(());
+        }
+
+        let tree = branch_ref.peel_to_tree()?;
+        let mut conflicts = Vec::new();
+        let checkout_result = {
+          let mut checkout_builder = CheckoutBuilder::new();
+          if force {
+            info!("Discarding local changes to check out {}", branch_name);
+            checkout_builder.force();
+          } else {
+            checkout_builder.safe();
+          }
+          checkout_builder.notify_on(CheckoutNotificationType::CONFLICT).notify(|_, path, _, _, _| {
+            conflicts.extend(path.map(|path| path.display().to_string()));
+            true
+          });
+          self.repo.checkout_tree(tree.as_object(), Some(&mut checkout_builder))
+        };
+
+        if let Err(err) = checkout_result {
+          error!("Failed to checkout tree: {}", err);
+          if err.code() == ErrorCode::Conflict {
+            conflicts.sort();
+            conflicts.dedup();
+            return Err(Error::CheckoutBlocked(String::from(branch_name), conflicts));
+          }
+          return Err(Error::Git("Failed to checkout tree".to_string()));
+        }
+
+        let set_head_result = self.repo.set_head(branch_ref.name().unwrap());
+        if set_head_result.is_err() {
+          error!("Failed to set head to: {}", branch_ref.name().unwrap());
+          return Err(Error::Git("Failed to set HEAD".to_string()));
+        }
+
+        Ok(())
+      })
+    })
   }
 }
 
 impl GitRepo for Git2Repo {
   fn local_branches(&self) -> Result<Vec<GitBranch>, Error> {
-    let branches = self.repo.branches(Some(BranchType::Local));
-    if branches.is_err() {
-      return Err(Error::Git2(branches.err().unwrap()));
+    with_context("Failed to list the local branches", || {
+      timed("local_branches", || {
+        self.branch_cache.get_or_load(|| {
+          let branches = self.repo.branches(Some(BranchType::Local));
+          if branches.is_err() {
+            return Err(Error::Git2(branches.err().unwrap()));
+          }
+          let branches: Vec<Branch> = branches.ok().unwrap().filter_map(|branch| Some(branch.ok()?.0)).collect();
+          let threads = available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+          if branches.len() < PARALLEL_BRANCH_THRESHOLD || threads < 2 {
+            return self.local_branches_iter().collect();
+          }
+          self.create_git_branches_in_parallel(&branches, threads)
+        })
+      })
+    })
+  }
+
+  fn local_branches_iter(&self) -> Box<dyn Iterator<Item = Result<GitBranch, Error>> + '_> {
+    let branches = match self.repo.branches(Some(BranchType::Local)) {
+      Ok(branches) => branches,
+      Err(err) => return Box::new(iter::once(Err(Error::Git2(err)))),
+    };
+    // Entries that can't be read are skipped, as they are when listing all at once
+    Box::new(branches.filter_map(|branch| self.create_git_branch(&branch.ok()?.0)).map(Ok))
+  }
+
+  fn refresh(&self) {
+    self.branch_cache.invalidate();
+  }
+
+  fn path(&self) -> &Path {
+    self.repo.path()
+  }
+
+  fn workdir(&self) -> Option<&Path> {
+    self.repo.workdir()
+  }
+
+  fn superproject_path(&self) -> Option<PathBuf> {
+    let workdir = self.repo.workdir()?.canonicalize().ok()?;
+    let parent = Repository::discover(workdir.parent()?).ok()?;
+    let parent_workdir = parent.workdir()?.canonicalize().ok()?;
+    // A nested repository that the parent doesn't list as a submodule is just an unrelated repository
+    let is_listed = parent.submodules().ok()?.iter().any(|submodule| {
+      parent_workdir.join(submodule.path()).canonicalize().is_ok_and(|submodule_path| submodule_path == workdir)
+    });
+    is_listed.then_some(parent_workdir)
+  }
+
+  fn open_superproject(&self) -> Result<Box<dyn GitRepo>, Error> {
+    with_context("Failed to open the superproject", || {
+      let Some(path) = self.superproject_path() else {
+        return Err(Error::NotASubmodule);
+      };
+      info!("Opening the superproject at {}", path.display());
+      let superproject = Git2Repo::new(Repository::open(&path)?)
+        .auth(self.auth)
+        .protected(self.protected.clone())
+        .dry_run(self.dry_run)
+        .read_only(self.read_only)
+        .untracked_is_dirty(self.untracked_is_dirty)
+        .allow_detached_reset(self.allow_detached_reset)
+        .abbrev_length(self.abbrev_length)
+        .remote(self.remote.clone());
+      // Fetches from the superproject report to the same place
+      let superproject = Git2Repo { progress: self.progress.clone(), ..superproject };
+      Ok(Box::new(superproject) as Box<dyn GitRepo>)
+    })
+  }
+
+  fn local_branches_sorted_by_date(&self) -> Result<Vec<GitBranch>, Error> {
+    with_context("Failed to list the local branches", || {
+      let mut branches = self.local_branches()?;
+      // Newest first, branches without a readable tip commit sort as None which Reverse pushes to the end. Branches
+      // committed in the same second are in name order.
+      branches.sort_by_cached_key(|branch| {
+        (Reverse(self.branch_commit_info(branch).ok().map(|info| info.time)), branch.name.clone())
+      });
+      Ok(branches)
+    })
+  }
+
+  fn current_branch(&self) -> Result<Option<GitBranch>, Error> {
+    with_context("Failed to read the current branch", || {
+      if self.repo.head_detached()? {
+        return Ok(None);
+      }
+      let head = match self.repo.head() {
+        Ok(head) => head,
+        Err(err) if err.code() == ErrorCode::UnbornBranch => return Ok(Some(self.unborn_branch()?)),
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      Ok(self.create_git_branch(&Branch::wrap(head)))
+    })
+  }
+
+  fn summary(&self) -> Result<RepoSummary, Error> {
+    with_context("Failed to summarize the repository", || {
+      let mut summary = RepoSummary::default();
+      for (branch, branch_type) in self.repo.branches(None)?.flatten() {
+        match branch_type {
+          BranchType::Local => {
+            summary.local_branches += 1;
+            if branch.is_head() {
+              summary.current_branch = branch.name().ok().flatten().map(String::from);
+            }
+          },
+          BranchType::Remote if branch.get().kind() != Some(ReferenceType::Symbolic) => summary.remote_branches += 1,
+          BranchType::Remote => {},
+        }
+      }
+      // An unborn branch has no ref to find while going over the branches
+      if summary.current_branch.is_none() {
+        summary.current_branch = self.current_branch()?.map(|branch| branch.name);
+      }
+      summary.clean = !self.is_working_tree_dirty()?;
+      Ok(summary)
+    })
+  }
+
+  fn head_short_id(&self) -> Result<Option<String>, Error> {
+    with_context("Failed to read HEAD", || {
+      let head = match self.repo.head() {
+        Ok(head) => head,
+        Err(err) if err.code() == ErrorCode::UnbornBranch => return Ok(None),
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      Ok(Some(self.abbreviate(head.peel_to_commit()?.id())?))
+    })
+  }
+
+  fn short_id(&self, commit_id: &str) -> Result<String, Error> {
+    with_context(format!("Failed to abbreviate {}", commit_id), || {
+      let commit = self.repo.revparse_single(commit_id)?.peel_to_commit()?;
+      self.abbreviate(commit.id())
+    })
+  }
+
+  fn resolve_commit(&self, hash: &str) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to find the commit {}", hash), || {
+      let object = match self.repo.revparse_single(hash) {
+        Ok(object) => object,
+        Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+      };
+      Ok(Some(object.peel_to_commit()?.id().to_string()))
+    })
+  }
+
+  fn branch_exists(&self, name: &str) -> bool {
+    self.repo.find_branch(name, BranchType::Local).is_ok()
+  }
+
+  fn remote_branch_exists(&self, name: &str) -> bool {
+    self.repo.find_branch(name, BranchType::Remote).is_ok()
+  }
+
+  fn remotes(&self) -> Result<Vec<String>, Error> {
+    with_context("Failed to list the remotes", || {
+      let mut remotes: Vec<String> = self.repo.remotes()?.iter().flatten().map(String::from).collect();
+      remotes.sort();
+      Ok(remotes)
+    })
+  }
+
+  fn configured_remote(&self) -> Option<&str> {
+    self.remote.as_deref()
+  }
+
+  fn branch_remote(&self, branch: &GitBranch) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to read the remote of {}", branch.name), || {
+      match self.repo.config()?.get_string(&format!("branch.{}.remote", branch.name)) {
+        Ok(remote) => Ok(Some(remote).filter(|remote| !remote.is_empty() && remote != ".")),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(Error::Git2(err)),
+      }
+    })
+  }
+
+  fn remote_url(&self, remote: &str) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to read the URL of {}", remote), || {
+      Ok(self.repo.find_remote(remote)?.url().map(String::from))
+    })
+  }
+
+  fn remote_branches(&self) -> Result<Vec<GitRemoteBranch>, Error> {
+    with_context("Failed to list the remote branches", || {
+      let remotes = self.remotes()?;
+      let branches = self.repo.branches(Some(BranchType::Remote))?;
+      let mut remote_branches: Vec<GitRemoteBranch> = branches
+        .filter_map(|result| {
+          let (branch, _branch_type) = result.ok()?;
+          // Skip symbolic refs like origin/HEAD, they just point at another remote branch
+          if branch.get().kind() == Some(ReferenceType::Symbolic) {
+            return None;
+          }
+          let name = branch.name().ok()??;
+          Some(GitRemoteBranch::on_remote(String::from(name), &remotes))
+        })
+        .collect();
+      remote_branches.sort_by(|a, b| a.name.cmp(&b.name));
+      remote_branches.dedup();
+      Ok(remote_branches)
+    })
+  }
+
+  fn remote_head(&self, remote_name: &str) -> Result<Option<GitRemoteBranch>, Error> {
+    with_context(format!("Failed to read the HEAD of {}", remote_name), || {
+      let head = match self.repo.find_reference(&format!("refs/remotes/{}/HEAD", remote_name)) {
+        Ok(head) => head,
+        Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      let target = head.symbolic_target().and_then(|target| target.strip_prefix("refs/remotes/"));
+      Ok(target.map(|target| GitRemoteBranch { name: String::from(target), remote: Some(String::from(remote_name)) }))
+    })
+  }
+
+  fn branch_commit_info(&self, branch: &GitBranch) -> Result<CommitInfo, Error> {
+    with_context(format!("Failed to read the last commit of {}", branch.name), || {
+      let local_branch = self.repo.find_branch(&branch.name, BranchType::Local)?;
+      let commit = local_branch.get().peel_to_commit()?;
+      commit_info(&commit, &self.mailmap()?)
+    })
+  }
+
+  fn branch_log(&self, branch: &GitBranch, limit: usize) -> Result<Vec<CommitInfo>, Error> {
+    with_context(format!("Failed to read the log of {}", branch.name), || {
+      let tip = self.branch_tip(&branch.name)?;
+      let mut revwalk = self.repo.revwalk()?;
+      revwalk.set_sorting(Sort::TIME)?;
+      revwalk.push(tip.id())?;
+      let mailmap = self.mailmap()?;
+      let mut commits = Vec::new();
+      for oid in revwalk.take(limit) {
+        commits.push(commit_info(&self.repo.find_commit(oid?)?, &mailmap)?);
+      }
+      Ok(commits)
+    })
+  }
+
+  fn branch_graph(&self, branch: &GitBranch, limit: usize) -> Result<Vec<GraphCommit>, Error> {
+    with_context(format!("Failed to read the history of {}", branch.name), || {
+      let tip = self.branch_tip(&branch.name)?;
+      let mut revwalk = self.repo.revwalk()?;
+      revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+      revwalk.push(tip.id())?;
+      let mut commits = Vec::new();
+      for oid in revwalk.take(limit) {
+        let commit = self.repo.find_commit(oid?)?;
+        let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).into_owned();
+        let parent_ids = commit.parent_ids().map(|parent_id| parent_id.to_string()).collect();
+        commits.push(GraphCommit::new(commit.id().to_string(), self.abbreviate(commit.id())?, parent_ids, summary));
+      }
+      Ok(commits)
+    })
+  }
+
+  fn commit_details(&self, branch: &GitBranch, with_diff: bool) -> Result<String, Error> {
+    with_context(format!("Failed to read the latest commit of {}", branch.name), || {
+      let commit = self.branch_tip(&branch.name)?;
+      // Laid out like `git show --format=fuller`
+      let mut details = format!("commit {}\n", commit.id());
+      if commit.parent_count() > 1 {
+        let parents: Vec<String> = commit.parent_ids().map(|id| self.abbreviate(id)).collect::<Result<_, _>>()?;
+        details.push_str(&format!("Merge: {}\n", parents.join(" ")));
+      }
+      let mailmap = self.mailmap()?;
+      let signatures =
+        [("Author", commit.author_with_mailmap(&mailmap)?), ("Commit", commit.committer_with_mailmap(&mailmap)?)];
+      for (label, signature) in signatures {
+        let name = String::from_utf8_lossy(signature.name_bytes());
+        let email = String::from_utf8_lossy(signature.email_bytes());
+        details.push_str(&format!("{:<11} {} <{}>\n", format!("{}:", label), name, email));
+        details.push_str(&format!("{:<11} {}\n", format!("{}Date:", label), format_signature_time(signature.when())));
+      }
+      details.push('\n');
+      for line in String::from_utf8_lossy(commit.message_bytes()).lines() {
+        if line.is_empty() {
+          details.push('\n');
+        } else {
+          details.push_str(&format!("    {}\n", line));
+        }
+      }
+      if with_diff {
+        let parent_tree = commit.parents().next().map(|parent| parent.tree()).transpose()?;
+        let diff = self.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        details.push('\n');
+        diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+          if matches!(line.origin(), '+' | '-' | ' ') {
+            details.push(line.origin());
+          }
+          details.push_str(&String::from_utf8_lossy(line.content()));
+          true
+        })?;
+      }
+      Ok(details)
+    })
+  }
+
+  fn branches_containing(&self, commit_id: &str) -> Result<Vec<GitBranch>, Error> {
+    with_context(format!("Failed to find the branches containing {}", commit_id), || {
+      let commit = self.repo.revparse_single(commit_id)?.peel_to_commit()?.id();
+      let mut containing = Vec::new();
+      for branch in self.local_branches()? {
+        let Some(tip) = branch.commit_id.as_deref().and_then(|tip| Oid::from_str(tip).ok()) else {
+          continue;
+        };
+        if tip == commit || self.repo.graph_descendant_of(tip, commit)? {
+          containing.push(branch);
+        }
+      }
+      Ok(containing)
+    })
+  }
+
+  fn search_commits(&self, query: &str, limit: usize) -> Result<Vec<GraphCommit>, Error> {
+    with_context(format!("Failed to search the commits for {}", query), || {
+      let query = query.to_lowercase();
+      let mut revwalk = self.repo.revwalk()?;
+      revwalk.set_sorting(Sort::TIME)?;
+      revwalk.push_glob("refs/heads")?;
+      let mut commits = Vec::new();
+      for oid in revwalk {
+        let commit = self.repo.find_commit(oid?)?;
+        if !String::from_utf8_lossy(commit.message_bytes()).to_lowercase().contains(&query) {
+          continue;
+        }
+        let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).into_owned();
+        let parent_ids = commit.parent_ids().map(|parent_id| parent_id.to_string()).collect();
+        commits.push(GraphCommit::new(commit.id().to_string(), self.abbreviate(commit.id())?, parent_ids, summary));
+        if commits.len() == limit {
+          break;
+        }
+      }
+      Ok(commits)
+    })
+  }
+
+  fn reflog_entries(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, Error> {
+    with_context(format!("Failed to read the reflog of {}", ref_name), || {
+      // A ref without a reflog, or one that doesn't exist, reads as an empty reflog
+      let reflog = self.repo.reflog(ref_name)?;
+      reflog
+        .iter()
+        .map(|entry| {
+          let message = String::from_utf8_lossy(entry.message_bytes().unwrap_or_default()).into_owned();
+          let commit_id = entry.id_new();
+          Ok(ReflogEntry::new(
+            commit_id.to_string(),
+            self.abbreviate(commit_id)?,
+            message,
+            entry.committer().when().seconds(),
+          ))
+        })
+        .collect()
+    })
+  }
+
+  fn fetch(&self, remote_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    let remote_name = self.remote_or_default(remote_name)?;
+    with_context(format!("Failed to fetch from {}", remote_name), || {
+      timed("fetch", || {
+        self.refresh();
+        self.fetch_with_prune(&remote_name, FetchPrune::Unspecified)
+      })
+    })
+  }
+
+  fn prune_remote(&self, remote_name: &str) -> Result<Vec<String>, Error> {
+    self.ensure_writable()?;
+    let remote_name = self.remote_or_default(remote_name)?;
+    with_context(format!("Failed to prune {}", remote_name), || {
+      // Pruning deletes remote tracking branches, which a dry run leaves alone
+      if self.dry_run {
+        info!("Dry run, not pruning {}", remote_name);
+        return Ok(Vec::new());
+      }
+      timed("prune_remote", || {
+        self.refresh();
+        let tracking_prefix = format!("{}/", remote_name);
+        let before = self.remote_branches()?;
+        self.fetch_with_prune(&remote_name, FetchPrune::On)?;
+        let after = self.remote_branches()?;
+        let pruned: Vec<String> = before
+          .into_iter()
+          .filter(|branch| branch.name.starts_with(&tracking_prefix) && !after.contains(branch))
+          .map(|branch| branch.name)
+          .collect();
+        info!("Pruned {} stale branches from {}", pruned.len(), remote_name);
+        Ok(pruned)
+      })
+    })
+  }
+
+  fn push_branch(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    let remote_name = match remote_name {
+      "" => self.default_remote(Some(branch))?,
+      remote_name => String::from(remote_name),
+    };
+    let remote_name = remote_name.as_str();
+    with_context(format!("Failed to push {} to {}", branch.name, remote_name), || {
+      timed("push_branch", || {
+        self.refresh();
+        info!("Pushing branch {} to remote {}", branch.name, remote_name);
+        let refspec = format!("refs/heads/{}:refs/heads/{}", branch.name, branch.name);
+        let push_result = self.push_refspec(remote_name, &refspec);
+
+        if let Err(err) = push_result {
+          error!("Failed to push {} to {}: {}", branch.name, remote_name, err);
+          if err.code() == ErrorCode::NotFastForward {
+            return Err(self.non_fast_forward_error(branch, remote_name));
+          }
+          return Err(remote_error(remote_name, err));
+        }
+        if let Ok(Some(reason)) = push_result {
+          error!("Push of {} to {} was rejected: {}", branch.name, remote_name, reason);
+          if is_non_fast_forward(&reason) {
+            return Err(self.non_fast_forward_error(branch, remote_name));
+          }
+          return Err(Error::Git(format!("Push of {} to {} was rejected: {}", branch.name, remote_name, reason)));
+        }
+
+        self.track_pushed(branch, remote_name)?;
+        info!("Successfully pushed branch {} to remote {}", branch.name, remote_name);
+        Ok(())
+      })
+    })
+  }
+
+  fn push_branch_force_with_lease(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    let remote_name = match remote_name {
+      "" => self.default_remote(Some(branch))?,
+      remote_name => String::from(remote_name),
+    };
+    let remote_name = remote_name.as_str();
+    with_context(format!("Failed to force push {} to {}", branch.name, remote_name), || {
+      timed("push_branch_force_with_lease", || {
+        self.refresh();
+        // The lease is where the remote branch was last fetched to, without one the remote mustn't have the branch
+        let remote_ref = format!("refs/heads/{}", branch.name);
+        let expected = self.repo.refname_to_id(&format!("refs/remotes/{}/{}", remote_name, branch.name)).ok();
+        let mut stale = false;
+        info!("Force pushing branch {} to remote {} with lease {:?}", branch.name, remote_name, expected);
+        let refspec = format!("+{0}:{0}", remote_ref);
+        // libgit2 has no lease of its own, checking where the remote has the branch during negotiation stops the push
+        // before anything is sent
+        let push_result = self.push_refspec_negotiated(remote_name, &refspec, |updates| {
+          for update in updates.iter().filter(|update| update.dst_refname() == Some(remote_ref.as_str())) {
+            let current = Some(update.src()).filter(|oid| !oid.is_zero());
+            if current != expected {
+              stale = true;
+              return Err(git2::Error::from_str("the remote branch has moved since it was last fetched"));
+            }
+          }
+          Ok(())
+        });
+
+        if stale {
+          error!("Refused to force push {} to {} as the remote branch has moved", branch.name, remote_name);
+          return Err(Error::StaleLease(branch.name.clone(), String::from(remote_name)));
+        }
+        match push_result {
+          Err(err) => {
+            error!("Failed to force push {} to {}: {}", branch.name, remote_name, err);
+            return Err(remote_error(remote_name, err));
+          },
+          Ok(Some(reason)) => {
+            error!("Force push of {} to {} was rejected: {}", branch.name, remote_name, reason);
+            return Err(Error::Git(format!("Push of {} to {} was rejected: {}", branch.name, remote_name, reason)));
+          },
+          Ok(None) => {},
+        }
+        self.track_pushed(branch, remote_name)?;
+        info!("Successfully force pushed branch {} to remote {}", branch.name, remote_name);
+        Ok(())
+      })
+    })
+  }
+
+  fn pull_current(&self, remote_name: &str) -> Result<PullOutcome, Error> {
+    self.ensure_writable()?;
+    let remote_name = self.remote_or_default(remote_name)?;
+    with_context(format!("Failed to pull from {}", remote_name), || {
+      timed("pull_current", || {
+        self.refresh();
+        if self.is_working_tree_dirty()? {
+          error!("Attempted to pull with a dirty working tree");
+          return Err(Error::Git("Cannot pull with uncommitted changes, commit or stash them first".to_string()));
+        }
+        let Some(current) = self.current_branch()? else {
+          return Err(Error::Git("Cannot pull while HEAD is detached".to_string()));
+        };
+        match current.upstream_status {
+          UpstreamStatus::None => {
+            return Err(Error::Git(format!("The branch {} has no upstream to pull from", current.name)));
+          },
+          UpstreamStatus::Gone => {
+            return Err(Error::Git(format!("The upstream of {} no longer exists on the remote", current.name)));
+          },
+          UpstreamStatus::Tracked => {},
+        }
+        self.fetch(&remote_name)?;
+
+        info!("Pulling branch {}", current.name);
+        let local_branch = self.repo.find_branch(&current.name, BranchType::Local)?;
+        let upstream_branch = local_branch.upstream()?;
+        let upstream_commit = self.repo.reference_to_annotated_commit(upstream_branch.get())?;
+        let (analysis, _preference) = self.repo.merge_analysis(&[&upstream_commit])?;
+        if analysis.is_up_to_date() {
+          info!("Branch {} is up to date", current.name);
+          return Ok(PullOutcome::UpToDate);
+        }
+        if !analysis.is_fast_forward() {
+          info!("Branch {} has diverged from its upstream", current.name);
+          return Ok(PullOutcome::MergeRequired);
+        }
+
+        let target = self.repo.find_commit(upstream_commit.id())?;
+        let mut checkout_builder = CheckoutBuilder::new();
+        checkout_builder.safe();
+        self.repo.checkout_tree(target.as_object(), Some(&mut checkout_builder))?;
+        local_branch.into_reference().set_target(target.id(), &format!("pull: fast-forward to {}", target.id()))?;
+        info!("Fast-forwarded branch {} to {}", current.name, target.id());
+        Ok(PullOutcome::FastForwarded)
+      })
+    })
+  }
+
+  fn merge_branch(&self, branch: &GitBranch) -> Result<MergeOutcome, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to merge {}", branch.name), || {
+      self.refresh();
+      if self.is_working_tree_dirty()? {
+        error!("Attempted to merge with a dirty working tree");
+        return Err(Error::Git("Cannot merge with uncommitted changes, commit or stash them first".to_string()));
+      }
+
+      info!("Merging branch {} into HEAD", branch.name);
+      let to_merge = self.repo.find_branch(&branch.name, BranchType::Local)?;
+      let annotated_commit = self.repo.reference_to_annotated_commit(to_merge.get())?;
+      let (analysis, _preference) = self.repo.merge_analysis(&[&annotated_commit])?;
+      if analysis.is_up_to_date() {
+        info!("HEAD is already up to date with {}", branch.name);
+        return Ok(MergeOutcome::UpToDate);
+      }
+
+      let mut checkout_builder = CheckoutBuilder::new();
+      checkout_builder.safe();
+      let their_commit = self.repo.find_commit(annotated_commit.id())?;
+      if analysis.is_fast_forward() {
+        self.repo.checkout_tree(their_commit.as_object(), Some(&mut checkout_builder))?;
+        self.repo.head()?.set_target(their_commit.id(), &format!("merge {}: Fast-forward", branch.name))?;
+        info!("Fast-forwarded HEAD to {}", branch.name);
+        return Ok(MergeOutcome::FastForwarded);
+      }
+
+      self.repo.merge(&[&annotated_commit], None, Some(&mut checkout_builder))?;
+      let mut index = self.repo.index()?;
+      if index.has_conflicts() {
+        let conflicted_paths = conflicted_paths(&index)?;
+        error!("Merging {} produced conflicts in {:?}", branch.name, conflicted_paths);
+        return Ok(MergeOutcome::Conflicts(conflicted_paths));
+      }
+
+      let tree = self.repo.find_tree(index.write_tree()?)?;
+      let head_commit = self.repo.head()?.peel_to_commit()?;
+      let signature = self.repo.signature()?;
+      let message = format!("Merge branch '{}'", branch.name);
+      self.repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &[&head_commit, &their_commit])?;
+      self.repo.cleanup_state()?;
+      info!("Successfully merged {} into HEAD", branch.name);
+      Ok(MergeOutcome::Merged)
+    })
+  }
+
+  fn cherry_pick(&self, commit_id: &str) -> Result<MergeOutcome, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to cherry-pick {}", commit_id), || {
+      self.refresh();
+      if self.is_working_tree_dirty()? {
+        error!("Attempted to cherry-pick with a dirty working tree");
+        return Err(Error::Git("Cannot cherry-pick with uncommitted changes, commit or stash them first".to_string()));
+      }
+      let commit = self.repo.revparse_single(commit_id)?.peel_to_commit()?;
+      if commit.parent_count() > 1 {
+        return Err(Error::Git(format!("{} is a merge commit and can't be cherry-picked", commit_id)));
+      }
+      if self.dry_run {
+        info!("Dry run, not cherry-picking {}", commit.id());
+        return Ok(MergeOutcome::Merged);
+      }
+
+      info!("Cherry-picking {} onto HEAD", commit.id());
+      let mut checkout_builder = CheckoutBuilder::new();
+      checkout_builder.safe();
+      let mut options = CherrypickOptions::new();
+      options.checkout_builder(checkout_builder);
+      self.repo.cherrypick(&commit, Some(&mut options))?;
+      let mut index = self.repo.index()?;
+      if index.has_conflicts() {
+        let conflicted_paths = conflicted_paths(&index)?;
+        error!("Cherry-picking {} produced conflicts in {:?}", commit.id(), conflicted_paths);
+        return Ok(MergeOutcome::Conflicts(conflicted_paths));
+      }
+
+      let head_commit = self.repo.head()?.peel_to_commit()?;
+      let tree = self.repo.find_tree(index.write_tree()?)?;
+      if tree.id() == head_commit.tree_id() {
+        self.repo.cleanup_state()?;
+        info!("HEAD already has the changes of {}", commit.id());
+        return Ok(MergeOutcome::UpToDate);
+      }
+      let signature = self.repo.signature()?;
+      let message = String::from_utf8_lossy(commit.message_bytes());
+      self.repo.commit(Some("HEAD"), &commit.author(), &signature, &message, &tree, &[&head_commit])?;
+      self.repo.cleanup_state()?;
+      info!("Successfully cherry-picked {} onto HEAD", commit.id());
+      Ok(MergeOutcome::Merged)
+    })
+  }
+
+  fn repo_state(&self) -> RepoState {
+    match self.repo.state() {
+      RepositoryState::Clean => RepoState::Clean,
+      RepositoryState::Merge => RepoState::Merge,
+      RepositoryState::Rebase
+      | RepositoryState::RebaseInteractive
+      | RepositoryState::RebaseMerge
+      | RepositoryState::ApplyMailboxOrRebase => RepoState::Rebase,
+      RepositoryState::ApplyMailbox => RepoState::ApplyMailbox,
+      RepositoryState::CherryPick | RepositoryState::CherryPickSequence => RepoState::CherryPick,
+      RepositoryState::Revert | RepositoryState::RevertSequence => RepoState::Revert,
+      RepositoryState::Bisect => RepoState::Bisect,
     }
-    let loaded_branches: Vec<GitBranch> =
-      branches.ok().unwrap().filter_map(|branch| self.create_git_branch(branch)).collect();
-    Ok(loaded_branches)
+  }
+
+  fn abort_operation(&self) -> Result<(), Error> {
+    self.ensure_writable()?;
+    let state = self.repo_state();
+    with_context(format!("Failed to abort the {}", state), || {
+      self.refresh();
+      if state.is_clean() {
+        return Err(Error::NoOperationInProgress);
+      }
+      if self.dry_run {
+        info!("Dry run, not aborting the {}", state);
+        return Ok(());
+      }
+
+      info!("Aborting the {}", state);
+      match state {
+        RepoState::Clean => {},
+        RepoState::Merge => self.hard_reset_to("HEAD")?,
+        RepoState::Rebase => self.abort_rebase()?,
+        // `git am` records where it started in ORIG_HEAD before applying anything
+        RepoState::ApplyMailbox => self.hard_reset_to("ORIG_HEAD")?,
+        RepoState::CherryPick | RepoState::Revert => {
+          // Picking a range of commits records where it started in the sequencer
+          let sequencer_head = self.repo.path().join("sequencer").join("head");
+          match fs::read_to_string(sequencer_head) {
+            Ok(start) => self.hard_reset_to(start.trim())?,
+            Err(_) => self.hard_reset_to("HEAD")?,
+          }
+        },
+        RepoState::Bisect => self.reset_bisect()?,
+      }
+      self.repo.cleanup_state()?;
+      info!("Aborted the {}", state);
+      Ok(())
+    })
+  }
+
+  fn worktrees(&self) -> Result<Vec<WorktreeInfo>, Error> {
+    with_context("Failed to list the worktrees", || {
+      let mut worktrees: Vec<WorktreeInfo> = Vec::new();
+      for name in self.repo.worktrees()?.iter().flatten() {
+        let worktree = self.repo.find_worktree(name)?;
+        let branch = Repository::open_from_worktree(&worktree).ok().and_then(|worktree_repo| {
+          if worktree_repo.head_detached().unwrap_or(true) {
+            return None;
+          }
+          worktree_repo.head().ok()?.shorthand().map(String::from)
+        });
+        worktrees.push(WorktreeInfo::new(String::from(name), worktree.path().to_path_buf(), branch));
+      }
+      Ok(worktrees)
+    })
+  }
+
+  fn tags(&self) -> Result<Vec<GitTag>, Error> {
+    with_context("Failed to list the tags", || {
+      let mut tags: Vec<GitTag> = Vec::new();
+      for name in self.repo.tag_names(None)?.iter().flatten() {
+        let reference = self.repo.find_reference(&format!("refs/tags/{}", name))?;
+        // Tags can point at trees or blobs, only keep the ones that end up at a commit
+        let Ok(commit) = reference.peel_to_commit() else {
+          continue;
+        };
+        let message =
+          reference.peel_to_tag().ok().and_then(|tag| tag.message().map(|message| message.trim().to_string()));
+        tags.push(GitTag::new(String::from(name), commit.id().to_string(), message));
+      }
+      Ok(tags)
+    })
+  }
+
+  fn create_tag(&self, name: &str, target: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to create the tag {}", name), || {
+      let commit = self.repo.revparse_single(target)?.peel_to_commit()?;
+      if self.dry_run {
+        info!("Dry run, not creating tag {} at {}", name, commit.id());
+        return Ok(());
+      }
+      self.repo.tag_lightweight(name, commit.as_object(), false)?;
+      info!("Created tag {} at {}", name, commit.id());
+      Ok(())
+    })
+  }
+
+  fn delete_tag(&self, name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete the tag {}", name), || {
+      if self.dry_run {
+        info!("Dry run, not deleting tag {}", name);
+        return Ok(());
+      }
+      self.repo.tag_delete(name)?;
+      info!("Deleted tag {}", name);
+      Ok(())
+    })
+  }
+
+  fn diff_branches(&self, from: &GitBranch, to: &GitBranch) -> Result<DiffSummary, Error> {
+    with_context(format!("Failed to compare {} with {}", from.name, to.name), || {
+      let from_tree = self.repo.find_branch(&from.name, BranchType::Local)?.get().peel_to_tree()?;
+      let to_tree = self.repo.find_branch(&to.name, BranchType::Local)?.get().peel_to_tree()?;
+      let diff = self.repo.diff_tree_to_tree(Some(&from_tree), Some(&to_tree), None)?;
+      let stats = diff.stats()?;
+      let files: Vec<FileChange> = diff
+        .deltas()
+        .filter_map(|delta| {
+          let (file, change_type) = match delta.status() {
+            Delta::Added => (delta.new_file(), ChangeType::Added),
+            Delta::Deleted => (delta.old_file(), ChangeType::Deleted),
+            _ => (delta.new_file(), ChangeType::Modified),
+          };
+          Some(FileChange::new(file.path()?.to_string_lossy().into_owned(), change_type))
+        })
+        .collect();
+      Ok(DiffSummary {
+        files_changed: stats.files_changed(),
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+        files,
+      })
+    })
+  }
+
+  fn diff_with_head(&self, branch: &GitBranch, limit: usize) -> Result<Patch, Error> {
+    with_context(format!("Failed to compare {} with HEAD", branch.name), || {
+      let head_tree = self.repo.head()?.peel_to_tree()?;
+      let branch_tree = self.branch_tip(&branch.name)?.tree()?;
+      let diff = self.repo.diff_tree_to_tree(Some(&head_tree), Some(&branch_tree), None)?;
+      let mut patch = Patch::default();
+      let printed = diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        let origin = if matches!(line.origin(), '+' | '-' | ' ') { String::from(line.origin()) } else { String::new() };
+        // File headers come as one line holding the `diff --git`, `index`, `---` and `+++` lines, and the missing newline
+        // notes start with the newline that is missing
+        let content = String::from_utf8_lossy(line.content());
+        for content in content.trim_start_matches('\n').lines() {
+          if patch.lines.len() == limit {
+            patch.truncated = true;
+            return false;
+          }
+          patch.lines.push(format!("{}{}", origin, content));
+        }
+        true
+      });
+      // Stopping the print early makes it fail
+      if !patch.truncated {
+        printed?;
+      }
+      Ok(patch)
+    })
   }
 
   fn stashes(&mut self) -> Result<Vec<GitStash>, Error> {
-    let mut stashes: Vec<GitStash> = vec![];
-    self.repo.stash_foreach(|index, message, stash_id| {
-      stashes.push(GitStash::new(index, String::from(message), stash_id.to_string()));
-      true
-    })?;
+    with_context("Failed to list the stashes", || {
+      let mut stashes: Vec<GitStash> = vec![];
+      self.repo.stash_foreach(|index, message, stash_id| {
+        stashes.push(GitStash::new(index, String::from(message), stash_id.to_string()));
+        true
+      })?;
 
-    Ok(stashes)
+      Ok(stashes)
+    })
   }
 
-  fn checkout_branch_from_name(&self, branch_name: &str) -> Result<(), Error> {
-    info!("Checking out branch {}", branch_name);
-    let branch = self.repo.find_branch(branch_name, BranchType::Local)?;
-    let branch_ref = branch.get();
-    info!("Found branch with ref {}", branch_ref.name().unwrap());
+  fn stash_save(&mut self, message: &str) -> Result<String, Error> {
+    self.ensure_writable()?;
+    with_context("Failed to stash the working tree", || {
+      self.refresh();
+      info!("Stashing working tree changes: {}", message);
+      let signature = self.repo.signature()?;
+      let stash_id = match self.repo.stash_save2(&signature, Some(message), None) {
+        Ok(stash_id) => stash_id,
+        // libgit2 reports a clean tree as nothing found to stash, which isn't a missing ref
+        Err(err) if err.code() == ErrorCode::NotFound => {
+          return Err(Error::Git("There are no local changes to stash".to_string()));
+        },
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      info!("Created stash {}", stash_id);
+      Ok(stash_id.to_string())
+    })
+  }
 
-    let tree = branch_ref.peel_to_tree()?;
-    let checkout_result = self.repo.checkout_tree(tree.as_object(), None);
+  fn stash_pop(&mut self, index: usize) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to pop stash {}", index), || {
+      self.refresh();
+      info!("Popping stash {}", index);
+      // libgit2's pop drops the stash even when applying it conflicted, so it is only dropped once it applied cleanly
+      let mut options = StashApplyOptions::new();
+      options.reinstantiate_index();
+      self.repo.stash_apply(index, Some(&mut options))?;
+      let conflicts = conflicted_paths(&self.repo.index()?)?;
+      if !conflicts.is_empty() {
+        error!("Popping stash {} conflicted in {}", index, conflicts.join(", "));
+        return Err(Error::Git(format!("The stash conflicts in {} and has been kept", conflicts.join(", "))));
+      }
+      self.repo.stash_drop(index)?;
+      Ok(())
+    })
+  }
 
-    if checkout_result.is_err() {
-      error!("Failed to checkout tree: {}", checkout_result.unwrap_err());
-      return Err(Error::Git("Failed to checkout tree".to_string()));
-    }
+  fn branch_from_stash(&mut self, index: usize, new_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to create {} from stash {}", new_name, index), || {
+      self.refresh();
+      if !self.validate_branch_name(new_name)? {
+        error!("Attempted to create branch with invalid name {}", new_name);
+        return Err(Error::Git(format!("{} is not a valid branch name", new_name)));
+      }
+      if self.branch_exists(new_name) {
+        return Err(Error::Git(format!("A branch named {} already exists", new_name)));
+      }
+      if self.is_working_tree_dirty()? {
+        return Err(Error::Git("Cannot create a branch from a stash with local changes".to_string()));
+      }
+      let base_id = self.repo.revparse_single(&format!("stash@{{{}}}", index))?.peel_to_commit()?.parent_id(0)?;
+      if self.dry_run {
+        info!("Dry run, not creating branch {} at {} from stash {}", new_name, base_id, index);
+        return Ok(());
+      }
+      {
+        let base = self.repo.find_commit(base_id)?;
+        self.repo.branch(new_name, &base, false)?;
+        self.repo.checkout_tree(base.as_object(), Some(CheckoutBuilder::new().safe()))?;
+      }
+      self.repo.set_head(&format!("refs/heads/{}", new_name))?;
+      info!("Created and checked out {} at {}, applying stash {}", new_name, base_id, index);
 
-    let set_head_result = self.repo.set_head(branch_ref.name().unwrap());
-    if set_head_result.is_err() {
-      error!("Failed to set head to: {}", branch_ref.name().unwrap());
-      return Err(Error::Git("Failed to set HEAD".to_string()));
-    }
+      let mut options = StashApplyOptions::new();
+      options.reinstantiate_index();
+      if let Err(err) = self.repo.stash_apply(index, Some(&mut options)) {
+        error!("Failed to apply stash {} to {}: {}", index, new_name, err);
+        return Err(Error::StashNotApplied(String::from(new_name), String::from(err.message())));
+      }
+      let conflicts = conflicted_paths(&self.repo.index()?)?;
+      if !conflicts.is_empty() {
+        error!("Applying stash {} to {} conflicted in {}", index, new_name, conflicts.join(", "));
+        return Err(Error::StashNotApplied(
+          String::from(new_name),
+          format!("it conflicts in {}", conflicts.join(", ")),
+        ));
+      }
+      self.repo.stash_drop(index)?;
+      info!("Applied and dropped stash {}", index);
+      Ok(())
+    })
+  }
 
-    Ok(())
+  fn checkout_branch_from_name(&self, branch_name: &str) -> Result<(), Error> {
+    self.switch_branch(branch_name, false)
   }
 
   fn checkout_branch(&self, branch: &GitBranch) -> Result<(), Error> {
     self.checkout_branch_from_name(&branch.name)
   }
 
+  fn force_checkout_branch(&self, branch: &GitBranch) -> Result<(), Error> {
+    self.switch_branch(&branch.name, true)
+  }
+
+  fn checkout_detached(&self, target: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to check out {}", target), || {
+      timed("checkout_detached", || {
+        self.refresh();
+        info!("Checking out {} detached", target);
+        if self.is_working_tree_dirty()? {
+          error!("Attempted to checkout {} with a dirty working tree", target);
+          return Err(Error::Git("Checkout would overwrite local changes".to_string()));
+        }
+        let commit = self.repo.revparse_single(target)?.peel_to_commit()?;
+        if self.dry_run {
+          info!("Dry run, not checking out {} detached", commit.id());
+          return Ok(());
+        }
+        let mut checkout_builder = CheckoutBuilder::new();
+        checkout_builder.safe();
+        self.repo.checkout_tree(commit.as_object(), Some(&mut checkout_builder))?;
+        self.repo.set_head_detached(commit.id())?;
+        info!("HEAD is now detached at {}", commit.id());
+        Ok(())
+      })
+    })
+  }
+
+  fn checkout_previous(&self) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context("Failed to check out the previous branch", || {
+      let (object, reference) = match self.repo.revparse_ext("@{-1}") {
+        Ok(previous) => previous,
+        Err(err) if err.code() == ErrorCode::NotFound => return Err(Error::NoPreviousBranch),
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      match reference.as_ref().filter(|reference| reference.is_branch()).and_then(|reference| reference.shorthand()) {
+        Some(branch_name) => self.checkout_branch_from_name(branch_name),
+        // The previous checkout was a detached HEAD
+        None => self.checkout_detached(&object.id().to_string()),
+      }
+    })
+  }
+
+  fn checkout_remote_branch(&self, remote: &GitRemoteBranch) -> Result<GitBranch, Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to check out {}", remote.name), || {
+      timed("checkout_remote_branch", || {
+        self.refresh();
+        info!("Checking out remote branch {}", remote.name);
+        let (_remote_name, local_name) = self.split_remote_branch(remote)?;
+        if self.branch_exists(local_name) {
+          error!("Attempted to checkout {} but local branch {} already exists", remote.name, local_name);
+          return Err(Error::Git(format!("A branch named {} already exists", local_name)));
+        }
+
+        let remote_branch = self.repo.find_branch(&remote.name, BranchType::Remote)?;
+        let commit = remote_branch.get().peel_to_commit()?;
+        if self.dry_run {
+          info!("Dry run, not creating local branch {} tracking {}", local_name, remote.name);
+          return Ok(GitBranch::new(String::from(local_name)));
+        }
+        let mut local_branch = self.repo.branch(local_name, &commit, false)?;
+        local_branch.set_upstream(Some(&remote.name))?;
+        info!("Created local branch {} tracking {}", local_name, remote.name);
+        self.checkout_branch_from_name(local_name)?;
+
+        Ok(GitBranch {
+          name: String::from(local_name),
+          is_head: true,
+          upstream: Some(remote.clone()),
+          upstream_status: UpstreamStatus::Tracked,
+          ahead_behind: Some((0, 0)),
+          commit_id: Some(commit.id().to_string()),
+          upstream_commit_id: Some(commit.id().to_string()),
+          name_is_lossy: false,
+          is_unborn: false,
+        })
+      })
+    })
+  }
+
+  fn reset_current(&self, target: &str, mode: ResetMode) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to reset to {}", target), || {
+      self.refresh();
+      if self.repo.head_detached()? && !self.allow_detached_reset {
+        error!("Attempted to reset a detached HEAD");
+        return Err(Error::DetachedHead);
+      }
+      let commit = self.repo.revparse_single(target)?.peel_to_commit()?;
+      if self.dry_run {
+        info!("Dry run, not resetting to {}", target);
+        return Ok(());
+      }
+
+      info!("Resetting ({}) to {}", mode, commit.id());
+      let reset_type = match mode {
+        ResetMode::Soft => ResetType::Soft,
+        ResetMode::Mixed => ResetType::Mixed,
+        ResetMode::Hard => ResetType::Hard,
+      };
+      self.repo.reset(commit.as_object(), reset_type, None)?;
+      Ok(())
+    })
+  }
+
+  fn move_branch(&self, branch: &GitBranch, target: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_checked_out(branch)?;
+    with_context(format!("Failed to move {} to {}", branch.name, target), || {
+      self.refresh();
+      let commit = self.repo.revparse_single(target)?.peel_to_commit()?;
+      let mut reference = self.repo.find_reference(&format!("refs/heads/{}", branch.name))?;
+      if self.dry_run {
+        info!("Dry run, not moving {} to {}", branch.name, target);
+        return Ok(());
+      }
+      info!("Moving {} to {}", branch.name, commit.id());
+      reference.set_target(commit.id(), &format!("branch: moved to {}", target))?;
+      Ok(())
+    })
+  }
+
+  fn fast_forward_branch(&self, branch: &GitBranch) -> Result<(), Error> {
+    let Some(branch) = self.fast_forward_check(branch)? else {
+      return Ok(());
+    };
+    with_context(format!("Failed to fast-forward {}", branch.name), || {
+      // The check filled the branch cache, which the move makes stale
+      self.refresh();
+      let Some(upstream_commit_id) = &branch.upstream_commit_id else {
+        return Err(Error::NoUpstream(branch.name.clone()));
+      };
+      let target = self.repo.find_commit(Oid::from_str(upstream_commit_id)?)?;
+      let mut reference = self.repo.find_reference(&format!("refs/heads/{}", branch.name))?;
+      if self.dry_run {
+        info!("Dry run, not fast-forwarding {} to {}", branch.name, target.id());
+        return Ok(());
+      }
+      if branch.is_head {
+        // Safe checkouts refuse to overwrite untracked files the upstream adds, as git merge does
+        let mut checkout_builder = CheckoutBuilder::new();
+        checkout_builder.safe();
+        self.repo.checkout_tree(target.as_object(), Some(&mut checkout_builder))?;
+      }
+      reference.set_target(target.id(), &format!("fast-forward to {}", target.id()))?;
+      info!("Fast-forwarded branch {} to {}", branch.name, target.id());
+      Ok(())
+    })
+  }
+
+  fn is_working_tree_dirty(&self) -> Result<bool, Error> {
+    if self.repo.is_bare() {
+      return Ok(false);
+    }
+    with_context("Failed to read the working tree status", || {
+      let mut status_options = StatusOptions::new();
+      // Untracked files are matched against .gitignore, .git/info/exclude and core.excludesFile, like git status
+      status_options.include_untracked(self.untracked_is_dirty).include_ignored(false);
+      let statuses = self.repo.statuses(Some(&mut status_options))?;
+      Ok(statuses.iter().any(|entry| entry.status() != Status::CURRENT))
+    })
+  }
+
+  fn checkout_preview(&self, branch: &GitBranch) -> Result<CheckoutPreview, Error> {
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to preview checking out {}", branch.name), || {
+      let tree = self.repo.find_branch(&branch.name, BranchType::Local)?.get().peel_to_tree()?;
+      let mut preview = CheckoutPreview::default();
+      let checkout_result = {
+        let mut checkout_builder = CheckoutBuilder::new();
+        checkout_builder.dry_run().safe();
+        checkout_builder.notify_on(CheckoutNotificationType::CONFLICT | CheckoutNotificationType::DIRTY).notify(
+          |notification, path, _, _, _| {
+            let files = match notification {
+              CheckoutNotificationType::CONFLICT => &mut preview.blocking,
+              _ => &mut preview.carried,
+            };
+            files.extend(path.map(|path| path.display().to_string()));
+            true
+          },
+        );
+        self.repo.checkout_tree(tree.as_object(), Some(&mut checkout_builder))
+      };
+      match checkout_result {
+        Err(err) if err.code() != ErrorCode::Conflict => return Err(Error::Git2(err)),
+        _ => {},
+      }
+      for files in [&mut preview.carried, &mut preview.blocking] {
+        files.sort();
+        files.dedup();
+      }
+      Ok(preview)
+    })
+  }
+
   fn validate_branch_name(&self, name: &str) -> Result<bool, Error> {
-    Ok(Branch::name_is_valid(name)?)
+    with_context(format!("Failed to validate the branch name {}", name), || Ok(Branch::name_is_valid(name)?))
   }
 
   fn create_branch(&self, to_create: &GitBranch) -> Result<(), Error> {
-    info!("Creating branch {}", to_create.name);
-    let head = self.repo.head()?;
-    let head_oid = head.target();
+    self.ensure_writable()?;
+    with_context(format!("Failed to create {}", to_create.name), || {
+      self.refresh();
+      info!("Creating branch {}", to_create.name);
+      let head = match self.repo.head() {
+        Ok(head) => head,
+        Err(err) if err.code() == ErrorCode::UnbornBranch => {
+          error!("Attempted to create branch {} before the first commit", to_create.name);
+          return Err(Error::UnbornBranch(self.unborn_branch()?.name));
+        },
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      let Some(head_oid) = head.target() else {
+        error!("Attempted to create a branch from a symbolic reference: {}", head.name().unwrap_or("HEAD"));
+        return Err(Error::Git("Attempted to create a branch from a symbolic reference".to_string()));
+      };
 
-    if head_oid.is_none() {
-      error!("Attempted to create a branch from a symbolic reference: {}", head_oid.unwrap());
-      return Err(Error::Git("Attempted to create a branch from a symbolic reference".to_string()));
-    }
+      let commit = self.repo.find_commit(head_oid)?;
+      info!("Using commit for new branch {}", commit.id());
+      if self.dry_run {
+        info!("Dry run, not creating branch {}", to_create.name);
+        return Ok(());
+      }
+      self.repo.branch(&to_create.name, &commit, false)?;
+      info!("Successfully created branch {}", to_create.name);
+      Ok(())
+    })
+  }
 
-    let commit = self.repo.find_commit(head.target().unwrap())?;
-    info!("Using commit for new branch {}", commit.id());
-    self.repo.branch(&to_create.name, &commit, false)?;
-    info!("Successfully created branch {}", to_create.name);
-    Ok(())
+  fn create_branch_from(&self, new_name: &str, base: &GitBranch) -> Result<GitBranch, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to create {} from {}", new_name, base.name), || {
+      self.refresh();
+      info!("Creating branch {} from {}", new_name, base.name);
+      if !self.validate_branch_name(new_name)? {
+        error!("Attempted to create branch with invalid name {}", new_name);
+        return Err(Error::Git(format!("{} is not a valid branch name", new_name)));
+      }
+
+      let base_branch = self.repo.find_branch(&base.name, BranchType::Local)?;
+      let commit = base_branch.get().peel_to_commit()?;
+      info!("Using commit for new branch {}", commit.id());
+      if self.dry_run {
+        info!("Dry run, not creating branch {}", new_name);
+        return Ok(GitBranch::new(String::from(new_name)));
+      }
+      self.repo.branch(new_name, &commit, false)?;
+      info!("Successfully created branch {}", new_name);
+      Ok(GitBranch::new(String::from(new_name)))
+    })
   }
 
-  fn delete_branch(&self, to_delete: &GitBranch) -> Result<(), Error> {
-    let branches = self.repo.branches(Some(BranchType::Local))?;
-    for res in branches.into_iter() {
-      if res.is_err() {
-        continue;
+  fn rename_branch(&self, branch: &GitBranch, new_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to rename {} to {}", branch.name, new_name), || {
+      self.refresh();
+      info!("Renaming branch {} to {}", branch.name, new_name);
+      if !self.validate_branch_name(new_name)? {
+        error!("Attempted to rename branch {} to invalid name {}", branch.name, new_name);
+        return Err(Error::Git(format!("{} is not a valid branch name", new_name)));
       }
-      let (mut branch, _branch_type) = res?;
-      if branch.name().is_err() {
-        continue;
+      if self.repo.find_branch(new_name, BranchType::Local).is_ok() {
+        error!("Attempted to rename branch {} to existing branch {}", branch.name, new_name);
+        return Err(Error::Git(format!("A branch named {} already exists", new_name)));
       }
-      let name = branch.name()?;
-      if name.is_some() && to_delete.name == name.unwrap() {
-        branch.delete()?;
-        break;
+
+      let mut existing = self.repo.find_branch(&branch.name, BranchType::Local)?;
+      if self.dry_run {
+        info!("Dry run, not renaming branch {} to {}", branch.name, new_name);
+        return Ok(());
       }
-    }
-    Ok(())
+      let renamed = existing.rename(new_name, false)?;
+      if branch.is_head {
+        let Some(renamed_ref) = renamed.get().name() else {
+          return Err(Error::Git(format!("Renamed branch {} has an invalid reference name", new_name)));
+        };
+        self.repo.set_head(renamed_ref)?;
+      }
+      info!("Successfully renamed branch {} to {}", branch.name, new_name);
+      Ok(())
+    })
+  }
+
+  fn set_upstream(&self, branch: &GitBranch, remote_branch: Option<&GitRemoteBranch>) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to set the upstream of {}", branch.name), || {
+      self.refresh();
+      let mut local_branch = self.repo.find_branch(&branch.name, BranchType::Local)?;
+      let Some(remote_branch) = remote_branch else {
+        if self.dry_run {
+          info!("Dry run, not clearing the upstream of {}", branch.name);
+          return Ok(());
+        }
+        info!("Clearing upstream for branch {}", branch.name);
+        local_branch.set_upstream(None)?;
+        return Ok(());
+      };
+
+      if self.repo.find_branch(&remote_branch.name, BranchType::Remote).is_err() {
+        error!("Unable to find remote branch {}", remote_branch.name);
+        return Err(Error::Git(format!("The remote branch {} does not exist", remote_branch.name)));
+      }
+      if self.dry_run {
+        info!("Dry run, not setting the upstream of {} to {}", branch.name, remote_branch.name);
+        return Ok(());
+      }
+      info!("Setting upstream for branch {} to {}", branch.name, remote_branch.name);
+      local_branch.set_upstream(Some(&remote_branch.name))?;
+      Ok(())
+    })
+  }
+
+  fn branch_description(&self, branch: &GitBranch) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to read the description of {}", branch.name), || {
+      let key = format!("branch.{}.description", branch.name);
+      match self.repo.config()?.get_string(&key) {
+        Ok(description) => Ok(Some(description.trim().to_string()).filter(|description| !description.is_empty())),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(Error::Git2(err)),
+      }
+    })
+  }
+
+  fn set_branch_description(&self, branch: &GitBranch, description: Option<&str>) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to set the description of {}", branch.name), || {
+      let key = format!("branch.{}.description", branch.name);
+      if self.dry_run {
+        info!("Dry run, not setting the description of {}", branch.name);
+        return Ok(());
+      }
+      let mut config = self.repo.config()?;
+      match description {
+        Some(description) => config.set_str(&key, description)?,
+        None => {
+          if let Err(err) = config.remove(&key) {
+            if err.code() != ErrorCode::NotFound {
+              return Err(Error::Git2(err));
+            }
+          }
+        },
+      }
+      info!("Set the description of {}", branch.name);
+      Ok(())
+    })
+  }
+
+  fn is_merged(&self, branch: &GitBranch) -> Result<bool, Error> {
+    with_context(format!("Failed to check whether {} is merged", branch.name), || {
+      let local_branch = self.repo.find_branch(&branch.name, BranchType::Local)?;
+      self.is_merged_into_head(&local_branch)
+    })
+  }
+
+  fn upstream_ahead_behind(&self, branch: &GitBranch) -> Result<Option<(usize, usize)>, Error> {
+    with_context(format!("Failed to compare {} with its upstream", branch.name), || {
+      let (Some(commit_id), Some(upstream_commit_id)) = (&branch.commit_id, &branch.upstream_commit_id) else {
+        return Ok(None);
+      };
+      let counts = self.repo.graph_ahead_behind(Oid::from_str(commit_id)?, Oid::from_str(upstream_commit_id)?)?;
+      Ok(Some(counts))
+    })
+  }
+
+  fn diffstat_vs_upstream(&self, branch: &GitBranch) -> Result<Option<(usize, usize)>, Error> {
+    with_context(format!("Failed to diff {} with its upstream", branch.name), || {
+      let (Some(commit_id), Some(upstream_commit_id)) = (&branch.commit_id, &branch.upstream_commit_id) else {
+        return Ok(None);
+      };
+      let upstream_tree = self.repo.find_commit(Oid::from_str(upstream_commit_id)?)?.tree()?;
+      let tree = self.repo.find_commit(Oid::from_str(commit_id)?)?.tree()?;
+      let stats = self.repo.diff_tree_to_tree(Some(&upstream_tree), Some(&tree), None)?.stats()?;
+      Ok(Some((stats.insertions(), stats.deletions())))
+    })
+  }
+
+  fn divergence(&self, branch: &GitBranch, base: &GitBranch) -> Result<Divergence, Error> {
+    with_context(format!("Failed to compare {} with {}", branch.name, base.name), || {
+      let (tip, base_tip) = (self.branch_tip(&branch.name)?.id(), self.branch_tip(&base.name)?.id());
+      let merge_base = match self.repo.merge_base(tip, base_tip) {
+        Ok(merge_base) => merge_base,
+        Err(err) if err.code() == ErrorCode::NotFound => return Err(Error::NoCommonHistory(base.name.clone())),
+        Err(err) => return Err(Error::Git2(err)),
+      };
+      let (ahead, behind) = self.repo.graph_ahead_behind(tip, base_tip)?;
+      Ok(Divergence { merge_base: merge_base.to_string(), ahead, behind })
+    })
+  }
+
+  fn commits_between(&self, hidden: &[String], tip: &str) -> Result<Vec<String>, Error> {
+    with_context(format!("Failed to list the commits of {}", tip), || {
+      let mut revwalk = self.repo.revwalk()?;
+      revwalk.set_sorting(Sort::TOPOLOGICAL)?;
+      revwalk.push(Oid::from_str(tip)?)?;
+      for commit_id in hidden {
+        revwalk.hide(Oid::from_str(commit_id)?)?;
+      }
+      Ok(revwalk.map(|oid| oid.map(|oid| oid.to_string())).collect::<Result<_, _>>()?)
+    })
+  }
+
+  fn merged_branches(&self, into: &GitBranch) -> Result<Vec<GitBranch>, Error> {
+    with_context(format!("Failed to find the branches merged into {}", into.name), || {
+      let target = self.repo.find_branch(&into.name, BranchType::Local)?;
+      let Some(target_oid) = target.get().target() else {
+        return Err(Error::Git(format!("Unable to find the commit of {}", into.name)));
+      };
+      let mut merged = Vec::new();
+      for res in self.repo.branches(Some(BranchType::Local))? {
+        let (branch, _branch_type) = res?;
+        if branch.is_head()
+          || branch.name_bytes()? == into.name.as_bytes()
+          || !self.is_merged_into(&branch, target_oid)?
+        {
+          continue;
+        }
+        merged.extend(self.create_git_branch(&branch));
+      }
+      Ok(merged)
+    })
+  }
+
+  fn is_dry_run(&self) -> bool {
+    self.dry_run
+  }
+
+  fn is_read_only(&self) -> bool {
+    self.read_only
+  }
+
+  fn is_bare(&self) -> bool {
+    self.repo.is_bare()
+  }
+
+  fn protected_branches(&self) -> &ProtectedBranches {
+    &self.protected
+  }
+
+  fn ref_signature(&self) -> Result<RefSignature, Error> {
+    with_context("Failed to read the refs", || {
+      let head = self.repo.find_reference("HEAD")?;
+      let mut parts = vec![
+        head.symbolic_target().map(String::from).unwrap_or_default(),
+        self.repo.refname_to_id("HEAD").map(|id| id.to_string()).unwrap_or_default(),
+      ];
+      for reference in self.repo.references_glob("refs/heads/*")? {
+        let reference = reference?;
+        parts.push(String::from_utf8_lossy(reference.name_bytes()).into_owned());
+        parts.push(reference.target().map(|id| id.to_string()).unwrap_or_default());
+      }
+      Ok(RefSignature::of(parts))
+    })
+  }
+
+  fn seen_refs(&self) -> &SeenRefs {
+    &self.seen_refs
+  }
+
+  fn delete_branch(&self, to_delete: &GitBranch) -> Result<String, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete {}", to_delete.name), || {
+      // Checked before the cache is invalidated, the check loads the branches that are about to change
+      self.ensure_not_protected(&to_delete.name)?;
+      self.refresh();
+      info!("Deleting branch {}", to_delete.name);
+      let branches = self.repo.branches(Some(BranchType::Local))?;
+      for res in branches {
+        let (mut branch, _branch_type) = res?;
+        let Some(name) = branch.name()? else {
+          continue;
+        };
+        if to_delete.name == name {
+          if !self.is_merged_into_head(&branch)? {
+            error!("Refusing to delete unmerged branch {}", to_delete.name);
+            return Err(Error::Git(format!("The branch {} is not fully merged", to_delete.name)));
+          }
+          let commit_id = branch.get().peel_to_commit()?.id().to_string();
+          if self.dry_run {
+            info!("Dry run, not deleting branch {}", to_delete.name);
+            return Ok(commit_id);
+          }
+          branch.delete()?;
+          info!("Successfully deleted branch {} at {}", to_delete.name, commit_id);
+          return Ok(commit_id);
+        }
+      }
+      error!("Unable to find branch {} to delete", to_delete.name);
+      Err(Error::Git(format!("Unable to find branch {} to delete", to_delete.name)))
+    })
+  }
+
+  fn delete_remote_branch(&self, remote: &GitRemoteBranch) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete {}", remote.name), || {
+      self.refresh();
+      info!("Deleting remote branch {}", remote.name);
+      let (remote_name, branch_name) = self.split_remote_branch(remote)?;
+      let refspec = format!(":refs/heads/{}", branch_name);
+      match self.push_refspec(&remote_name, &refspec) {
+        Err(err) => {
+          error!("Failed to delete remote branch {}: {}", remote.name, err);
+          Err(remote_error(&remote_name, err))
+        },
+        Ok(Some(reason)) => {
+          error!("Deletion of remote branch {} was rejected: {}", remote.name, reason);
+          Err(Error::Git(format!("{} refused to delete {}: {}", remote_name, branch_name, reason)))
+        },
+        Ok(None) => {
+          info!("Successfully deleted remote branch {}", remote.name);
+          Ok(())
+        },
+      }
+    })
+  }
+
+  fn orphaned_commits(&self, branch: &GitBranch) -> Result<usize, Error> {
+    with_context(format!("Failed to check which commits only {} has", branch.name), || {
+      let local = self.repo.find_branch(&branch.name, BranchType::Local)?;
+      let branch_ref = local.get().name().map(String::from);
+      let mut revwalk = self.repo.revwalk()?;
+      revwalk.push(local.get().peel_to_commit()?.id())?;
+      for reference in self.repo.references()? {
+        let reference = reference?;
+        if reference.name() == branch_ref.as_deref() {
+          continue;
+        }
+        // Tags of trees or blobs don't reach any commits
+        if let Ok(commit) = reference.peel_to_commit() {
+          revwalk.hide(commit.id())?;
+        }
+      }
+      if let Ok(head) = self.repo.head() {
+        revwalk.hide(head.peel_to_commit()?.id())?;
+      }
+      Ok(revwalk.count())
+    })
+  }
+
+  fn force_delete_branch(&self, to_delete: &GitBranch) -> Result<String, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete {}", to_delete.name), || {
+      self.ensure_not_protected(&to_delete.name)?;
+      self.refresh();
+      info!("Force deleting branch {}", to_delete.name);
+      let mut branch = self.repo.find_branch(&to_delete.name, BranchType::Local)?;
+      if branch.is_head() {
+        error!("Refusing to delete the checked out branch {}", to_delete.name);
+        return Err(Error::Git(format!("Cannot delete the checked out branch {}", to_delete.name)));
+      }
+      let commit_id = branch.get().peel_to_commit()?.id().to_string();
+      if self.dry_run {
+        info!("Dry run, not force deleting branch {}", to_delete.name);
+        return Ok(commit_id);
+      }
+      branch.delete()?;
+      info!("Successfully force deleted branch {} at {}", to_delete.name, commit_id);
+      Ok(commit_id)
+    })
+  }
+
+  fn restore_branch(&self, name: &str, commit_id: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to restore {}", name), || {
+      self.refresh();
+      info!("Restoring branch {} at {}", name, commit_id);
+      let commit = self.repo.find_commit(Oid::from_str(commit_id)?)?;
+      if self.dry_run {
+        info!("Dry run, not restoring branch {}", name);
+        return Ok(());
+      }
+      self.repo.branch(name, &commit, false)?;
+      info!("Successfully restored branch {}", name);
+      Ok(())
+    })
   }
 }
 
 fn extract_upstream_branch(local_branch: &Branch) -> Option<GitRemoteBranch> {
   let upstream_branch = local_branch.upstream().ok()?;
   let upstream_name = upstream_branch.name().ok()??;
-  Some(GitRemoteBranch { name: String::from(upstream_name) })
+  Some(GitRemoteBranch::new(String::from(upstream_name)))
+}
+
+/// The paths with conflicts in the index, each listed once.
+fn conflicted_paths(index: &Index) -> Result<Vec<String>, Error> {
+  let mut paths: Vec<String> = index
+    .conflicts()?
+    .filter_map(|conflict| {
+      let conflict = conflict.ok()?;
+      let entry = conflict.our.or(conflict.their).or(conflict.ancestor)?;
+      Some(String::from_utf8_lossy(&entry.path).into_owned())
+    })
+    .collect();
+  paths.sort();
+  paths.dedup();
+  Ok(paths)
+}
+
+fn remote_error(remote_name: &str, err: git2::Error) -> Error {
+  if err.code() == ErrorCode::Auth || err.class() == ErrorClass::Ssh {
+    return Error::Git(format!("Authentication failed for remote {}: {}", remote_name, err.message()));
+  }
+  Error::Git2(err)
+}
+
+/// The commit's summary, time and author with the author resolved through `mailmap`.
+fn commit_info(commit: &Commit, mailmap: &Mailmap) -> Result<CommitInfo, Error> {
+  let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).into_owned();
+  let author = commit.author_with_mailmap(mailmap)?;
+  let name = String::from_utf8_lossy(author.name_bytes()).into_owned();
+  let email = String::from_utf8_lossy(author.email_bytes()).into_owned();
+  Ok(CommitInfo::new(summary, name, email, commit.time().seconds()))
+}
+
+/// Formats the time in its own timezone like `2024-05-01 13:45:00 +0200`.
+fn format_signature_time(time: git2::Time) -> String {
+  let offset_minutes = i64::from(time.offset_minutes());
+  let local = time.seconds() + offset_minutes * 60;
+  let seconds_of_day = local.rem_euclid(86_400);
+  format!(
+    "{} {:02}:{:02}:{:02} {}{:02}{:02}",
+    format_date(local),
+    seconds_of_day / 3600,
+    seconds_of_day % 3600 / 60,
+    seconds_of_day % 60,
+    if offset_minutes < 0 { '-' } else { '+' },
+    offset_minutes.abs() / 60,
+    offset_minutes.abs() % 60
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+
+  use super::*;
+  use crate::test_support::{git_in, lock_env, Fixture, TempDir};
+
+  #[test]
+  fn deleting_an_unmerged_branch_is_an_error() {
+    let fixture = Fixture::new();
+    fixture.branch_with_commit("feature");
+    let repo = fixture.git2();
+    assert!(repo.delete_branch(&GitBranch::new(String::from("feature"))).is_err());
+    assert!(repo.branch_exists("feature"));
+  }
+
+  #[test]
+  fn deleting_a_missing_branch_is_an_error() {
+    let fixture = Fixture::new();
+    assert!(fixture.git2().delete_branch(&GitBranch::new(String::from("missing"))).is_err());
+  }
+
+  #[test]
+  fn checkout_refuses_to_overwrite_local_changes() {
+    let fixture = Fixture::new();
+    fixture.git(&["checkout", "-q", "-b", "feature"]);
+    fixture.commit("README.md", "feature\n", "Change the readme");
+    fixture.git(&["checkout", "-q", "main"]);
+    fixture.write("README.md", "local changes\n");
+    let repo = fixture.git2();
+    assert!(repo.checkout_branch_from_name("feature").is_err());
+    assert_eq!(fs::read_to_string(fixture.path.join("README.md")).unwrap(), "local changes\n");
+    assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+  }
+
+  #[test]
+  fn opening_a_subdirectory_finds_the_repository_around_it() {
+    let fixture = Fixture::new();
+    fixture.write("nested/dir/file.txt", "nested");
+    let repo = Git2Repo::from_path(&fixture.path.join("nested/dir")).unwrap();
+    assert_eq!(repo.workdir().unwrap().canonicalize().unwrap(), fixture.path);
+  }
+
+  #[test]
+  fn opening_a_directory_outside_a_repository_is_an_error() {
+    // The fixture's own directory holds the repository but isn't in one
+    let fixture = Fixture::new();
+    let result = Git2Repo::from_path(fixture.dir.path());
+    assert!(matches!(result, Err(Error::NotARepository(_))), "{:?}", result.err());
+  }
+
+  #[test]
+  fn git_dir_takes_precedence_over_discovery() {
+    let _env = lock_env();
+    let dir = TempDir::new();
+    git_in(dir.path(), &["init", "-q", "--bare", "bare.git"]);
+    env::set_var("GIT_DIR", dir.path().join("bare.git"));
+    let repo = Git2Repo::from_path(dir.path());
+    env::remove_var("GIT_DIR");
+    let repo = repo.unwrap();
+    assert_eq!(repo.path().canonicalize().unwrap(), dir.path().join("bare.git"));
+    assert!(repo.is_bare());
+  }
+
+  #[test]
+  fn loading_branches_in_parallel_matches_loading_them_one_by_one() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    for index in 0..10 {
+      fixture.git(&["branch", &format!("branch-{}", index)]);
+    }
+    fixture.git(&["push", "-q", "-u", "origin", "branch-3"]);
+    fixture.branch_with_commit("ahead");
+    fixture.git(&["checkout", "-q", "branch-7"]);
+    let repo = fixture.git2();
+    let branches: Vec<Branch> =
+      repo.repo.branches(Some(BranchType::Local)).unwrap().map(|branch| branch.unwrap().0).collect();
+    let serial: Vec<GitBranch> = repo.local_branches_iter().collect::<Result<_, _>>().unwrap();
+    assert_eq!(serial.len(), 12);
+    for threads in [1, 2, 3, 16] {
+      assert_eq!(repo.create_git_branches_in_parallel(&branches, threads).unwrap(), serial, "{} threads", threads);
+    }
+  }
+
+  #[test]
+  fn a_handle_opened_on_another_thread_lists_the_same_branches_with_the_same_settings() {
+    let fixture = Fixture::new();
+    fixture.branch_with_commit("feature");
+    let repo = fixture.git2().read_only(true);
+    let handle = repo.handle();
+    let (branches, read_only) = std::thread::spawn(move || {
+      let opened = handle.open().unwrap();
+      let read_only =
+        opened.delete_branch(&GitBranch::new(String::from("feature"))).is_err_and(|err| matches!(err, Error::ReadOnly));
+      (opened.local_branches().unwrap(), read_only)
+    })
+    .join()
+    .unwrap();
+    assert_eq!(branches, repo.local_branches().unwrap());
+    assert!(read_only);
+  }
+
+  #[test]
+  fn fetching_reports_the_objects_received_to_the_progress() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    let other = fixture.other_clone();
+    for i in 0..3 {
+      std::fs::write(other.join(format!("file-{}.txt", i)), format!("contents {}\n", i)).unwrap();
+      git_in(&other, &["add", "."]);
+      git_in(&other, &["commit", "-q", "-m", &format!("Commit {}", i)]);
+    }
+    git_in(&other, &["push", "-q", "origin", "main"]);
+    let progress = TransferProgress::default();
+    fixture.git2().progress(progress.clone()).fetch("origin").unwrap();
+    let transfer = progress.get().expect("No progress was reported");
+    assert!(transfer.total_objects > 0);
+    assert_eq!(transfer.objects, transfer.total_objects);
+    assert!(transfer.bytes > 0);
+  }
 }
@@ -1,98 +1,1378 @@
-use std::process::Command;
+use std::{
+  collections::{HashMap, HashSet},
+  env::current_dir,
+  io::{BufRead, BufReader, Read, Split},
+  iter,
+  path::{Path, PathBuf},
+  process::{Child, ChildStdout, Command, Stdio},
+};
 
-use regex::Regex;
 use tracing::{error, info};
 
 use crate::{
-  error::Error,
-  git::git_repo::{GitBranch, GitRemoteBranch, GitRepo, GitStash},
+  error::{with_context, Error},
+  git::{
+    git_repo::{
+      is_non_fast_forward, BranchCache, ChangeType, CheckoutPreview, CommitInfo, DiffSummary, Divergence, FileChange,
+      GitBranch, GitRemoteBranch, GitRepo, GitStash, GitTag, GraphCommit, MergeOutcome, Patch, PullOutcome,
+      RefSignature, ReflogEntry, RepoState, RepoSummary, ResetMode, SeenRefs, UpstreamStatus, WorktreeInfo,
+      DEFAULT_ABBREV_LENGTH,
+    },
+    protected::ProtectedBranches,
+    timing::timed,
+  },
 };
 
-pub struct GitCliRepo {}
+/// Lists each branch as `<* if HEAD>\0<full ref name>\0<full upstream ref name>\0<commit id>`. The upstream tracking
+/// counts are left out as git walks the history of every branch to work them out.
+const BRANCH_FORMAT: &str = "--format=%(HEAD)%00%(refname)%00%(upstream)%00%(objectname)";
+
+pub struct GitCliRepo {
+  /// Where git commands are run, the directory the repository was opened from.
+  command_dir: PathBuf,
+  git_dir: PathBuf,
+  workdir: Option<PathBuf>,
+  bare: bool,
+  branch_cache: BranchCache,
+  seen_refs: SeenRefs,
+  protected: ProtectedBranches,
+  dry_run: bool,
+  read_only: bool,
+  untracked_is_dirty: bool,
+  allow_detached_reset: bool,
+  abbrev_length: usize,
+  remote: Option<String>,
+}
 
 impl GitCliRepo {
   pub fn from_cwd() -> Result<GitCliRepo, Error> {
-    // TODO check that the user is in a repo and throw if not
-    Ok(GitCliRepo {})
+    let path_buf = current_dir().expect("Unable to get current working directory");
+    GitCliRepo::from_path(&path_buf)
+  }
+
+  /// Opens the repository containing `path`, failing if it isn't inside a git repository.
+  pub fn from_path(path: &Path) -> Result<GitCliRepo, Error> {
+    let git_dir = run_git_command(path, &["rev-parse", "--absolute-git-dir"]).map_err(|err| {
+      match err {
+        Error::Git(message) if message.contains("not a git repository") => {
+          Error::NotARepository(path.display().to_string())
+        },
+        err => err,
+      }
+    })?;
+    // Fails without a work tree, as in a bare repository
+    let workdir = run_git_command(path, &["rev-parse", "--show-toplevel"]).ok();
+    let bare = run_git_command(path, &["rev-parse", "--is-bare-repository"])?;
+    Ok(GitCliRepo {
+      command_dir: path.to_path_buf(),
+      git_dir: PathBuf::from(git_dir.trim()),
+      workdir: workdir.map(|workdir| PathBuf::from(workdir.trim())).filter(|workdir| !workdir.as_os_str().is_empty()),
+      bare: bare.trim() == "true",
+      branch_cache: BranchCache::default(),
+      seen_refs: SeenRefs::default(),
+      protected: ProtectedBranches::default(),
+      dry_run: false,
+      read_only: false,
+      untracked_is_dirty: false,
+      allow_detached_reset: false,
+      abbrev_length: DEFAULT_ABBREV_LENGTH,
+      remote: None,
+    })
+  }
+
+  /// Refuses to delete branches matching these patterns, on top of the default branch.
+  pub fn protected(mut self, protected: ProtectedBranches) -> Self {
+    self.protected = protected;
+    self
+  }
+
+  /// Only logs checkouts and branch creates, renames and deletes instead of making them.
+  pub fn dry_run(mut self, dry_run: bool) -> Self {
+    self.dry_run = dry_run;
+    self
+  }
+
+  /// Refuses everything that would change the repository or a remote with [`Error::ReadOnly`], where a dry run
+  /// pretends to make the change.
+  pub fn read_only(mut self, read_only: bool) -> Self {
+    self.read_only = read_only;
+    self
+  }
+
+  /// Counts untracked files as local changes in [`GitRepo::is_working_tree_dirty`], ignored files never count.
+  pub fn untracked_is_dirty(mut self, untracked_is_dirty: bool) -> Self {
+    self.untracked_is_dirty = untracked_is_dirty;
+    self
+  }
+
+  /// Network operations given no remote use this one rather than `origin`, unless the branch has its own.
+  pub fn remote(mut self, remote: Option<String>) -> Self {
+    self.remote = remote;
+    self
+  }
+
+  /// Lets [`GitRepo::reset_current`] move a detached HEAD rather than refusing.
+  pub fn allow_detached_reset(mut self, allow_detached_reset: bool) -> Self {
+    self.allow_detached_reset = allow_detached_reset;
+    self
+  }
+
+  /// Abbreviates commit ids to at least this many characters, longer when that is still ambiguous. Git never
+  /// abbreviates to fewer than 4.
+  pub fn abbrev_length(mut self, abbrev_length: usize) -> Self {
+    self.abbrev_length = abbrev_length.max(4);
+    self
+  }
+
+  fn run_git_command(&self, args: &[&str]) -> Result<String, Error> {
+    run_git_command(&self.command_dir, args)
+  }
+
+  /// Runs `git log` with `args` listing the commits it prints with their parents.
+  fn graph_commits(&self, args: &[&str]) -> Result<Vec<GraphCommit>, Error> {
+    let abbrev = format!("--abbrev={}", self.abbrev_length);
+    let mut log_args = vec!["log", &abbrev, "--format=%H%x00%h%x00%P%x00%s"];
+    log_args.extend_from_slice(args);
+    let res = self.run_git_command(&log_args)?;
+    res
+      .lines()
+      .map(|line| {
+        let mut parts = line.splitn(4, '\0');
+        let (Some(id), Some(short_id), Some(parent_ids), Some(summary)) =
+          (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+          error!("Failed to parse commit information: {}", line);
+          return Err(Error::Git(format!("Unable to parse the commit {}", line)));
+        };
+        let parent_ids = parent_ids.split_whitespace().map(String::from).collect();
+        Ok(GraphCommit::new(String::from(id), String::from(short_id), parent_ids, String::from(summary)))
+      })
+      .collect()
+  }
+
+  /// Whether the full ref name exists, with --quiet a missing ref prints nothing rather than failing.
+  fn ref_exists(&self, ref_name: &str) -> bool {
+    self
+      .run_git_command(&["rev-parse", "--verify", "--quiet", ref_name])
+      .is_ok_and(|commit_id| !commit_id.trim().is_empty())
+  }
+
+  /// `--short` at the configured abbreviation length for `rev-parse`.
+  fn short_flag(&self) -> String {
+    format!("--short={}", self.abbrev_length)
+  }
+
+  /// Runs a command that changes branches or HEAD, in a dry run it is only logged.
+  fn run_mutating_command(&self, args: &[&str]) -> Result<String, Error> {
+    if self.dry_run {
+      info!("Dry run, not running `git {}`", args.join(" "));
+      return Ok(String::new());
+    }
+    self.run_git_command(args)
+  }
+
+  /// Counts the commits unique to each side of `left...right`.
+  fn ahead_behind(&self, left: &str, right: &str) -> Result<(usize, usize), Error> {
+    let res = self.run_git_command(&["rev-list", "--left-right", "--count", &format!("{}...{}", left, right)])?;
+    let counts: Vec<usize> = res.split_whitespace().filter_map(|count| count.parse().ok()).collect();
+    let [ahead, behind] = counts[..] else {
+      return Err(Error::Git(format!("Unable to compare {} with {}", left, right)));
+    };
+    Ok((ahead, behind))
+  }
+
+  /// The full id of the commit a local branch points at.
+  fn branch_commit_id(&self, name: &str) -> Result<String, Error> {
+    let res = self.run_git_command(&["rev-parse", "--verify", &format!("refs/heads/{}^{{commit}}", name)])?;
+    Ok(res.trim().to_string())
+  }
+
+  fn list_branches(&self, extra_args: &[&str]) -> Result<Vec<GitBranch>, Error> {
+    let mut args = vec!["branch", "--list", BRANCH_FORMAT];
+    args.extend_from_slice(extra_args);
+    let res = self.run_git_command(&args)?;
+    let ref_commits = self.ref_commits()?;
+    Ok(res.lines().filter_map(|line| parse_branch_line(line, &ref_commits)).collect())
+  }
+
+  /// The commit each local and remote branch ref points at, by full ref name.
+  fn ref_commits(&self) -> Result<HashMap<String, String>, Error> {
+    let res =
+      self.run_git_command(&["for-each-ref", "--format=%(refname)%00%(objectname)", "refs/heads", "refs/remotes"])?;
+    Ok(
+      res
+        .lines()
+        .filter_map(|line| line.split_once('\0'))
+        .map(|(ref_name, commit_id)| (String::from(ref_name), String::from(commit_id)))
+        .collect(),
+    )
   }
 }
 
 impl GitRepo for GitCliRepo {
   fn local_branches(&self) -> Result<Vec<GitBranch>, Error> {
-    let res = run_git_command(&["branch", "--list", "-vv"])?;
+    with_context("Failed to list the local branches", || {
+      timed("local_branches", || self.branch_cache.get_or_load(|| self.local_branches_iter().collect()))
+    })
+  }
 
-    let branches: Vec<GitBranch> = res
-      .lines()
-      .map(|line| {
-        let trimmed = line.trim();
-        // A regex to capture the following git list outputs
-        // * git-cli-repo 911ec26 [origin/git-cli-repo] Linting
-        //   main         8fb5d9b [origin/main] Fix build
-        //   stash-list   6442450 [origin/stash-list: gone] Formatting
-        //   test         dbcf785 Updates
-        let re = Regex::new(
-          r"((?<head>\*)\s+)?(?<name>\S+)\s+(?<sha>[A-Fa-f0-9]+)\s+(\[(?<upstream>[^:|^\]]+)(?<gone>[:\sgone]+)?)?",
-        )
-        .unwrap();
-        let Some(captures) = re.captures(trimmed) else {
-          error!("Failed to capture git branch information for: {}", trimmed);
-          return GitBranch::new(String::from(trimmed));
+  fn local_branches_iter(&self) -> Box<dyn Iterator<Item = Result<GitBranch, Error>> + '_> {
+    let ref_commits = match self.ref_commits() {
+      Ok(ref_commits) => ref_commits,
+      Err(err) => return Box::new(iter::once(Err(err))),
+    };
+    let lines = match GitLines::spawn(&self.command_dir, &["branch", "--list", BRANCH_FORMAT]) {
+      Ok(lines) => lines,
+      Err(err) => return Box::new(iter::once(Err(err))),
+    };
+    Box::new(lines.filter_map(move |line| {
+      match line {
+        Ok(line) => parse_branch_line(&line, &ref_commits).map(Ok),
+        Err(err) => Some(Err(err)),
+      }
+    }))
+  }
+
+  fn refresh(&self) {
+    self.branch_cache.invalidate();
+  }
+
+  fn path(&self) -> &Path {
+    &self.git_dir
+  }
+
+  fn workdir(&self) -> Option<&Path> {
+    self.workdir.as_deref()
+  }
+
+  fn superproject_path(&self) -> Option<PathBuf> {
+    let res = self.run_git_command(&["rev-parse", "--show-superproject-working-tree"]).ok()?;
+    // Nothing is printed outside of a submodule
+    let path = res.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+  }
+
+  fn open_superproject(&self) -> Result<Box<dyn GitRepo>, Error> {
+    with_context("Failed to open the superproject", || {
+      let Some(path) = self.superproject_path() else {
+        return Err(Error::NotASubmodule);
+      };
+      info!("Opening the superproject at {}", path.display());
+      let superproject = GitCliRepo::from_path(&path)?
+        .protected(self.protected.clone())
+        .dry_run(self.dry_run)
+        .read_only(self.read_only)
+        .untracked_is_dirty(self.untracked_is_dirty)
+        .allow_detached_reset(self.allow_detached_reset)
+        .abbrev_length(self.abbrev_length)
+        .remote(self.remote.clone());
+      Ok(Box::new(superproject) as Box<dyn GitRepo>)
+    })
+  }
+
+  fn local_branches_sorted_by_date(&self) -> Result<Vec<GitBranch>, Error> {
+    with_context("Failed to list the local branches", || {
+      // The last sort key is the main one, so branches committed in the same second are in name order
+      self.list_branches(&["--sort=refname", "--sort=-committerdate"])
+    })
+  }
+
+  fn current_branch(&self) -> Result<Option<GitBranch>, Error> {
+    with_context("Failed to read the current branch", || {
+      let res = self.run_git_command(&["branch", "--show-current"])?;
+      let name = res.trim();
+      // An empty response means HEAD is detached
+      if name.is_empty() {
+        return Ok(None);
+      }
+      let current = self.list_branches(&[name])?.into_iter().next();
+      // An unborn branch is named by HEAD but isn't listed as it has no ref yet
+      if current.is_none() && self.head_short_id()?.is_none() {
+        return Ok(Some(GitBranch {
+          is_head: true,
+          name_is_lossy: name.contains(char::REPLACEMENT_CHARACTER),
+          is_unborn: true,
+          ..GitBranch::new(String::from(name))
+        }));
+      }
+      Ok(current)
+    })
+  }
+
+  fn summary(&self) -> Result<RepoSummary, Error> {
+    with_context("Failed to summarize the repository", || {
+      let res = self.run_git_command(&[
+        "for-each-ref",
+        "--format=%(HEAD)%00%(refname)%00%(symref)",
+        "refs/heads",
+        "refs/remotes",
+      ])?;
+      let mut summary = RepoSummary::default();
+      for line in res.lines() {
+        let mut parts = line.splitn(3, '\0');
+        let (Some(head), Some(ref_name), symref) = (parts.next(), parts.next(), parts.next().unwrap_or_default())
+        else {
+          continue;
         };
-        let is_head = captures.name("head").is_some();
-        let name = String::from(captures.name("name").unwrap().as_str());
-        let upstream = captures.name("upstream");
-        GitBranch {
-          name,
-          is_head,
-          upstream: upstream.map(|upstream_name| GitRemoteBranch::new(String::from(upstream_name.as_str()))),
+        if let Some(name) = ref_name.strip_prefix("refs/heads/") {
+          summary.local_branches += 1;
+          if head == "*" {
+            summary.current_branch = Some(String::from(name));
+          }
+        } else if symref.is_empty() {
+          summary.remote_branches += 1;
+        }
+      }
+      // An unborn branch has no ref to list, but HEAD still names it
+      if summary.current_branch.is_none() {
+        let name = self.run_git_command(&["branch", "--show-current"])?;
+        summary.current_branch = Some(name.trim()).filter(|name| !name.is_empty()).map(String::from);
+      }
+      summary.clean = !self.is_working_tree_dirty()?;
+      Ok(summary)
+    })
+  }
+
+  fn head_short_id(&self) -> Result<Option<String>, Error> {
+    with_context("Failed to read HEAD", || {
+      // With --quiet an unborn HEAD prints nothing rather than failing
+      let res = self.run_git_command(&["rev-parse", &self.short_flag(), "--verify", "--quiet", "HEAD"])?;
+      let short_id = res.trim();
+      Ok((!short_id.is_empty()).then(|| short_id.to_string()))
+    })
+  }
+
+  fn short_id(&self, commit_id: &str) -> Result<String, Error> {
+    with_context(format!("Failed to abbreviate {}", commit_id), || {
+      let commit = format!("{}^{{commit}}", commit_id);
+      let res = self.run_git_command(&["rev-parse", &self.short_flag(), "--verify", "--quiet", &commit])?;
+      let short_id = res.trim();
+      if short_id.is_empty() {
+        return Err(Error::Git(format!("{} is not a commit", commit_id)));
+      }
+      Ok(short_id.to_string())
+    })
+  }
+
+  fn resolve_commit(&self, hash: &str) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to find the commit {}", hash), || {
+      let commit = format!("{}^{{commit}}", hash);
+      match self.run_git_command(&["rev-parse", "--verify", &commit]) {
+        Ok(res) => Ok(Some(res.trim().to_string())),
+        // Only an ambiguous hash has an error line, it is followed by hints listing the candidates. A hash nothing has
+        // just fails with "Needed a single revision".
+        Err(Error::Git(stderr)) => {
+          match stderr.lines().find_map(|line| line.strip_prefix("error: ")) {
+            Some(message) => Err(Error::Git(message.to_string())),
+            None => Ok(None),
+          }
+        },
+        Err(err) => Err(err),
+      }
+    })
+  }
+
+  fn branch_exists(&self, name: &str) -> bool {
+    self.ref_exists(&format!("refs/heads/{}", name))
+  }
+
+  fn remote_branch_exists(&self, name: &str) -> bool {
+    self.ref_exists(&format!("refs/remotes/{}", name))
+  }
+
+  fn remotes(&self) -> Result<Vec<String>, Error> {
+    with_context("Failed to list the remotes", || {
+      let res = self.run_git_command(&["remote"])?;
+      let mut remotes: Vec<String> =
+        res.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect();
+      remotes.sort();
+      Ok(remotes)
+    })
+  }
+
+  fn configured_remote(&self) -> Option<&str> {
+    self.remote.as_deref()
+  }
+
+  fn branch_remote(&self, branch: &GitBranch) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to read the remote of {}", branch.name), || {
+      // A missing key exits with an error but prints nothing
+      let res = self.run_git_command(&["config", "--get", &format!("branch.{}.remote", branch.name)])?;
+      Ok(Some(String::from(res.trim())).filter(|remote| !remote.is_empty() && remote != "."))
+    })
+  }
+
+  fn remote_url(&self, remote: &str) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to read the URL of {}", remote), || {
+      let res = self.run_git_command(&["remote", "get-url", remote])?;
+      Ok(Some(String::from(res.trim())).filter(|url| !url.is_empty()))
+    })
+  }
+
+  fn remote_branches(&self) -> Result<Vec<GitRemoteBranch>, Error> {
+    with_context("Failed to list the remote branches", || {
+      let remotes = self.remotes()?;
+      let res = self.run_git_command(&["branch", "--list", "--remotes"])?;
+
+      let mut remote_branches: Vec<GitRemoteBranch> = res
+        .lines()
+        .map(|line| line.trim())
+        // Skip symbolic refs like `origin/HEAD -> origin/main`, they just point at another remote branch
+        .filter(|line| !line.is_empty() && !line.contains(" -> "))
+        .map(|line| GitRemoteBranch::on_remote(String::from(line), &remotes))
+        .collect();
+      remote_branches.sort_by(|a, b| a.name.cmp(&b.name));
+      remote_branches.dedup();
+      Ok(remote_branches)
+    })
+  }
+
+  fn remote_head(&self, remote_name: &str) -> Result<Option<GitRemoteBranch>, Error> {
+    with_context(format!("Failed to read the HEAD of {}", remote_name), || {
+      // With --quiet a missing or non-symbolic ref prints nothing rather than failing
+      let head_ref = format!("refs/remotes/{}/HEAD", remote_name);
+      let res = self.run_git_command(&["symbolic-ref", "--quiet", &head_ref])?;
+      let target = res.trim().strip_prefix("refs/remotes/");
+      Ok(target.map(|target| GitRemoteBranch { name: String::from(target), remote: Some(String::from(remote_name)) }))
+    })
+  }
+
+  fn branch_commit_info(&self, branch: &GitBranch) -> Result<CommitInfo, Error> {
+    with_context(format!("Failed to read the last commit of {}", branch.name), || {
+      // %aN and %aE are the author as the mailmap maps them
+      let res = self.run_git_command(&["log", "-1", "--format=%s%x00%aN%x00%aE%x00%ct", &branch.name, "--"])?;
+      let mut parts = res.trim_end().splitn(4, '\0');
+      let (Some(summary), Some(author), Some(email), Some(time)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+      else {
+        error!("Failed to parse commit information for branch {}: {}", branch.name, res);
+        return Err(Error::Git(format!("Unable to read the last commit of {}", branch.name)));
+      };
+      let time = time.parse::<i64>().map_err(|err| Error::Git(format!("Unable to parse commit time: {}", err)))?;
+      Ok(CommitInfo::new(String::from(summary), String::from(author), String::from(email), time))
+    })
+  }
+
+  fn branch_log(&self, branch: &GitBranch, limit: usize) -> Result<Vec<CommitInfo>, Error> {
+    with_context(format!("Failed to read the log of {}", branch.name), || {
+      let limit = limit.to_string();
+      let res = self.run_git_command(&[
+        "log",
+        "-n",
+        &limit,
+        "--date-order",
+        "--format=%s%x00%aN%x00%aE%x00%ct%x01",
+        &branch.name,
+        "--",
+      ])?;
+      // Each commit ends with \x01 so a newline in a field can't split a record
+      res
+        .split('\x01')
+        .map(|record| record.trim_start_matches('\n'))
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+          let mut parts = record.splitn(4, '\0');
+          let (Some(summary), Some(author), Some(email), Some(time)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+          else {
+            error!("Failed to parse commit information for branch {}: {}", branch.name, record);
+            return Err(Error::Git(format!("Unable to read the log of {}", branch.name)));
+          };
+          let time = time.parse::<i64>().map_err(|err| Error::Git(format!("Unable to parse commit time: {}", err)))?;
+          Ok(CommitInfo::new(String::from(summary), String::from(author), String::from(email), time))
+        })
+        .collect()
+    })
+  }
+
+  fn branch_graph(&self, branch: &GitBranch, limit: usize) -> Result<Vec<GraphCommit>, Error> {
+    with_context(format!("Failed to read the history of {}", branch.name), || {
+      let limit = limit.to_string();
+      self
+        .graph_commits(&["-n", &limit, "--topo-order", &branch.name, "--"])
+        .map_err(|_| Error::Git(format!("Unable to read the history of {}", branch.name)))
+    })
+  }
+
+  fn branches_containing(&self, commit_id: &str) -> Result<Vec<GitBranch>, Error> {
+    with_context(format!("Failed to find the branches containing {}", commit_id), || {
+      self.list_branches(&["--contains", commit_id])
+    })
+  }
+
+  fn search_commits(&self, query: &str, limit: usize) -> Result<Vec<GraphCommit>, Error> {
+    with_context(format!("Failed to search the commits for {}", query), || {
+      let limit = limit.to_string();
+      let grep = format!("--grep={}", query);
+      self
+        .graph_commits(&["-n", &limit, "--branches", "--regexp-ignore-case", "--fixed-strings", &grep, "--"])
+        .map_err(|_| Error::Git(format!("Unable to search the commits for {}", query)))
+    })
+  }
+
+  fn commit_details(&self, branch: &GitBranch, with_diff: bool) -> Result<String, Error> {
+    with_context(format!("Failed to read the latest commit of {}", branch.name), || {
+      let patch = if with_diff { "--patch" } else { "--no-patch" };
+      self.run_git_command(&["show", "--no-color", "--format=fuller", patch, &branch.name, "--"])
+    })
+  }
+
+  fn reflog_entries(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, Error> {
+    with_context(format!("Failed to read the reflog of {}", ref_name), || {
+      // git fails for a ref that doesn't exist, match git2 which reads that as an empty reflog
+      if self.run_git_command(&["rev-parse", "--verify", "--quiet", ref_name])?.trim().is_empty() {
+        return Ok(Vec::new());
+      }
+      // With --date=unix the reflog selector %gd is `<ref>@{<time>}`
+      let res = self.run_git_command(&[
+        "log",
+        "--walk-reflogs",
+        "--date=unix",
+        &format!("--abbrev={}", self.abbrev_length),
+        "--format=%H%x00%h%x00%gs%x00%gd%x01",
+        ref_name,
+        "--",
+      ])?;
+      res
+        .split('\x01')
+        .map(|record| record.trim_start_matches('\n'))
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+          let mut parts = record.splitn(4, '\0');
+          let (Some(commit_id), Some(short_id), Some(message), Some(selector)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+          else {
+            error!("Failed to parse reflog entry of {}: {}", ref_name, record);
+            return Err(Error::Git(format!("Unable to read the reflog of {}", ref_name)));
+          };
+          let time = selector
+            .rsplit_once("@{")
+            .and_then(|(_ref, time)| time.strip_suffix('}'))
+            .and_then(|time| time.parse::<i64>().ok())
+            .ok_or_else(|| Error::Git(format!("Unable to parse reflog time: {}", selector)))?;
+          Ok(ReflogEntry::new(String::from(commit_id), String::from(short_id), String::from(message), time))
+        })
+        .collect()
+    })
+  }
+
+  fn fetch(&self, remote_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    let remote_name = self.remote_or_default(remote_name)?;
+    with_context(format!("Failed to fetch from {}", remote_name), || {
+      timed("fetch", || {
+        self.refresh();
+        self.run_git_command(&["fetch", &remote_name])?;
+        Ok(())
+      })
+    })
+  }
+
+  fn push_branch(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to push {} to {}", branch.name, remote_name), || {
+      let remote_name = match remote_name {
+        "" => self.default_remote(Some(branch))?,
+        remote_name => String::from(remote_name),
+      };
+      let remote_name = remote_name.as_str();
+      timed("push_branch", || {
+        self.refresh();
+        let mut args = vec!["push"];
+        if branch.upstream.is_none() {
+          args.push("--set-upstream");
+        }
+        args.extend_from_slice(&[remote_name, &branch.name]);
+        match self.run_git_command(&args) {
+          Err(Error::Git(err)) if is_non_fast_forward(&err) => Err(self.non_fast_forward_error(branch, remote_name)),
+          Err(err) => Err(err),
+          Ok(_) => Ok(()),
+        }
+      })
+    })
+  }
+
+  fn push_branch_force_with_lease(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to force push {} to {}", branch.name, remote_name), || {
+      let remote_name = match remote_name {
+        "" => self.default_remote(Some(branch))?,
+        remote_name => String::from(remote_name),
+      };
+      let remote_name = remote_name.as_str();
+      timed("push_branch_force_with_lease", || {
+        self.refresh();
+        // The lease is given explicitly so it holds without an upstream, an empty one means the remote mustn't have it
+        let tracking = format!("refs/remotes/{}/{}", remote_name, branch.name);
+        let expected = self.run_git_command(&["rev-parse", "--verify", "--quiet", &tracking]).unwrap_or_default();
+        let lease = format!("--force-with-lease=refs/heads/{}:{}", branch.name, expected.trim());
+        let mut args = vec!["push", lease.as_str()];
+        if branch.upstream.is_none() {
+          args.push("--set-upstream");
+        }
+        args.extend_from_slice(&[remote_name, &branch.name]);
+        match self.run_git_command(&args) {
+          Err(Error::Git(err)) if err.contains("stale info") => {
+            Err(Error::StaleLease(branch.name.clone(), String::from(remote_name)))
+          },
+          Err(err) => Err(err),
+          Ok(_) => Ok(()),
         }
       })
-      .collect();
+    })
+  }
+
+  fn pull_current(&self, remote_name: &str) -> Result<PullOutcome, Error> {
+    self.ensure_writable()?;
+    let remote_name = self.remote_or_default(remote_name)?;
+    with_context(format!("Failed to pull from {}", remote_name), || {
+      timed("pull_current", || {
+        self.refresh();
+        if self.is_working_tree_dirty()? {
+          return Err(Error::Git("Cannot pull with uncommitted changes, commit or stash them first".to_string()));
+        }
+        let Some(current) = self.current_branch()? else {
+          return Err(Error::Git("Cannot pull while HEAD is detached".to_string()));
+        };
+        match current.upstream_status {
+          UpstreamStatus::None => {
+            return Err(Error::Git(format!("The branch {} has no upstream to pull from", current.name)));
+          },
+          UpstreamStatus::Gone => {
+            return Err(Error::Git(format!("The upstream of {} no longer exists on the remote", current.name)));
+          },
+          UpstreamStatus::Tracked => {},
+        }
+        self.fetch(&remote_name)?;
+
+        let (ahead, behind) = self.ahead_behind("HEAD", "@{upstream}")?;
+        if behind == 0 {
+          return Ok(PullOutcome::UpToDate);
+        }
+        if ahead > 0 {
+          return Ok(PullOutcome::MergeRequired);
+        }
+        self.run_git_command(&["merge", "--ff-only", "@{upstream}"])?;
+        Ok(PullOutcome::FastForwarded)
+      })
+    })
+  }
+
+  fn merge_branch(&self, branch: &GitBranch) -> Result<MergeOutcome, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to merge {}", branch.name), || {
+      self.refresh();
+      if self.is_working_tree_dirty()? {
+        return Err(Error::Git("Cannot merge with uncommitted changes, commit or stash them first".to_string()));
+      }
+      let (ahead, behind) = self.ahead_behind("HEAD", &branch.name)?;
+      if behind == 0 {
+        return Ok(MergeOutcome::UpToDate);
+      }
+      if ahead == 0 {
+        self.run_git_command(&["merge", "--ff-only", &branch.name])?;
+        return Ok(MergeOutcome::FastForwarded);
+      }
 
-    Ok(branches)
+      // A conflicted merge exits with an error, so check for conflicts before reporting it
+      let merge_result = self.run_git_command(&["merge", "--no-edit", &branch.name]);
+      let conflicts = self.run_git_command(&["diff", "--name-only", "--diff-filter=U"])?;
+      let conflicted_paths: Vec<String> = conflicts.lines().map(String::from).collect();
+      if !conflicted_paths.is_empty() {
+        return Ok(MergeOutcome::Conflicts(conflicted_paths));
+      }
+      merge_result?;
+      Ok(MergeOutcome::Merged)
+    })
+  }
+
+  fn cherry_pick(&self, commit_id: &str) -> Result<MergeOutcome, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to cherry-pick {}", commit_id), || {
+      self.refresh();
+      if self.is_working_tree_dirty()? {
+        return Err(Error::Git("Cannot cherry-pick with uncommitted changes, commit or stash them first".to_string()));
+      }
+      let second_parent = self.run_git_command(&["rev-parse", "--verify", "--quiet", &format!("{}^2", commit_id)])?;
+      if !second_parent.trim().is_empty() {
+        return Err(Error::Git(format!("{} is a merge commit and can't be cherry-picked", commit_id)));
+      }
+
+      // Like a merge a conflicted cherry-pick exits with an error, so check for conflicts before reporting it
+      let pick_result = self.run_mutating_command(&["cherry-pick", commit_id]);
+      let conflicts = self.run_git_command(&["diff", "--name-only", "--diff-filter=U"])?;
+      let conflicted_paths: Vec<String> = conflicts.lines().map(String::from).collect();
+      if !conflicted_paths.is_empty() {
+        return Ok(MergeOutcome::Conflicts(conflicted_paths));
+      }
+      // A pick that changes nothing stops without conflicts, there is nothing to keep
+      if pick_result.is_err() && self.repo_state() == RepoState::CherryPick {
+        self.run_git_command(&["cherry-pick", "--abort"])?;
+        return Ok(MergeOutcome::UpToDate);
+      }
+      pick_result?;
+      Ok(MergeOutcome::Merged)
+    })
+  }
+
+  fn repo_state(&self) -> RepoState {
+    state_from_git_dir(&self.git_dir)
+  }
+
+  fn abort_operation(&self) -> Result<(), Error> {
+    self.ensure_writable()?;
+    let state = self.repo_state();
+    with_context(format!("Failed to abort the {}", state), || {
+      self.refresh();
+      let args: &[&str] = match state {
+        RepoState::Clean => return Err(Error::NoOperationInProgress),
+        RepoState::Merge => &["merge", "--abort"],
+        RepoState::Rebase => &["rebase", "--abort"],
+        RepoState::ApplyMailbox => &["am", "--abort"],
+        RepoState::CherryPick => &["cherry-pick", "--abort"],
+        RepoState::Revert => &["revert", "--abort"],
+        RepoState::Bisect => &["bisect", "reset"],
+      };
+      self.run_mutating_command(args)?;
+      Ok(())
+    })
+  }
+
+  fn prune_remote(&self, remote_name: &str) -> Result<Vec<String>, Error> {
+    self.ensure_writable()?;
+    let remote_name = self.remote_or_default(remote_name)?;
+    with_context(format!("Failed to prune {}", remote_name), || {
+      // Pruning deletes remote tracking branches, which a dry run leaves alone
+      if self.dry_run {
+        info!("Dry run, not pruning {}", remote_name);
+        return Ok(Vec::new());
+      }
+      timed("prune_remote", || {
+        self.refresh();
+        let tracking_prefix = format!("{}/", remote_name);
+        let before = self.remote_branches()?;
+        self.run_git_command(&["fetch", "--prune", &remote_name])?;
+        let after = self.remote_branches()?;
+        let pruned: Vec<String> = before
+          .into_iter()
+          .filter(|branch| branch.name.starts_with(&tracking_prefix) && !after.contains(branch))
+          .map(|branch| branch.name)
+          .collect();
+        Ok(pruned)
+      })
+    })
+  }
+
+  fn worktrees(&self) -> Result<Vec<WorktreeInfo>, Error> {
+    with_context("Failed to list the worktrees", || {
+      let res = self.run_git_command(&["worktree", "list", "--porcelain"])?;
+      // Each worktree is a block of `worktree <path>`, `HEAD <sha>` and `branch <ref>` or `detached` lines,
+      // the first block is always the main worktree
+      let worktrees: Vec<WorktreeInfo> = res
+        .split("\n\n")
+        .skip(1)
+        .filter_map(|block| {
+          let path = PathBuf::from(block.lines().find_map(|line| line.strip_prefix("worktree "))?);
+          let name = path.file_name()?.to_string_lossy().into_owned();
+          let branch = block
+            .lines()
+            .find_map(|line| line.strip_prefix("branch "))
+            .map(|branch_ref| String::from(branch_ref.strip_prefix("refs/heads/").unwrap_or(branch_ref)));
+          Some(WorktreeInfo::new(name, path, branch))
+        })
+        .collect();
+      Ok(worktrees)
+    })
+  }
+
+  fn tags(&self) -> Result<Vec<GitTag>, Error> {
+    with_context("Failed to list the tags", || {
+      // Messages can span multiple lines so records end with \x01 and fields are separated by \x00
+      let res = self.run_git_command(&[
+        "for-each-ref",
+        "refs/tags",
+        "--format=%(refname:short)%00%(objecttype)%00%(objectname)%00%(*objectname)%00%(contents)%01",
+      ])?;
+      let tags: Vec<GitTag> = res
+        .split("\x01\n")
+        .filter_map(|record| {
+          let fields: Vec<&str> = record.split('\0').collect();
+          let [name, object_type, object_name, peeled_name, contents] = fields[..] else {
+            return None;
+          };
+          if object_type == "tag" {
+            return Some(GitTag::new(String::from(name), String::from(peeled_name), Some(contents.trim().to_string())));
+          }
+          Some(GitTag::new(String::from(name), String::from(object_name), None))
+        })
+        .collect();
+      Ok(tags)
+    })
+  }
+
+  fn create_tag(&self, name: &str, target: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to create the tag {}", name), || {
+      self.run_mutating_command(&["tag", name, &format!("{}^{{commit}}", target)])?;
+      Ok(())
+    })
+  }
+
+  fn delete_tag(&self, name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete the tag {}", name), || {
+      self.run_mutating_command(&["tag", "--delete", name])?;
+      Ok(())
+    })
+  }
+
+  fn diff_branches(&self, from: &GitBranch, to: &GitBranch) -> Result<DiffSummary, Error> {
+    with_context(format!("Failed to compare {} with {}", from.name, to.name), || {
+      let name_status = self.run_git_command(&["diff", "--no-renames", "--name-status", &from.name, &to.name, "--"])?;
+      let files: Vec<FileChange> = name_status
+        .lines()
+        .filter_map(|line| {
+          let (status, path) = line.split_once('\t')?;
+          let change_type = match status {
+            "A" => ChangeType::Added,
+            "D" => ChangeType::Deleted,
+            _ => ChangeType::Modified,
+          };
+          Some(FileChange::new(String::from(path), change_type))
+        })
+        .collect();
+
+      // Each line is `<insertions>\t<deletions>\t<path>`, binary files report `-` for both counts
+      let num_stat = self.run_git_command(&["diff", "--no-renames", "--numstat", &from.name, &to.name, "--"])?;
+      let (insertions, deletions) = num_stat.lines().fold((0, 0), |(insertions, deletions), line| {
+        let mut counts = line.split('\t').map(|count| count.parse::<usize>().unwrap_or(0));
+        (insertions + counts.next().unwrap_or(0), deletions + counts.next().unwrap_or(0))
+      });
+      Ok(DiffSummary { files_changed: files.len(), insertions, deletions, files })
+    })
+  }
+
+  fn diff_with_head(&self, branch: &GitBranch, limit: usize) -> Result<Patch, Error> {
+    with_context(format!("Failed to compare {} with HEAD", branch.name), || {
+      let branch_ref = format!("refs/heads/{}", branch.name);
+      let res =
+        self.run_git_command(&["diff", "--no-color", "--no-ext-diff", "--no-renames", "HEAD", &branch_ref, "--"])?;
+      let mut lines = res.lines();
+      let kept: Vec<String> = lines.by_ref().take(limit).map(String::from).collect();
+      Ok(Patch { lines: kept, truncated: lines.next().is_some() })
+    })
   }
 
   fn stashes(&mut self) -> Result<Vec<GitStash>, Error> {
-    let res = run_git_command(&["branch", "--list"])?;
+    with_context("Failed to list the stashes", || {
+      let res = self.run_git_command(&["stash", "list", "--format=%gs%x00%H"])?;
 
-    let stashes: Vec<GitStash> = res
-      .lines()
-      .enumerate()
-      .map(|(index, line)| GitStash::new(index, String::from(line.trim()), String::new()))
-      .collect();
+      let stashes: Vec<GitStash> = res
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+          let (message, stash_id) = line.split_once('\0')?;
+          Some(GitStash::new(index, String::from(message), String::from(stash_id)))
+        })
+        .collect();
+
+      Ok(stashes)
+    })
+  }
+
+  fn stash_save(&mut self, message: &str) -> Result<String, Error> {
+    self.ensure_writable()?;
+    with_context("Failed to stash the working tree", || {
+      self.refresh();
+      let res = self.run_git_command(&["stash", "push", "--message", message])?;
+      if res.contains("No local changes to save") {
+        return Err(Error::Git("There are no local changes to stash".to_string()));
+      }
+      let stash_id = self.run_git_command(&["rev-parse", "stash@{0}"])?;
+      Ok(String::from(stash_id.trim()))
+    })
+  }
+
+  fn stash_pop(&mut self, index: usize) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to pop stash {}", index), || {
+      self.refresh();
+      let pop_result = self.run_git_command(&["stash", "pop", &format!("stash@{{{}}}", index)]);
+      // Git keeps a stash that conflicted, but only says so on stdout
+      let conflicts = self.run_git_command(&["diff", "--name-only", "--diff-filter=U"])?;
+      let conflicted_paths: Vec<String> = conflicts.lines().map(String::from).collect();
+      if !conflicted_paths.is_empty() {
+        return Err(Error::Git(format!("The stash conflicts in {} and has been kept", conflicted_paths.join(", "))));
+      }
+      pop_result?;
+      Ok(())
+    })
+  }
 
-    Ok(stashes)
+  fn branch_from_stash(&mut self, index: usize, new_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to create {} from stash {}", new_name, index), || {
+      self.refresh();
+      if !self.validate_branch_name(new_name)? {
+        return Err(Error::Git(format!("{} is not a valid branch name", new_name)));
+      }
+      if self.branch_exists(new_name) {
+        return Err(Error::Git(format!("A branch named {} already exists", new_name)));
+      }
+      if self.is_working_tree_dirty()? {
+        return Err(Error::Git("Cannot create a branch from a stash with local changes".to_string()));
+      }
+      match self.run_mutating_command(&["stash", "branch", new_name, &format!("stash@{{{}}}", index)]) {
+        // Git creates and checks out the branch before applying, so it only exists after a failure if applying failed
+        Err(Error::Git(err)) if self.branch_exists(new_name) => {
+          error!("Failed to apply stash {} to {}: {}", index, new_name, err);
+          let reason = err.lines().filter(|line| !line.starts_with("Switched to")).collect::<Vec<_>>().join(" ");
+          Err(Error::StashNotApplied(String::from(new_name), reason))
+        },
+        Err(err) => Err(err),
+        Ok(_) => Ok(()),
+      }
+    })
   }
 
   fn checkout_branch_from_name(&self, branch_name: &str) -> Result<(), Error> {
-    run_git_command(&["checkout", branch_name])?;
-    Ok(())
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to check out {}", branch_name), || {
+      timed("checkout_branch_from_name", || {
+        self.refresh();
+        // The `--` makes git take the name as a branch only, without it a file of the same name is restored instead
+        match self.run_mutating_command(&["checkout", branch_name, "--"]) {
+          Err(Error::Git(err)) if err.contains("invalid reference") => {
+            Err(Error::Git(format!("There is no branch named {}", branch_name)))
+          },
+          Err(Error::Git(err)) => {
+            let paths = overwritten_paths(&err);
+            if paths.is_empty() {
+              return Err(Error::Git(err));
+            }
+            Err(Error::CheckoutBlocked(String::from(branch_name), paths))
+          },
+          result => result.map(|_| ()),
+        }
+      })
+    })
   }
 
   fn checkout_branch(&self, branch: &GitBranch) -> Result<(), Error> {
     self.checkout_branch_from_name(&branch.name)
   }
 
+  fn force_checkout_branch(&self, branch: &GitBranch) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to check out {}", branch.name), || {
+      timed("force_checkout_branch", || {
+        self.refresh();
+        self.run_mutating_command(&["checkout", "--force", &branch.name, "--"]).map(|_| ())
+      })
+    })
+  }
+
+  fn checkout_detached(&self, target: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to check out {}", target), || {
+      timed("checkout_detached", || {
+        self.refresh();
+        if self.is_working_tree_dirty()? {
+          return Err(Error::Git("Checkout would overwrite local changes".to_string()));
+        }
+        self.run_mutating_command(&["checkout", "--detach", target])?;
+        Ok(())
+      })
+    })
+  }
+
+  fn checkout_previous(&self) -> Result<(), Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context("Failed to check out the previous branch", || {
+      // Both print nothing with --quiet when there is no previous checkout
+      let previous_ref =
+        self.run_git_command(&["rev-parse", "--verify", "--quiet", "--symbolic-full-name", "@{-1}"])?;
+      if let Some(branch_name) = previous_ref.trim().strip_prefix("refs/heads/") {
+        return self.checkout_branch_from_name(branch_name);
+      }
+      let previous_commit = self.run_git_command(&["rev-parse", "--verify", "--quiet", "@{-1}"])?;
+      if previous_commit.trim().is_empty() {
+        return Err(Error::NoPreviousBranch);
+      }
+      self.checkout_detached(previous_commit.trim())
+    })
+  }
+
+  fn checkout_remote_branch(&self, remote: &GitRemoteBranch) -> Result<GitBranch, Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to check out {}", remote.name), || {
+      timed("checkout_remote_branch", || {
+        self.refresh();
+        let Some((_remote_name, local_name)) = remote.split() else {
+          return Err(Error::Git(format!("Unable to determine a local name for {}", remote.name)));
+        };
+        if self.branch_exists(local_name) {
+          return Err(Error::Git(format!("A branch named {} already exists", local_name)));
+        }
+        self.run_mutating_command(&["checkout", "--track", &remote.name])?;
+        let commit_id = self.branch_commit_id(local_name).ok();
+        Ok(GitBranch {
+          name: String::from(local_name),
+          is_head: true,
+          upstream: Some(remote.clone()),
+          upstream_status: UpstreamStatus::Tracked,
+          ahead_behind: Some((0, 0)),
+          upstream_commit_id: commit_id.clone(),
+          commit_id,
+          name_is_lossy: false,
+          is_unborn: false,
+        })
+      })
+    })
+  }
+
+  fn reset_current(&self, target: &str, mode: ResetMode) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to reset to {}", target), || {
+      self.refresh();
+      if !self.allow_detached_reset && self.current_branch()?.is_none() {
+        error!("Attempted to reset a detached HEAD");
+        return Err(Error::DetachedHead);
+      }
+      let commit = self.run_git_command(&["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", target)])?;
+      if commit.trim().is_empty() {
+        return Err(Error::Git(format!("{} is not a commit", target)));
+      }
+      self.run_mutating_command(&["reset", &format!("--{}", mode), "--quiet", commit.trim(), "--"])?;
+      Ok(())
+    })
+  }
+
+  fn move_branch(&self, branch: &GitBranch, target: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to move {} to {}", branch.name, target), || {
+      self.ensure_not_checked_out(branch)?;
+      self.refresh();
+      if !self.branch_exists(&branch.name) {
+        return Err(Error::Git(format!("There is no branch named {}", branch.name)));
+      }
+      let commit = self.run_git_command(&["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", target)])?;
+      if commit.trim().is_empty() {
+        return Err(Error::Git(format!("{} is not a commit", target)));
+      }
+      // update-ref rather than `branch -f`, which would set up tracking when the target is a remote branch
+      let reference = format!("refs/heads/{}", branch.name);
+      let message = format!("branch: moved to {}", target);
+      self.run_mutating_command(&["update-ref", "-m", &message, &reference, commit.trim()])?;
+      Ok(())
+    })
+  }
+
+  fn fast_forward_branch(&self, branch: &GitBranch) -> Result<(), Error> {
+    with_context(format!("Failed to fast-forward {}", branch.name), || {
+      let Some(branch) = self.fast_forward_check(branch)? else {
+        return Ok(());
+      };
+      // The check filled the branch cache, which the move makes stale
+      self.refresh();
+      let (Some(commit_id), Some(upstream_commit_id)) = (&branch.commit_id, &branch.upstream_commit_id) else {
+        return Err(Error::NoUpstream(branch.name));
+      };
+      if branch.is_head {
+        // Like a pull this refuses to overwrite untracked files the upstream adds
+        self.run_mutating_command(&["merge", "--ff-only", upstream_commit_id])?;
+        return Ok(());
+      }
+      // The old value makes the update fail if the branch moved since it was checked
+      let reference = format!("refs/heads/{}", branch.name);
+      let message = format!("fast-forward to {}", upstream_commit_id);
+      self.run_mutating_command(&["update-ref", "-m", &message, &reference, upstream_commit_id, commit_id])?;
+      Ok(())
+    })
+  }
+
+  fn is_working_tree_dirty(&self) -> Result<bool, Error> {
+    with_context("Failed to read the working tree status", || {
+      if self.bare {
+        return Ok(false);
+      }
+      let untracked_files = if self.untracked_is_dirty { "--untracked-files=normal" } else { "--untracked-files=no" };
+      let res = self.run_git_command(&["status", "--porcelain", "--ignored=no", untracked_files])?;
+      Ok(!res.trim().is_empty())
+    })
+  }
+
+  /// Git has no dry run for checkouts, so a changed file is taken to block the checkout when the branch changes it too,
+  /// as git would refuse unless the changes happen to match the branch.
+  fn checkout_preview(&self, branch: &GitBranch) -> Result<CheckoutPreview, Error> {
+    self.ensure_not_bare()?;
+    with_context(format!("Failed to preview checking out {}", branch.name), || {
+      let branch_ref = format!("refs/heads/{}", branch.name);
+      let changed = self.run_git_command(&["diff", "--name-only", "--no-renames", "HEAD", &branch_ref, "--"])?;
+      let changed: HashSet<&str> = changed.lines().collect();
+      let dirty = self.run_git_command(&["diff", "--name-only", "--no-renames", "HEAD", "--"])?;
+      let untracked = self.run_git_command(&["ls-files", "--others", "--exclude-standard"])?;
+      let (blocking, carried): (Vec<&str>, Vec<&str>) = dirty.lines().partition(|path| changed.contains(path));
+      let mut blocking: Vec<String> = blocking.into_iter().map(String::from).collect();
+      blocking.extend(untracked.lines().filter(|path| changed.contains(path)).map(String::from));
+      blocking.sort();
+      Ok(CheckoutPreview { carried: carried.into_iter().map(String::from).collect(), blocking })
+    })
+  }
+
   fn validate_branch_name(&self, name: &str) -> Result<bool, Error> {
-    let res = run_git_command(&["check-ref-format", "--branch", name]);
-    Ok(res.is_ok())
+    with_context(format!("Failed to validate the branch name {}", name), || {
+      let res = self.run_git_command(&["check-ref-format", "--branch", name]);
+      Ok(res.is_ok())
+    })
   }
 
   fn create_branch(&self, to_create: &GitBranch) -> Result<(), Error> {
-    run_git_command(&["checkout", "-b", &to_create.name])?;
-    Ok(())
+    self.ensure_writable()?;
+    with_context(format!("Failed to create {}", to_create.name), || {
+      self.refresh();
+      if let Some(current) = self.current_branch()?.filter(|current| current.is_unborn) {
+        error!("Attempted to create branch {} before the first commit", to_create.name);
+        return Err(Error::UnbornBranch(current.name));
+      }
+      self.run_mutating_command(&["branch", "--", &to_create.name])?;
+      Ok(())
+    })
   }
 
-  fn delete_branch(&self, to_delete: &GitBranch) -> Result<(), Error> {
-    run_git_command(&["branch", "-D", &to_delete.name])?;
-    Ok(())
+  fn create_branch_from(&self, new_name: &str, base: &GitBranch) -> Result<GitBranch, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to create {} from {}", new_name, base.name), || {
+      self.refresh();
+      if !self.validate_branch_name(new_name)? {
+        return Err(Error::Git(format!("{} is not a valid branch name", new_name)));
+      }
+      self.run_mutating_command(&["branch", new_name, &base.name])?;
+      Ok(GitBranch::new(String::from(new_name)))
+    })
+  }
+
+  fn rename_branch(&self, branch: &GitBranch, new_name: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to rename {} to {}", branch.name, new_name), || {
+      self.refresh();
+      if !self.validate_branch_name(new_name)? {
+        return Err(Error::Git(format!("{} is not a valid branch name", new_name)));
+      }
+      self.run_mutating_command(&["branch", "-m", &branch.name, new_name])?;
+      Ok(())
+    })
+  }
+
+  fn set_upstream(&self, branch: &GitBranch, remote_branch: Option<&GitRemoteBranch>) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to set the upstream of {}", branch.name), || {
+      self.refresh();
+      let Some(remote_branch) = remote_branch else {
+        if branch.upstream.is_some() {
+          self.run_mutating_command(&["branch", "--unset-upstream", &branch.name])?;
+        }
+        return Ok(());
+      };
+
+      let existing = self.run_git_command(&["branch", "--list", "--remotes", &remote_branch.name])?;
+      if existing.trim().is_empty() {
+        return Err(Error::Git(format!("The remote branch {} does not exist", remote_branch.name)));
+      }
+      if self.dry_run {
+        info!("Dry run, not setting the upstream of {} to {}", branch.name, remote_branch.name);
+        return Ok(());
+      }
+      self.run_git_command(&["branch", &format!("--set-upstream-to={}", remote_branch.name), &branch.name])?;
+      Ok(())
+    })
+  }
+
+  fn branch_description(&self, branch: &GitBranch) -> Result<Option<String>, Error> {
+    with_context(format!("Failed to read the description of {}", branch.name), || {
+      // A missing key exits with an error but prints nothing
+      let res = self.run_git_command(&["config", "--get", &format!("branch.{}.description", branch.name)])?;
+      Ok(Some(res.trim().to_string()).filter(|description| !description.is_empty()))
+    })
+  }
+
+  fn set_branch_description(&self, branch: &GitBranch, description: Option<&str>) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to set the description of {}", branch.name), || {
+      let key = format!("branch.{}.description", branch.name);
+      match description {
+        Some(description) => self.run_mutating_command(&["config", &key, description])?,
+        // Unsetting a missing key fails quietly, so there is nothing to check first
+        None => self.run_mutating_command(&["config", "--unset", &key])?,
+      };
+      Ok(())
+    })
+  }
+
+  fn is_merged(&self, branch: &GitBranch) -> Result<bool, Error> {
+    with_context(format!("Failed to check whether {} is merged", branch.name), || {
+      let res = self.run_git_command(&["branch", "--list", "--merged", "HEAD", &branch.name])?;
+      Ok(!res.trim().is_empty())
+    })
+  }
+
+  fn upstream_ahead_behind(&self, branch: &GitBranch) -> Result<Option<(usize, usize)>, Error> {
+    with_context(format!("Failed to compare {} with its upstream", branch.name), || {
+      let (Some(commit_id), Some(upstream_commit_id)) = (&branch.commit_id, &branch.upstream_commit_id) else {
+        return Ok(None);
+      };
+      Ok(Some(self.ahead_behind(commit_id, upstream_commit_id)?))
+    })
+  }
+
+  fn diffstat_vs_upstream(&self, branch: &GitBranch) -> Result<Option<(usize, usize)>, Error> {
+    with_context(format!("Failed to diff {} with its upstream", branch.name), || {
+      let (Some(commit_id), Some(upstream_commit_id)) = (&branch.commit_id, &branch.upstream_commit_id) else {
+        return Ok(None);
+      };
+      let res = self.run_git_command(&["diff", "--numstat", upstream_commit_id, commit_id])?;
+      // Binary files are listed with `-` for both counts, git2 doesn't count them either
+      let counts = res.lines().filter_map(|line| {
+        let mut parts = line.split('\t');
+        Some((parts.next()?.parse::<usize>().ok()?, parts.next()?.parse::<usize>().ok()?))
+      });
+      Ok(Some(
+        counts.fold((0, 0), |(insertions, deletions), (added, removed)| (insertions + added, deletions + removed)),
+      ))
+    })
+  }
+
+  fn divergence(&self, branch: &GitBranch, base: &GitBranch) -> Result<Divergence, Error> {
+    with_context(format!("Failed to compare {} with {}", branch.name, base.name), || {
+      // Without a common commit merge-base prints nothing and fails without saying why
+      let merge_base = self.run_git_command(&["merge-base", &branch.name, &base.name])?;
+      let merge_base = merge_base.trim();
+      if merge_base.is_empty() {
+        return Err(Error::NoCommonHistory(base.name.clone()));
+      }
+      let (ahead, behind) = self.ahead_behind(&branch.name, &base.name)?;
+      Ok(Divergence { merge_base: String::from(merge_base), ahead, behind })
+    })
+  }
+
+  fn commits_between(&self, hidden: &[String], tip: &str) -> Result<Vec<String>, Error> {
+    with_context(format!("Failed to list the commits of {}", tip), || {
+      let hidden: Vec<String> = hidden.iter().map(|commit_id| format!("^{}", commit_id)).collect();
+      let mut args = vec!["rev-list", "--topo-order", tip];
+      args.extend(hidden.iter().map(String::as_str));
+      args.push("--");
+      let res = self.run_git_command(&args)?;
+      Ok(res.lines().map(String::from).collect())
+    })
+  }
+
+  fn merged_branches(&self, into: &GitBranch) -> Result<Vec<GitBranch>, Error> {
+    with_context(format!("Failed to find the branches merged into {}", into.name), || {
+      let target = format!("refs/heads/{}", into.name);
+      let merged = self.list_branches(&["--merged", &target])?;
+      Ok(merged.into_iter().filter(|branch| !branch.is_head && branch.name != into.name).collect())
+    })
+  }
+
+  fn is_dry_run(&self) -> bool {
+    self.dry_run
+  }
+
+  fn is_read_only(&self) -> bool {
+    self.read_only
+  }
+
+  fn is_bare(&self) -> bool {
+    self.bare
+  }
+
+  fn protected_branches(&self) -> &ProtectedBranches {
+    &self.protected
+  }
+
+  fn ref_signature(&self) -> Result<RefSignature, Error> {
+    with_context("Failed to read the refs", || {
+      // With --quiet a detached or unborn HEAD prints nothing rather than failing
+      let head = self.run_git_command(&["symbolic-ref", "--quiet", "HEAD"])?;
+      let head_id = self.run_git_command(&["rev-parse", "--verify", "--quiet", "HEAD"])?;
+      let branches = self.run_git_command(&["for-each-ref", "--format=%(refname)%00%(objectname)", "refs/heads"])?;
+      Ok(RefSignature::of([head, head_id, branches]))
+    })
+  }
+
+  fn seen_refs(&self) -> &SeenRefs {
+    &self.seen_refs
+  }
+
+  fn delete_branch(&self, to_delete: &GitBranch) -> Result<String, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete {}", to_delete.name), || {
+      // Checked before the cache is invalidated, the check loads the branches that are about to change
+      self.ensure_not_protected(&to_delete.name)?;
+      self.refresh();
+      let commit_id = self.branch_commit_id(&to_delete.name)?;
+      // Checked here rather than left to git so a dry run refuses the same branches a real delete would
+      if !self.is_merged(to_delete)? {
+        error!("Refusing to delete unmerged branch {}", to_delete.name);
+        return Err(Error::Git(format!("The branch {} is not fully merged", to_delete.name)));
+      }
+      self.run_mutating_command(&["branch", "-d", &to_delete.name])?;
+      Ok(commit_id)
+    })
+  }
+
+  fn delete_remote_branch(&self, remote: &GitRemoteBranch) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete {}", remote.name), || {
+      self.refresh();
+      let Some((remote_name, branch_name)) = remote.split() else {
+        return Err(Error::Git(format!("Unable to determine the remote for {}", remote.name)));
+      };
+      self.run_git_command(&["push", remote_name, "--delete", branch_name])?;
+      Ok(())
+    })
+  }
+
+  fn orphaned_commits(&self, branch: &GitBranch) -> Result<usize, Error> {
+    with_context(format!("Failed to check which commits only {} has", branch.name), || {
+      let branch_ref = format!("--exclude=refs/heads/{}", branch.name);
+      let tip = format!("refs/heads/{}", branch.name);
+      let res = self.run_git_command(&["rev-list", "--count", &tip, "--not", &branch_ref, "--all"])?;
+      res.trim().parse().map_err(|_| Error::Git(format!("Unable to count the commits only {} has", branch.name)))
+    })
+  }
+
+  fn force_delete_branch(&self, to_delete: &GitBranch) -> Result<String, Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to delete {}", to_delete.name), || {
+      self.ensure_not_protected(&to_delete.name)?;
+      self.refresh();
+      let commit_id = self.branch_commit_id(&to_delete.name)?;
+      self.run_mutating_command(&["branch", "-D", &to_delete.name])?;
+      Ok(commit_id)
+    })
+  }
+
+  fn restore_branch(&self, name: &str, commit_id: &str) -> Result<(), Error> {
+    self.ensure_writable()?;
+    with_context(format!("Failed to restore {}", name), || {
+      self.refresh();
+      self.run_mutating_command(&["branch", name, commit_id])?;
+      Ok(())
+    })
   }
 }
 
-fn run_git_command(args: &[&str]) -> Result<String, Error> {
+fn run_git_command(dir: &Path, args: &[&str]) -> Result<String, Error> {
   let args_log_command = args.join(" ");
   info!("Running `git {}`", args_log_command);
-  let res = Command::new("git").args(args).output();
+  // Never let git prompt for credentials, the prompt would hang behind the TUI
+  let res = Command::new("git").current_dir(dir).args(args).env("GIT_TERMINAL_PROMPT", "0").output();
   if res.is_err() {
     let err = res.err().unwrap();
     error!("Failed to run `git {}`, error: {}", args_log_command, err);
@@ -105,7 +1385,153 @@ fn run_git_command(args: &[&str]) -> Result<String, Error> {
     error!("Failed to run `git {}`, error: {}", args_log_command, err);
     return Err(Error::Git(err));
   }
-  let content = String::from_utf8(output.stdout)?;
+  let content = String::from_utf8_lossy(&output.stdout).into_owned();
   info!("Received git cli reply:\n{}", content);
   Ok(content)
 }
+
+/// The files git lists as blocking a checkout, indented under lines like "Your local changes to the following files
+/// would be overwritten by checkout:". Both changed and untracked files are listed this way.
+fn overwritten_paths(stderr: &str) -> Vec<String> {
+  let mut paths = Vec::new();
+  let mut in_list = false;
+  for line in stderr.lines() {
+    if line.ends_with("would be overwritten by checkout:") {
+      in_list = true;
+    } else if in_list && line.starts_with('\t') {
+      paths.push(String::from(line.trim()));
+    } else {
+      in_list = false;
+    }
+  }
+  paths.sort();
+  paths.dedup();
+  paths
+}
+
+/// The lines a git command prints, read as it prints them rather than once it has finished. A failure, judged like
+/// [`run_git_command`] does, is the last item.
+struct GitLines {
+  args: String,
+  child: Child,
+  lines: Split<BufReader<ChildStdout>>,
+  finished: bool,
+}
+
+impl GitLines {
+  fn spawn(dir: &Path, args: &[&str]) -> Result<GitLines, Error> {
+    let args_log_command = args.join(" ");
+    info!("Streaming `git {}`", args_log_command);
+    let mut child = Command::new("git")
+      .current_dir(dir)
+      .args(args)
+      .env("GIT_TERMINAL_PROMPT", "0")
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|err| {
+        error!("Failed to run `git {}`, error: {}", args_log_command, err);
+        Error::Git(format!("{}", err))
+      })?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok(GitLines { args: args_log_command, child, lines: BufReader::new(stdout).split(b'\n'), finished: false })
+  }
+
+  /// Waits for git to exit once everything has been read, the error it printed when it failed.
+  fn finish(&mut self) -> Option<Error> {
+    let status = match self.child.wait() {
+      Ok(status) => status,
+      Err(err) => return Some(Error::Io(err)),
+    };
+    let mut err = String::new();
+    if let Some(mut stderr) = self.child.stderr.take() {
+      let _ = stderr.read_to_string(&mut err);
+    }
+    if !status.success() && !err.is_empty() {
+      error!("Failed to run `git {}`, error: {}", self.args, err);
+      return Some(Error::Git(err));
+    }
+    None
+  }
+}
+
+impl Iterator for GitLines {
+  type Item = Result<String, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.finished {
+      return None;
+    }
+    match self.lines.next() {
+      Some(Ok(line)) => Some(Ok(String::from_utf8_lossy(&line).into_owned())),
+      Some(Err(err)) => {
+        self.finished = true;
+        Some(Err(Error::Io(err)))
+      },
+      None => {
+        self.finished = true;
+        self.finish().map(Err)
+      },
+    }
+  }
+}
+
+/// Parses a line of [`BRANCH_FORMAT`], `None` for the pseudo entry git lists for a detached HEAD.
+fn parse_branch_line(line: &str, ref_commits: &HashMap<String, String>) -> Option<GitBranch> {
+  let mut fields = line.split('\0');
+  let (Some(head), Some(ref_name), Some(upstream_ref), Some(commit_id)) =
+    (fields.next(), fields.next(), fields.next(), fields.next())
+  else {
+    error!("Failed to parse git branch information for: {}", line);
+    return None;
+  };
+  // The detached HEAD entry has a ref name like `(HEAD detached at 911ec26)`
+  let name = String::from(ref_name.strip_prefix("refs/heads/")?);
+  // Git output is read lossily, so replacement characters mean the real name isn't valid UTF-8
+  let name_is_lossy = name.contains(char::REPLACEMENT_CHARACTER);
+  // The upstream is read from the branch config, so it is still named after its ref has gone
+  let upstream_commit_id = ref_commits.get(upstream_ref).cloned();
+  let upstream_status = match (upstream_ref.is_empty(), upstream_commit_id.is_none()) {
+    (true, _) => UpstreamStatus::None,
+    (false, true) => UpstreamStatus::Gone,
+    (false, false) => UpstreamStatus::Tracked,
+  };
+  let upstream = upstream_ref
+    .strip_prefix("refs/remotes/")
+    .or_else(|| upstream_ref.strip_prefix("refs/heads/"))
+    .unwrap_or(upstream_ref);
+  Some(GitBranch {
+    name,
+    is_head: head == "*",
+    upstream: (!upstream.is_empty()).then(|| GitRemoteBranch::new(String::from(upstream))),
+    upstream_status,
+    ahead_behind: None,
+    commit_id: Some(String::from(commit_id)),
+    upstream_commit_id,
+    name_is_lossy,
+    is_unborn: false,
+  })
+}
+
+/// Works out the operation in progress from the state files git leaves in its directory, checked in the same order
+/// libgit2 checks them.
+fn state_from_git_dir(git_dir: &Path) -> RepoState {
+  let rebase_apply = git_dir.join("rebase-apply");
+  if git_dir.join("rebase-merge").is_dir() {
+    RepoState::Rebase
+  } else if rebase_apply.join("applying").exists() {
+    RepoState::ApplyMailbox
+  } else if rebase_apply.is_dir() {
+    RepoState::Rebase
+  } else if git_dir.join("MERGE_HEAD").exists() {
+    RepoState::Merge
+  } else if git_dir.join("REVERT_HEAD").exists() {
+    RepoState::Revert
+  } else if git_dir.join("CHERRY_PICK_HEAD").exists() {
+    RepoState::CherryPick
+  } else if git_dir.join("BISECT_LOG").exists() {
+    RepoState::Bisect
+  } else {
+    RepoState::Clean
+  }
+}
@@ -1,29 +1,424 @@
-use crate::error::Error;
+use std::{
+  cell::{Cell, RefCell},
+  collections::hash_map::DefaultHasher,
+  fmt,
+  hash::{Hash, Hasher},
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+  error::Error,
+  git::{
+    protected::ProtectedBranches,
+    wip_name::{fill_pattern, unique_name},
+  },
+};
+
+/// The remote used by network operations when no remote name is given.
+pub const DEFAULT_REMOTE: &str = "origin";
+/// Commit ids are abbreviated to at least this many characters, git's own default.
+pub const DEFAULT_ABBREV_LENGTH: usize = 7;
+/// Archived branches are kept as tags named with this prefix followed by the branch name.
+pub const ARCHIVE_TAG_PREFIX: &str = "archive/";
+/// Stashes made by [`GitRepo::quick_checkout`] have this message followed by the name of the branch they were made on.
+pub const AUTO_STASH_PREFIX: &str = "auto-stash on ";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GitRemoteBranch {
   pub name: String,
+  /// The remote the branch is on, `None` when it wasn't looked up.
+  pub remote: Option<String>,
 }
 
 impl GitRemoteBranch {
   pub fn new(name: String) -> Self {
-    GitRemoteBranch { name }
+    GitRemoteBranch { name, remote: None }
+  }
+
+  /// Attributes the branch to the longest of `remotes` its name starts with, so a remote named like `team/shared`
+  /// wins over one named `team`.
+  pub fn on_remote(name: String, remotes: &[String]) -> Self {
+    let remote = remotes
+      .iter()
+      .filter(|remote| name.strip_prefix(remote.as_str()).is_some_and(|rest| rest.starts_with('/')))
+      .max_by_key(|remote| remote.len())
+      .cloned();
+    GitRemoteBranch { name, remote }
+  }
+
+  /// The remote and the name of the branch on it, split at the first `/` when the remote isn't known.
+  pub fn split(&self) -> Option<(&str, &str)> {
+    match &self.remote {
+      Some(remote) => Some((remote, self.name.strip_prefix(remote.as_str())?.strip_prefix('/')?)),
+      None => self.name.split_once('/'),
+    }
   }
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Whether a branch tracks an upstream and if that upstream still exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamStatus {
+  #[default]
+  None,
+  Tracked,
+  /// An upstream is configured but its ref is gone, usually as it was deleted on the remote and then pruned.
+  Gone,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GitBranch {
   pub name: String,
   pub is_head: bool,
+  /// The configured upstream, which is kept when its ref is gone.
   pub upstream: Option<GitRemoteBranch>,
+  pub upstream_status: UpstreamStatus,
+  /// Commits ahead and behind the upstream, `None` when there is no upstream or they haven't been counted. Listing
+  /// branches doesn't count them as it is slow for many branches, see [`GitRepo::upstream_ahead_behind`].
+  pub ahead_behind: Option<(usize, usize)>,
+  /// The commit the branch points at.
+  pub commit_id: Option<String>,
+  /// The commit the upstream points at, `None` when there is no upstream or it is gone.
+  pub upstream_commit_id: Option<String>,
+  /// True when the name is not valid UTF-8 and has been lossily converted, so it can't be used to find the branch.
+  pub name_is_lossy: bool,
+  /// True for the branch HEAD points at before its first commit, as after `git checkout --orphan`. It has no ref yet
+  /// so it isn't listed with the other branches.
+  pub is_unborn: bool,
 }
 
 impl GitBranch {
+  /// Whether the branch may have commits that aren't on a remote, because it is ahead of its upstream or has no
+  /// upstream it can be compared with. Branches that haven't been counted yet are assumed to have some.
+  pub fn has_unpushed_commits(&self) -> bool {
+    match (self.upstream_status, self.ahead_behind) {
+      (UpstreamStatus::Tracked, Some((ahead, _))) => ahead > 0,
+      _ => true,
+    }
+  }
+
+  /// Whether the upstream is gone and no remote in `remote_branches` has a branch of the same name either, so nothing
+  /// on any remote still matches the branch. The upstream is attributed to one of the configured `remotes`, which may
+  /// have slashes in their names.
+  pub fn is_orphaned_tracking(&self, remote_branches: &[GitRemoteBranch], remotes: &[String]) -> bool {
+    if self.upstream_status != UpstreamStatus::Gone {
+      return false;
+    }
+    let Some(upstream) = &self.upstream else {
+      return false;
+    };
+    let upstream = match upstream.remote {
+      Some(_) => upstream.clone(),
+      None => GitRemoteBranch::on_remote(upstream.name.clone(), remotes),
+    };
+    let name_on_remote = upstream.split().map_or(upstream.name.as_str(), |(_, name)| name);
+    !remote_branches.iter().any(|remote| remote.split().is_some_and(|(_, name)| name == name_on_remote))
+  }
+
   pub fn new(name: String) -> Self {
-    GitBranch { name, is_head: false, upstream: None }
+    GitBranch {
+      name,
+      is_head: false,
+      upstream: None,
+      upstream_status: UpstreamStatus::None,
+      ahead_behind: None,
+      commit_id: None,
+      upstream_commit_id: None,
+      name_is_lossy: false,
+      is_unborn: false,
+    }
+  }
+}
+
+/// What a [`GitRepo::quick_checkout`] did with local changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QuickCheckout {
+  /// The changes on the branch left were stashed.
+  pub stashed: bool,
+  /// The changes stashed when last leaving the branch checked out were put back.
+  pub restored: bool,
+}
+
+/// Counts and state for a one line summary of the repository.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepoSummary {
+  pub local_branches: usize,
+  /// Not counting symbolic refs like `origin/HEAD`.
+  pub remote_branches: usize,
+  /// The name of the checked out branch, `None` when HEAD is detached.
+  pub current_branch: Option<String>,
+  pub clean: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitInfo {
+  /// The first line of the commit message.
+  pub summary: String,
+  /// The author's name, and below their email, as the repository's `.mailmap` maps them when it has one.
+  pub author: String,
+  pub author_email: String,
+  /// Commit time as seconds since the unix epoch.
+  pub time: i64,
+}
+
+impl CommitInfo {
+  pub fn new(summary: String, author: String, author_email: String, time: i64) -> Self {
+    CommitInfo { summary, author, author_email, time }
+  }
+}
+
+/// How a branch and a base branch like the default branch have moved apart since they last shared a commit.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Divergence {
+  /// The id of the newest commit both have.
+  pub merge_base: String,
+  /// Commits on the branch since the merge base.
+  pub ahead: usize,
+  /// Commits on the base since the merge base.
+  pub behind: usize,
+}
+
+/// What is left of a bisect between known good and bad commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BisectStep {
+  /// The commit to check out and mark next, with how many commits could still be the first bad one.
+  Test { commit_id: String, remaining: usize },
+  /// The bad commit is the first one, every commit before it is good.
+  Found(String),
+}
+
+/// A commit with the parents needed to draw it in a graph.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+  pub id: String,
+  /// The shortest unambiguous abbreviation of the id.
+  pub short_id: String,
+  /// First parent first, a merge commit has two or more.
+  pub parent_ids: Vec<String>,
+  pub summary: String,
+}
+
+impl GraphCommit {
+  pub fn new(id: String, short_id: String, parent_ids: Vec<String>, summary: String) -> Self {
+    GraphCommit { id, short_id, parent_ids, summary }
+  }
+}
+
+/// A recorded update of a ref, e.g. a commit, checkout or reset.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+  /// The id of the commit the ref pointed at after the update.
+  pub commit_id: String,
+  /// The shortest unambiguous abbreviation of the commit id.
+  pub short_id: String,
+  pub message: String,
+  /// When the update happened as seconds since the unix epoch.
+  pub time: i64,
+}
+
+impl ReflogEntry {
+  pub fn new(commit_id: String, short_id: String, message: String, time: i64) -> Self {
+    ReflogEntry { commit_id, short_id, message, time }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullOutcome {
+  UpToDate,
+  FastForwarded,
+  /// The branch has diverged from its upstream and needs to be merged manually.
+  MergeRequired,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+  UpToDate,
+  FastForwarded,
+  /// The changes were committed without conflicts, as a merge commit when merging.
+  Merged,
+  /// The merge stopped with these conflicted paths, the repository is left mid-merge for them to be resolved.
+  Conflicts(Vec<String>),
+}
+
+/// A multi-step operation the repository is part way through, HEAD, the index and the working tree stay mid-way until
+/// it is finished or aborted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RepoState {
+  #[default]
+  Clean,
+  Merge,
+  Rebase,
+  /// Applying patches with `git am`.
+  ApplyMailbox,
+  CherryPick,
+  Revert,
+  Bisect,
+}
+
+impl RepoState {
+  pub fn is_clean(self) -> bool {
+    self == RepoState::Clean
+  }
+}
+
+impl fmt::Display for RepoState {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      RepoState::Clean => "clean",
+      RepoState::Merge => "merge",
+      RepoState::Rebase => "rebase",
+      RepoState::ApplyMailbox => "patch application",
+      RepoState::CherryPick => "cherry-pick",
+      RepoState::Revert => "revert",
+      RepoState::Bisect => "bisect",
+    };
+    f.write_str(name)
+  }
+}
+
+/// How much a reset puts back to the target commit, like the `--soft`, `--mixed` and `--hard` flags of `git reset`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResetMode {
+  /// Only moves the branch, the changes since the target stay staged.
+  Soft,
+  /// Moves the branch and resets the index, the changes since the target stay in the working tree.
+  #[default]
+  Mixed,
+  /// Moves the branch and resets the index and working tree, discarding every change since the target.
+  Hard,
+}
+
+impl ResetMode {
+  pub fn next(self) -> Self {
+    match self {
+      ResetMode::Soft => ResetMode::Mixed,
+      ResetMode::Mixed => ResetMode::Hard,
+      ResetMode::Hard => ResetMode::Soft,
+    }
+  }
+}
+
+impl fmt::Display for ResetMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      ResetMode::Soft => "soft",
+      ResetMode::Mixed => "mixed",
+      ResetMode::Hard => "hard",
+    };
+    f.write_str(name)
+  }
+}
+
+/// Why a proposed branch name can't be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidBranchName {
+  #[error("The name can't be empty")]
+  Empty,
+  #[error("HEAD is reserved by git")]
+  Reserved,
+  #[error("The name contains characters git doesn't allow")]
+  IllegalCharacters,
+  #[error("A branch with this name already exists")]
+  AlreadyExists,
+}
+
+/// Something questionable about a usable branch name, to be shown to the user without blocking them.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BranchNameWarning {
+  /// A remote branch has the same name once its remote is stripped, tracking it is probably what was meant.
+  #[error("{0} already exists, consider checking it out to track it")]
+  MatchesRemote(String),
+}
+
+/// What could go wrong deleting a branch, worst first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum DeleteRisk {
+  #[default]
+  Safe,
+  /// The branch is the only ref reaching this many commits, they would only be left in the reflog.
+  UnmergedCommits(usize),
+  /// The branch is checked out in the linked worktree at this path, git refuses to delete it.
+  CheckedOutElsewhere(PathBuf),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorktreeInfo {
+  pub name: String,
+  pub path: PathBuf,
+  /// The branch checked out in the worktree, `None` when its HEAD is detached.
+  pub branch: Option<String>,
+}
+
+impl WorktreeInfo {
+  pub fn new(name: String, path: PathBuf, branch: Option<String>) -> Self {
+    WorktreeInfo { name, path, branch }
+  }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GitTag {
+  pub name: String,
+  /// The id of the commit the tag points at.
+  pub target: String,
+  /// The tagger message, only annotated tags have one.
+  pub message: Option<String>,
+}
+
+impl GitTag {
+  pub fn new(name: String, target: String, message: Option<String>) -> Self {
+    GitTag { name, target, message }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+  Added,
+  Modified,
+  Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+  pub path: String,
+  pub change_type: ChangeType,
+}
+
+impl FileChange {
+  pub fn new(path: String, change_type: ChangeType) -> Self {
+    FileChange { path, change_type }
   }
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DiffSummary {
+  pub files_changed: usize,
+  pub insertions: usize,
+  pub deletions: usize,
+  pub files: Vec<FileChange>,
+}
+
+/// What checking out a branch would do with the local changes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CheckoutPreview {
+  /// Changed files the branch has the same as HEAD, so their changes are carried over to it.
+  pub carried: Vec<String>,
+  /// Changed files the branch changes too, or untracked files it adds, which stop the checkout.
+  pub blocking: Vec<String>,
+}
+
+/// A diff as `git diff` prints it, cut short once it gets long.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Patch {
+  pub lines: Vec<String>,
+  /// Lines were left out after the last one.
+  pub truncated: bool,
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct GitStash {
   pub index: usize,
@@ -37,12 +432,3555 @@ impl GitStash {
   }
 }
 
+/// Holds the local branch list between calls until a mutation invalidates it.
+#[derive(Debug, Default)]
+pub struct BranchCache {
+  branches: RefCell<Option<Vec<GitBranch>>>,
+}
+
+impl BranchCache {
+  pub fn get_or_load(&self, load: impl FnOnce() -> Result<Vec<GitBranch>, Error>) -> Result<Vec<GitBranch>, Error> {
+    if let Some(branches) = self.branches.borrow().as_ref() {
+      return Ok(branches.clone());
+    }
+    let branches = load()?;
+    *self.branches.borrow_mut() = Some(branches.clone());
+    Ok(branches)
+  }
+
+  pub fn invalidate(&self) {
+    *self.branches.borrow_mut() = None;
+  }
+}
+
+/// A fingerprint of where HEAD and the local branches point, cheap to work out and compare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefSignature(u64);
+
+impl RefSignature {
+  pub fn of(parts: impl IntoIterator<Item = impl Hash>) -> Self {
+    let mut hasher = DefaultHasher::new();
+    for part in parts {
+      part.hash(&mut hasher);
+    }
+    RefSignature(hasher.finish())
+  }
+}
+
+/// The refs the caller's view of the branches was built from, see [`GitRepo::ensure_refs_unchanged`].
+#[derive(Debug, Default)]
+pub struct SeenRefs {
+  signature: Cell<Option<RefSignature>>,
+}
+
+impl SeenRefs {
+  pub fn get(&self) -> Option<RefSignature> {
+    self.signature.get()
+  }
+
+  pub fn set(&self, signature: RefSignature) {
+    self.signature.set(Some(signature));
+  }
+}
+
+/// How many branches are read before they are handed over while loading in the background.
+pub const LOAD_BATCH_SIZE: usize = 200;
+
+/// Opens a repository and loads its local branches on a blocking thread, so large repositories don't hold up the
+/// caller. Each call opens its own repository handle as the caller's one can't be shared across threads.
+///
+/// Every [`LOAD_BATCH_SIZE`] branches are passed to `on_batch` as soon as they are read so they can be shown early,
+/// and all of them are returned once the last has been read.
+pub fn load_local_branches_in_background<R, F, B>(
+  open_repo: F,
+  mut on_batch: B,
+) -> JoinHandle<Result<Vec<GitBranch>, Error>>
+where
+  R: GitRepo,
+  F: FnOnce() -> Result<R, Error> + Send + 'static,
+  B: FnMut(Vec<GitBranch>) + Send + 'static,
+{
+  tokio::task::spawn_blocking(move || {
+    let repo = open_repo()?;
+    let mut branches = Vec::new();
+    for branch in repo.local_branches_iter() {
+      branches.push(branch?);
+      if branches.len() % LOAD_BATCH_SIZE == 0 {
+        on_batch(branches[branches.len() - LOAD_BATCH_SIZE..].to_vec());
+      }
+    }
+    Ok(branches)
+  })
+}
+
+/// Each branch a bulk delete tried, with the id of the commit it pointed at or why it couldn't be deleted.
+pub type Deletions = Vec<(GitBranch, Result<String, Error>)>;
+
+/// Whether a push was rejected because the remote branch has commits the pushed branch doesn't, going by the status
+/// receive-pack reports for the ref, which git's own error output repeats.
+pub fn is_non_fast_forward(rejection: &str) -> bool {
+  rejection.contains("non-fast-forward") || rejection.contains("fetch first")
+}
+
+/// Opens a repository and fetches from the remote on a blocking thread, so a slow network doesn't hold up the caller.
+pub fn fetch_in_background<R, F>(open_repo: F, remote_name: String) -> JoinHandle<Result<(), Error>>
+where
+  R: GitRepo,
+  F: FnOnce() -> Result<R, Error> + Send + 'static,
+{
+  tokio::task::spawn_blocking(move || open_repo()?.fetch(&remote_name))
+}
+
+/// Like [`fetch_in_background`] but also prunes the remote branches deleted on the remote, returning their names.
+pub fn prune_in_background<R, F>(open_repo: F, remote_name: String) -> JoinHandle<Result<Vec<String>, Error>>
+where
+  R: GitRepo,
+  F: FnOnce() -> Result<R, Error> + Send + 'static,
+{
+  tokio::task::spawn_blocking(move || open_repo()?.prune_remote(&remote_name))
+}
+
 pub trait GitRepo {
+  /// Lists local branches, the result is cached until [`GitRepo::refresh`] or a mutating operation is called.
   fn local_branches(&self) -> Result<Vec<GitBranch>, Error>;
+  /// Reads the local branches one at a time so the first can be used before the rest have been read. Nothing is
+  /// cached, and a failure to list the branches at all is the only item.
+  fn local_branches_iter(&self) -> Box<dyn Iterator<Item = Result<GitBranch, Error>> + '_>;
+  /// Drops any cached repository state so the next read goes back to git.
+  fn refresh(&self);
+  /// The directory git keeps the repository in, like `.git` or the directory of a bare repository.
+  fn path(&self) -> &Path;
+  /// The root of the checked out files, `None` for a bare repository.
+  fn workdir(&self) -> Option<&Path>;
+  /// The working tree of the repository this one is checked out in as a submodule, `None` when it isn't a submodule.
+  fn superproject_path(&self) -> Option<PathBuf>;
+  fn is_submodule(&self) -> bool {
+    self.superproject_path().is_some()
+  }
+  /// Opens the repository this one is a submodule of with the same backend.
+  fn open_superproject(&self) -> Result<Box<dyn GitRepo>, Error>;
+  /// The local branches, most recently committed to first and by name when committed in the same second.
+  fn local_branches_sorted_by_date(&self) -> Result<Vec<GitBranch>, Error>;
+  /// The checked out branch, `None` when HEAD is detached. A branch without commits is returned marked as unborn.
+  fn current_branch(&self) -> Result<Option<GitBranch>, Error>;
+  /// Counts the local and remote branches and reads the current branch and working tree state, going over the
+  /// branches once.
+  fn summary(&self) -> Result<RepoSummary, Error>;
+  /// The abbreviated id of the commit HEAD points at, `None` when HEAD is unborn.
+  fn head_short_id(&self) -> Result<Option<String>, Error>;
+  /// The shortest abbreviation of the commit that is unambiguous in the repository, like the ids git prints, and at
+  /// least as long as the repo is built to abbreviate to.
+  fn short_id(&self, commit_id: &str) -> Result<String, Error>;
+  /// The full id of the commit a full or abbreviated hash names, `None` when no commit has it. A hash shared by
+  /// several commits is an error.
+  fn resolve_commit(&self, hash: &str) -> Result<Option<String>, Error>;
+  /// The names of the configured remotes, sorted.
+  fn remotes(&self) -> Result<Vec<String>, Error>;
+  /// The remote the repo is built to use when none is given, `None` to use [`DEFAULT_REMOTE`].
+  fn configured_remote(&self) -> Option<&str>;
+  /// The remote the branch fetches from by `branch.<name>.remote`, `None` when that isn't set or is `.`, which means
+  /// the repository itself.
+  fn branch_remote(&self, branch: &GitBranch) -> Result<Option<String>, Error>;
+  /// The remote to use for the branch when none is given, its own remote when it has one, then the configured
+  /// remote and then [`DEFAULT_REMOTE`].
+  fn default_remote(&self, branch: Option<&GitBranch>) -> Result<String, Error> {
+    if let Some(remote) = branch.map(|branch| self.branch_remote(branch)).transpose()?.flatten() {
+      return Ok(remote);
+    }
+    Ok(String::from(self.configured_remote().unwrap_or(DEFAULT_REMOTE)))
+  }
+  /// `remote_name`, or when it is empty the [`GitRepo::default_remote`] of the current branch.
+  fn remote_or_default(&self, remote_name: &str) -> Result<String, Error> {
+    if !remote_name.is_empty() {
+      return Ok(String::from(remote_name));
+    }
+    // A detached HEAD just has no remote of its own to prefer
+    let current = self.current_branch().ok().flatten();
+    self.default_remote(current.as_ref())
+  }
+  /// The URL the remote is fetched from, `None` when it isn't valid UTF-8.
+  fn remote_url(&self, remote: &str) -> Result<Option<String>, Error>;
+  /// The remote tracking branches, each attributed to its remote.
+  fn remote_branches(&self) -> Result<Vec<GitRemoteBranch>, Error>;
+  /// Whether a local branch has this name, without listing every branch.
+  fn branch_exists(&self, name: &str) -> bool;
+  /// Whether a remote branch has this name, like `origin/main`.
+  fn remote_branch_exists(&self, name: &str) -> bool;
+  /// The remote branch the remote's HEAD points at, like `origin/main`, `None` when it has no HEAD.
+  fn remote_head(&self, remote_name: &str) -> Result<Option<GitRemoteBranch>, Error>;
+  /// The local branch for the default remote's HEAD, falling back to a local `main` then `master`.
+  fn default_branch(&self) -> Result<Option<GitBranch>, Error> {
+    let branches = self.local_branches()?;
+    let find = |name: &str| branches.iter().find(|branch| branch.name == name).cloned();
+    let remote_head = self.remote_head(&self.default_remote(None)?)?;
+    let from_remote = remote_head.as_ref().and_then(|head| head.split()).and_then(|(_, name)| find(name));
+    Ok(from_remote.or_else(|| find("main")).or_else(|| find("master")))
+  }
+  fn branch_commit_info(&self, branch: &GitBranch) -> Result<CommitInfo, Error>;
+  /// The most recent commits reachable from the branch, newest first, at most `limit` of them.
+  fn branch_log(&self, branch: &GitBranch, limit: usize) -> Result<Vec<CommitInfo>, Error>;
+  /// Up to `limit` commits reachable from the branch in topological order, so a commit always comes before its parents.
+  fn branch_graph(&self, branch: &GitBranch, limit: usize) -> Result<Vec<GraphCommit>, Error>;
+  /// The full message and authorship of the commit at the tip of the branch, like `git show`, followed by its diff
+  /// against its first parent when `with_diff` is set.
+  fn commit_details(&self, branch: &GitBranch, with_diff: bool) -> Result<String, Error>;
+  /// The local branches whose history includes the commit.
+  fn branches_containing(&self, commit_id: &str) -> Result<Vec<GitBranch>, Error>;
+  /// Commits on any local branch whose message contains `query`, ignoring case, newest first and at most `limit`.
+  fn search_commits(&self, query: &str, limit: usize) -> Result<Vec<GraphCommit>, Error>;
+  /// The reflog of a full ref name like `HEAD` or `refs/heads/main`, newest first. Empty when the ref has no reflog.
+  fn reflog_entries(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, Error>;
+  fn fetch(&self, remote_name: &str) -> Result<(), Error>;
+  /// Fetches with prune, returning the remote tracking branches it deleted. Nothing is fetched or pruned in a dry run.
+  fn prune_remote(&self, remote_name: &str) -> Result<Vec<String>, Error>;
+  fn push_branch(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error>;
+  /// Pushes the branch over whatever its remote branch has, but only if the remote branch is still where it was last
+  /// fetched, failing with [`Error::StaleLease`] otherwise so commits pushed since then aren't lost.
+  fn push_branch_force_with_lease(&self, branch: &GitBranch, remote_name: &str) -> Result<(), Error>;
+  /// Whether pushing the branch would have to replace commits on its upstream, as after a rebase, because each has
+  /// commits the other doesn't. Being only ahead or only behind doesn't, and neither does having no upstream.
+  fn push_requires_force(&self, branch: &GitBranch) -> Result<bool, Error> {
+    Ok(self.upstream_ahead_behind(branch)?.is_some_and(|(ahead, behind)| ahead > 0 && behind > 0))
+  }
+  /// The error for a push of the branch rejected as not a fast-forward, which needs a force push when the branch has
+  /// diverged and a pull otherwise.
+  fn non_fast_forward_error(&self, branch: &GitBranch, remote_name: &str) -> Error {
+    match self.push_requires_force(branch) {
+      Ok(true) => Error::PushRequiresForce(branch.name.clone(), String::from(remote_name)),
+      _ => {
+        Error::Git(format!(
+          "Push of {} to {} was rejected as it is not a fast-forward, pull first",
+          branch.name, remote_name
+        ))
+      },
+    }
+  }
+  fn pull_current(&self, remote_name: &str) -> Result<PullOutcome, Error>;
+  fn merge_branch(&self, branch: &GitBranch) -> Result<MergeOutcome, Error>;
+  /// Applies the changes of the commit onto HEAD as a new commit with its message and author. Reports
+  /// [`MergeOutcome::UpToDate`] when HEAD already has the changes, and leaves the repository mid cherry-pick on
+  /// conflicts. Merge commits are refused.
+  fn cherry_pick(&self, commit_id: &str) -> Result<MergeOutcome, Error>;
+  /// The multi-step operation the repository is part way through, [`RepoState::Clean`] when there is none.
+  fn repo_state(&self) -> RepoState;
+  /// Abandons the operation in progress and puts HEAD, the index and the working tree back to how they were before it
+  /// started, discarding any local changes. Fails with [`Error::NoOperationInProgress`] when there is nothing to abort.
+  fn abort_operation(&self) -> Result<(), Error>;
+  /// Lists the linked worktrees, the main worktree is not included.
+  fn worktrees(&self) -> Result<Vec<WorktreeInfo>, Error>;
+  fn tags(&self) -> Result<Vec<GitTag>, Error>;
+  /// Creates a lightweight tag at the commit `target` resolves to, failing when the tag already exists.
+  fn create_tag(&self, name: &str, target: &str) -> Result<(), Error>;
+  fn delete_tag(&self, name: &str) -> Result<(), Error>;
+  fn diff_branches(&self, from: &GitBranch, to: &GitBranch) -> Result<DiffSummary, Error>;
+  /// The patch taking HEAD to the branch, file by file with their hunks, at most `limit` lines of it.
+  fn diff_with_head(&self, branch: &GitBranch, limit: usize) -> Result<Patch, Error>;
   fn stashes(&mut self) -> Result<Vec<GitStash>, Error>;
+  /// Stashes the working tree and index, returning the id of the new stash.
+  fn stash_save(&mut self, message: &str) -> Result<String, Error>;
+  /// Applies the stash at `index` and drops it, keeping it when it doesn't apply cleanly.
+  fn stash_pop(&mut self, index: usize) -> Result<(), Error>;
+  /// Checks out the branch with local changes set aside rather than in the way. They are stashed as
+  /// [`AUTO_STASH_PREFIX`] and the name of the branch left, then the stash made when `branch` was last left this
+  /// way is popped. When that doesn't apply cleanly the stash is kept and [`Error::AutoStashNotApplied`] returned,
+  /// with `branch` checked out.
+  fn quick_checkout(&mut self, branch: &GitBranch) -> Result<QuickCheckout, Error> {
+    self.ensure_writable()?;
+    self.ensure_not_bare()?;
+    let mut outcome = QuickCheckout::default();
+    if self.is_working_tree_dirty()? {
+      let Some(current) = self.current_branch()? else {
+        return Err(Error::DetachedHead);
+      };
+      // Stashing isn't covered by dry runs, so the changes stay where they are
+      if self.is_dry_run() {
+        info!("Dry run, not stashing the changes on {}", current.name);
+      } else {
+        self.stash_save(&format!("{}{}", AUTO_STASH_PREFIX, current.name))?;
+      }
+      outcome.stashed = true;
+    }
+    if let Err(err) = self.checkout_branch(branch) {
+      if outcome.stashed && !self.is_dry_run() {
+        if let Err(pop_err) = self.stash_pop(0) {
+          error!("Failed to put back the changes stashed before checking out {}: {}", branch.name, pop_err);
+        }
+      }
+      return Err(err);
+    }
+    // Stash messages are recorded as `On <branch>: <message>`
+    let suffix = format!(": {}{}", AUTO_STASH_PREFIX, branch.name);
+    let Some(stash) = self.stashes()?.into_iter().find(|stash| stash.message.ends_with(&suffix)) else {
+      return Ok(outcome);
+    };
+    if self.is_dry_run() {
+      info!("Dry run, not popping stash {} onto {}", stash.index, branch.name);
+    } else if let Err(err) = self.stash_pop(stash.index) {
+      error!("Failed to pop stash {} onto {}: {}", stash.index, branch.name, err);
+      return Err(Error::AutoStashNotApplied(branch.name.clone(), err.to_string()));
+    }
+    outcome.restored = true;
+    Ok(outcome)
+  }
+  /// Like `git stash branch`, creates and checks out a branch at the commit the stash was made on, then applies the
+  /// stash and drops it. When applying fails the branch stays checked out with whatever did apply, and the stash is
+  /// kept, see [`Error::StashNotApplied`].
+  fn branch_from_stash(&mut self, index: usize, new_name: &str) -> Result<(), Error>;
   fn checkout_branch_from_name(&self, branch_name: &str) -> Result<(), Error>;
   fn checkout_branch(&self, branch: &GitBranch) -> Result<(), Error>;
+  /// Checks out the branch even when that overwrites local changes, throwing away edits to tracked files. Only for
+  /// when the user has confirmed it, [`GitRepo::checkout_branch`] is the safe default.
+  fn force_checkout_branch(&self, branch: &GitBranch) -> Result<(), Error>;
+  /// Checks out a tag name, short sha or full oid with a detached HEAD.
+  fn checkout_detached(&self, target: &str) -> Result<(), Error>;
+  /// Checks out the commit the branch's upstream points at with a detached HEAD, to look at the remote's state. Fails
+  /// with [`Error::NoUpstream`] when the branch doesn't track one.
+  fn checkout_upstream(&self, branch: &GitBranch) -> Result<(), Error> {
+    let Some(upstream) = &branch.upstream else {
+      return Err(Error::NoUpstream(branch.name.clone()));
+    };
+    if !self.remote_branch_exists(&upstream.name) {
+      return Err(Error::Git(format!("The upstream {} is gone, fetch to update it", upstream.name)));
+    }
+    // The full ref name can't be mistaken for a local branch called the same, like `origin/main`
+    self.checkout_detached(&format!("refs/remotes/{}", upstream.name))
+  }
+  /// Checks out whatever was checked out before the current branch, like `git switch -`. Fails with
+  /// [`Error::NoPreviousBranch`] when nothing was.
+  fn checkout_previous(&self) -> Result<(), Error>;
+  fn checkout_remote_branch(&self, remote: &GitRemoteBranch) -> Result<GitBranch, Error>;
+  /// Moves the current branch to the commit `target` resolves to, like `git reset --<mode>`. Fails with
+  /// [`Error::DetachedHead`] when HEAD isn't on a branch, unless the repo is built to allow detached resets.
+  fn reset_current(&self, target: &str, mode: ResetMode) -> Result<(), Error>;
+  /// Points the branch at the commit `target` resolves to without checking it out, like `git branch -f`. Fails with
+  /// [`Error::CheckedOutBranch`] for the current branch and [`Error::CheckedOutInWorktree`] for one a linked worktree
+  /// has checked out.
+  fn move_branch(&self, branch: &GitBranch, target: &str) -> Result<(), Error>;
+  /// Fails when the branch is checked out here or in a linked worktree, called before moving it.
+  fn ensure_not_checked_out(&self, branch: &GitBranch) -> Result<(), Error> {
+    if self.current_branch()?.is_some_and(|current| current.name == branch.name) {
+      return Err(Error::CheckedOutBranch(branch.name.clone()));
+    }
+    let worktree = self.worktrees()?.into_iter().find(|worktree| worktree.branch.as_ref() == Some(&branch.name));
+    if let Some(worktree) = worktree {
+      return Err(Error::CheckedOutInWorktree(branch.name.clone(), worktree.path.display().to_string()));
+    }
+    Ok(())
+  }
+  /// The local branches behind their upstreams without commits of their own, which can be fast-forwarded.
+  fn fast_forwardable(&self) -> Result<Vec<GitBranch>, Error> {
+    let mut branches = Vec::new();
+    for branch in self.local_branches()? {
+      if branch.upstream_status != UpstreamStatus::Tracked || branch.name_is_lossy {
+        continue;
+      }
+      if matches!(self.upstream_ahead_behind(&branch)?, Some((0, behind)) if behind > 0) {
+        branches.push(branch);
+      }
+    }
+    Ok(branches)
+  }
+  /// Moves the branch to the commit its upstream points at when it has no commits of its own, like
+  /// `git merge --ff-only` but without checking it out. The checked out branch needs a clean working tree and its
+  /// files are updated too, one a linked worktree has checked out is left alone.
+  fn fast_forward_branch(&self, branch: &GitBranch) -> Result<(), Error>;
+  /// Checks the branch can be fast-forwarded, returning it freshly read so its upstream commit is current, or `None`
+  /// when it is already up to date.
+  fn fast_forward_check(&self, branch: &GitBranch) -> Result<Option<GitBranch>, Error> {
+    self.ensure_writable()?;
+    self.refresh();
+    let Some(branch) = self.local_branches()?.into_iter().find(|local| local.name == branch.name) else {
+      return Err(Error::Git(format!("There is no branch named {}", branch.name)));
+    };
+    if branch.upstream_status != UpstreamStatus::Tracked {
+      return Err(Error::NoUpstream(branch.name));
+    }
+    match self.upstream_ahead_behind(&branch)? {
+      Some((0, 0)) => return Ok(None),
+      Some((0, _)) => {},
+      _ => return Err(Error::NotFastForward(branch.name)),
+    }
+    match self.ensure_not_checked_out(&branch) {
+      Err(Error::CheckedOutBranch(_)) if self.is_working_tree_dirty()? => Err(Error::FastForwardBlocked(branch.name)),
+      Err(Error::CheckedOutBranch(_)) => Ok(Some(branch)),
+      result => result.map(|_| Some(branch)),
+    }
+  }
+  /// Whether tracked files have changes, staged or not. Untracked files only count when the repo is built to count
+  /// them, and files ignored by .gitignore, .git/info/exclude or core.excludesFile never do. A bare repository has
+  /// no working tree, so it is never dirty.
+  fn is_working_tree_dirty(&self) -> Result<bool, Error>;
+  /// Which local changes checking out the branch would carry over and which would stop it, without checking it out.
+  fn checkout_preview(&self, branch: &GitBranch) -> Result<CheckoutPreview, Error>;
   fn validate_branch_name(&self, name: &str) -> Result<bool, Error>;
+  /// A name for a new branch at HEAD made from `pattern`, see [`fill_pattern`], with a counter appended when a branch
+  /// already has it.
+  fn propose_branch_name(&self, pattern: &str) -> Result<String, Error> {
+    let Some(short_sha) = self.head_short_id()? else {
+      return Err(Error::NoCommits);
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or(0);
+    let taken: Vec<String> = self.local_branches()?.into_iter().map(|branch| branch.name).collect();
+    let name = unique_name(&fill_pattern(pattern, now, &short_sha), &taken);
+    if !self.validate_branch_name(&name)? {
+      return Err(Error::Git(format!("The pattern {} makes the invalid branch name {}", pattern, name)));
+    }
+    Ok(name)
+  }
+  /// Like [`GitRepo::validate_branch_name`] but also checks the name is free, returning why it can't be used or
+  /// `None` when it can.
+  fn validate_branch_name_detailed(&self, name: &str) -> Result<Option<InvalidBranchName>, Error> {
+    if name.is_empty() {
+      return Ok(Some(InvalidBranchName::Empty));
+    }
+    if name == "HEAD" {
+      return Ok(Some(InvalidBranchName::Reserved));
+    }
+    if !self.validate_branch_name(name)? {
+      return Ok(Some(InvalidBranchName::IllegalCharacters));
+    }
+    if self.branch_exists(name) {
+      return Ok(Some(InvalidBranchName::AlreadyExists));
+    }
+    Ok(None)
+  }
+  /// Checks a valid name for problems that shouldn't stop it being used, like clashing with a remote branch.
+  fn branch_name_warning(&self, name: &str) -> Result<Option<BranchNameWarning>, Error> {
+    let remote_match =
+      self.remote_branches()?.into_iter().find(|remote| remote.split().is_some_and(|(_remote, branch)| branch == name));
+    Ok(remote_match.map(|remote| BranchNameWarning::MatchesRemote(remote.name)))
+  }
+  /// Creates the branch at HEAD without checking it out.
   fn create_branch(&self, to_create: &GitBranch) -> Result<(), Error>;
-  fn delete_branch(&self, to_delete: &GitBranch) -> Result<(), Error>;
+  /// Creates the branch at HEAD and checks it out, deleting it again if the checkout fails.
+  fn create_and_checkout(&self, to_create: &GitBranch) -> Result<(), Error> {
+    self.ensure_not_bare()?;
+    self.create_branch(to_create)?;
+    if let Err(err) = self.checkout_branch(to_create) {
+      if let Err(rollback_err) = self.force_delete_branch(to_create) {
+        error!("Failed to remove {} after its checkout failed: {}", to_create.name, rollback_err);
+      }
+      return Err(err);
+    }
+    Ok(())
+  }
+  fn create_branch_from(&self, new_name: &str, base: &GitBranch) -> Result<GitBranch, Error>;
+  /// Creates the branch from `base` and checks it out, deleting it again if the checkout fails.
+  fn create_from_and_checkout(&self, new_name: &str, base: &GitBranch) -> Result<GitBranch, Error> {
+    self.ensure_not_bare()?;
+    let created = self.create_branch_from(new_name, base)?;
+    if let Err(err) = self.checkout_branch(&created) {
+      if let Err(rollback_err) = self.force_delete_branch(&created) {
+        error!("Failed to remove {} after its checkout failed: {}", created.name, rollback_err);
+      }
+      return Err(err);
+    }
+    Ok(created)
+  }
+  fn rename_branch(&self, branch: &GitBranch, new_name: &str) -> Result<(), Error>;
+  /// After [`GitRepo::rename_branch`], renames the branch's upstream on its remote to match by pushing the branch
+  /// under its new name, tracking that and deleting the old remote branch. It goes over the network, so it is only
+  /// done when asked for. Returns the new upstream, or the current one when the names already match.
+  fn rename_upstream(&self, branch: &GitBranch) -> Result<GitRemoteBranch, Error> {
+    self.ensure_writable()?;
+    let Some(upstream) = &branch.upstream else {
+      return Err(Error::NoUpstream(branch.name.clone()));
+    };
+    let upstream = GitRemoteBranch::on_remote(upstream.name.clone(), &self.remotes()?);
+    let Some((remote_name, old_name)) = upstream.split() else {
+      return Err(Error::Git(format!("Unable to determine the remote for {}", upstream.name)));
+    };
+    if old_name == branch.name {
+      return Ok(upstream.clone());
+    }
+    let remote_name = String::from(remote_name);
+    let renamed =
+      GitRemoteBranch { name: format!("{}/{}", remote_name, branch.name), remote: Some(remote_name.clone()) };
+    if self.is_dry_run() {
+      info!("Dry run, not renaming {} to {}", upstream.name, renamed.name);
+      return Ok(renamed);
+    }
+    // Pushing first keeps the commits on the remote should deleting the old name fail
+    self.push_branch(&GitBranch { upstream: None, ..branch.clone() }, &remote_name)?;
+    self.delete_remote_branch(&upstream)?;
+    info!("Renamed {} to {}", upstream.name, renamed.name);
+    Ok(renamed)
+  }
+  fn set_upstream(&self, branch: &GitBranch, remote_branch: Option<&GitRemoteBranch>) -> Result<(), Error>;
+  /// A remote branch with the same name the branch could track, for a branch without an upstream. The one on the
+  /// branch's [`GitRepo::default_remote`] is preferred when several remotes have one.
+  fn suggest_upstream(&self, branch: &GitBranch) -> Result<Option<GitRemoteBranch>, Error> {
+    if branch.upstream.is_some() {
+      return Ok(None);
+    }
+    let default_remote = self.default_remote(Some(branch))?;
+    let mut matches: Vec<GitRemoteBranch> = self
+      .remotes()?
+      .into_iter()
+      .map(|remote| GitRemoteBranch { name: format!("{}/{}", remote, branch.name), remote: Some(remote) })
+      .filter(|remote_branch| self.remote_branch_exists(&remote_branch.name))
+      .collect();
+    // Remotes are sorted so the first is the pick when none of them is the default
+    let position = matches.iter().position(|remote_branch| remote_branch.remote.as_deref() == Some(&default_remote));
+    Ok(match position {
+      Some(position) => Some(matches.swap_remove(position)),
+      None => matches.into_iter().next(),
+    })
+  }
+  /// The description kept in `branch.<name>.description`, as `git branch --edit-description` writes it.
+  fn branch_description(&self, branch: &GitBranch) -> Result<Option<String>, Error>;
+  /// Replaces the branch's description, or removes it with `None`.
+  fn set_branch_description(&self, branch: &GitBranch, description: Option<&str>) -> Result<(), Error>;
+  fn is_merged(&self, branch: &GitBranch) -> Result<bool, Error>;
+  /// Counts the commits the branch is ahead and behind its upstream, `None` when there is no upstream to compare with.
+  fn upstream_ahead_behind(&self, branch: &GitBranch) -> Result<Option<(usize, usize)>, Error>;
+  /// The lines inserted and deleted going from the upstream's tree to the branch's, `None` without an upstream.
+  fn diffstat_vs_upstream(&self, branch: &GitBranch) -> Result<Option<(usize, usize)>, Error>;
+  /// The local branches whose tips are reachable from `into`, leaving out `into` itself and the checked out branch.
+  fn merged_branches(&self, into: &GitBranch) -> Result<Vec<GitBranch>, Error>;
+  /// Where the branch forked from `base` and how many commits each has had since. Fails with
+  /// [`Error::NoCommonHistory`] when they share no commits.
+  fn divergence(&self, branch: &GitBranch, base: &GitBranch) -> Result<Divergence, Error>;
+  /// The ids of the commits reachable from `tip` but from none of `hidden`, newest first in topological order.
+  fn commits_between(&self, hidden: &[String], tip: &str) -> Result<Vec<String>, Error>;
+  /// The next commit to test when bisecting for the first bad commit, the middle of the commits reachable from `bad`
+  /// but from none of the `good` ones. In a history with merges the middle is taken in topological order, so it
+  /// halves the commits left less evenly than `git bisect` does.
+  fn bisect_next(&self, good: &[String], bad: &str) -> Result<BisectStep, Error> {
+    let candidates = self.commits_between(good, bad)?;
+    if candidates.is_empty() {
+      return Err(Error::Git(format!("{} is reachable from a good commit, so it can't be bad", bad)));
+    }
+    // `bad` is always the first candidate, it is the first bad commit once it is the only one
+    if candidates.len() <= 1 {
+      return Ok(BisectStep::Found(String::from(bad)));
+    }
+    let remaining = candidates.len();
+    Ok(BisectStep::Test { commit_id: candidates[remaining / 2].clone(), remaining })
+  }
+  /// Whether branch changes are only logged instead of being made.
+  fn is_dry_run(&self) -> bool;
+  /// Whether everything that would change the repository or a remote is refused.
+  fn is_read_only(&self) -> bool;
+  /// Fails with [`Error::ReadOnly`] in a read-only repository, called before anything changes the repository.
+  fn ensure_writable(&self) -> Result<(), Error> {
+    if self.is_read_only() {
+      return Err(Error::ReadOnly);
+    }
+    Ok(())
+  }
+  /// Whether the repository has no working tree, like a clone made with `--bare`.
+  fn is_bare(&self) -> bool;
+  /// Fails with [`Error::BareRepository`] in a bare repository, called before anything checks out into the working
+  /// tree. Listing, renaming and deleting branches work without one.
+  fn ensure_not_bare(&self) -> Result<(), Error> {
+    if self.is_bare() {
+      return Err(Error::BareRepository);
+    }
+    Ok(())
+  }
+  /// The current [`RefSignature`] of HEAD and the local branches.
+  fn ref_signature(&self) -> Result<RefSignature, Error>;
+  fn seen_refs(&self) -> &SeenRefs;
+  /// Records the refs as they are now as the ones the caller's view of the branches is built from, after reading
+  /// the branches or changing them.
+  fn mark_refs_seen(&self) -> Result<(), Error> {
+    self.seen_refs().set(self.ref_signature()?);
+    Ok(())
+  }
+  /// Fails with [`Error::RepositoryChanged`] when HEAD or a local branch moved since [`GitRepo::mark_refs_seen`], as
+  /// when another git process checked out or deleted a branch. Called before a change the view was used to decide
+  /// on, nothing is checked until the refs are marked seen.
+  fn ensure_refs_unchanged(&self) -> Result<(), Error> {
+    let Some(seen) = self.seen_refs().get() else {
+      return Ok(());
+    };
+    if self.ref_signature()? != seen {
+      warn!("The refs changed since the branches were read");
+      return Err(Error::RepositoryChanged);
+    }
+    Ok(())
+  }
+  /// The configured patterns for branches that can't be deleted.
+  fn protected_branches(&self) -> &ProtectedBranches;
+  /// Whether the branch matches a protected pattern or is the default branch.
+  fn is_protected(&self, name: &str) -> Result<bool, Error> {
+    if self.protected_branches().matches(name) {
+      return Ok(true);
+    }
+    Ok(self.default_branch()?.is_some_and(|default_branch| default_branch.name == name))
+  }
+  /// Fails with [`Error::ProtectedBranch`] for a protected branch, called before any branch is deleted.
+  fn ensure_not_protected(&self, name: &str) -> Result<(), Error> {
+    if self.is_protected(name)? {
+      return Err(Error::ProtectedBranch(name.to_string()));
+    }
+    Ok(())
+  }
+  /// Counts the commits that no ref other than the branch reaches, HEAD included.
+  fn orphaned_commits(&self, branch: &GitBranch) -> Result<usize, Error>;
+  /// Whether the branch used to track a remote branch that is gone from its remote and from every other remote, which
+  /// makes it a candidate for cleaning up.
+  fn is_orphaned_tracking(&self, branch: &GitBranch) -> Result<bool, Error> {
+    if branch.upstream_status != UpstreamStatus::Gone {
+      return Ok(false);
+    }
+    Ok(branch.is_orphaned_tracking(&self.remote_branches()?, &self.remotes()?))
+  }
+  /// Whether deleting the branch would fail because a worktree has it checked out, or leave commits unreachable.
+  fn delete_risk(&self, branch: &GitBranch) -> Result<DeleteRisk, Error> {
+    let worktree = self.worktrees()?.into_iter().find(|worktree| worktree.branch.as_ref() == Some(&branch.name));
+    if let Some(worktree) = worktree {
+      return Ok(DeleteRisk::CheckedOutElsewhere(worktree.path));
+    }
+    match self.orphaned_commits(branch)? {
+      0 => Ok(DeleteRisk::Safe),
+      count => Ok(DeleteRisk::UnmergedCommits(count)),
+    }
+  }
+  /// Deletes a merged branch, returning the id of the commit it pointed at so it can be restored.
+  fn delete_branch(&self, to_delete: &GitBranch) -> Result<String, Error>;
+  /// Deletes each branch in turn, a failure doesn't stop the rest from being deleted.
+  fn delete_branches(&self, branches: &[GitBranch]) -> Deletions {
+    branches.iter().map(|branch| (branch.clone(), self.delete_branch(branch))).collect()
+  }
+  /// The local branches whose upstream is gone from every remote, as after fetching with prune once they were deleted
+  /// on the remote. The checked out, default and protected branches are never included.
+  fn gone_branches(&self) -> Result<Vec<GitBranch>, Error> {
+    let branches = self.local_branches()?;
+    // Listing the remote branches is only worth it when some upstream is gone
+    if !branches.iter().any(|branch| branch.upstream_status == UpstreamStatus::Gone) {
+      return Ok(Vec::new());
+    }
+    let remote_branches = self.remote_branches()?;
+    let remotes = self.remotes()?;
+    let default_name = self.default_branch()?.map(|branch| branch.name);
+    Ok(
+      branches
+        .into_iter()
+        .filter(|branch| !branch.is_head && !branch.name_is_lossy && Some(&branch.name) != default_name.as_ref())
+        .filter(|branch| !self.protected_branches().matches(&branch.name))
+        .filter(|branch| branch.is_orphaned_tracking(&remote_branches, &remotes))
+        .collect(),
+    )
+  }
+  /// Deletes the named branches that are still among the [`GitRepo::gone_branches`]. Their commits may not be merged
+  /// anywhere, as when the remote branch was squashed into another, so they are deleted regardless.
+  fn delete_gone_branches(&self, names: &[String]) -> Result<Deletions, Error> {
+    self.ensure_writable()?;
+    self.refresh();
+    Ok(
+      self
+        .gone_branches()?
+        .into_iter()
+        .filter(|branch| names.contains(&branch.name))
+        .map(|branch| {
+          let result = self.force_delete_branch(&branch);
+          (branch, result)
+        })
+        .collect(),
+    )
+  }
+  /// Deletes a branch even if it isn't merged, returning the id of the commit it pointed at.
+  fn force_delete_branch(&self, to_delete: &GitBranch) -> Result<String, Error>;
+  /// Tags the tip of the branch as [`ARCHIVE_TAG_PREFIX`] followed by its name and then deletes it, so its commits stay
+  /// reachable without it being listed. The tag is removed again when the branch can't be deleted.
+  fn archive_branch(&self, branch: &GitBranch) -> Result<(), Error> {
+    self.ensure_not_protected(&branch.name)?;
+    let tag = format!("{}{}", ARCHIVE_TAG_PREFIX, branch.name);
+    self.create_tag(&tag, &format!("refs/heads/{}", branch.name))?;
+    if let Err(err) = self.force_delete_branch(branch) {
+      if let Err(rollback_err) = self.delete_tag(&tag) {
+        error!("Failed to remove the tag {} after {} couldn't be deleted: {}", tag, branch.name, rollback_err);
+      }
+      return Err(err);
+    }
+    Ok(())
+  }
+  /// Recreates a deleted branch at the commit it pointed at.
+  fn restore_branch(&self, name: &str, commit_id: &str) -> Result<(), Error>;
+  /// Creates a branch at any commit, like one further back in a branch's log, checking the name first.
+  fn create_branch_at(&self, name: &str, commit_id: &str) -> Result<GitBranch, Error> {
+    if !self.validate_branch_name(name)? {
+      return Err(Error::Git(format!("{} is not a valid branch name", name)));
+    }
+    if self.branch_exists(name) {
+      return Err(Error::Git(format!("A branch named {} already exists", name)));
+    }
+    self.restore_branch(name, commit_id)?;
+    Ok(GitBranch { commit_id: Some(String::from(commit_id)), ..GitBranch::new(String::from(name)) })
+  }
+  fn delete_remote_branch(&self, remote: &GitRemoteBranch) -> Result<(), Error>;
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    fs,
+    os::unix::ffi::OsStrExt,
+    process::Command,
+    sync::{Arc, Mutex},
+  };
+
+  use super::*;
+  use crate::{
+    error::exit_code,
+    git::{git2_repo::Git2Repo, git_cli_repo::GitCliRepo},
+    test_support::{each_backend, git_in, local_branch, named, naming_backend, Fixture, TempDir},
+  };
+
+  #[test]
+  fn force_deleting_an_unmerged_branch_succeeds() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+      },
+      |_, repo| {
+        let feature = local_branch(repo, "feature");
+        assert_eq!(repo.force_delete_branch(&feature).unwrap(), feature.commit_id.unwrap());
+        assert!(!repo.branch_exists("feature"));
+      },
+    );
+  }
+
+  #[test]
+  fn force_deleting_the_checked_out_branch_is_refused() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+      },
+      |_, repo| {
+        assert!(repo.force_delete_branch(&local_branch(repo, "feature")).is_err());
+        assert!(repo.branch_exists("feature"));
+      },
+    );
+  }
+
+  #[test]
+  fn renaming_a_branch_moves_it_to_the_new_name() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+      },
+      |_, repo| {
+        repo.rename_branch(&local_branch(repo, "feature"), "renamed").unwrap();
+        assert!(!repo.branch_exists("feature"));
+        assert!(repo.branch_exists("renamed"));
+      },
+    );
+  }
+
+  #[test]
+  fn renaming_the_checked_out_branch_keeps_it_checked_out() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+      },
+      |fixture, repo| {
+        repo.rename_branch(&local_branch(repo, "feature"), "renamed").unwrap();
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "renamed");
+      },
+    );
+  }
+
+  #[test]
+  fn renaming_to_an_existing_branch_is_an_error() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+        fixture.branch_with_commit("other");
+      },
+      |_, repo| {
+        assert!(repo.rename_branch(&local_branch(repo, "feature"), "other").is_err());
+        assert!(repo.branch_exists("feature"));
+      },
+    );
+  }
+
+  #[test]
+  fn ahead_behind_counts_commits_ahead_of_the_upstream() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.commit("a.txt", "a", "Ahead one");
+        fixture.commit("b.txt", "b", "Ahead two");
+      },
+      |_, repo| assert_eq!(repo.upstream_ahead_behind(&local_branch(repo, "main")).unwrap(), Some((2, 0))),
+    );
+  }
+
+  #[test]
+  fn ahead_behind_counts_commits_behind_the_upstream() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.commit("a.txt", "a", "Pushed");
+        fixture.git(&["push", "-q"]);
+        fixture.git(&["reset", "-q", "--hard", "HEAD~1"]);
+      },
+      |_, repo| assert_eq!(repo.upstream_ahead_behind(&local_branch(repo, "main")).unwrap(), Some((0, 1))),
+    );
+  }
+
+  #[test]
+  fn ahead_behind_is_none_without_an_upstream() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+      },
+      |_, repo| assert_eq!(repo.upstream_ahead_behind(&local_branch(repo, "feature")).unwrap(), None),
+    );
+  }
+
+  #[test]
+  fn remote_branches_lists_the_remote_refs_without_head() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature-a");
+        fixture.branch_with_commit("feature-b");
+        fixture.git(&["push", "-q", "origin", "feature-a", "feature-b"]);
+        fixture.git(&["remote", "set-head", "origin", "main"]);
+      },
+      |_, repo| {
+        let mut names: Vec<String> = repo.remote_branches().unwrap().into_iter().map(|branch| branch.name).collect();
+        names.sort();
+        assert_eq!(names, ["origin/feature-a", "origin/feature-b", "origin/main"]);
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_a_remote_branch_creates_a_tracking_branch() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature-x");
+        fixture.git(&["push", "-q", "origin", "feature-x"]);
+        fixture.git(&["branch", "-q", "-D", "feature-x"]);
+      },
+      |fixture, repo| {
+        let branch = repo.checkout_remote_branch(&GitRemoteBranch::new(String::from("origin/feature-x"))).unwrap();
+        assert_eq!(branch.name, "feature-x");
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "feature-x");
+        assert_eq!(fixture.git(&["rev-parse", "--abbrev-ref", "feature-x@{upstream}"]), "origin/feature-x");
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_a_remote_branch_keeps_an_existing_local_branch() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature-x");
+        fixture.git(&["push", "-q", "origin", "feature-x"]);
+        fixture.git(&["branch", "-q", "-f", "feature-x", "main"]);
+      },
+      |fixture, repo| {
+        let main = fixture.head();
+        assert!(repo.checkout_remote_branch(&GitRemoteBranch::new(String::from("origin/feature-x"))).is_err());
+        assert_eq!(fixture.git(&["rev-parse", "feature-x"]), main);
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  fn on_origin(name: &str) -> GitRemoteBranch {
+    GitRemoteBranch { name: format!("origin/{}", name), remote: Some(String::from("origin")) }
+  }
+
+  #[test]
+  fn setting_an_upstream_on_a_branch_without_one() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature");
+        fixture.git(&["push", "-q", "origin", "feature"]);
+      },
+      |fixture, repo| {
+        repo.set_upstream(&local_branch(repo, "feature"), Some(&on_origin("feature"))).unwrap();
+        assert_eq!(fixture.git(&["rev-parse", "--abbrev-ref", "feature@{upstream}"]), "origin/feature");
+      },
+    );
+  }
+
+  #[test]
+  fn changing_an_existing_upstream() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature");
+        fixture.git(&["push", "-q", "-u", "origin", "feature"]);
+      },
+      |fixture, repo| {
+        repo.set_upstream(&local_branch(repo, "feature"), Some(&on_origin("main"))).unwrap();
+        assert_eq!(fixture.git(&["rev-parse", "--abbrev-ref", "feature@{upstream}"]), "origin/main");
+      },
+    );
+  }
+
+  #[test]
+  fn clearing_an_upstream() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+      },
+      |_, repo| {
+        repo.set_upstream(&local_branch(repo, "main"), None).unwrap();
+        assert_eq!(local_branch(repo, "main").upstream, None);
+      },
+    );
+  }
+
+  #[test]
+  fn setting_a_missing_upstream_is_an_error() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+      },
+      |fixture, repo| {
+        assert!(repo.set_upstream(&local_branch(repo, "main"), Some(&on_origin("missing"))).is_err());
+        assert_eq!(fixture.git(&["rev-parse", "--abbrev-ref", "main@{upstream}"]), "origin/main");
+      },
+    );
+  }
+
+  #[test]
+  fn a_clean_working_tree_is_not_dirty() {
+    each_backend(
+      |fixture| fixture.write("untracked.txt", "untracked"),
+      |_, repo| assert!(!repo.is_working_tree_dirty().unwrap()),
+    );
+  }
+
+  #[test]
+  fn a_modified_tracked_file_makes_the_working_tree_dirty() {
+    each_backend(
+      |fixture| fixture.write("README.md", "modified\n"),
+      |_, repo| assert!(repo.is_working_tree_dirty().unwrap()),
+    );
+  }
+
+  #[test]
+  fn current_branch_is_the_checked_out_branch() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+      },
+      |_, repo| {
+        let current = repo.current_branch().unwrap().unwrap();
+        assert_eq!(current.name, "feature");
+        assert!(current.is_head);
+      },
+    );
+  }
+
+  #[test]
+  fn current_branch_is_none_when_detached() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "--detach"]);
+      },
+      |_, repo| assert_eq!(repo.current_branch().unwrap(), None),
+    );
+  }
+
+  #[test]
+  fn listing_branches_when_detached_flags_none_as_head() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+        fixture.git(&["checkout", "-q", "--detach"]);
+      },
+      |_, repo| {
+        let branches = repo.local_branches().unwrap();
+        assert_eq!(branches.len(), 2);
+        assert!(branches.iter().all(|branch| !branch.is_head));
+        assert_eq!(repo.current_branch().unwrap(), None);
+      },
+    );
+  }
+
+  #[test]
+  fn branches_with_non_utf8_names_are_still_listed() {
+    each_backend(
+      |fixture| {
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9");
+        std::fs::write(fixture.path.join(".git/refs/heads").join(name), format!("{}\n", fixture.head())).unwrap();
+      },
+      |_, repo| {
+        let branches = repo.local_branches().unwrap();
+        let lossy = branches.iter().find(|branch| branch.name_is_lossy).expect("The branch wasn't listed");
+        assert_eq!(lossy.name, "caf\u{FFFD}");
+        assert!(branches.iter().any(|branch| branch.name == "main" && !branch.name_is_lossy));
+      },
+    );
+  }
+
+  #[test]
+  fn creating_a_branch_from_another_branch_starts_at_its_tip() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("base");
+      },
+      |fixture, repo| {
+        let created = repo.create_branch_from("topic", &local_branch(repo, "base")).unwrap();
+        assert_eq!(created.name, "topic");
+        assert_eq!(fixture.git(&["rev-parse", "topic"]), fixture.git(&["rev-parse", "base"]));
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn creating_a_branch_from_another_with_an_invalid_name_is_an_error() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("base");
+      },
+      |_, repo| {
+        assert!(repo.create_branch_from("not..valid", &local_branch(repo, "base")).is_err());
+        assert!(!repo.branch_exists("not..valid"));
+      },
+    );
+  }
+
+  #[test]
+  fn branch_commit_info_reads_the_tip_commit() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("feature.txt", "feature", "Add the feature\n\nWith a body");
+      },
+      |_, repo| {
+        let info = repo.branch_commit_info(&local_branch(repo, "feature")).unwrap();
+        assert_eq!(info.summary, "Add the feature");
+        assert_eq!(info.author, "Test Author");
+        assert_eq!(info.author_email, "author@example.com");
+      },
+    );
+  }
+
+  #[test]
+  fn branches_sorted_by_date_put_the_newest_first() {
+    each_backend(
+      |fixture| {
+        for (name, time) in [("old", 1_000_000_000), ("newest", 1_200_000_000), ("middle", 1_100_000_000)] {
+          fixture.git(&["checkout", "-q", "-b", name, "main"]);
+          fixture.commit_at(&format!("{}.txt", name), name, time);
+        }
+        // main keeps its initial commit, made just now
+        fixture.git(&["checkout", "-q", "main"]);
+      },
+      |_, repo| {
+        let names: Vec<String> =
+          repo.local_branches_sorted_by_date().unwrap().into_iter().map(|branch| branch.name).collect();
+        assert_eq!(names, ["main", "newest", "middle", "old"]);
+      },
+    );
+  }
+
+  #[test]
+  fn is_merged_for_merged_unmerged_and_head_branches() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "merged"]);
+        fixture.branch_with_commit("unmerged");
+      },
+      |_, repo| {
+        assert!(repo.is_merged(&local_branch(repo, "merged")).unwrap());
+        assert!(!repo.is_merged(&local_branch(repo, "unmerged")).unwrap());
+        assert!(repo.is_merged(&local_branch(repo, "main")).unwrap());
+      },
+    );
+  }
+
+  #[test]
+  fn fetching_brings_in_new_remote_branches() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        let other = fixture.other_clone();
+        git_in(&other, &["checkout", "-q", "-b", "from-elsewhere"]);
+        git_in(&other, &["push", "-q", "origin", "from-elsewhere"]);
+      },
+      |_, repo| {
+        assert!(!repo.remote_branch_exists("origin/from-elsewhere"));
+        repo.fetch("origin").unwrap();
+        assert!(repo.remote_branch_exists("origin/from-elsewhere"));
+      },
+    );
+  }
+
+  #[test]
+  fn pushing_a_branch_creates_it_on_the_remote() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature");
+      },
+      |fixture, repo| {
+        repo.push_branch(&local_branch(repo, "feature"), "origin").unwrap();
+        let remote = fixture.dir.path().join("origin.git");
+        assert_eq!(git_in(&remote, &["rev-parse", "feature"]), fixture.git(&["rev-parse", "feature"]));
+      },
+    );
+  }
+
+  #[test]
+  fn pushing_a_branch_behind_the_remote_is_not_a_fast_forward() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        let other = fixture.other_clone();
+        git_in(&other, &["commit", "-q", "--allow-empty", "-m", "Elsewhere"]);
+        git_in(&other, &["push", "-q"]);
+        fixture.commit("local.txt", "local", "Diverged");
+      },
+      |_, repo| {
+        let err = repo.push_branch(&local_branch(repo, "main"), "origin").unwrap_err();
+        assert!(err.to_string().contains("not a fast-forward"), "{}", err);
+      },
+    );
+  }
+
+  /// A repository tracking `origin/main`, which someone else has pushed a commit to when `pushed_elsewhere` is set.
+  fn tracking_origin(fixture: &Fixture, pushed_elsewhere: bool) {
+    fixture.with_remote();
+    if pushed_elsewhere {
+      let other = fixture.other_clone();
+      git_in(&other, &["commit", "-q", "--allow-empty", "-m", "Elsewhere"]);
+      git_in(&other, &["push", "-q"]);
+    }
+  }
+
+  #[test]
+  fn pulling_an_up_to_date_branch() {
+    each_backend(
+      |fixture| tracking_origin(fixture, false),
+      |_, repo| assert_eq!(repo.pull_current("origin").unwrap(), PullOutcome::UpToDate),
+    );
+  }
+
+  #[test]
+  fn pulling_fast_forwards_a_branch_behind_its_upstream() {
+    each_backend(
+      |fixture| tracking_origin(fixture, true),
+      |fixture, repo| {
+        assert_eq!(repo.pull_current("origin").unwrap(), PullOutcome::FastForwarded);
+        assert_eq!(fixture.git(&["log", "-1", "--format=%s"]), "Elsewhere");
+      },
+    );
+  }
+
+  #[test]
+  fn pulling_a_diverged_branch_requires_a_merge() {
+    each_backend(
+      |fixture| {
+        tracking_origin(fixture, true);
+        fixture.commit("local.txt", "local", "Local");
+      },
+      |fixture, repo| {
+        assert_eq!(repo.pull_current("origin").unwrap(), PullOutcome::MergeRequired);
+        assert_eq!(fixture.git(&["log", "-1", "--format=%s"]), "Local");
+      },
+    );
+  }
+
+  #[test]
+  fn pulling_with_local_changes_is_refused() {
+    each_backend(
+      |fixture| {
+        tracking_origin(fixture, true);
+        fixture.write("README.md", "local changes\n");
+      },
+      |_, repo| assert!(repo.pull_current("origin").is_err()),
+    );
+  }
+
+  #[test]
+  fn merging_a_branch_ahead_fast_forwards() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+      },
+      |fixture, repo| {
+        assert_eq!(repo.merge_branch(&local_branch(repo, "feature")).unwrap(), MergeOutcome::FastForwarded);
+        assert_eq!(fixture.head(), fixture.git(&["rev-parse", "feature"]));
+      },
+    );
+  }
+
+  #[test]
+  fn merging_a_diverged_branch_makes_a_merge_commit() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+        fixture.commit("main.txt", "main", "Work on main");
+      },
+      |fixture, repo| {
+        assert_eq!(repo.merge_branch(&local_branch(repo, "feature")).unwrap(), MergeOutcome::Merged);
+        assert_eq!(fixture.git(&["rev-list", "--count", "--merges", "HEAD"]), "1");
+        assert!(fixture.path.join("feature.txt").exists());
+      },
+    );
+  }
+
+  #[test]
+  fn merging_conflicting_changes_lists_the_conflicts() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("README.md", "feature\n", "Feature readme");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.commit("README.md", "main\n", "Main readme");
+      },
+      |_, repo| {
+        let outcome = repo.merge_branch(&local_branch(repo, "feature")).unwrap();
+        assert_eq!(outcome, MergeOutcome::Conflicts(vec![String::from("README.md")]));
+        assert_eq!(repo.repo_state(), RepoState::Merge);
+      },
+    );
+  }
+
+  #[test]
+  fn merging_with_local_changes_is_refused() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+        fixture.write("README.md", "local changes\n");
+      },
+      |fixture, repo| {
+        assert!(repo.merge_branch(&local_branch(repo, "feature")).is_err());
+        assert_ne!(fixture.head(), fixture.git(&["rev-parse", "feature"]));
+      },
+    );
+  }
+
+  #[test]
+  fn deleting_a_remote_branch_removes_it_from_the_remote() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature");
+        fixture.git(&["push", "-q", "origin", "feature"]);
+      },
+      |fixture, repo| {
+        repo.delete_remote_branch(&GitRemoteBranch::new(String::from("origin/feature"))).unwrap();
+        let remote = fixture.dir.path().join("origin.git");
+        assert_eq!(git_in(&remote, &["branch", "--list", "feature"]), "");
+        assert!(!repo.remote_branch_exists("origin/feature"));
+      },
+    );
+  }
+
+  #[test]
+  fn pruning_removes_branches_deleted_on_the_remote() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature");
+        fixture.git(&["push", "-q", "origin", "feature"]);
+        let other = fixture.other_clone();
+        git_in(&other, &["push", "-q", "origin", "--delete", "feature"]);
+      },
+      |_, repo| {
+        assert!(repo.remote_branch_exists("origin/feature"));
+        assert_eq!(repo.prune_remote("origin").unwrap(), ["origin/feature"]);
+        assert!(!repo.remote_branch_exists("origin/feature"));
+        assert!(repo.remote_branch_exists("origin/main"));
+      },
+    );
+  }
+
+  /// Adds a linked worktree next to the repository with a new branch `linked` checked out in it.
+  fn add_worktree(fixture: &Fixture) -> PathBuf {
+    let path = fixture.dir.path().join("linked");
+    fixture.git(&["worktree", "add", "-q", "-b", "linked", path.to_str().unwrap()]);
+    path
+  }
+
+  #[test]
+  fn worktrees_lists_linked_worktrees_with_their_branch() {
+    each_backend(
+      |fixture| {
+        add_worktree(fixture);
+      },
+      |fixture, repo| {
+        let worktrees = repo.worktrees().unwrap();
+        let linked = worktrees.iter().find(|worktree| worktree.name == "linked").expect("The worktree wasn't listed");
+        assert_eq!(linked.path.canonicalize().unwrap(), fixture.dir.path().join("linked"));
+        assert_eq!(linked.branch.as_deref(), Some("linked"));
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_a_branch_checked_out_in_another_worktree_is_refused() {
+    each_backend(
+      |fixture| {
+        add_worktree(fixture);
+      },
+      |fixture, repo| {
+        let err = repo.checkout_branch_from_name("linked").unwrap_err();
+        // Both name the worktree the branch is checked out in
+        assert!(err.to_string().contains(fixture.dir.path().join("linked").to_str().unwrap()), "{}", err);
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn tags_lists_lightweight_and_annotated_tags() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["tag", "v1"]);
+        fixture.commit("two.txt", "two", "Second release");
+        fixture.git(&["tag", "-a", "v2", "-m", "Release two"]);
+      },
+      |fixture, repo| {
+        let mut tags = repo.tags().unwrap();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(tags, [
+          GitTag::new(String::from("v1"), fixture.git(&["rev-parse", "HEAD~1"]), None),
+          GitTag::new(String::from("v2"), fixture.head(), Some(String::from("Release two"))),
+        ]);
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_a_tag_detaches_head_at_its_commit() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["tag", "-a", "v1", "-m", "Release one"]);
+        fixture.commit("two.txt", "two", "After the release");
+      },
+      |fixture, repo| {
+        repo.checkout_detached("v1").unwrap();
+        assert_eq!(fixture.head(), fixture.git(&["rev-parse", "v1^{commit}"]));
+        assert_eq!(repo.current_branch().unwrap(), None);
+        assert!(!fixture.path.join("two.txt").exists());
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_a_short_sha_detaches_head_at_it() {
+    each_backend(
+      |fixture| {
+        fixture.commit("two.txt", "two", "Second commit");
+      },
+      |fixture, repo| {
+        let first = fixture.git(&["rev-parse", "HEAD~1"]);
+        repo.checkout_detached(&first[..8]).unwrap();
+        assert_eq!(fixture.head(), first);
+        assert_eq!(repo.current_branch().unwrap(), None);
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_a_commit_with_local_changes_is_refused() {
+    each_backend(
+      |fixture| {
+        fixture.commit("README.md", "second\n", "Second commit");
+        fixture.write("README.md", "local changes\n");
+      },
+      |fixture, repo| {
+        assert!(repo.checkout_detached("HEAD~1").is_err());
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn stashing_cleans_the_tree_and_popping_brings_the_changes_back() {
+    each_backend(
+      |fixture| fixture.write("README.md", "local changes\n"),
+      |fixture, repo| {
+        repo.stash_save("Work in progress").unwrap();
+        assert!(!repo.is_working_tree_dirty().unwrap());
+        assert_eq!(std::fs::read_to_string(fixture.path.join("README.md")).unwrap(), "init\n");
+        repo.stash_pop(0).unwrap();
+        assert_eq!(std::fs::read_to_string(fixture.path.join("README.md")).unwrap(), "local changes\n");
+        assert!(repo.stashes().unwrap().is_empty());
+      },
+    );
+  }
+
+  #[test]
+  fn stashes_are_listed_newest_first() {
+    each_backend(
+      |_| {},
+      |fixture, repo| {
+        fixture.write("README.md", "first\n");
+        repo.stash_save("First").unwrap();
+        fixture.write("README.md", "second\n");
+        repo.stash_save("Second").unwrap();
+        let stashes = repo.stashes().unwrap();
+        let listed: Vec<(usize, bool, bool)> = stashes
+          .iter()
+          .map(|stash| (stash.index, stash.message.contains("Second"), stash.message.contains("First")))
+          .collect();
+        assert_eq!(listed, [(0, true, false), (1, false, true)]);
+      },
+    );
+  }
+
+  #[test]
+  fn diff_between_branches_counts_files_and_lines() {
+    each_backend(
+      |fixture| {
+        fixture.commit("old.txt", "old\n", "Add a file to remove");
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.write("README.md", "changed\n");
+        fixture.write("new.txt", "one\ntwo\n");
+        fixture.git(&["rm", "-q", "old.txt"]);
+        fixture.git(&["add", "README.md", "new.txt"]);
+        fixture.git(&["commit", "-q", "-m", "Change, add and remove"]);
+        fixture.git(&["checkout", "-q", "main"]);
+      },
+      |_, repo| {
+        let mut summary = repo.diff_branches(&local_branch(repo, "main"), &local_branch(repo, "feature")).unwrap();
+        summary.files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(summary, DiffSummary {
+          files_changed: 3,
+          insertions: 3,
+          deletions: 2,
+          files: vec![
+            FileChange::new(String::from("README.md"), ChangeType::Modified),
+            FileChange::new(String::from("new.txt"), ChangeType::Added),
+            FileChange::new(String::from("old.txt"), ChangeType::Deleted),
+          ],
+        });
+      },
+    );
+  }
+
+  #[test]
+  fn the_branch_cache_loads_once_until_invalidated() {
+    let cache = BranchCache::default();
+    let loads = Cell::new(0);
+    let load = || {
+      loads.set(loads.get() + 1);
+      Ok(vec![GitBranch::new(String::from("main"))])
+    };
+    assert_eq!(cache.get_or_load(load).unwrap(), cache.get_or_load(load).unwrap());
+    assert_eq!(loads.get(), 1);
+    cache.invalidate();
+    cache.get_or_load(load).unwrap();
+    assert_eq!(loads.get(), 2);
+  }
+
+  #[test]
+  fn creating_a_branch_invalidates_the_cached_branches() {
+    each_backend(
+      |_| {},
+      |fixture, repo| {
+        let names = |repo: &dyn GitRepo| -> Vec<String> {
+          let mut names: Vec<String> = repo.local_branches().unwrap().into_iter().map(|branch| branch.name).collect();
+          names.sort();
+          names
+        };
+        assert_eq!(names(repo), ["main"]);
+        // Made behind the repository's back, so it is only seen once the cache is invalidated
+        fixture.git(&["branch", "outside"]);
+        assert_eq!(names(repo), ["main"]);
+        repo.create_branch(&GitBranch::new(String::from("created"))).unwrap();
+        assert_eq!(names(repo), ["created", "main", "outside"]);
+      },
+    );
+  }
+
+  #[tokio::test]
+  async fn loading_in_the_background_yields_every_branch_in_batches() {
+    let fixture = Fixture::new();
+    // Written as loose refs, one git command per branch would make the test slow
+    let head = format!("{}\n", fixture.head());
+    for i in 0..LOAD_BATCH_SIZE {
+      std::fs::write(fixture.path.join(".git/refs/heads").join(format!("branch-{:03}", i)), &head).unwrap();
+    }
+    let path = fixture.path.clone();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let seen = batches.clone();
+    let branches = load_local_branches_in_background(
+      move || Git2Repo::from_path(&path),
+      move |batch: Vec<GitBranch>| seen.lock().unwrap().push(batch.len()),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(branches.len(), LOAD_BATCH_SIZE + 1);
+    assert_eq!(*batches.lock().unwrap(), [LOAD_BATCH_SIZE]);
+  }
+
+  #[test]
+  fn deleting_several_branches_reports_each_result() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "merged"]);
+        fixture.branch_with_commit("unmerged");
+      },
+      |_, repo| {
+        let branches = [local_branch(repo, "unmerged"), local_branch(repo, "merged")];
+        let results: Vec<(String, bool)> =
+          repo.delete_branches(&branches).into_iter().map(|(branch, result)| (branch.name, result.is_ok())).collect();
+        assert_eq!(results, [(String::from("unmerged"), false), (String::from("merged"), true)]);
+        assert!(!repo.branch_exists("merged"));
+      },
+    );
+  }
+
+  #[test]
+  fn branch_log_is_newest_first_up_to_the_limit() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        // After the initial commit, which is made now
+        for (i, time) in [(1, 1_900_000_000), (2, 2_000_000_000), (3, 2_100_000_000)] {
+          fixture.commit_at(&format!("{}.txt", i), &format!("Commit {}", i), time);
+        }
+      },
+      |_, repo| {
+        let feature = local_branch(repo, "feature");
+        let summaries = |limit| -> Vec<String> {
+          repo.branch_log(&feature, limit).unwrap().into_iter().map(|commit| commit.summary).collect()
+        };
+        assert_eq!(summaries(2), ["Commit 3", "Commit 2"]);
+        // Fewer commits than the limit are all returned
+        assert_eq!(summaries(10), ["Commit 3", "Commit 2", "Commit 1", "Initial commit"]);
+      },
+    );
+  }
+
+  #[test]
+  fn branch_names_are_rejected_with_the_reason() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "taken"]);
+      },
+      |_, repo| {
+        assert_eq!(repo.validate_branch_name_detailed("").unwrap(), Some(InvalidBranchName::Empty));
+        assert_eq!(repo.validate_branch_name_detailed("HEAD").unwrap(), Some(InvalidBranchName::Reserved));
+        assert_eq!(
+          repo.validate_branch_name_detailed("bad..name").unwrap(),
+          Some(InvalidBranchName::IllegalCharacters)
+        );
+        assert_eq!(
+          repo.validate_branch_name_detailed("no spaces").unwrap(),
+          Some(InvalidBranchName::IllegalCharacters)
+        );
+        assert_eq!(repo.validate_branch_name_detailed("taken").unwrap(), Some(InvalidBranchName::AlreadyExists));
+        assert_eq!(repo.validate_branch_name_detailed("feature/fresh").unwrap(), None);
+      },
+    );
+  }
+
+  #[test]
+  fn a_name_matching_a_remote_branch_is_valid_with_a_warning() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.git(&["push", "-q", "origin", "main:foo"]);
+      },
+      |_, repo| {
+        assert_eq!(repo.validate_branch_name_detailed("foo").unwrap(), None);
+        assert_eq!(
+          repo.branch_name_warning("foo").unwrap(),
+          Some(BranchNameWarning::MatchesRemote(String::from("origin/foo")))
+        );
+        assert_eq!(repo.branch_name_warning("bar").unwrap(), None);
+      },
+    );
+  }
+
+  #[test]
+  fn the_head_reflog_lists_commits_newest_first() {
+    each_backend(
+      |fixture| {
+        fixture.commit("a.txt", "a", "Second commit");
+        fixture.commit("b.txt", "b", "Third commit");
+      },
+      |fixture, repo| {
+        let entries = repo.reflog_entries("HEAD").unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].commit_id, fixture.head());
+        assert!(entries[0].message.contains("Third commit"), "{}", entries[0].message);
+        assert!(entries[1].message.contains("Second commit"), "{}", entries[1].message);
+        assert!(entries[0].time > 0);
+      },
+    );
+  }
+
+  #[test]
+  fn a_ref_without_a_reflog_has_no_entries() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["update-ref", "refs/custom/no-log", "HEAD"]);
+      },
+      |_, repo| {
+        assert_eq!(repo.reflog_entries("refs/custom/no-log").unwrap(), Vec::new());
+        assert_eq!(repo.reflog_entries("refs/heads/missing").unwrap(), Vec::new());
+      },
+    );
+  }
+
+  #[test]
+  fn an_upstream_deleted_on_the_remote_is_gone() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.git(&["push", "-q", "-u", "origin", "feature"]);
+        fixture.git(&["checkout", "-q", "-b", "local"]);
+        fixture.git(&["update-ref", "-d", "refs/remotes/origin/feature"]);
+      },
+      |_, repo| {
+        let feature = local_branch(repo, "feature");
+        assert_eq!(feature.upstream_status, UpstreamStatus::Gone);
+        assert_eq!(feature.upstream.map(|upstream| upstream.name).as_deref(), Some("origin/feature"));
+        assert_eq!(local_branch(repo, "main").upstream_status, UpstreamStatus::Tracked);
+        assert_eq!(local_branch(repo, "local").upstream_status, UpstreamStatus::None);
+      },
+    );
+  }
+
+  #[test]
+  fn merged_branches_are_the_ones_reachable_from_the_target() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "at-main"]);
+        fixture.branch_with_commit("merged-in");
+        fixture.git(&["merge", "-q", "--no-ff", "-m", "Merge merged-in", "merged-in"]);
+        fixture.branch_with_commit("unmerged");
+        fixture.git(&["checkout", "-q", "-b", "current"]);
+      },
+      |_, repo| {
+        let main = local_branch(repo, "main");
+        let mut names: Vec<String> =
+          repo.merged_branches(&main).unwrap().into_iter().map(|branch| branch.name).collect();
+        names.sort();
+        assert_eq!(names, ["at-main", "merged-in"]);
+      },
+    );
+  }
+
+  fn default_branch_name(repo: &dyn GitRepo) -> Option<String> {
+    repo.default_branch().unwrap().map(|branch| branch.name)
+  }
+
+  #[test]
+  fn the_default_branch_follows_the_remote_head() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.git(&["branch", "trunk"]);
+        fixture.git(&["push", "-q", "origin", "trunk"]);
+        fixture.git(&["remote", "set-head", "origin", "trunk"]);
+      },
+      |_, repo| assert_eq!(default_branch_name(repo).as_deref(), Some("trunk")),
+    );
+  }
+
+  #[test]
+  fn the_default_branch_falls_back_to_main_then_master() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "master"]);
+      },
+      |_, repo| assert_eq!(default_branch_name(repo).as_deref(), Some("main")),
+    );
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "-m", "master"]);
+      },
+      |_, repo| assert_eq!(default_branch_name(repo).as_deref(), Some("master")),
+    );
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "-m", "trunk"]);
+      },
+      |_, repo| assert_eq!(default_branch_name(repo), None),
+    );
+  }
+
+  #[test]
+  fn checking_out_the_previous_branch_goes_back() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "a"]);
+        fixture.git(&["branch", "b"]);
+      },
+      |_, repo| {
+        repo.checkout_branch(&local_branch(repo, "a")).unwrap();
+        repo.checkout_branch(&local_branch(repo, "b")).unwrap();
+        repo.checkout_previous().unwrap();
+        assert_eq!(repo.current_branch().unwrap().map(|branch| branch.name).as_deref(), Some("a"));
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_the_previous_branch_without_one_is_an_error() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        assert!(matches!(repo.checkout_previous(), Err(Error::NoPreviousBranch)));
+        assert_eq!(repo.current_branch().unwrap().map(|branch| branch.name).as_deref(), Some("main"));
+      },
+    );
+  }
+
+  #[test]
+  fn deleting_a_protected_branch_is_refused() {
+    let fixture = Fixture::new();
+    for name in ["release/1", "git2-feature", "cli-feature"] {
+      fixture.git(&["branch", name]);
+    }
+    fixture.git(&["checkout", "-q", "-b", "current"]);
+    let protected = ProtectedBranches::new(vec![String::from("release/*")]);
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(fixture.git2().protected(protected.clone()))),
+      ("cli", Box::new(fixture.cli().protected(protected))),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        // main is protected as the default branch without being listed
+        for name in ["release/1", "main"] {
+          let err = repo.delete_branch(&local_branch(&*repo, name)).unwrap_err();
+          assert!(matches!(err, Error::ProtectedBranch(ref protected) if protected == name), "{}", err);
+        }
+        let feature = format!("{}-feature", backend);
+        repo.delete_branch(&local_branch(&*repo, &feature)).unwrap();
+        assert_eq!(fixture.git(&["branch", "--list", &feature]), "");
+      });
+    }
+    assert_ne!(fixture.git(&["branch", "--list", "release/1"]), "");
+  }
+
+  fn dry_run_backends(fixture: &Fixture) -> [(&'static str, Box<dyn GitRepo>); 2] {
+    [("git2", Box::new(fixture.git2().dry_run(true))), ("cli", Box::new(fixture.cli().dry_run(true)))]
+  }
+
+  #[test]
+  fn a_dry_run_delete_leaves_the_branch() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "merged"]);
+    let tip = fixture.branch_with_commit("unmerged");
+    for (backend, repo) in dry_run_backends(&fixture) {
+      naming_backend(backend, || {
+        assert_eq!(repo.delete_branch(&local_branch(&*repo, "merged")).unwrap(), fixture.head());
+        assert_eq!(fixture.git(&["rev-parse", "merged"]), fixture.head());
+        // Refused just as a real delete would be
+        let err = repo.delete_branch(&local_branch(&*repo, "unmerged")).unwrap_err();
+        assert!(err.to_string().contains("not fully merged"), "{}", err);
+        assert_eq!(repo.force_delete_branch(&local_branch(&*repo, "unmerged")).unwrap(), tip);
+        assert_eq!(fixture.git(&["rev-parse", "unmerged"]), tip);
+      });
+    }
+  }
+
+  #[test]
+  fn a_dry_run_leaves_the_upstream_config_alone() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.git(&["branch", "feature"]);
+    fixture.git(&["push", "-q", "origin", "feature"]);
+    let config_before = fixture.git(&["config", "--list", "--local"]);
+    for (backend, repo) in dry_run_backends(&fixture) {
+      naming_backend(backend, || {
+        let upstream = GitRemoteBranch::new(String::from("origin/feature"));
+        repo.set_upstream(&local_branch(&*repo, "feature"), Some(&upstream)).unwrap();
+        repo.set_upstream(&local_branch(&*repo, "main"), None).unwrap();
+        // Still checked, so a dry run fails where a real one would
+        let missing = GitRemoteBranch::new(String::from("origin/missing"));
+        assert!(repo.set_upstream(&local_branch(&*repo, "feature"), Some(&missing)).is_err());
+        assert_eq!(fixture.git(&["config", "--list", "--local"]), config_before);
+      });
+    }
+  }
+
+  #[test]
+  fn a_dry_run_prunes_nothing() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.git(&["branch", "gone"]);
+    fixture.git(&["push", "-q", "-u", "origin", "gone"]);
+    fixture.git(&["push", "-q", "origin", "--delete", "gone"]);
+    fixture.git(&["update-ref", "refs/remotes/origin/gone", "HEAD"]);
+    for (backend, repo) in dry_run_backends(&fixture) {
+      naming_backend(backend, || {
+        assert_eq!(repo.prune_remote("origin").unwrap(), Vec::<String>::new());
+        assert_eq!(fixture.git(&["rev-parse", "origin/gone"]), fixture.head());
+      });
+    }
+  }
+
+  #[test]
+  fn a_dry_run_leaves_created_renamed_and_checked_out_branches_alone() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "feature"]);
+    for (backend, repo) in dry_run_backends(&fixture) {
+      naming_backend(backend, || {
+        repo.create_branch(&GitBranch::new(String::from("created"))).unwrap();
+        repo.rename_branch(&local_branch(&*repo, "feature"), "renamed").unwrap();
+        repo.checkout_branch(&local_branch(&*repo, "feature")).unwrap();
+        assert_eq!(fixture.git(&["branch", "--format=%(refname:short)"]), "feature\nmain");
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      });
+    }
+  }
+
+  #[test]
+  fn creating_and_checking_out_a_branch() {
+    each_backend(
+      |_| {},
+      |fixture, repo| {
+        repo.create_and_checkout(&GitBranch::new(String::from("feature"))).unwrap();
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "feature");
+        assert_eq!(fixture.git(&["rev-parse", "feature"]), fixture.git(&["rev-parse", "main"]));
+      },
+    );
+  }
+
+  #[test]
+  fn a_failed_checkout_removes_the_created_branch() {
+    each_backend(
+      |fixture| {
+        // Held by another git process as far as anything writing HEAD can tell
+        fixture.write(".git/HEAD.lock", "");
+      },
+      |fixture, repo| {
+        assert!(repo.create_and_checkout(&GitBranch::new(String::from("feature"))).is_err());
+        assert_eq!(fixture.git(&["branch", "--list", "feature"]), "");
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn a_proposed_branch_name_is_valid_and_unique() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "wip/taken"]);
+      },
+      |fixture, repo| {
+        let short_sha = fixture.git(&["rev-parse", "--short", "HEAD"]);
+        assert_eq!(repo.propose_branch_name("wip/{sha}").unwrap(), format!("wip/{}", short_sha));
+        let name = repo.propose_branch_name("wip/taken").unwrap();
+        assert_eq!(name, "wip/taken-2");
+        assert_eq!(repo.validate_branch_name_detailed(&name).unwrap(), None);
+        assert!(repo.propose_branch_name("bad..{sha}").is_err());
+      },
+    );
+  }
+
+  #[test]
+  fn an_empty_repository_has_no_branches_and_reports_no_commits() {
+    let fixture = Fixture::empty();
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [("git2", Box::new(fixture.git2())), ("cli", Box::new(fixture.cli()))];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        assert_eq!(repo.local_branches().unwrap(), Vec::new());
+        let current = repo.current_branch().unwrap().unwrap();
+        assert_eq!((current.name.as_str(), current.is_unborn, current.commit_id), ("main", true, None));
+        let created = repo.create_branch(&GitBranch::new(String::from("feature")));
+        assert!(created.as_ref().is_err_and(|err| err.to_string().contains("no commits yet")), "{:?}", created);
+        assert!(matches!(repo.propose_branch_name("wip/{sha}"), Err(Error::NoCommits)));
+      });
+    }
+  }
+
+  fn counted(upstream_status: UpstreamStatus, ahead_behind: Option<(usize, usize)>) -> GitBranch {
+    GitBranch { upstream_status, ahead_behind, ..GitBranch::new(String::from("feature")) }
+  }
+
+  #[test]
+  fn unpushed_commits_are_ahead_of_the_upstream_or_have_nowhere_to_go() {
+    assert!(counted(UpstreamStatus::Tracked, Some((2, 0))).has_unpushed_commits());
+    assert!(counted(UpstreamStatus::Tracked, Some((1, 3))).has_unpushed_commits());
+    assert!(!counted(UpstreamStatus::Tracked, Some((0, 0))).has_unpushed_commits());
+    assert!(!counted(UpstreamStatus::Tracked, Some((0, 4))).has_unpushed_commits());
+    assert!(counted(UpstreamStatus::None, None).has_unpushed_commits());
+    assert!(counted(UpstreamStatus::Gone, None).has_unpushed_commits());
+    // Not counted yet
+    assert!(counted(UpstreamStatus::Tracked, None).has_unpushed_commits());
+  }
+
+  #[test]
+  fn a_submodule_opens_its_superproject() {
+    let fixture = Fixture::new();
+    fixture.git(&["branch", "outer-branch"]);
+    let submodule = fixture.with_submodule();
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(Git2Repo::from_path(&submodule).unwrap())),
+      ("cli", Box::new(GitCliRepo::from_path(&submodule).unwrap())),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        assert!(repo.is_submodule());
+        assert_eq!(repo.superproject_path(), Some(fixture.path.clone()));
+        let superproject = repo.open_superproject().unwrap();
+        assert!(!superproject.is_submodule());
+        let names: Vec<String> = superproject.local_branches().unwrap().into_iter().map(|branch| branch.name).collect();
+        assert_eq!(names, ["main", "outer-branch"]);
+        assert!(matches!(superproject.open_superproject(), Err(Error::NotASubmodule)));
+      });
+    }
+  }
+
+  #[test]
+  fn failures_say_what_was_being_attempted() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        let err = repo.checkout_branch_from_name("missing").unwrap_err();
+        assert!(err.to_string().starts_with("Failed to check out missing: "), "{}", err);
+        let err = repo.rename_branch(&local_branch(repo, "main"), "bad..name").unwrap_err();
+        assert!(err.to_string().starts_with("Failed to rename main to bad..name: "), "{}", err);
+        let err = repo.push_branch(&local_branch(repo, "main"), "nowhere").unwrap_err();
+        assert!(err.to_string().starts_with("Failed to push main to nowhere: "), "{}", err);
+        let err = repo.branch_log(&GitBranch::new(String::from("missing")), 5).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read the log of missing: "), "{}", err);
+      },
+    );
+  }
+
+  #[test]
+  fn errors_that_say_what_went_wrong_keep_their_type() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        assert!(matches!(repo.delete_branch(&local_branch(repo, "main")), Err(Error::ProtectedBranch(_))));
+        assert!(matches!(repo.abort_operation(), Err(Error::NoOperationInProgress)));
+      },
+    );
+  }
+
+  #[test]
+  fn a_conflicted_merge_is_detected_and_aborted() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("README.md", "feature\n", "Change on feature");
+        fixture.git(&["checkout", "-q", "main"]);
+        let main_tip = fixture.commit("README.md", "main\n", "Change on main");
+        // Fails as the changes conflict, leaving the merge in progress
+        let merge = Command::new("git").args(["merge", "feature"]).current_dir(&fixture.path).output().unwrap();
+        assert!(!merge.status.success());
+        assert_eq!(fixture.head(), main_tip);
+      },
+      |fixture, repo| {
+        assert_eq!(repo.repo_state(), RepoState::Merge);
+        let main_tip = fixture.head();
+        repo.abort_operation().unwrap();
+        assert_eq!(repo.repo_state(), RepoState::Clean);
+        assert_eq!(fixture.head(), main_tip);
+        assert_eq!(fs::read_to_string(fixture.path.join("README.md")).unwrap(), "main\n");
+        assert!(matches!(repo.abort_operation(), Err(Error::NoOperationInProgress)));
+      },
+    );
+  }
+
+  #[test]
+  fn ignored_files_never_make_the_working_tree_dirty() {
+    let fixture = Fixture::new();
+    fixture.commit(".gitignore", "target/\n", "Ignore the build output");
+    fixture.write("target/out.o", "");
+    fixture.write(".git/info/exclude", "*.local\n");
+    fixture.write("settings.local", "");
+    let global_excludes = fixture.dir.path().join("global-ignore");
+    fs::write(&global_excludes, "*.swp\n").unwrap();
+    fixture.git(&["config", "core.excludesFile", global_excludes.to_str().unwrap()]);
+    fixture.write("notes.swp", "");
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(fixture.git2().untracked_is_dirty(true))),
+      ("cli", Box::new(fixture.cli().untracked_is_dirty(true))),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        assert!(!repo.is_working_tree_dirty().unwrap());
+      });
+    }
+  }
+
+  #[test]
+  fn untracked_files_only_make_the_working_tree_dirty_when_asked() {
+    let fixture = Fixture::new();
+    fixture.write("new.txt", "");
+    let repos: [(&str, Box<dyn GitRepo>, bool); 4] = [
+      ("git2", Box::new(fixture.git2()), false),
+      ("cli", Box::new(fixture.cli()), false),
+      ("git2", Box::new(fixture.git2().untracked_is_dirty(true)), true),
+      ("cli", Box::new(fixture.cli().untracked_is_dirty(true)), true),
+    ];
+    for (backend, repo, dirty) in repos {
+      naming_backend(backend, || {
+        assert_eq!(repo.is_working_tree_dirty().unwrap(), dirty);
+      });
+    }
+  }
+
+  #[test]
+  fn commit_details_show_the_full_message_and_optionally_the_diff() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.write("notes.txt", "a note\n");
+        fixture.git(&["add", "notes.txt"]);
+        fixture.git(&["commit", "-q", "-m", "Add notes", "-m", "With a longer body."]);
+        fixture.git(&["checkout", "-q", "main"]);
+      },
+      |fixture, repo| {
+        let feature = local_branch(repo, "feature");
+        let details = repo.commit_details(&feature, false).unwrap();
+        assert!(details.contains(&fixture.git(&["rev-parse", "feature"])), "{}", details);
+        assert!(details.contains("Add notes") && details.contains("With a longer body."), "{}", details);
+        assert!(details.contains("Test Author"), "{}", details);
+        assert!(!details.contains("+a note"), "{}", details);
+        let with_diff = repo.commit_details(&feature, true).unwrap();
+        assert!(with_diff.contains("notes.txt") && with_diff.contains("+a note"), "{}", with_diff);
+      },
+    );
+  }
+
+  #[test]
+  fn divergence_counts_each_side_since_the_merge_base() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["tag", "fork"]);
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("a.txt", "a", "First on feature");
+        fixture.commit("b.txt", "b", "Second on feature");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.commit("c.txt", "c", "On main");
+      },
+      |fixture, repo| {
+        let divergence = repo.divergence(&local_branch(repo, "feature"), &local_branch(repo, "main")).unwrap();
+        let merge_base = fixture.git(&["rev-parse", "fork"]);
+        assert_eq!(divergence, Divergence { merge_base, ahead: 2, behind: 1 });
+      },
+    );
+  }
+
+  #[test]
+  fn divergence_without_shared_history_is_an_error() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "--orphan", "unrelated"]);
+        fixture.commit("other.txt", "other", "Unrelated root");
+      },
+      |_, repo| {
+        let err = repo.divergence(&local_branch(repo, "unrelated"), &local_branch(repo, "main")).unwrap_err();
+        assert!(matches!(err, Error::NoCommonHistory(ref base) if base == "main"), "{}", err);
+      },
+    );
+  }
+
+  /// The fixture with `notes.txt` committed twice, the reset target being the first of them.
+  fn reset_fixture(fixture: &Fixture) {
+    fixture.commit("notes.txt", "first\n", "First notes");
+    fixture.git(&["tag", "target"]);
+    fixture.commit("notes.txt", "second\n", "Second notes");
+  }
+
+  #[test]
+  fn each_reset_mode_puts_back_what_it_should() {
+    for (mode, staged, contents) in
+      [(ResetMode::Soft, "notes.txt", "second\n"), (ResetMode::Mixed, "", "second\n"), (ResetMode::Hard, "", "first\n")]
+    {
+      each_backend(reset_fixture, |fixture, repo| {
+        named(&format!("{} mode", mode), || {
+          repo.reset_current("target", mode).unwrap();
+          assert_eq!(fixture.head(), fixture.git(&["rev-parse", "target"]));
+          assert_eq!(fixture.git(&["diff", "--cached", "--name-only"]), staged);
+          assert_eq!(fs::read_to_string(fixture.path.join("notes.txt")).unwrap(), contents);
+        })
+      });
+    }
+  }
+
+  #[test]
+  fn a_detached_head_is_only_reset_when_allowed() {
+    let fixture = Fixture::new();
+    reset_fixture(&fixture);
+    fixture.git(&["checkout", "-q", "--detach"]);
+    let refused: [(&str, Box<dyn GitRepo>); 2] = [("git2", Box::new(fixture.git2())), ("cli", Box::new(fixture.cli()))];
+    for (backend, repo) in refused {
+      naming_backend(backend, || {
+        assert!(matches!(repo.reset_current("target", ResetMode::Soft), Err(Error::DetachedHead)));
+      });
+    }
+    let allowed: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(fixture.git2().allow_detached_reset(true))),
+      ("cli", Box::new(fixture.cli().allow_detached_reset(true))),
+    ];
+    for (backend, repo) in allowed {
+      naming_backend(backend, || {
+        fixture.git(&["checkout", "-q", "--detach", "main"]);
+        repo.reset_current("target", ResetMode::Soft).unwrap();
+        assert_eq!(fixture.head(), fixture.git(&["rev-parse", "target"]));
+      });
+    }
+  }
+
+  #[test]
+  fn a_clean_cherry_pick_commits_the_change_on_the_current_branch() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("other.txt", "other\n", "Unwanted change");
+        fixture.commit("wanted.txt", "wanted\n", "Wanted change");
+        fixture.git(&["checkout", "-q", "main"]);
+      },
+      |fixture, repo| {
+        let picked = fixture.git(&["rev-parse", "feature"]);
+        assert_eq!(repo.cherry_pick(&picked).unwrap(), MergeOutcome::Merged);
+        assert_eq!(fixture.git(&["log", "-1", "--format=%s"]), "Wanted change");
+        assert!(fixture.path.join("wanted.txt").exists());
+        assert!(!fixture.path.join("other.txt").exists());
+        assert_eq!(repo.repo_state(), RepoState::Clean);
+      },
+    );
+  }
+
+  #[test]
+  fn a_conflicting_cherry_pick_reports_the_conflicted_paths() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("README.md", "feature\n", "Change on feature");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.commit("README.md", "main\n", "Change on main");
+      },
+      |fixture, repo| {
+        let picked = fixture.git(&["rev-parse", "feature"]);
+        assert_eq!(repo.cherry_pick(&picked).unwrap(), MergeOutcome::Conflicts(vec![String::from("README.md")]));
+        assert_eq!(repo.repo_state(), RepoState::CherryPick);
+      },
+    );
+  }
+
+  #[test]
+  fn a_cherry_pick_is_refused_on_a_dirty_tree() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+        fixture.write("README.md", "uncommitted\n");
+      },
+      |fixture, repo| {
+        let err = repo.cherry_pick(&fixture.git(&["rev-parse", "feature"])).unwrap_err();
+        assert!(err.to_string().contains("uncommitted changes"), "{}", err);
+        assert_eq!(fixture.git(&["log", "-1", "--format=%s"]), "Initial commit");
+      },
+    );
+  }
+
+  #[test]
+  fn commits_sharing_a_prefix_get_longer_abbreviations_than_a_unique_one() {
+    let fixture = Fixture::new();
+    fixture.git(&["config", "core.abbrev", "4"]);
+    // Commits that aren't on any branch, until two of them start with the same four characters
+    let raw = git2::Repository::open(&fixture.path).unwrap();
+    let head = raw.head().unwrap().peel_to_commit().unwrap();
+    let signature = git2::Signature::now("Test Author", "author@example.com").unwrap();
+    let mut by_prefix = std::collections::HashMap::new();
+    let (first, second) = (0..)
+      .find_map(|n| {
+        let message = format!("Commit {}", n);
+        let id = raw.commit(None, &signature, &signature, &message, &head.tree().unwrap(), &[&head]).unwrap();
+        let id = id.to_string();
+        by_prefix.insert(id[..4].to_string(), id.clone()).map(|earlier| (earlier, id))
+      })
+      .unwrap();
+    let objects = fixture.git(&["cat-file", "--batch-all-objects", "--batch-check=%(objectname)"]);
+    let unique = by_prefix
+      .values()
+      .find(|id| objects.lines().filter(|object| object.starts_with(&id[..4])).count() == 1)
+      .unwrap()
+      .clone();
+
+    let backends: [(&str, Box<dyn GitRepo>); 2] =
+      [("git2", Box::new(fixture.git2().abbrev_length(4))), ("cli", Box::new(fixture.cli().abbrev_length(4)))];
+    for (backend, repo) in backends {
+      naming_backend(backend, || {
+        let (first_short, second_short) = (repo.short_id(&first).unwrap(), repo.short_id(&second).unwrap());
+        assert!(first_short.len() > 4 && first.starts_with(&first_short), "{}", first_short);
+        assert!(second_short.len() > 4 && second.starts_with(&second_short), "{}", second_short);
+        assert_ne!(first_short, second_short);
+        assert_eq!(repo.short_id(&unique).unwrap(), unique[..4]);
+      });
+    }
+  }
+
+  #[test]
+  fn delete_risk_tells_merged_unmerged_and_checked_out_branches_apart() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "merged"]);
+        fixture.git(&["checkout", "-q", "-b", "unmerged"]);
+        fixture.commit("one.txt", "one", "One");
+        fixture.commit("two.txt", "two", "Two");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.git(&["branch", "elsewhere"]);
+        let worktree = fixture.dir.path().join("elsewhere");
+        fixture.git(&["worktree", "add", "-q", worktree.to_str().unwrap(), "elsewhere"]);
+      },
+      |fixture, repo| {
+        assert_eq!(repo.delete_risk(&local_branch(repo, "merged")).unwrap(), DeleteRisk::Safe);
+        assert_eq!(repo.delete_risk(&local_branch(repo, "unmerged")).unwrap(), DeleteRisk::UnmergedCommits(2));
+        let worktree = fixture.dir.path().join("elsewhere");
+        assert_eq!(
+          repo.delete_risk(&local_branch(repo, "elsewhere")).unwrap(),
+          DeleteRisk::CheckedOutElsewhere(worktree)
+        );
+      },
+    );
+  }
+
+  #[test]
+  fn branch_existence_is_checked_by_name_locally_and_on_remotes() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.git(&["branch", "feature"]);
+      },
+      |_, repo| {
+        assert!(repo.branch_exists("feature"));
+        assert!(!repo.branch_exists("missing"));
+        // Remote branch names aren't local ones
+        assert!(!repo.branch_exists("origin/main"));
+        assert!(repo.remote_branch_exists("origin/main"));
+        assert!(!repo.remote_branch_exists("origin/feature"));
+        assert!(!repo.remote_branch_exists("feature"));
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_the_upstream_detaches_at_the_remote_tip() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        let other = fixture.other_clone();
+        fs::write(other.join("remote.txt"), "remote").unwrap();
+        git_in(&other, &["add", "remote.txt"]);
+        git_in(&other, &["commit", "-q", "-m", "Remote change"]);
+        git_in(&other, &["push", "-q", "origin", "main"]);
+        fixture.git(&["fetch", "-q", "origin"]);
+      },
+      |fixture, repo| {
+        repo.checkout_upstream(&local_branch(repo, "main")).unwrap();
+        assert_eq!(fixture.head(), fixture.git(&["rev-parse", "origin/main"]));
+        assert_ne!(fixture.head(), fixture.git(&["rev-parse", "main"]));
+        assert!(repo.current_branch().unwrap().is_none());
+      },
+    );
+  }
+
+  #[test]
+  fn checking_out_the_upstream_of_an_untracked_branch_is_an_error() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "untracked"]);
+      },
+      |_, repo| {
+        let err = repo.checkout_upstream(&local_branch(repo, "untracked")).unwrap_err();
+        assert!(matches!(err, Error::NoUpstream(ref name) if name == "untracked"), "{}", err);
+      },
+    );
+  }
+
+  #[test]
+  fn a_commit_on_two_branches_is_found_on_both() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "first"]);
+        fixture.commit("shared.txt", "shared", "Fix the Login redirect");
+        fixture.git(&["branch", "second"]);
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.branch_with_commit("unrelated");
+      },
+      |fixture, repo| {
+        let found = repo.search_commits("login", 10).unwrap();
+        assert_eq!(found.iter().map(|commit| commit.summary.as_str()).collect::<Vec<_>>(), ["Fix the Login redirect"]);
+        assert_eq!(found[0].id, fixture.git(&["rev-parse", "first"]));
+
+        let mut containing: Vec<String> =
+          repo.branches_containing(&found[0].id).unwrap().into_iter().map(|branch| branch.name).collect();
+        containing.sort();
+        assert_eq!(containing, ["first", "second"]);
+      },
+    );
+  }
+
+  #[test]
+  fn an_orphan_branch_is_the_unborn_current_branch() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "--orphan", "fresh"]);
+      },
+      |_, repo| {
+        let current = repo.current_branch().unwrap().unwrap();
+        assert_eq!((current.name.as_str(), current.is_unborn, current.commit_id), ("fresh", true, None));
+        // main still has its commit and isn't unborn
+        assert!(!local_branch(repo, "main").is_unborn);
+        let err = repo.create_branch(&GitBranch::new(String::from("feature"))).unwrap_err();
+        assert!(matches!(err, Error::UnbornBranch(ref name) if name == "fresh"), "{}", err);
+      },
+    );
+  }
+
+  #[test]
+  fn the_branch_iterator_yields_what_local_branches_collects() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("feature/one");
+        fixture.git(&["branch", "two"]);
+        fixture.git(&["checkout", "-q", "--detach"]);
+      },
+      |_, repo| {
+        let streamed: Vec<GitBranch> = repo.local_branches_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(streamed, repo.local_branches().unwrap());
+        assert_eq!(streamed.len(), 3);
+      },
+    );
+  }
+
+  #[test]
+  fn archiving_tags_the_tip_and_deletes_the_branch() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("old-work");
+      },
+      |fixture, repo| {
+        let tip = fixture.git(&["rev-parse", "old-work"]);
+        repo.archive_branch(&local_branch(repo, "old-work")).unwrap();
+        assert!(!repo.branch_exists("old-work"));
+        let tag = format!("{}old-work", ARCHIVE_TAG_PREFIX);
+        assert_eq!(fixture.git(&["rev-parse", &format!("refs/tags/{}", tag)]), tip);
+        // Unreachable commits wouldn't be listed from the tag
+        assert_eq!(fixture.git(&["tag", "--contains", &tip]), tag);
+      },
+    );
+  }
+
+  #[test]
+  fn the_archive_tag_is_removed_when_the_branch_cant_be_deleted() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "current"]);
+      },
+      |fixture, repo| {
+        assert!(repo.archive_branch(&local_branch(repo, "current")).is_err());
+        assert!(repo.branch_exists("current"));
+        assert_eq!(fixture.git(&["tag", "--list"]), "");
+      },
+    );
+  }
+
+  /// Adds a bare repository as the remote `name` and pushes `main` to it.
+  fn add_remote(fixture: &Fixture, name: &str) {
+    let dir = format!("{}.git", name.replace('/', "-"));
+    git_in(fixture.dir.path(), &["init", "-q", "--bare", "-b", "main", &dir]);
+    fixture.git(&["remote", "add", name, fixture.dir.path().join(&dir).to_str().unwrap()]);
+    fixture.git(&["push", "-q", name, "main"]);
+  }
+
+  /// Pushes `branch` to `remote` with tracking, then deletes it there and prunes the remote tracking branch.
+  fn prune_upstream(fixture: &Fixture, remote: &str, branch: &str) {
+    fixture.git(&["branch", branch]);
+    fixture.git(&["push", "-q", "-u", remote, branch]);
+    fixture.git(&["push", "-q", remote, "--delete", branch]);
+    fixture.git(&["fetch", "-q", "--prune", remote]);
+  }
+
+  #[test]
+  fn a_branch_whose_upstream_was_pruned_is_orphaned() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        prune_upstream(fixture, "origin", "feature");
+        fixture.git(&["branch", "tracked"]);
+        fixture.git(&["push", "-q", "-u", "origin", "tracked"]);
+      },
+      |_, repo| {
+        let feature = local_branch(repo, "feature");
+        assert_eq!(feature.upstream_status, UpstreamStatus::Gone);
+        assert!(repo.is_orphaned_tracking(&feature).unwrap());
+        assert!(!repo.is_orphaned_tracking(&local_branch(repo, "tracked")).unwrap());
+        assert_eq!(repo.gone_branches().unwrap().into_iter().map(|branch| branch.name).collect::<Vec<_>>(), [
+          "feature"
+        ]);
+      },
+    );
+  }
+
+  #[test]
+  fn a_pruned_upstream_still_on_another_remote_isnt_orphaned() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        add_remote(fixture, "fork");
+        prune_upstream(fixture, "origin", "feature");
+        fixture.git(&["push", "-q", "fork", "feature"]);
+      },
+      |_, repo| assert!(!repo.is_orphaned_tracking(&local_branch(repo, "feature")).unwrap()),
+    );
+  }
+
+  #[test]
+  fn an_upstream_on_a_remote_with_a_slash_is_matched_by_its_name_on_that_remote() {
+    each_backend(
+      |fixture| {
+        add_remote(fixture, "team/shared");
+        add_remote(fixture, "other");
+        prune_upstream(fixture, "team/shared", "feature");
+        // Split at the first slash the upstream would look like `shared/feature` on a remote called `team`
+        fixture.git(&["push", "-q", "other", "main:shared/feature"]);
+      },
+      |_, repo| assert!(repo.is_orphaned_tracking(&local_branch(repo, "feature")).unwrap()),
+    );
+  }
+
+  #[test]
+  fn the_default_branch_follows_the_head_of_a_remote_with_a_slash() {
+    let fixture = Fixture::new();
+    add_remote(&fixture, "team/shared");
+    fixture.git(&["branch", "trunk"]);
+    fixture.git(&["push", "-q", "team/shared", "trunk"]);
+    fixture.git(&["remote", "set-head", "team/shared", "trunk"]);
+    let remote = Some(String::from("team/shared"));
+    let repos: [(&str, Box<dyn GitRepo>); 2] =
+      [("git2", Box::new(fixture.git2().remote(remote.clone()))), ("cli", Box::new(fixture.cli().remote(remote)))];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        assert_eq!(default_branch_name(&*repo).as_deref(), Some("trunk"));
+      });
+    }
+  }
+
+  #[test]
+  fn the_workdir_is_the_checkout_and_a_bare_repository_has_none() {
+    let fixture = Fixture::new();
+    let remote = fixture.with_remote();
+    let repos: [(&str, Box<dyn GitRepo>); 4] = [
+      ("git2", Box::new(fixture.git2())),
+      ("cli", Box::new(fixture.cli())),
+      ("git2 bare", Box::new(Git2Repo::from_path(&remote).unwrap())),
+      ("cli bare", Box::new(GitCliRepo::from_path(&remote).unwrap())),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        if repo.is_bare() {
+          assert_eq!((repo.workdir(), repo.path()), (None, remote.as_path()));
+        } else {
+          assert_eq!(
+            (repo.workdir(), repo.path()),
+            (Some(fixture.path.as_path()), fixture.path.join(".git").as_path())
+          );
+        }
+      });
+    }
+  }
+
+  #[test]
+  fn a_branch_from_a_stash_starts_where_it_was_made_with_the_changes_applied() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["tag", "stashed-on"]);
+        fixture.write("README.md", "stashed changes\n");
+        fixture.git(&["stash", "-q"]);
+        fixture.commit("later.txt", "later", "Later commit");
+      },
+      |fixture, repo| {
+        repo.branch_from_stash(0, "from-stash").unwrap();
+        assert_eq!(repo.current_branch().unwrap().unwrap().name, "from-stash");
+        assert_eq!(fixture.head(), fixture.git(&["rev-parse", "stashed-on"]));
+        assert_eq!(fs::read_to_string(fixture.path.join("README.md")).unwrap(), "stashed changes\n");
+        assert_eq!(repo.stashes().unwrap(), Vec::new());
+      },
+    );
+  }
+
+  #[test]
+  fn a_branch_from_a_stash_needs_a_valid_new_name() {
+    each_backend(
+      |fixture| {
+        fixture.write("README.md", "stashed changes\n");
+        fixture.git(&["stash", "-q"]);
+      },
+      |_, repo| {
+        assert!(repo.branch_from_stash(0, "bad..name").is_err());
+        assert!(repo.branch_from_stash(0, "main").is_err());
+        assert_eq!(repo.stashes().unwrap().len(), 1);
+        assert_eq!(repo.current_branch().unwrap().unwrap().name, "main");
+      },
+    );
+  }
+
+  #[test]
+  fn remote_branches_are_attributed_to_the_remote_they_are_on() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        add_remote(fixture, "upstream");
+        add_remote(fixture, "team");
+        add_remote(fixture, "team/shared");
+        fixture.git(&["push", "-q", "upstream", "main:release"]);
+      },
+      |_, repo| {
+        let mut remotes = repo.remotes().unwrap();
+        remotes.sort();
+        assert_eq!(remotes, ["origin", "team", "team/shared", "upstream"]);
+        let mut attributed: Vec<(String, Option<String>)> =
+          repo.remote_branches().unwrap().into_iter().map(|remote| (remote.name, remote.remote)).collect();
+        attributed.sort();
+        let expected = [
+          ("origin/main", "origin"),
+          ("team/main", "team"),
+          ("team/shared/main", "team/shared"),
+          ("upstream/main", "upstream"),
+          ("upstream/release", "upstream"),
+        ]
+        .map(|(name, remote)| (String::from(name), Some(String::from(remote))));
+        assert_eq!(attributed, expected);
+      },
+    );
+  }
+
+  #[test]
+  fn a_remote_branch_name_is_split_at_the_longest_remote_it_starts_with() {
+    let remotes = [String::from("team"), String::from("team/shared")];
+    let branch = GitRemoteBranch::on_remote(String::from("team/shared/feature/login"), &remotes);
+    assert_eq!(branch.split(), Some(("team/shared", "feature/login")));
+    let branch = GitRemoteBranch::on_remote(String::from("teammate/main"), &remotes);
+    assert_eq!((branch.remote, branch.name.as_str()), (None, "teammate/main"));
+  }
+
+  #[test]
+  fn a_blocked_checkout_lists_the_local_changes_in_the_way() {
+    each_backend(
+      |fixture| {
+        fixture.commit("untouched.txt", "main\n", "Add a file feature leaves alone");
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("README.md", "feature\n", "Change the readme");
+        fixture.commit("docs/guide.md", "feature\n", "Add a guide");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.commit("docs/guide.md", "main\n", "Add a different guide");
+        fixture.write("README.md", "local changes\n");
+        fixture.write("docs/guide.md", "local changes\n");
+        fixture.write("untouched.txt", "local changes\n");
+      },
+      |fixture, repo| {
+        let err = repo.checkout_branch_from_name("feature").unwrap_err();
+        let Error::CheckoutBlocked(branch, mut paths) = err else {
+          panic!("Expected the checkout to be blocked, got {}", err);
+        };
+        paths.sort();
+        assert_eq!(
+          (branch.as_str(), paths),
+          ("feature", vec![String::from("README.md"), String::from("docs/guide.md")])
+        );
+        assert_eq!(fixture.git(&["branch", "--show-current"]), "main");
+        assert_eq!(fs::read_to_string(fixture.path.join("README.md")).unwrap(), "local changes\n");
+      },
+    );
+  }
+
+  #[test]
+  fn the_diffstat_counts_the_lines_changed_since_the_upstream() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.commit("README.md", "one\ntwo\n", "Rewrite the readme");
+        fixture.commit("notes.txt", "a\nb\nc\n", "Add notes");
+        fixture.git(&["branch", "untracked"]);
+      },
+      |_, repo| {
+        assert_eq!(repo.diffstat_vs_upstream(&local_branch(repo, "main")).unwrap(), Some((5, 1)));
+        assert_eq!(repo.diffstat_vs_upstream(&local_branch(repo, "untracked")).unwrap(), None);
+      },
+    );
+  }
+
+  fn assert_refused<T: std::fmt::Debug>(method: &str, result: Result<T, Error>) {
+    assert!(matches!(result, Err(Error::ReadOnly)), "{} wasn't refused: {:?}", method, result);
+  }
+
+  #[test]
+  fn every_change_is_refused_when_read_only() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    fixture.branch_with_commit("feature");
+    fixture.git(&["push", "-q", "-u", "origin", "feature"]);
+    fixture.git(&["tag", "v1"]);
+    fixture.write("README.md", "stashed changes\n");
+    fixture.git(&["stash", "-q"]);
+    let refs_before = fixture.git(&["show-ref"]);
+    let repos: [(&str, Box<dyn GitRepo>); 2] =
+      [("git2", Box::new(fixture.git2().read_only(true))), ("cli", Box::new(fixture.cli().read_only(true)))];
+    for (backend, mut repo) in repos {
+      naming_backend(backend, || {
+        let feature = local_branch(&*repo, "feature");
+        let remote = GitRemoteBranch::on_remote(String::from("origin/feature"), &[String::from("origin")]);
+        let new = GitBranch::new(String::from("new"));
+        assert!(repo.is_read_only());
+        assert_refused("abort_operation", repo.abort_operation());
+        assert_refused("branch_from_stash", repo.branch_from_stash(0, "from-stash"));
+        assert_refused("checkout_branch_from_name", repo.checkout_branch_from_name("feature"));
+        assert_refused("checkout_branch", repo.checkout_branch(&feature));
+        assert_refused("force_checkout_branch", repo.force_checkout_branch(&feature));
+        assert_refused("checkout_detached", repo.checkout_detached("v1"));
+        assert_refused("checkout_previous", repo.checkout_previous());
+        assert_refused("checkout_remote_branch", repo.checkout_remote_branch(&remote));
+        assert_refused("checkout_upstream", repo.checkout_upstream(&feature));
+        assert_refused("quick_checkout", repo.quick_checkout(&feature));
+        assert_refused("cherry_pick", repo.cherry_pick("feature"));
+        assert_refused("merge_branch", repo.merge_branch(&feature));
+        assert_refused("reset_current", repo.reset_current("feature", ResetMode::Hard));
+        assert_refused("create_branch", repo.create_branch(&new));
+        assert_refused("create_and_checkout", repo.create_and_checkout(&new));
+        assert_refused("create_branch_from", repo.create_branch_from("new", &feature));
+        assert_refused("create_branch_at", repo.create_branch_at("new", &fixture.head()));
+        assert_refused("restore_branch", repo.restore_branch("new", &fixture.head()));
+        assert_refused("rename_branch", repo.rename_branch(&feature, "renamed"));
+        assert_refused("move_branch", repo.move_branch(&feature, "main"));
+        assert_refused("set_upstream", repo.set_upstream(&feature, None));
+        assert_refused("set_branch_description", repo.set_branch_description(&feature, Some("Described")));
+        assert_refused("delete_branch", repo.delete_branch(&feature));
+        assert_refused("force_delete_branch", repo.force_delete_branch(&feature));
+        assert_refused("archive_branch", repo.archive_branch(&feature));
+        assert_refused("delete_gone_branches", repo.delete_gone_branches(&[String::from("feature")]));
+        assert_refused("create_tag", repo.create_tag("v2", "main"));
+        assert_refused("delete_tag", repo.delete_tag("v1"));
+        assert_refused("stash_save", repo.stash_save("Stashed"));
+        assert_refused("stash_pop", repo.stash_pop(0));
+        assert_refused("fetch", repo.fetch("origin"));
+        assert_refused("prune_remote", repo.prune_remote("origin"));
+        assert_refused("pull_current", repo.pull_current("origin"));
+        assert_refused("push_branch", repo.push_branch(&feature, "origin"));
+        assert_refused("push_branch_force_with_lease", repo.push_branch_force_with_lease(&feature, "origin"));
+        assert_refused("delete_remote_branch", repo.delete_remote_branch(&remote));
+        assert!(repo.delete_branches(&[feature]).into_iter().all(|(_, result)| matches!(result, Err(Error::ReadOnly))));
+        assert_eq!(fixture.git(&["show-ref"]), refs_before);
+        assert_eq!(fixture.git(&["branch", "--show-current"]), "main");
+      });
+    }
+  }
+
+  #[test]
+  fn a_read_only_submodule_opens_a_read_only_superproject() {
+    let fixture = Fixture::new();
+    let submodule = fixture.with_submodule();
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(Git2Repo::from_path(&submodule).unwrap().read_only(true))),
+      ("cli", Box::new(GitCliRepo::from_path(&submodule).unwrap().read_only(true))),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        let superproject = repo.open_superproject().unwrap();
+        assert!(superproject.is_read_only());
+        assert_refused("create_branch", superproject.create_branch(&GitBranch::new(String::from("new"))));
+      });
+    }
+  }
+
+  #[test]
+  fn a_forced_checkout_replaces_local_changes_with_the_branch() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("README.md", "feature\n", "Change the readme");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.write("README.md", "local changes\n");
+        fixture.write("untracked.txt", "untracked\n");
+      },
+      |fixture, repo| {
+        let feature = local_branch(repo, "feature");
+        assert!(repo.checkout_branch(&feature).is_err());
+        repo.force_checkout_branch(&feature).unwrap();
+        assert_eq!(fixture.git(&["branch", "--show-current"]), "feature");
+        assert_eq!(fs::read_to_string(fixture.path.join("README.md")).unwrap(), "feature\n");
+        assert_eq!(fixture.git(&["status", "--porcelain"]), "?? untracked.txt");
+      },
+    );
+  }
+
+  #[test]
+  fn the_summary_counts_branches_without_remote_heads() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        for name in ["one", "two"] {
+          fixture.git(&["branch", name]);
+          fixture.git(&["push", "-q", "origin", name]);
+        }
+        fixture.git(&["branch", "local-only"]);
+        fixture.git(&["remote", "set-head", "origin", "main"]);
+        fixture.write("README.md", "local changes\n");
+      },
+      |fixture, repo| {
+        let expected = RepoSummary {
+          local_branches: 4,
+          remote_branches: 3,
+          current_branch: Some(String::from("main")),
+          clean: false,
+        };
+        assert_eq!(repo.summary().unwrap(), expected);
+        fixture.git(&["checkout", "-q", "--", "README.md"]);
+        fixture.git(&["checkout", "-q", "--detach"]);
+        repo.refresh();
+        let summary = repo.summary().unwrap();
+        assert_eq!((summary.current_branch, summary.clean), (None, true));
+      },
+    );
+  }
+
+  #[test]
+  fn refs_changed_outside_are_detected_once_marked_seen() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "feature"]);
+      },
+      |fixture, repo| {
+        // Nothing is checked before the refs were first seen
+        fixture.git(&["branch", "early"]);
+        repo.ensure_refs_unchanged().unwrap();
+
+        let external_changes: [&[&str]; 4] = [
+          &["branch", "created"],
+          &["branch", "-D", "feature"],
+          &["checkout", "-q", "early"],
+          &["commit", "-q", "--allow-empty", "-m", "Moved"],
+        ];
+        for change in external_changes {
+          repo.mark_refs_seen().unwrap();
+          repo.ensure_refs_unchanged().unwrap();
+          fixture.git(change);
+          repo.refresh();
+          let result = repo.ensure_refs_unchanged();
+          assert!(matches!(result, Err(Error::RepositoryChanged)), "git {:?}: {:?}", change, result);
+        }
+      },
+    );
+  }
+
+  #[test]
+  fn a_change_made_through_the_repo_can_be_marked_seen_again() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        repo.mark_refs_seen().unwrap();
+        repo.create_branch(&GitBranch::new(String::from("mine"))).unwrap();
+        assert!(matches!(repo.ensure_refs_unchanged(), Err(Error::RepositoryChanged)));
+        repo.mark_refs_seen().unwrap();
+        repo.ensure_refs_unchanged().unwrap();
+      },
+    );
+  }
+
+  #[test]
+  fn a_branch_description_round_trips_through_the_config_and_can_be_cleared() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "feature"]);
+      },
+      |fixture, repo| {
+        let feature = local_branch(repo, "feature");
+        assert_eq!(repo.branch_description(&feature).unwrap(), None);
+        repo.set_branch_description(&feature, Some("Login form rework\n\nSplit out of the big PR")).unwrap();
+        assert_eq!(
+          repo.branch_description(&feature).unwrap().as_deref(),
+          Some("Login form rework\n\nSplit out of the big PR")
+        );
+        assert_eq!(
+          fixture.git(&["config", "branch.feature.description"]),
+          "Login form rework\n\nSplit out of the big PR"
+        );
+        repo.set_branch_description(&feature, None).unwrap();
+        assert_eq!(repo.branch_description(&feature).unwrap(), None);
+        // Clearing one that isn't set is fine too
+        repo.set_branch_description(&feature, None).unwrap();
+      },
+    );
+  }
+
+  #[test]
+  fn renaming_the_upstream_moves_the_remote_branch_and_tracks_it() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.branch_with_commit("old");
+        fixture.git(&["push", "-q", "-u", "origin", "old"]);
+      },
+      |fixture, repo| {
+        let remote = fixture.dir.path().join("origin.git");
+        let remote_branches = || git_in(&remote, &["branch", "--format=%(refname:short)"]);
+        repo.rename_branch(&local_branch(repo, "old"), "new").unwrap();
+        // Renaming alone leaves the remote alone
+        assert_eq!(remote_branches(), "main\nold");
+
+        let renamed = repo.rename_upstream(&local_branch(repo, "new")).unwrap();
+        assert_eq!((renamed.name.as_str(), renamed.remote.as_deref()), ("origin/new", Some("origin")));
+        assert_eq!(remote_branches(), "main\nnew");
+        assert_eq!(fixture.git(&["rev-parse", "--abbrev-ref", "new@{upstream}"]), "origin/new");
+        assert_eq!(local_branch(repo, "new").upstream_status, UpstreamStatus::Tracked);
+      },
+    );
+  }
+
+  #[test]
+  fn a_bare_repository_lists_renames_and_deletes_but_refuses_checkouts() {
+    let fixture = Fixture::new();
+    let bare = fixture.with_remote();
+    for name in ["feature", "old"] {
+      fixture.git(&["branch", name]);
+      fixture.git(&["push", "-q", "origin", name]);
+    }
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(Git2Repo::from_path(&bare).unwrap())),
+      ("cli", Box::new(GitCliRepo::from_path(&bare).unwrap())),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        assert!(repo.is_bare());
+        let names =
+          |repo: &dyn GitRepo| repo.local_branches().unwrap().into_iter().map(|branch| branch.name).collect::<Vec<_>>();
+        assert_eq!(names(&*repo), ["feature", "main", "old"]);
+        assert_eq!(repo.remote_branches().unwrap(), Vec::new());
+
+        let err = repo.checkout_branch(&local_branch(&*repo, "feature")).unwrap_err();
+        assert!(matches!(err, Error::BareRepository), "{}", err);
+        assert_eq!(err.to_string(), "Cannot checkout in a bare repository");
+        assert!(matches!(repo.checkout_detached("main"), Err(Error::BareRepository)));
+        assert!(matches!(repo.create_and_checkout(&GitBranch::new(String::from("new"))), Err(Error::BareRepository)));
+
+        repo.rename_branch(&local_branch(&*repo, "old"), "renamed").unwrap();
+        repo.delete_branch(&local_branch(&*repo, "renamed")).unwrap();
+        assert_eq!(names(&*repo), ["feature", "main"]);
+        // Put back for the next backend
+        git_in(&bare, &["branch", "old", "main"]);
+      });
+    }
+  }
+
+  #[test]
+  fn the_remote_url_is_read_from_the_config() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["remote", "add", "origin", "git@example.com:team/repo.git"]);
+      },
+      |_, repo| {
+        assert_eq!(repo.remote_url("origin").unwrap().as_deref(), Some("git@example.com:team/repo.git"));
+        assert!(repo.remote_url("missing").is_err());
+      },
+    );
+  }
+
+  #[test]
+  fn the_default_remote_is_the_branchs_own_then_the_configured_one_then_origin() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    add_remote(&fixture, "upstream");
+    fixture.git(&["branch", "untracked"]);
+    let configured = Some(String::from("upstream"));
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(fixture.git2().remote(configured.clone()))),
+      ("cli", Box::new(fixture.cli().remote(configured))),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        // main tracks origin, which wins over the configured remote
+        assert_eq!(repo.default_remote(Some(&local_branch(&*repo, "main"))).unwrap(), "origin");
+        assert_eq!(repo.default_remote(Some(&local_branch(&*repo, "untracked"))).unwrap(), "upstream");
+        assert_eq!(repo.default_remote(None).unwrap(), "upstream");
+      });
+    }
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "untracked"]);
+      },
+      |_, repo| assert_eq!(repo.default_remote(Some(&local_branch(repo, "untracked"))).unwrap(), DEFAULT_REMOTE),
+    );
+  }
+
+  #[test]
+  fn fetching_without_a_remote_uses_the_configured_one() {
+    let fixture = Fixture::new();
+    add_remote(&fixture, "upstream");
+    fixture.git(&["checkout", "-q", "-b", "untracked"]);
+    fixture.commit("new.txt", "new\n", "Pushed elsewhere");
+    fixture.git(&["push", "-q", "upstream", "untracked:shared"]);
+    let configured = Some(String::from("upstream"));
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(fixture.git2().remote(configured.clone()))),
+      ("cli", Box::new(fixture.cli().remote(configured))),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        // Forgotten again so each backend has to fetch it
+        fixture.git(&["update-ref", "-d", "refs/remotes/upstream/shared"]);
+        repo.fetch("").unwrap();
+        assert!(repo.remote_branch_exists("upstream/shared"));
+      });
+    }
+  }
+
+  #[test]
+  fn a_branch_without_an_upstream_is_offered_the_same_named_remote_branch() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        add_remote(fixture, "fork");
+        for name in ["feature", "forked", "local"] {
+          fixture.git(&["branch", name]);
+        }
+        fixture.git(&["push", "-q", "origin", "feature"]);
+        fixture.git(&["push", "-q", "fork", "feature", "forked"]);
+      },
+      |_, repo| {
+        let suggested = |name: &str| repo.suggest_upstream(&local_branch(repo, name)).unwrap();
+        // Both remotes have feature, the default remote's is preferred
+        assert_eq!(
+          suggested("feature"),
+          Some(GitRemoteBranch { name: String::from("origin/feature"), remote: Some(String::from("origin")) })
+        );
+        assert_eq!(suggested("forked").map(|remote_branch| remote_branch.name).as_deref(), Some("fork/forked"));
+        assert_eq!(suggested("local"), None);
+        // main already tracks origin/main
+        assert_eq!(suggested("main"), None);
+      },
+    );
+  }
+
+  #[test]
+  fn a_quick_checkout_away_and_back_restores_the_changes() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+        fixture.write("README.md", "local changes\n");
+      },
+      |fixture, repo| {
+        let readme = || std::fs::read_to_string(fixture.path.join("README.md")).unwrap();
+        let away = repo.quick_checkout(&local_branch(repo, "feature")).unwrap();
+        assert_eq!(away, QuickCheckout { stashed: true, restored: false });
+        assert_eq!(readme(), "init\n");
+        assert_eq!(repo.stashes().unwrap().len(), 1);
+
+        let back = repo.quick_checkout(&local_branch(repo, "main")).unwrap();
+        assert_eq!(back, QuickCheckout { stashed: false, restored: true });
+        assert_eq!(readme(), "local changes\n");
+        assert!(repo.stashes().unwrap().is_empty());
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn a_quick_checkout_keeps_the_stash_when_it_conflicts_on_the_way_back() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "feature"]);
+        fixture.write("README.md", "local changes\n");
+      },
+      |fixture, repo| {
+        repo.quick_checkout(&local_branch(repo, "feature")).unwrap();
+        // main moves on to a different README while the changes are stashed
+        fixture.commit("README.md", "theirs\n", "Change the README");
+        fixture.git(&["branch", "-f", "main", "HEAD"]);
+
+        let err = repo.quick_checkout(&local_branch(repo, "main")).unwrap_err();
+        assert!(matches!(err, Error::AutoStashNotApplied(ref name, _) if name == "main"), "{:?}", err);
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+        let stashes = repo.stashes().unwrap();
+        assert_eq!(stashes.len(), 1);
+        assert!(stashes[0].message.ends_with(&format!("{}main", AUTO_STASH_PREFIX)), "{}", stashes[0].message);
+      },
+    );
+  }
+
+  #[test]
+  fn a_missing_ref_exits_as_not_found() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        let missing = GitBranch::new(String::from("nope"));
+        assert_eq!(repo.checkout_branch(&missing).unwrap_err().exit_code(), exit_code::NOT_FOUND);
+        assert_eq!(repo.delete_branch(&missing).unwrap_err().exit_code(), exit_code::NOT_FOUND);
+        assert_eq!(repo.reset_current("nope", ResetMode::Hard).unwrap_err().exit_code(), exit_code::NOT_FOUND);
+      },
+    );
+  }
+
+  #[test]
+  fn local_changes_in_the_way_exit_as_a_dirty_tree() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("README.md", "feature\n", "Change the README");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.write("README.md", "local changes\n");
+      },
+      |_, repo| {
+        let feature = local_branch(repo, "feature");
+        assert_eq!(repo.checkout_branch(&feature).unwrap_err().exit_code(), exit_code::DIRTY_TREE);
+        assert_eq!(repo.merge_branch(&feature).unwrap_err().exit_code(), exit_code::DIRTY_TREE);
+      },
+    );
+  }
+
+  #[test]
+  fn a_refused_change_exits_as_refused() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        let main = local_branch(repo, "main");
+        assert_eq!(repo.delete_branch(&main).unwrap_err().exit_code(), exit_code::REFUSED);
+      },
+    );
+  }
+
+  #[test]
+  fn a_repository_in_the_wrong_state_exits_as_such() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["checkout", "-q", "--detach"]);
+      },
+      |_, repo| {
+        assert_eq!(repo.abort_operation().unwrap_err().exit_code(), exit_code::REPOSITORY_STATE);
+        assert_eq!(repo.reset_current("HEAD", ResetMode::Mixed).unwrap_err().exit_code(), exit_code::REPOSITORY_STATE);
+      },
+    );
+  }
+
+  #[test]
+  fn a_directory_outside_any_repository_exits_as_not_a_repository() {
+    let dir = TempDir::new();
+    let errors = [Git2Repo::from_path(dir.path()).err().unwrap(), GitCliRepo::from_path(dir.path()).err().unwrap()];
+    for err in errors {
+      assert_eq!(err.exit_code(), exit_code::NOT_A_REPOSITORY, "{:?}", err);
+    }
+  }
+
+  #[test]
+  fn any_other_failure_exits_as_a_failure() {
+    each_backend(|_| {}, |_, repo| assert_eq!(repo.stash_save("Nothing").unwrap_err().exit_code(), exit_code::FAILURE));
+  }
+
+  #[test]
+  fn only_a_branch_diverged_from_its_upstream_needs_a_force_push() {
+    let cases = [("ahead", false, true, false), ("behind", true, false, false), ("diverged", true, true, true)];
+    for (case, pushed_elsewhere, committed_here, requires_force) in cases {
+      named(case, || {
+        each_backend(
+          |fixture| {
+            tracking_origin(fixture, pushed_elsewhere);
+            fixture.git(&["fetch", "-q"]);
+            if committed_here {
+              fixture.commit("local.txt", "local", "Only here");
+            }
+          },
+          |_, repo| assert_eq!(repo.push_requires_force(&local_branch(repo, "main")).unwrap(), requires_force),
+        )
+      });
+    }
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "untracked"]);
+      },
+      |_, repo| assert!(!repo.push_requires_force(&local_branch(repo, "untracked")).unwrap()),
+    );
+  }
+
+  #[test]
+  fn pushing_a_diverged_branch_asks_for_a_force_push() {
+    each_backend(
+      |fixture| {
+        tracking_origin(fixture, true);
+        fixture.git(&["fetch", "-q"]);
+        fixture.commit("local.txt", "local", "Only here");
+      },
+      |_, repo| {
+        let err = repo.push_branch(&local_branch(repo, "main"), "origin").unwrap_err();
+        assert!(matches!(err, Error::PushRequiresForce(ref name, ref remote) if name == "main" && remote == "origin"));
+      },
+    );
+  }
+
+  #[test]
+  fn a_lease_push_replaces_the_remote_branch_it_was_last_fetched_at() {
+    each_backend(
+      |fixture| {
+        tracking_origin(fixture, true);
+        fixture.git(&["fetch", "-q"]);
+        fixture.commit("local.txt", "local", "Rebased");
+      },
+      |fixture, repo| {
+        repo.push_branch_force_with_lease(&local_branch(repo, "main"), "origin").unwrap();
+        let remote = fixture.dir.path().join("origin.git");
+        assert_eq!(git_in(&remote, &["rev-parse", "main"]), fixture.head());
+      },
+    );
+  }
+
+  #[test]
+  fn a_lease_push_is_refused_once_the_remote_branch_moved() {
+    each_backend(
+      |fixture| {
+        tracking_origin(fixture, false);
+        fixture.commit("local.txt", "local", "Rebased");
+        // Pushed after the last fetch, so the lease no longer holds
+        let other = fixture.other_clone();
+        git_in(&other, &["commit", "-q", "--allow-empty", "-m", "Elsewhere"]);
+        git_in(&other, &["push", "-q"]);
+      },
+      |fixture, repo| {
+        let err = repo.push_branch_force_with_lease(&local_branch(repo, "main"), "origin").unwrap_err();
+        assert!(
+          matches!(err, Error::StaleLease(ref name, ref remote) if name == "main" && remote == "origin"),
+          "{:?}",
+          err
+        );
+        let remote = fixture.dir.path().join("origin.git");
+        assert_eq!(git_in(&remote, &["log", "-1", "--format=%s", "main"]), "Elsewhere");
+      },
+    );
+  }
+
+  #[test]
+  fn a_branch_is_created_at_a_commit_behind_the_tip() {
+    each_backend(
+      |fixture| {
+        fixture.commit("one.txt", "one", "One");
+      },
+      |fixture, repo| {
+        let parent = fixture.git(&["rev-parse", "HEAD~1"]);
+        let created = repo.create_branch_at("earlier", &parent).unwrap();
+        assert_eq!(created.name, "earlier");
+        assert_eq!(fixture.git(&["rev-parse", "earlier"]), parent);
+        assert!(repo.create_branch_at("not..valid", &parent).is_err());
+        assert!(!repo.branch_exists("not..valid"));
+      },
+    );
+  }
+
+  #[test]
+  fn a_branch_that_isnt_checked_out_is_moved_to_the_target() {
+    each_backend(
+      |fixture| {
+        fixture.git(&["branch", "feature"]);
+        fixture.commit("one.txt", "one", "One");
+      },
+      |fixture, repo| {
+        repo.move_branch(&local_branch(repo, "feature"), "main").unwrap();
+        assert_eq!(fixture.git(&["rev-parse", "feature"]), fixture.head());
+        repo.move_branch(&local_branch(repo, "feature"), "HEAD~1").unwrap();
+        assert_eq!(fixture.git(&["rev-parse", "feature"]), fixture.git(&["rev-parse", "HEAD~1"]));
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn a_checked_out_branch_is_not_moved() {
+    each_backend(
+      |fixture| {
+        fixture.commit("one.txt", "one", "One");
+        fixture.git(&["branch", "elsewhere", "HEAD~1"]);
+        fixture.git(&["worktree", "add", "-q", fixture.dir.path().join("elsewhere").to_str().unwrap(), "elsewhere"]);
+      },
+      |fixture, repo| {
+        let head = fixture.head();
+        let err = repo.move_branch(&local_branch(repo, "main"), "HEAD~1").unwrap_err();
+        assert!(matches!(err, Error::CheckedOutBranch(ref name) if name == "main"), "{:?}", err);
+        let err = repo.move_branch(&local_branch(repo, "elsewhere"), "main").unwrap_err();
+        assert!(matches!(err, Error::CheckedOutInWorktree(ref name, _) if name == "elsewhere"), "{:?}", err);
+        assert_eq!(fixture.head(), head);
+        assert_eq!(fixture.git(&["rev-parse", "elsewhere"]), fixture.git(&["rev-parse", "HEAD~1"]));
+      },
+    );
+  }
+
+  #[test]
+  fn bisecting_a_linear_history_tests_the_middle_commit() {
+    each_backend(
+      |fixture| {
+        for n in 1..=8 {
+          fixture.commit(&format!("{}.txt", n), "n", &format!("Commit {}", n));
+        }
+      },
+      |fixture, repo| {
+        // Commits 1 to 8 are left once the initial commit is good
+        let commit = |n: usize| fixture.git(&["rev-parse", &format!("HEAD~{}", 8 - n)]);
+        let good = [commit(0)];
+        assert_eq!(repo.bisect_next(&good, &commit(8)).unwrap(), BisectStep::Test {
+          commit_id: commit(4),
+          remaining: 8
+        });
+        assert_eq!(repo.bisect_next(&[commit(4)], &commit(6)).unwrap(), BisectStep::Test {
+          commit_id: commit(5),
+          remaining: 2
+        });
+        assert_eq!(repo.bisect_next(&[commit(4)], &commit(5)).unwrap(), BisectStep::Found(commit(5)));
+        assert!(repo.bisect_next(&[commit(5)], &commit(3)).is_err());
+      },
+    );
+  }
+
+  /// A branch changing the README and adding a file, compared with `main`.
+  fn branch_with_changes(fixture: &Fixture) {
+    fixture.git(&["checkout", "-q", "-b", "feature"]);
+    fixture.commit("README.md", "changed\n", "Change the README");
+    fixture.commit("added.txt", "one\ntwo\n", "Add a file");
+    fixture.git(&["checkout", "-q", "main"]);
+  }
+
+  #[test]
+  fn the_patch_against_head_has_each_files_header_and_hunks() {
+    let fixture = Fixture::new();
+    branch_with_changes(&fixture);
+    let patches: Vec<Patch> = [Box::new(fixture.git2()) as Box<dyn GitRepo>, Box::new(fixture.cli())]
+      .iter()
+      .map(|repo| repo.diff_with_head(&local_branch(&**repo, "feature"), 100).unwrap())
+      .collect();
+    let patch = &patches[0];
+    assert!(!patch.truncated);
+    for line in ["diff --git a/README.md b/README.md", "@@ -1 +1 @@", "-init", "+changed", "@@ -0,0 +1,2 @@", "+two"] {
+      assert!(patch.lines.iter().any(|patch_line| patch_line == line), "{} missing from {:#?}", line, patch.lines);
+    }
+    // Both backends print it like `git diff`
+    assert_eq!(patches[0], patches[1]);
+  }
+
+  #[test]
+  fn a_long_patch_against_head_is_cut_at_the_limit() {
+    each_backend(branch_with_changes, |_, repo| {
+      let patch = repo.diff_with_head(&local_branch(repo, "feature"), 3).unwrap();
+      assert_eq!(patch.lines.len(), 3);
+      assert!(patch.truncated);
+      assert_eq!(patch.lines[0], "diff --git a/README.md b/README.md");
+    });
+  }
+
+  #[test]
+  fn checkout_preview_splits_the_local_changes_into_carried_and_blocking() {
+    each_backend(
+      |fixture| {
+        fixture.commit("shared.txt", "shared\n", "Add shared");
+        fixture.commit("conflict.txt", "conflict\n", "Add conflict");
+        fixture.git(&["checkout", "-q", "-b", "feature"]);
+        fixture.commit("conflict.txt", "changed on feature\n", "Change conflict");
+        fixture.commit("added.txt", "added on feature\n", "Add added");
+        fixture.git(&["checkout", "-q", "main"]);
+        fixture.write("shared.txt", "edited\n");
+        fixture.write("conflict.txt", "edited\n");
+        fixture.write("added.txt", "untracked\n");
+      },
+      |_, repo| {
+        let preview = repo.checkout_preview(&local_branch(repo, "feature")).unwrap();
+        assert_eq!(preview.carried, ["shared.txt"]);
+        assert_eq!(preview.blocking, ["added.txt", "conflict.txt"]);
+      },
+    );
+  }
+
+  #[test]
+  fn checkout_preview_of_a_clean_tree_is_empty() {
+    each_backend(
+      |fixture| {
+        fixture.branch_with_commit("feature");
+      },
+      |_, repo| {
+        assert_eq!(repo.checkout_preview(&local_branch(repo, "feature")).unwrap(), CheckoutPreview::default());
+      },
+    );
+  }
+
+  #[test]
+  fn resolve_commit_finds_full_and_short_hashes() {
+    each_backend(
+      |fixture| {
+        fixture.commit("second.txt", "second\n", "Second");
+      },
+      |fixture, repo| {
+        let head = fixture.head();
+        assert_eq!(repo.resolve_commit(&head).unwrap(), Some(head.clone()));
+        assert_eq!(repo.resolve_commit(&head[..7]).unwrap(), Some(head.clone()));
+        let first = fixture.git(&["rev-parse", "HEAD~1"]);
+        assert_eq!(repo.resolve_commit(&first[..8]).unwrap(), Some(first));
+        assert_eq!(repo.resolve_commit("0000000000").unwrap(), None);
+      },
+    );
+  }
+
+  #[test]
+  fn resolve_commit_refuses_an_ambiguous_short_hash() {
+    for backend in ["git2", "cli"] {
+      let fixture = Fixture::new();
+      let prefix = fixture.ambiguous_prefix();
+      let repo: Box<dyn GitRepo> = match backend {
+        "git2" => Box::new(fixture.git2()),
+        _ => Box::new(fixture.cli()),
+      };
+      naming_backend(backend, || {
+        let error = repo.resolve_commit(&prefix).unwrap_err().to_string();
+        assert!(error.contains("ambiguous"), "{}", error);
+      });
+    }
+  }
+
+  #[test]
+  fn branches_committed_in_the_same_second_sort_by_name() {
+    each_backend(
+      |fixture| {
+        fixture.commit_at("base.txt", "Base", 1_000);
+        for name in ["zeta", "alpha", "mid"] {
+          fixture.git(&["checkout", "-q", "-b", name, "main"]);
+          fixture.commit_at(&format!("{}.txt", name), name, 2_000);
+        }
+        fixture.git(&["checkout", "-q", "-b", "newest", "main"]);
+        fixture.commit_at("newest.txt", "Newest", 3_000);
+        fixture.git(&["checkout", "-q", "main"]);
+      },
+      |_, repo| {
+        for _ in 0..2 {
+          let names: Vec<String> =
+            repo.local_branches_sorted_by_date().unwrap().into_iter().map(|branch| branch.name).collect();
+          assert_eq!(names, ["newest", "alpha", "mid", "zeta", "main"]);
+        }
+      },
+    );
+  }
+
+  /// Pushes `behind`, `diverged` and `level` with tracking, then moves `behind`, `diverged` and `main` on the remote
+  /// and fetches, with a commit of its own on `diverged`. `untracked` has no upstream.
+  fn behind_upstreams(fixture: &Fixture) {
+    fixture.with_remote();
+    for name in ["behind", "diverged", "level"] {
+      fixture.git(&["branch", name]);
+      fixture.git(&["push", "-q", "-u", "origin", name]);
+    }
+    fixture.git(&["branch", "untracked"]);
+    let other = fixture.other_clone();
+    for name in ["behind", "diverged", "main"] {
+      git_in(&other, &["checkout", "-q", name]);
+      fs::write(other.join("upstream.txt"), name).unwrap();
+      git_in(&other, &["add", "upstream.txt"]);
+      git_in(&other, &["commit", "-q", "-m", &format!("Upstream work on {}", name)]);
+      git_in(&other, &["push", "-q", "origin", name]);
+    }
+    fixture.git(&["checkout", "-q", "diverged"]);
+    fixture.commit("local.txt", "local\n", "Local work on diverged");
+    fixture.git(&["checkout", "-q", "main"]);
+    fixture.git(&["fetch", "-q", "origin"]);
+  }
+
+  #[test]
+  fn fast_forwardable_lists_the_branches_behind_without_commits_of_their_own() {
+    each_backend(behind_upstreams, |_, repo| {
+      let names: Vec<String> = repo.fast_forwardable().unwrap().into_iter().map(|branch| branch.name).collect();
+      assert_eq!(names, ["behind", "main"]);
+    });
+  }
+
+  #[test]
+  fn fast_forwarding_a_branch_that_isnt_checked_out_only_moves_its_ref() {
+    each_backend(behind_upstreams, |fixture, repo| {
+      let head = fixture.head();
+      repo.fast_forward_branch(&local_branch(repo, "behind")).unwrap();
+      assert_eq!(fixture.git(&["rev-parse", "behind"]), fixture.git(&["rev-parse", "origin/behind"]));
+      assert_eq!(fixture.head(), head);
+      assert!(!fixture.path.join("upstream.txt").exists());
+      assert_eq!(repo.upstream_ahead_behind(&local_branch(repo, "behind")).unwrap(), Some((0, 0)));
+      // Already up to date is nothing to do
+      repo.fast_forward_branch(&local_branch(repo, "level")).unwrap();
+    });
+  }
+
+  #[test]
+  fn fast_forwarding_the_checked_out_branch_updates_its_files_once_clean() {
+    each_backend(behind_upstreams, |fixture, repo| {
+      fixture.write("README.md", "local changes\n");
+      let dirty = repo.fast_forward_branch(&local_branch(repo, "main"));
+      assert!(matches!(dirty, Err(Error::FastForwardBlocked(ref name)) if name == "main"), "{:?}", dirty);
+      assert_eq!(dirty.unwrap_err().exit_code(), exit_code::DIRTY_TREE);
+
+      fixture.git(&["checkout", "-q", "--", "README.md"]);
+      repo.fast_forward_branch(&local_branch(repo, "main")).unwrap();
+      assert_eq!(fixture.head(), fixture.git(&["rev-parse", "origin/main"]));
+      assert_eq!(fs::read_to_string(fixture.path.join("upstream.txt")).unwrap(), "main");
+      assert_eq!(fixture.git(&["status", "--porcelain"]), "");
+    });
+  }
+
+  #[test]
+  fn fast_forwarding_is_refused_for_diverged_and_untracked_branches() {
+    each_backend(behind_upstreams, |_, repo| {
+      let diverged = repo.fast_forward_branch(&local_branch(repo, "diverged"));
+      assert!(matches!(diverged, Err(Error::NotFastForward(ref name)) if name == "diverged"), "{:?}", diverged);
+      let untracked = repo.fast_forward_branch(&local_branch(repo, "untracked"));
+      assert!(matches!(untracked, Err(Error::NoUpstream(ref name)) if name == "untracked"), "{:?}", untracked);
+    });
+  }
+
+  #[test]
+  fn authors_and_committers_are_shown_as_the_mailmap_maps_them() {
+    each_backend(
+      |fixture| {
+        let mailmap =
+          "Canonical Name <canonical@example.com> <author@example.com>\nCanonical Committer <committer@example.com>\n";
+        fixture.commit(".mailmap", mailmap, "Add a mailmap");
+      },
+      |_, repo| {
+        let main = local_branch(repo, "main");
+        let info = repo.branch_commit_info(&main).unwrap();
+        assert_eq!((info.author.as_str(), info.author_email.as_str()), ("Canonical Name", "canonical@example.com"));
+        let log = repo.branch_log(&main, 10).unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log.iter().all(|commit| commit.author == "Canonical Name"), "{:?}", log);
+        let details = repo.commit_details(&main, false).unwrap();
+        assert!(details.contains("Author:     Canonical Name <canonical@example.com>"), "{}", details);
+        assert!(details.contains("Commit:     Canonical Committer <committer@example.com>"), "{}", details);
+      },
+    );
+  }
+
+  #[test]
+  fn without_a_mailmap_authors_are_shown_as_committed() {
+    each_backend(
+      |_| {},
+      |_, repo| {
+        let info = repo.branch_commit_info(&local_branch(repo, "main")).unwrap();
+        assert_eq!((info.author.as_str(), info.author_email.as_str()), ("Test Author", "author@example.com"));
+      },
+    );
+  }
+
+  /// Pushes each branch with tracking and deletes it on the remote without fetching, so only a prune notices.
+  fn deleted_on_remote(fixture: &Fixture, names: &[&str]) {
+    for name in names {
+      fixture.git(&["branch", name]);
+      fixture.git(&["push", "-q", "-u", "origin", name]);
+      fixture.git(&["push", "-q", "origin", "--delete", name]);
+      fixture.git(&["update-ref", &format!("refs/remotes/origin/{}", name), "HEAD"]);
+    }
+  }
+
+  #[test]
+  fn pruning_then_deleting_the_gone_branches_removes_exactly_those() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.git(&["branch", "kept"]);
+        fixture.git(&["push", "-q", "-u", "origin", "kept"]);
+        fixture.git(&["branch", "local-only"]);
+        deleted_on_remote(fixture, &["gone"]);
+        fixture.git(&["checkout", "-q", "gone"]);
+        fixture.commit("gone.txt", "unmerged\n", "Work nobody merged");
+        fixture.git(&["checkout", "-q", "main"]);
+      },
+      |fixture, repo| {
+        assert_eq!(repo.gone_branches().unwrap(), []);
+        assert_eq!(repo.prune_remote("origin").unwrap(), ["origin/gone"]);
+        let gone: Vec<String> = repo.gone_branches().unwrap().into_iter().map(|branch| branch.name).collect();
+        assert_eq!(gone, ["gone"]);
+
+        let tip = fixture.git(&["rev-parse", "gone"]);
+        let deletions = repo.delete_gone_branches(&gone).unwrap();
+        assert_eq!(deletions.len(), 1);
+        let (branch, result) = &deletions[0];
+        assert_eq!(branch.name, "gone");
+        // Deleted though unmerged, with the commit it pointed at to undo it
+        assert_eq!(result.as_ref().unwrap(), &tip);
+        let names: Vec<String> = repo.local_branches().unwrap().into_iter().map(|branch| branch.name).collect();
+        assert_eq!(names, ["kept", "local-only", "main"]);
+      },
+    );
+  }
+
+  #[test]
+  fn the_checked_out_default_and_protected_branches_are_never_gone() {
+    let fixture = Fixture::new();
+    fixture.with_remote();
+    deleted_on_remote(&fixture, &["current", "release/1", "stale"]);
+    fixture.git(&["fetch", "-q", "--prune", "origin"]);
+    fixture.git(&["checkout", "-q", "current"]);
+    // The default branch's upstream goes missing too
+    fixture.git(&["update-ref", "-d", "refs/remotes/origin/main"]);
+    let protected = || ProtectedBranches::new(vec![String::from("release/*")]);
+    let repos: [(&str, Box<dyn GitRepo>); 2] = [
+      ("git2", Box::new(fixture.git2().protected(protected()))),
+      ("cli", Box::new(fixture.cli().protected(protected()))),
+    ];
+    for (backend, repo) in repos {
+      naming_backend(backend, || {
+        assert_eq!(local_branch(&*repo, "main").upstream_status, UpstreamStatus::Gone);
+        assert_eq!(repo.default_branch().unwrap().map(|branch| branch.name).as_deref(), Some("main"));
+        let gone: Vec<String> = repo.gone_branches().unwrap().into_iter().map(|branch| branch.name).collect();
+        assert_eq!(gone, ["stale"]);
+        let names = [String::from("current"), String::from("main"), String::from("release/1")];
+        assert!(repo.delete_gone_branches(&names).unwrap().is_empty());
+      });
+    }
+  }
+
+  #[test]
+  fn checking_out_a_file_name_that_isnt_a_branch_keeps_the_files_changes() {
+    each_backend(
+      |fixture| fixture.write("README.md", "local changes\n"),
+      |fixture, repo| {
+        let err = repo.checkout_branch_from_name("README.md").unwrap_err();
+        assert_eq!(err.exit_code(), exit_code::NOT_FOUND, "{}", err);
+        assert!(repo.force_checkout_branch(&GitBranch::new(String::from("README.md"))).is_err());
+        assert_eq!(fs::read_to_string(fixture.path.join("README.md")).unwrap(), "local changes\n");
+        assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "main");
+      },
+    );
+  }
+
+  #[test]
+  fn only_a_non_fast_forward_status_is_read_as_one() {
+    assert!(is_non_fast_forward("non-fast-forward"));
+    assert!(is_non_fast_forward(" ! [rejected]        main -> main (fetch first)"));
+    assert!(!is_non_fast_forward("pre-receive hook declined: fast-forwards disallowed by policy"));
+    assert!(!is_non_fast_forward("deny updating a hidden ref"));
+  }
+
+  #[test]
+  fn a_push_declined_by_a_hook_mentioning_fast_forwards_isnt_a_non_fast_forward() {
+    let fixture = Fixture::new();
+    let remote = fixture.with_remote();
+    let hook = remote.join("hooks/pre-receive");
+    fs::write(&hook, "#!/bin/sh\necho 'fast-forwards disallowed by policy' >&2\nexit 1\n").unwrap();
+    fs::set_permissions(&hook, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    fixture.commit("a.txt", "a\n", "Ahead of origin");
+    // Only the cli, libgit2 pushes to a local path without running the remote's hooks
+    let repo = fixture.cli();
+    let err = repo.push_branch(&local_branch(&repo, "main"), "origin").unwrap_err();
+    assert!(!matches!(err, Error::PushRequiresForce(..)), "{:?}", err);
+    assert!(err.to_string().contains("fast-forwards disallowed by policy"), "{}", err);
+  }
+
+  #[test]
+  fn failing_to_reach_the_default_remote_names_it() {
+    each_backend(
+      |fixture| {
+        fixture.with_remote();
+        fixture.git(&["remote", "set-url", "origin", fixture.dir.path().join("missing.git").to_str().unwrap()]);
+      },
+      |_, repo| {
+        let fetch = repo.fetch("").unwrap_err().to_string();
+        assert!(fetch.starts_with("Failed to fetch from origin: "), "{}", fetch);
+        let prune = repo.prune_remote("").unwrap_err().to_string();
+        assert!(prune.starts_with("Failed to prune origin: "), "{}", prune);
+        // Pulling fetches first, so it fails with the fetch's context
+        let pull = repo.pull_current("").unwrap_err().to_string();
+        assert!(pull.starts_with("Failed to fetch from origin: "), "{}", pull);
+      },
+    );
+  }
 }
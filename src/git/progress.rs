@@ -0,0 +1,110 @@
+use std::{
+  fmt::{self, Display, Formatter},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+};
+
+/// How far a fetch or push running on another thread has got. git2's transfer callbacks write to it and the render
+/// loop reads it, neither waits on the other.
+#[derive(Debug, Clone, Default)]
+pub struct TransferProgress(Arc<Counts>);
+
+#[derive(Debug, Default)]
+struct Counts {
+  objects: AtomicUsize,
+  total_objects: AtomicUsize,
+  bytes: AtomicUsize,
+}
+
+impl TransferProgress {
+  pub fn update(&self, objects: usize, total_objects: usize, bytes: usize) {
+    self.0.objects.store(objects, Ordering::Relaxed);
+    self.0.total_objects.store(total_objects, Ordering::Relaxed);
+    self.0.bytes.store(bytes, Ordering::Relaxed);
+  }
+
+  /// Forgets the last transfer, for when another starts.
+  pub fn reset(&self) {
+    self.update(0, 0, 0);
+  }
+
+  /// The transfer so far, `None` until the remote has said how many objects it is sending.
+  pub fn get(&self) -> Option<Transfer> {
+    let total_objects = self.0.total_objects.load(Ordering::Relaxed);
+    if total_objects == 0 {
+      return None;
+    }
+    Some(Transfer {
+      objects: self.0.objects.load(Ordering::Relaxed).min(total_objects),
+      total_objects,
+      bytes: self.0.bytes.load(Ordering::Relaxed),
+    })
+  }
+}
+
+/// A snapshot of a [`TransferProgress`], shown like `12/40 objects, 1.2 MiB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+  pub objects: usize,
+  pub total_objects: usize,
+  pub bytes: usize,
+}
+
+impl Display for Transfer {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}/{} objects, {}", self.objects, self.total_objects, format_bytes(self.bytes))
+  }
+}
+
+fn format_bytes(bytes: usize) -> String {
+  const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+  if bytes < 1024 {
+    return format!("{} B", bytes);
+  }
+  let mut size = bytes as f64 / 1024.0;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nothing_is_shown_until_the_remote_says_how_many_objects_it_is_sending() {
+    let progress = TransferProgress::default();
+    assert_eq!(progress.get(), None);
+    progress.update(0, 0, 512);
+    assert_eq!(progress.get(), None);
+    progress.update(12, 40, 1_258_291);
+    assert_eq!(progress.get(), Some(Transfer { objects: 12, total_objects: 40, bytes: 1_258_291 }));
+    progress.reset();
+    assert_eq!(progress.get(), None);
+  }
+
+  #[test]
+  fn clones_share_the_counts_across_threads() {
+    let progress = TransferProgress::default();
+    let writer = progress.clone();
+    std::thread::spawn(move || writer.update(3, 10, 2048)).join().unwrap();
+    assert_eq!(progress.get(), Some(Transfer { objects: 3, total_objects: 10, bytes: 2048 }));
+    // More objects than the total, as the deltas are counted again once resolved, is shown as all of them
+    progress.update(11, 10, 2048);
+    assert_eq!(progress.get().unwrap().objects, 10);
+  }
+
+  #[test]
+  fn transfers_read_like_objects_and_bytes() {
+    let transfer = |bytes| Transfer { objects: 12, total_objects: 40, bytes }.to_string();
+    assert_eq!(transfer(900), "12/40 objects, 900 B");
+    assert_eq!(transfer(2048), "12/40 objects, 2.0 KiB");
+    assert_eq!(transfer(1_258_291), "12/40 objects, 1.2 MiB");
+    assert_eq!(transfer(3 << 30), "12/40 objects, 3.0 GiB");
+  }
+}
@@ -0,0 +1,63 @@
+/// Glob patterns for branches that must never be deleted, `*` matches any run of chars and `?` matches one.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProtectedBranches {
+  patterns: Vec<String>,
+}
+
+impl ProtectedBranches {
+  pub fn new(patterns: Vec<String>) -> Self {
+    ProtectedBranches { patterns }
+  }
+
+  pub fn matches(&self, name: &str) -> bool {
+    self.patterns.iter().any(|pattern| glob_match(pattern, name))
+  }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let name: Vec<char> = name.chars().collect();
+  let (mut p, mut n) = (0, 0);
+  // Where to resume after the last `*` if the chars after it stop matching
+  let mut backtrack: Option<(usize, usize)> = None;
+  while n < name.len() {
+    match pattern.get(p) {
+      Some('*') => {
+        backtrack = Some((p, n));
+        p += 1;
+      },
+      Some(c) if *c == '?' || *c == name[n] => {
+        p += 1;
+        n += 1;
+      },
+      _ => {
+        let Some((star, matched)) = backtrack else {
+          return false;
+        };
+        // Let the `*` swallow one more char and try again
+        backtrack = Some((star, matched + 1));
+        p = star + 1;
+        n = matched + 1;
+      },
+    }
+  }
+  pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn names_match_the_glob_patterns() {
+    let protected =
+      ProtectedBranches::new(vec![String::from("develop"), String::from("release/*"), String::from("v?")]);
+    assert!(protected.matches("develop"));
+    assert!(protected.matches("release/1.0"));
+    assert!(protected.matches("v2"));
+    assert!(!protected.matches("develop2"));
+    assert!(!protected.matches("release"));
+    assert!(!protected.matches("v10"));
+    assert!(!ProtectedBranches::default().matches("main"));
+  }
+}
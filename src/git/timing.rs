@@ -0,0 +1,45 @@
+use std::time::Instant;
+
+use tracing::{debug, enabled, Level};
+
+/// Runs `run` and logs how long it took at debug level, the clock isn't read at all when debug logging is off.
+pub fn timed<T>(operation: &str, run: impl FnOnce() -> T) -> T {
+  if !enabled!(Level::DEBUG) {
+    return run();
+  }
+  let start = Instant::now();
+  let result = run();
+  debug!("{} took {:?}", operation, start.elapsed());
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use tracing::subscriber::with_default;
+  use tracing_subscriber::{filter::LevelFilter, fmt, layer::SubscriberExt, Registry};
+
+  use super::*;
+
+  fn logging_at(level: LevelFilter) -> impl tracing::Subscriber {
+    Registry::default().with(fmt::layer().with_test_writer()).with(level)
+  }
+
+  #[test]
+  fn the_result_is_returned_unchanged_whether_or_not_it_is_logged() {
+    for level in [LevelFilter::DEBUG, LevelFilter::INFO] {
+      with_default(logging_at(level), || {
+        assert_eq!(timed("Loading", || Ok::<_, String>(vec![1, 2])), Ok(vec![1, 2]));
+        assert_eq!(timed("Checking out", || Err::<(), _>(String::from("failed"))), Err(String::from("failed")));
+      });
+    }
+  }
+
+  #[test]
+  fn the_operation_runs_exactly_once() {
+    with_default(logging_at(LevelFilter::DEBUG), || {
+      let mut runs = 0;
+      timed("Fetching", || runs += 1);
+      assert_eq!(runs, 1);
+    });
+  }
+}
@@ -0,0 +1,52 @@
+/// Used when the config doesn't set `wip_branch_pattern`.
+pub const DEFAULT_WIP_PATTERN: &str = "wip/{date}-{sha}";
+
+/// Fills `{date}` with the UTC `YYYY-MM-DD` date of the unix `time` and `{sha}` with `short_sha`.
+pub fn fill_pattern(pattern: &str, time: i64, short_sha: &str) -> String {
+  pattern.replace("{date}", &format_date(time)).replace("{sha}", short_sha)
+}
+
+/// `name`, or `name-2`, `name-3` and so on when it is already taken.
+pub fn unique_name(name: &str, taken: &[String]) -> String {
+  if !taken.iter().any(|existing| existing == name) {
+    return name.to_string();
+  }
+  (2..)
+    .map(|counter| format!("{}-{}", name, counter))
+    .find(|candidate| !taken.contains(candidate))
+    .expect("there are always more counters to try")
+}
+
+/// Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+pub fn format_date(time: i64) -> String {
+  let days = time.div_euclid(86_400) + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days.rem_euclid(146_097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn the_pattern_is_filled_with_the_date_and_sha() {
+    assert_eq!(fill_pattern(DEFAULT_WIP_PATTERN, 1_700_000_000, "abc1234"), "wip/2023-11-14-abc1234");
+    assert_eq!(fill_pattern("try/{sha}", 0, "abc1234"), "try/abc1234");
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(951_782_400), "2000-02-29");
+  }
+
+  #[test]
+  fn a_taken_name_gets_the_next_free_counter() {
+    let taken = vec![String::from("wip/x"), String::from("wip/x-2")];
+    assert_eq!(unique_name("wip/y", &taken), "wip/y");
+    assert_eq!(unique_name("wip/x", &taken), "wip/x-3");
+  }
+}
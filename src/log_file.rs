@@ -0,0 +1,119 @@
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+/// The log is rotated once it would grow past this size.
+pub const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+
+/// How many rotated logs are kept next to the current one, as `<name>.1` for the newest up to `<name>.<count>`.
+pub const KEPT_LOG_FILES: usize = 3;
+
+/// A log file that is appended to until it is full, then moved aside to `<name>.1` so writing continues in a fresh
+/// file. Older rotations shift along and the oldest is dropped.
+#[derive(Debug)]
+pub struct RotatingFile {
+  path: PathBuf,
+  max_bytes: u64,
+  kept: usize,
+  file: File,
+  written: u64,
+}
+
+impl RotatingFile {
+  pub fn open(path: &Path, max_bytes: u64, kept: usize) -> io::Result<Self> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let written = file.metadata()?.len();
+    Ok(RotatingFile { path: path.to_path_buf(), max_bytes, kept, file, written })
+  }
+
+  fn rotated_path(&self, index: usize) -> PathBuf {
+    let mut name = self.path.clone().into_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+  }
+
+  fn rotate(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+    if self.kept == 0 {
+      self.file = File::create(&self.path)?;
+      self.written = 0;
+      return Ok(());
+    }
+    // Missing rotations are fine, there are fewer of them until the log has filled up that many times
+    let _ = fs::remove_file(self.rotated_path(self.kept));
+    for index in (1..self.kept).rev() {
+      let _ = fs::rename(self.rotated_path(index), self.rotated_path(index + 1));
+    }
+    fs::rename(&self.path, self.rotated_path(1))?;
+    self.file = File::create(&self.path)?;
+    self.written = 0;
+    Ok(())
+  }
+}
+
+impl Write for RotatingFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    // A single line longer than the limit still goes into a file of its own rather than being split
+    if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+      self.rotate()?;
+    }
+    let written = self.file.write(buf)?;
+    self.written += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::TempDir;
+
+  fn read(path: PathBuf) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+  }
+
+  #[test]
+  fn writes_go_to_the_file_until_it_is_full() {
+    let dir = TempDir::new();
+    let path = dir.path().join("app.log");
+    let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+    file.write_all(b"first\n").unwrap();
+    file.flush().unwrap();
+    assert_eq!(read(path.clone()), "first\n");
+    assert!(!file.rotated_path(1).exists());
+  }
+
+  #[test]
+  fn a_full_log_is_rotated_and_only_the_kept_rotations_remain() {
+    let dir = TempDir::new();
+    let path = dir.path().join("app.log");
+    let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+    for line in ["one\n", "two\n", "three\n", "four\n", "five\n", "six\n"] {
+      file.write_all(line.as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+    // The first rotation held one and two, which were dropped once there were more than two rotations
+    assert_eq!(read(path.clone()), "six\n");
+    assert_eq!(read(file.rotated_path(1)), "four\nfive\n");
+    assert_eq!(read(file.rotated_path(2)), "three\n");
+    assert!(!file.rotated_path(3).exists());
+  }
+
+  #[test]
+  fn reopening_appends_and_counts_what_is_already_there() {
+    let dir = TempDir::new();
+    let path = dir.path().join("app.log");
+    fs::write(&path, "earlier\n").unwrap();
+    let mut file = RotatingFile::open(&path, 10, 1).unwrap();
+    file.write_all(b"later\n").unwrap();
+    file.flush().unwrap();
+    assert_eq!(read(path.clone()), "later\n");
+    assert_eq!(read(file.rotated_path(1)), "earlier\n");
+  }
+}
@@ -1,41 +1,125 @@
+use std::{
+  env::current_dir,
+  io::{stdin, stdout},
+  path::PathBuf,
+  process::ExitCode,
+};
+
 use clap::Parser;
 use color_eyre::eyre::Result;
 
 use crate::{
   app::App,
   cli::Cli,
+  error::{exit_code, Error},
+  export::branches_json,
+  git::{git_cli_repo::GitCliRepo, git_repo::GitRepo},
+  rpc::serve,
   utils::{initialize_logging, initialize_panic_handler},
 };
 
 pub mod action;
 pub mod app;
 pub mod cli;
+pub mod clipboard;
 pub mod components;
 pub mod config;
 pub mod error;
+pub mod export;
 pub mod git;
+pub mod log_file;
 pub mod mode;
+pub mod rpc;
+#[cfg(test)]
+mod test_support;
 pub mod tui;
 pub mod utils;
+pub mod viewer;
+
+/// The repository the cli points at, or the one containing it with `--superproject`.
+fn repo_path(cli: &Cli) -> Result<PathBuf, Error> {
+  let path = match &cli.path {
+    Some(path) => path.clone(),
+    None => current_dir()?,
+  };
+  if cli.superproject {
+    let superproject = GitCliRepo::from_path(&path)?.open_superproject()?;
+    // A superproject always has a working tree, as the submodule is checked out in it
+    return Ok(superproject.workdir().unwrap_or(superproject.path()).to_path_buf());
+  }
+  Ok(path)
+}
 
-async fn tokio_main() -> Result<()> {
+/// Runs a mode that exits without starting the UI, printing just the message of a failure and exiting with its
+/// [`Error::exit_code`] so scripts can tell failures apart.
+fn run_non_interactive(mode: impl FnOnce() -> Result<(), Error>) -> ExitCode {
+  match mode() {
+    Ok(()) => ExitCode::from(exit_code::SUCCESS),
+    Err(err) => {
+      eprintln!("{} error: {}", env!("CARGO_PKG_NAME"), err);
+      ExitCode::from(err.exit_code())
+    },
+  }
+}
+
+async fn tokio_main() -> Result<ExitCode> {
   initialize_logging()?;
 
   initialize_panic_handler()?;
 
-  Cli::parse();
-  let mut app = App::new()?;
+  let cli = Cli::parse();
+  if cli.json {
+    return Ok(run_non_interactive(|| {
+      println!("{}", branches_json(&GitCliRepo::from_path(&repo_path(&cli)?)?)?);
+      Ok(())
+    }));
+  }
+  if cli.stdio {
+    return Ok(run_non_interactive(|| {
+      let repo = GitCliRepo::from_path(&repo_path(&cli)?)?.dry_run(cli.dry_run).read_only(cli.read_only);
+      serve(&repo, stdin().lock(), stdout().lock())
+    }));
+  }
+
+  let mut app = App::new(repo_path(&cli)?, cli.dry_run, cli.read_only)?;
   app.run().await?;
 
-  Ok(())
+  Ok(ExitCode::SUCCESS)
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-  if let Err(e) = tokio_main().await {
-    eprintln!("{} error: Something went wrong", env!("CARGO_PKG_NAME"));
-    Err(e)
-  } else {
-    Ok(())
+async fn main() -> Result<ExitCode> {
+  match tokio_main().await {
+    Ok(code) => Ok(code),
+    Err(e) => {
+      eprintln!("{} error: Something went wrong", env!("CARGO_PKG_NAME"));
+      Err(e)
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::Fixture;
+
+  fn cli_for(args: &[&str]) -> Cli {
+    Cli::parse_from([env!("CARGO_PKG_NAME")].iter().chain(args))
+  }
+
+  #[test]
+  fn the_superproject_flag_opens_the_repository_containing_the_submodule() {
+    let fixture = Fixture::new();
+    let submodule = fixture.with_submodule();
+    let path = submodule.to_str().unwrap();
+    assert_eq!(repo_path(&cli_for(&[path])).unwrap(), submodule);
+    assert_eq!(repo_path(&cli_for(&["--superproject", path])).unwrap(), fixture.path);
+  }
+
+  #[test]
+  fn the_superproject_flag_outside_a_submodule_is_an_error() {
+    let fixture = Fixture::new();
+    let err = repo_path(&cli_for(&["--superproject", fixture.path.to_str().unwrap()])).unwrap_err();
+    assert!(matches!(err, Error::NotASubmodule), "{}", err);
   }
 }
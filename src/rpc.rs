@@ -0,0 +1,184 @@
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+  error::{with_context, Error},
+  export::branch_exports,
+  git::git_repo::{GitBranch, GitRepo},
+};
+
+/// What a request asks for, named by its `op` field like `{"op":"checkout","name":"main"}`. A request can also have
+/// an `id`, which is echoed back in its response.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Command {
+  /// The local branches as the json export writes them.
+  List,
+  /// The checked out branch, `null` when HEAD is detached.
+  Current,
+  Checkout {
+    name: String,
+  },
+  /// Creates a branch at HEAD without checking it out.
+  Create {
+    name: String,
+  },
+}
+
+/// Why a request failed, with the code the other non-interactive modes would exit with, one of
+/// [`crate::error::exit_code`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResponseError {
+  pub message: String,
+  pub code: u8,
+}
+
+/// The answer to a request, written as a single line of json with either a `result` or an `error`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Response {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub result: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<ResponseError>,
+}
+
+impl Response {
+  fn new(id: Option<Value>, result: Result<Value, Error>) -> Self {
+    match result {
+      Ok(result) => Response { id, result: Some(result), error: None },
+      Err(err) => {
+        let error = ResponseError { message: err.to_string().trim().to_string(), code: err.exit_code() };
+        Response { id, result: None, error: Some(error) }
+      },
+    }
+  }
+}
+
+fn run(repo: &dyn GitRepo, command: Command) -> Result<Value, Error> {
+  // Picks up changes made outside since the last request, like commits made in the editor
+  repo.refresh();
+  match command {
+    Command::List => Ok(serde_json::to_value(branch_exports(repo)?)?),
+    Command::Current => Ok(serde_json::to_value(repo.current_branch()?)?),
+    Command::Checkout { name } => {
+      repo.checkout_branch_from_name(&name)?;
+      Ok(Value::Null)
+    },
+    Command::Create { name } => {
+      // Checked first so the editor is told why a name can't be used, git only says it isn't valid
+      if let Some(problem) = repo.validate_branch_name_detailed(&name)? {
+        return with_context(format!("Failed to create {}", name), || Err(Error::Git(problem.to_string())));
+      }
+      repo.create_branch(&GitBranch::new(name))?;
+      Ok(Value::Null)
+    },
+  }
+}
+
+/// Answers one line of input, a line that isn't a known command gets an error response like a failed command.
+pub fn respond(repo: &dyn GitRepo, line: &str) -> Response {
+  let request: Value = match serde_json::from_str(line) {
+    Ok(request) => request,
+    Err(err) => return Response::new(None, Err(err.into())),
+  };
+  let id = request.get("id").cloned();
+  let result = serde_json::from_value(request).map_err(Error::from).and_then(|command| run(repo, command));
+  Response::new(id, result)
+}
+
+/// Reads newline delimited json commands from `input` until it ends, writing each response to `output` as soon as it
+/// is ready so an editor can wait on it. Blank lines are skipped.
+pub fn serve(repo: &dyn GitRepo, input: impl BufRead, mut output: impl Write) -> Result<(), Error> {
+  for line in input.lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    writeln!(output, "{}", serde_json::to_string(&respond(repo, &line))?)?;
+    output.flush()?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::{error::exit_code, test_support::Fixture};
+
+  /// Serves `requests` one per line, returning each response parsed back from json.
+  fn serve_lines(repo: &dyn GitRepo, requests: &[Value]) -> Vec<Value> {
+    let input: String = requests.iter().map(|request| format!("{}\n\n", request)).collect();
+    let mut output = Vec::new();
+    serve(repo, input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+  }
+
+  #[test]
+  fn a_sequence_of_commands_is_answered_in_order() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    let responses = serve_lines(&repo, &[
+      json!({"id": 1, "op": "create", "name": "feature"}),
+      json!({"id": 2, "op": "checkout", "name": "feature"}),
+      json!({"id": "three", "op": "current"}),
+      json!({"op": "list"}),
+    ]);
+    assert_eq!(responses.len(), 4);
+    assert_eq!(responses[0], json!({"id": 1, "result": null}));
+    assert_eq!(responses[1], json!({"id": 2, "result": null}));
+    assert_eq!(responses[2]["id"], "three");
+    assert_eq!(responses[2]["result"]["name"], "feature");
+    let names: Vec<&Value> = responses[3]["result"].as_array().unwrap().iter().map(|branch| &branch["name"]).collect();
+    assert_eq!(names, [&json!("feature"), &json!("main")]);
+    assert_eq!(fixture.git(&["symbolic-ref", "--short", "HEAD"]), "feature");
+  }
+
+  #[test]
+  fn an_unknown_branch_is_a_not_found_error() {
+    let fixture = Fixture::new();
+    // A file of the same name, which checking out by name must not restore
+    fixture.write("README.md", "local changes\n");
+    let response = respond(&fixture.cli(), r#"{"id":7,"op":"checkout","name":"README.md"}"#);
+    assert_eq!(response.id, Some(json!(7)));
+    assert_eq!(response.result, None);
+    let error = response.error.unwrap();
+    assert_eq!(error.message, "Failed to check out README.md: There is no branch named README.md");
+    assert_eq!(error.code, exit_code::NOT_FOUND);
+    assert_eq!(fixture.git(&["status", "--porcelain"]), "M README.md");
+  }
+
+  #[test]
+  fn a_name_that_cant_be_used_is_refused_with_the_reason() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    let responses = serve_lines(&repo, &[
+      json!({"op": "create", "name": "-d"}),
+      json!({"op": "create", "name": "main"}),
+      json!({"op": "create", "name": "HEAD"}),
+    ]);
+    let messages: Vec<&Value> = responses.iter().map(|response| &response["error"]["message"]).collect();
+    assert_eq!(messages, [
+      &json!("Failed to create -d: The name contains characters git doesn't allow"),
+      &json!("Failed to create main: A branch with this name already exists"),
+      &json!("Failed to create HEAD: HEAD is reserved by git"),
+    ]);
+    assert_eq!(fixture.git(&["branch", "--format=%(refname:short)"]), "main");
+  }
+
+  #[test]
+  fn a_line_that_isnt_a_command_gets_an_error() {
+    let fixture = Fixture::new();
+    let repo = fixture.cli();
+    for line in ["not json", r#"{"id":1,"op":"merge"}"#] {
+      let response = respond(&repo, line);
+      assert_eq!(response.result, None);
+      assert_eq!(response.error.unwrap().code, exit_code::FAILURE);
+    }
+  }
+}
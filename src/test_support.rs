@@ -0,0 +1,271 @@
+//! Throwaway repositories for the tests, built with the git cli in a fresh directory that is removed again on drop.
+
+use std::{
+  collections::HashSet,
+  env::temp_dir,
+  fs,
+  panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+  path::{Path, PathBuf},
+  process::Command,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    RwLock, RwLockReadGuard, RwLockWriteGuard,
+  },
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use ratatui::{backend::TestBackend, buffer::Buffer, layout::Position, Terminal};
+
+use crate::{
+  components::Component,
+  git::{
+    git2_repo::Git2Repo,
+    git_cli_repo::GitCliRepo,
+    git_repo::{GitBranch, GitRepo},
+  },
+};
+
+/// Held shared by every fixture and exclusively by the tests that set git's environment variables, which the cli
+/// backend would otherwise pick up in whichever tests run alongside them.
+static ENV_LOCK: RwLock<()> = RwLock::new(());
+
+/// Blocks every fixture until dropped, for a test that sets git's environment variables.
+pub fn lock_env() -> RwLockWriteGuard<'static, ()> {
+  ENV_LOCK.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of its own under the system temp dir, removed with everything in it on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+  pub fn new() -> Self {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.subsec_nanos()).unwrap_or_default();
+    let name = format!("gbm-test-{}-{}-{}", std::process::id(), NEXT_DIR.fetch_add(1, Ordering::Relaxed), nanos);
+    let path = temp_dir().join(name);
+    fs::create_dir_all(&path).expect("Unable to create a temp dir");
+    // Canonical so it compares equal with the paths git reports, /tmp is a symlink on some systems
+    TempDir(path.canonicalize().expect("Unable to resolve the temp dir"))
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.0
+  }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.0);
+  }
+}
+
+/// Runs git in `dir` with a fixed identity, panicking with its output when it fails.
+pub fn git_in(dir: &Path, args: &[&str]) -> String {
+  let output = Command::new("git")
+    .args(args)
+    .current_dir(dir)
+    .env("GIT_AUTHOR_NAME", "Test Author")
+    .env("GIT_AUTHOR_EMAIL", "author@example.com")
+    .env("GIT_COMMITTER_NAME", "Test Committer")
+    .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+    .output()
+    .expect("Unable to run git");
+  assert!(output.status.success(), "git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr));
+  String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// A repository on `main` with one commit, plus a bare `origin` once [`Fixture::with_remote`] is called.
+pub struct Fixture {
+  pub dir: TempDir,
+  /// The working tree of the repository.
+  pub path: PathBuf,
+  _env: RwLockReadGuard<'static, ()>,
+}
+
+impl Fixture {
+  pub fn new() -> Self {
+    let fixture = Fixture::empty();
+    fixture.commit("README.md", "init\n", "Initial commit");
+    fixture
+  }
+
+  /// A freshly initialized repository on an unborn `main`, without any commits.
+  pub fn empty() -> Self {
+    let env = ENV_LOCK.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let dir = TempDir::new();
+    let path = dir.path().join("work");
+    fs::create_dir(&path).unwrap();
+    let fixture = Fixture { dir, path, _env: env };
+    fixture.git(&["init", "-q", "-b", "main"]);
+    fixture.git(&["config", "user.name", "Test Author"]);
+    fixture.git(&["config", "user.email", "author@example.com"]);
+    fixture.git(&["config", "commit.gpgsign", "false"]);
+    fixture
+  }
+
+  pub fn git(&self, args: &[&str]) -> String {
+    git_in(&self.path, args)
+  }
+
+  pub fn write(&self, file: &str, contents: &str) {
+    let path = self.path.join(file);
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(path, contents).unwrap();
+  }
+
+  /// Writes `file` and commits it on the checked out branch, returning the new commit's id.
+  pub fn commit(&self, file: &str, contents: &str, message: &str) -> String {
+    self.write(file, contents);
+    self.git(&["add", file]);
+    self.git(&["commit", "-q", "-m", message]);
+    self.head()
+  }
+
+  /// Like [`Fixture::commit`] with the author and committer dates set to `time`, in seconds since the epoch.
+  pub fn commit_at(&self, file: &str, message: &str, time: i64) -> String {
+    self.write(file, message);
+    self.git(&["add", file]);
+    let date = format!("@{} +0000", time);
+    let output = Command::new("git")
+      .args(["commit", "-q", "-m", message])
+      .current_dir(&self.path)
+      .env("GIT_AUTHOR_DATE", &date)
+      .env("GIT_COMMITTER_DATE", &date)
+      .output()
+      .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    self.head()
+  }
+
+  pub fn head(&self) -> String {
+    self.git(&["rev-parse", "HEAD"])
+  }
+
+  /// Creates `name` at HEAD and checks it out with a commit of its own, then goes back to `main`.
+  pub fn branch_with_commit(&self, name: &str) -> String {
+    self.git(&["checkout", "-q", "-b", name]);
+    let file = format!("{}.txt", name.replace('/', "-"));
+    let commit = self.commit(&file, name, &format!("Work on {}", name));
+    self.git(&["checkout", "-q", "main"]);
+    commit
+  }
+
+  /// Adds a bare repository as `origin` and pushes `main` to it with tracking, returning the bare repository's path.
+  pub fn with_remote(&self) -> PathBuf {
+    let remote = self.dir.path().join("origin.git");
+    git_in(self.dir.path(), &["init", "-q", "--bare", "-b", "main", "origin.git"]);
+    self.git(&["remote", "add", "origin", remote.to_str().unwrap()]);
+    self.git(&["push", "-q", "-u", "origin", "main"]);
+    remote
+  }
+
+  /// Clones `origin` next to the repository as someone else, to change the remote behind the repository's back.
+  pub fn other_clone(&self) -> PathBuf {
+    let clone = self.dir.path().join("other");
+    let remote = self.dir.path().join("origin.git");
+    git_in(self.dir.path(), &["clone", "-q", remote.to_str().unwrap(), "other"]);
+    git_in(&clone, &["config", "user.name", "Someone Else"]);
+    git_in(&clone, &["config", "user.email", "else@example.com"]);
+    clone
+  }
+
+  /// Adds a repository with a commit of its own as the submodule `sub` and commits it, returning the submodule's
+  /// working tree.
+  pub fn with_submodule(&self) -> PathBuf {
+    let inner = self.dir.path().join("inner");
+    git_in(self.dir.path(), &["init", "-q", "-b", "main", "inner"]);
+    fs::write(inner.join("lib.txt"), "lib\n").unwrap();
+    git_in(&inner, &["add", "lib.txt"]);
+    git_in(&inner, &["commit", "-q", "-m", "Library"]);
+    // Local paths are only allowed as submodule urls when asked for
+    self.git(&["-c", "protocol.file.allow=always", "submodule", "add", "-q", inner.to_str().unwrap(), "sub"]);
+    self.git(&["commit", "-q", "-m", "Add the submodule"]);
+    self.path.join("sub")
+  }
+
+  /// Writes commits nothing refers to until two of them share the first four characters of their ids, returning those
+  /// characters. Their dates are fixed so it is the same commits every time.
+  pub fn ambiguous_prefix(&self) -> String {
+    let repo = git2::Repository::open(&self.path).unwrap();
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    let signature = git2::Signature::new("Test Author", "author@example.com", &git2::Time::new(0, 0)).unwrap();
+    let mut seen = HashSet::new();
+    for i in 0.. {
+      let id = repo.commit(None, &signature, &signature, &format!("Commit {}", i), &tree, &[]).unwrap();
+      let prefix = id.to_string()[..4].to_string();
+      if !seen.insert(prefix.clone()) {
+        return prefix;
+      }
+    }
+    unreachable!()
+  }
+
+  pub fn git2(&self) -> Git2Repo {
+    Git2Repo::from_path(&self.path).unwrap()
+  }
+
+  pub fn cli(&self) -> GitCliRepo {
+    GitCliRepo::from_path(&self.path).unwrap()
+  }
+}
+
+/// Runs `test` on a fresh fixture for each backend, after `setup` has built what the test needs in it.
+/// Runs `test`, re-raising any failure prefixed with `label`, for the assertions repeated across cases that don't say
+/// which case they ran against.
+pub fn named<T>(label: &str, test: impl FnOnce() -> T) -> T {
+  match catch_unwind(AssertUnwindSafe(test)) {
+    Ok(value) => value,
+    Err(payload) => {
+      let message = match (payload.downcast_ref::<String>(), payload.downcast_ref::<&str>()) {
+        (Some(message), _) => message.clone(),
+        (_, Some(message)) => message.to_string(),
+        _ => resume_unwind(payload),
+      };
+      panic!("{}: {}", label, message);
+    },
+  }
+}
+
+/// Runs `test`, re-raising any failure with `backend` named.
+pub fn naming_backend<T>(backend: &str, test: impl FnOnce() -> T) -> T {
+  named(&format!("{} backend", backend), test)
+}
+
+pub fn each_backend(setup: impl Fn(&Fixture), test: impl Fn(&Fixture, &mut dyn GitRepo)) {
+  for backend in ["git2", "cli"] {
+    let fixture = Fixture::new();
+    setup(&fixture);
+    let mut repo: Box<dyn GitRepo> = match backend {
+      "git2" => Box::new(fixture.git2()),
+      _ => Box::new(fixture.cli()),
+    };
+    naming_backend(backend, || test(&fixture, &mut *repo));
+  }
+}
+
+/// The local branch called `name` as the repository lists it, with its upstream and commit filled in.
+pub fn local_branch(repo: &dyn GitRepo, name: &str) -> GitBranch {
+  repo.local_branches().unwrap().into_iter().find(|branch| branch.name == name).expect("No such branch")
+}
+
+/// Draws the component over the whole of a terminal `width` columns wide and `height` rows tall, returning the cells
+/// it drew.
+pub fn render(component: &mut dyn Component, width: u16, height: u16) -> Buffer {
+  let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+  terminal.draw(|f| component.draw(f, f.area()).unwrap()).unwrap();
+  terminal.backend().buffer().clone()
+}
+
+/// The text of each row of the buffer with the trailing blanks trimmed.
+pub fn rows(buffer: &Buffer) -> Vec<String> {
+  let area = buffer.area;
+  (area.top()..area.bottom())
+    .map(|y| {
+      let row: String = (area.left()..area.right()).map(|x| buffer[Position::new(x, y)].symbol()).collect();
+      row.trim_end().to_string()
+    })
+    .collect()
+}
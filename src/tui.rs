@@ -28,6 +28,17 @@ pub fn io() -> IO {
 }
 pub type Frame<'a> = ratatui::Frame<'a>;
 
+/// Puts the terminal back how it was before [`Tui::enter`] for when the app can't exit normally, like on a panic. It
+/// needs neither a [`Tui`] nor a tokio runtime, so it works from any thread, and turns mouse capture and bracketed
+/// paste off whether or not they were on.
+pub fn restore_terminal() -> Result<()> {
+  if crossterm::terminal::is_raw_mode_enabled()? {
+    crossterm::execute!(io(), DisableBracketedPaste, DisableMouseCapture, LeaveAlternateScreen, cursor::Show)?;
+    crossterm::terminal::disable_raw_mode()?;
+  }
+  Ok(())
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Event {
   Init,
@@ -1,11 +1,18 @@
-use std::path::PathBuf;
+use std::{
+  path::PathBuf,
+  sync::{Mutex, OnceLock},
+};
 
 use color_eyre::eyre::Result;
 use directories::ProjectDirs;
 use lazy_static::lazy_static;
-use tracing::error;
+use tracing::{error, warn};
 use tracing_error::ErrorLayer;
-use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, Layer};
+use tracing_subscriber::{
+  filter::EnvFilter, prelude::__tracing_subscriber_SubscriberExt, reload, util::SubscriberInitExt, Layer, Registry,
+};
+
+use crate::log_file::{RotatingFile, KEPT_LOG_FILES, MAX_LOG_BYTES};
 
 const VERSION_MESSAGE: &str =
   concat!(env!("CARGO_PKG_VERSION"), "-", env!("VERGEN_GIT_DESCRIBE"), " (", env!("VERGEN_BUILD_DATE"), ")");
@@ -20,11 +27,21 @@ lazy_static! {
   pub static ref LOG_FILE: String = format!("{}.log", env!("CARGO_PKG_NAME"));
 }
 
+/// Swaps the log filter once the configured level is known, as logging starts before the config is read.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
 fn project_directory() -> Option<ProjectDirs> {
   ProjectDirs::from("com", "rrushton", env!("CARGO_PKG_NAME"))
 }
 
+/// Installs a panic hook that restores the terminal before reporting the panic, so the report is readable and the
+/// shell usable afterwards.
 pub fn initialize_panic_handler() -> Result<()> {
+  install_panic_hook(crate::tui::restore_terminal)
+}
+
+/// Like [`initialize_panic_handler`] with `restore` putting the terminal back.
+fn install_panic_hook(restore: fn() -> Result<()>) -> Result<()> {
   let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default()
     .panic_section(format!("This is a bug. Consider reporting it at {}", env!("CARGO_PKG_REPOSITORY")))
     .capture_span_trace_by_default(false)
@@ -33,10 +50,9 @@ pub fn initialize_panic_handler() -> Result<()> {
     .into_hooks();
   eyre_hook.install()?;
   std::panic::set_hook(Box::new(move |panic_info| {
-    if let Ok(mut t) = crate::tui::Tui::new() {
-      if let Err(r) = t.exit() {
-        error!("Unable to exit Terminal: {:?}", r);
-      }
+    // Setting up a Tui to exit needs the tokio runtime, which a panic on another thread doesn't have
+    if let Err(err) = restore() {
+      error!("Unable to restore the terminal: {:?}", err);
     }
 
     #[cfg(not(debug_assertions))]
@@ -94,28 +110,68 @@ pub fn get_config_dir() -> PathBuf {
   directory
 }
 
+/// Where state that can safely be lost, like the remembered UI state, is kept.
+pub fn get_cache_dir() -> PathBuf {
+  match project_directory() {
+    Some(proj_dirs) => proj_dirs.cache_dir().to_path_buf(),
+    None => PathBuf::from(".").join(".cache"),
+  }
+}
+
+/// The log goes in the cache directory, or the data folder when one is set as dev builds do.
+pub fn get_log_path() -> PathBuf {
+  DATA_FOLDER.clone().unwrap_or_else(get_cache_dir).join(LOG_FILE.clone())
+}
+
+/// The filter set by `RUST_LOG` or the log level environment variable, which win over the configured level.
+fn env_log_filter() -> Option<String> {
+  std::env::var("RUST_LOG").or_else(|_| std::env::var(LOG_ENV.clone())).ok()
+}
+
+fn level_filter(level: &str) -> String {
+  format!("{}={}", env!("CARGO_CRATE_NAME"), level)
+}
+
+/// Sends everything logged, including records from the `log` crate, to a rotating file in the cache directory. Nothing
+/// is written to the terminal as it would corrupt the TUI.
 pub fn initialize_logging() -> Result<()> {
-  let directory = get_data_dir();
-  std::fs::create_dir_all(directory.clone())?;
-  let log_path = directory.join(LOG_FILE.clone());
-  let log_file = std::fs::File::create(log_path)?;
-  std::env::set_var(
-    "RUST_LOG",
-    std::env::var("RUST_LOG")
-      .or_else(|_| std::env::var(LOG_ENV.clone()))
-      .unwrap_or_else(|_| format!("{}=info", env!("CARGO_CRATE_NAME"))),
-  );
+  let log_path = get_log_path();
+  if let Some(directory) = log_path.parent() {
+    std::fs::create_dir_all(directory)?;
+  }
+  let log_file = RotatingFile::open(&log_path, MAX_LOG_BYTES, KEPT_LOG_FILES)?;
+  let filter = EnvFilter::new(env_log_filter().unwrap_or_else(|| level_filter("info")));
+  let (filter, handle) = reload::Layer::new(filter);
+  let _ = LOG_FILTER.set(handle);
   let file_subscriber = tracing_subscriber::fmt::layer()
     .with_file(true)
     .with_line_number(true)
-    .with_writer(log_file)
+    .with_writer(Mutex::new(log_file))
     .with_target(false)
     .with_ansi(false)
-    .with_filter(tracing_subscriber::filter::EnvFilter::from_default_env());
+    .with_filter(filter);
   tracing_subscriber::registry().with(file_subscriber).with(ErrorLayer::default()).init();
   Ok(())
 }
 
+/// Logs at `level`, like `debug` or `warn`, from now on unless a level was set in the environment.
+pub fn set_log_level(level: &str) {
+  if env_log_filter().is_some() {
+    return;
+  }
+  let Some(handle) = LOG_FILTER.get() else {
+    return;
+  };
+  match EnvFilter::try_new(level_filter(level)) {
+    Ok(filter) => {
+      if let Err(err) = handle.reload(filter) {
+        warn!("Failed to change the log level to {}: {}", level, err);
+      }
+    },
+    Err(err) => warn!("Invalid log level {}, keeping the current one: {}", level, err),
+  }
+}
+
 /// Similar to the `std::dbg!` macro, but generates `tracing` events rather
 /// than printing to stdout.
 ///
@@ -148,7 +204,8 @@ pub fn version() -> String {
   // let current_exe_path = PathBuf::from(clap::crate_name!()).display().to_string();
   let config_dir_path = get_config_dir().display().to_string();
   let data_dir_path = get_data_dir().display().to_string();
-  let log_level = std::env::var(LOG_ENV.clone()).unwrap_or_else(|_| format!("{}=info", env!("CARGO_CRATE_NAME")));
+  let log_path = get_log_path().display().to_string();
+  let log_level = env_log_filter().unwrap_or_else(|| level_filter("info"));
 
   format!(
     "\
@@ -157,6 +214,44 @@ pub fn version() -> String {
 Authors: {author}
 Config directory: {config_dir_path}
 Data directory: {data_dir_path}
+Log file: {log_path}
 Log leve: {log_level}"
   )
 }
+
+#[cfg(test)]
+mod tests {
+  use std::process::Command;
+
+  use super::*;
+
+  const PANIC_CHILD: &str = "GBM_TEST_PANIC_CHILD";
+
+  /// Only panics when run by [`the_terminal_is_restored_before_the_panic_is_reported`], as the hook exits the process.
+  #[test]
+  fn panic_with_the_hook_installed() {
+    if std::env::var_os(PANIC_CHILD).is_none() {
+      return;
+    }
+    install_panic_hook(|| {
+      eprintln!("terminal restored");
+      Ok(())
+    })
+    .unwrap();
+    panic!("something broke");
+  }
+
+  #[test]
+  fn the_terminal_is_restored_before_the_panic_is_reported() {
+    let output = Command::new(std::env::current_exe().unwrap())
+      .args(["utils::tests::panic_with_the_hook_installed", "--exact", "--nocapture", "--test-threads=1"])
+      .env(PANIC_CHILD, "1")
+      .output()
+      .unwrap();
+    assert_eq!(output.status.code(), Some(libc::EXIT_FAILURE));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let restored = stderr.find("terminal restored").expect("The terminal wasn't restored");
+    let reported = stderr.find("something broke").expect("The panic wasn't reported");
+    assert!(restored < reported, "{}", stderr);
+  }
+}
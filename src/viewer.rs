@@ -0,0 +1,90 @@
+use std::{
+  env, fs,
+  io::Write,
+  process::{Command, Stdio},
+};
+
+use serde::Deserialize;
+use tracing::info;
+
+use crate::error::Error;
+
+/// Where text too long for the TUI, like a full commit, is opened.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Viewer {
+  /// `$PAGER`, or `less` when it isn't set, reading the text from stdin.
+  #[default]
+  Pager,
+  /// `$EDITOR`, or `vi` when it isn't set, opening a temporary file holding the text.
+  Editor,
+}
+
+/// The program and its arguments for the viewer, from the value of its environment variable when that is set and not
+/// blank. The value is split on whitespace so a variable like `less -R` works.
+pub fn viewer_command(viewer: Viewer, pager: Option<String>, editor: Option<String>) -> Vec<String> {
+  let (value, fallback) = match viewer {
+    Viewer::Pager => (pager, "less"),
+    Viewer::Editor => (editor, "vi"),
+  };
+  let command: Vec<String> = value.unwrap_or_default().split_whitespace().map(String::from).collect();
+  if command.is_empty() {
+    return vec![String::from(fallback)];
+  }
+  command
+}
+
+/// Shows `text` in the viewer and waits for it to be closed. The terminal has to be handed over to it first.
+pub fn open_in_viewer(viewer: Viewer, text: &str) -> Result<(), Error> {
+  let command = viewer_command(viewer, env::var("PAGER").ok(), env::var("EDITOR").ok());
+  let (program, args) = command.split_first().expect("there is always a fallback program");
+  info!("Opening text in {}", command.join(" "));
+  let status = match viewer {
+    Viewer::Pager => {
+      let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::Viewer(format!("Failed to run {}: {}", program, err)))?;
+      if let Some(mut stdin) = child.stdin.take() {
+        // A pager quit before reading everything closes the pipe, which isn't a failure
+        let _ = stdin.write_all(text.as_bytes());
+      }
+      child.wait()?
+    },
+    Viewer::Editor => {
+      let path = env::temp_dir().join(format!("git-branch-manager-{}.txt", std::process::id()));
+      fs::write(&path, text)?;
+      let status = Command::new(program).args(args).arg(&path).status();
+      fs::remove_file(&path)?;
+      status.map_err(|err| Error::Viewer(format!("Failed to run {}: {}", program, err)))?
+    },
+  };
+  if !status.success() {
+    return Err(Error::Viewer(format!("{} exited with {}", program, status)));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn set(value: &str) -> Option<String> {
+    Some(String::from(value))
+  }
+
+  #[test]
+  fn the_viewer_comes_from_its_environment_variable_split_into_arguments() {
+    assert_eq!(viewer_command(Viewer::Pager, set("less -R"), set("nano")), ["less", "-R"]);
+    assert_eq!(viewer_command(Viewer::Editor, set("less -R"), set("code --wait")), ["code", "--wait"]);
+  }
+
+  #[test]
+  fn an_unset_or_blank_variable_falls_back_to_less_or_vi() {
+    assert_eq!(viewer_command(Viewer::Pager, None, set("nano")), ["less"]);
+    assert_eq!(viewer_command(Viewer::Pager, set("  "), None), ["less"]);
+    assert_eq!(viewer_command(Viewer::Editor, set("bat"), None), ["vi"]);
+    assert_eq!(viewer_command(Viewer::Editor, set("bat"), set("")), ["vi"]);
+  }
+}